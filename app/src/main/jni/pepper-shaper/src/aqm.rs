@@ -0,0 +1,602 @@
+/*
+ * Active queue management for PepperShaper
+ * CoDel on chunk sojourn times, FQ-CoDel flow queues for TUN packets
+ */
+
+use std::collections::VecDeque;
+use std::hash::{DefaultHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use log::debug;
+
+use crate::pump::PumpStats;
+use crate::queue::{PepperRingBuffer, CHUNK_HEADER};
+
+// CoDel defaults (RFC 8289): acceptable standing delay, and how long it may
+// be exceeded before dropping starts
+const TARGET_NS: u64 = 5_000_000;
+const INTERVAL_NS: u64 = 100_000_000;
+
+// FQ-CoDel flow queues for TUN; flow 0 is the handle's own queue
+const FQ_FLOWS: usize = 16;
+const FLOW_QUEUE_SIZE: usize = 16 * 1024;
+// DRR quantum: one full-size packet per round
+const FQ_QUANTUM: i64 = 1514;
+// Streams: the read-ahead window halves per mark, down to this many
+// chunks, and grows back by one chunk per interval without marks
+const MIN_WINDOW_CHUNKS: usize = 2;
+
+/// Upper bounds of the queue delay histogram buckets in microseconds;
+/// one more bucket counts everything above the last
+pub const DELAY_BUCKETS_US: [u64; 10] = [1_000, 2_000, 5_000, 10_000, 20_000, 50_000, 100_000, 200_000, 500_000, 1_000_000];
+
+/// QueueDiscipline ordinals from Kotlin
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum QueueDiscipline {
+    Fq,     // FQ-CoDel: flow queues for TUN packets, CoDel on each
+    Codel,  // CoDel on a single queue
+    Simple, // FIFO with tail drop
+}
+
+impl QueueDiscipline {
+    pub fn from_ordinal(ordinal: i32) -> Option<Self> {
+        match ordinal {
+            0 => Some(QueueDiscipline::Fq),
+            1 => Some(QueueDiscipline::Codel),
+            2 => Some(QueueDiscipline::Simple),
+            _ => None,
+        }
+    }
+}
+
+/// What the queue carries, which decides how CoDel acts on it
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum QueueKind {
+    /// Byte stream: nothing may be lost, so a CoDel mark shrinks how much is read ahead
+    Stream,
+    /// Datagrams without visible headers: dropped
+    Datagram,
+    /// IP packets: ECN-capable ones get CE marked, others dropped
+    Tun,
+}
+
+/// Queue delay histogram (see DELAY_BUCKETS_US)
+#[derive(Default)]
+pub struct DelayHistogram {
+    counts: [AtomicU64; DELAY_BUCKETS_US.len() + 1],
+}
+
+impl DelayHistogram {
+    pub fn record(&self, sojourn_ns: u64) {
+        let us = sojourn_ns / 1000;
+        let bucket = DELAY_BUCKETS_US.iter().position(|&bound| us <= bound).unwrap_or(DELAY_BUCKETS_US.len());
+        self.counts[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> [u64; DELAY_BUCKETS_US.len() + 1] {
+        std::array::from_fn(|i| self.counts[i].load(Ordering::Relaxed))
+    }
+}
+
+/// CoDel drop scheduler (RFC 8289), consulted once per dequeued chunk
+#[derive(Default)]
+struct CoDel {
+    // When the sojourn time may have been above target for a full interval (0 = below)
+    first_above_ns: u64,
+    drop_next_ns: u64,
+    count: u32,
+    last_count: u32,
+    dropping: bool,
+    // Largest chunk seen; a queue holding no more than this is never dropped from
+    max_chunk: usize,
+}
+
+impl CoDel {
+    /// The queue ran empty
+    fn on_empty(&mut self) {
+        self.first_above_ns = 0;
+        self.dropping = false;
+    }
+
+    /// Whether to drop (or mark) a chunk of `len` bytes that waited
+    /// `sojourn_ns`, with `backlog` bytes left behind it
+    fn should_drop(&mut self, len: usize, sojourn_ns: u64, backlog: usize, now_ns: u64) -> bool {
+        self.max_chunk = self.max_chunk.max(len);
+
+        let ok_to_drop = if sojourn_ns < TARGET_NS || backlog <= self.max_chunk {
+            self.first_above_ns = 0;
+            false
+        } else if self.first_above_ns == 0 {
+            self.first_above_ns = now_ns + INTERVAL_NS;
+            false
+        } else {
+            now_ns >= self.first_above_ns
+        };
+
+        if self.dropping {
+            if !ok_to_drop {
+                self.dropping = false;
+                return false;
+            }
+            if now_ns < self.drop_next_ns {
+                return false;
+            }
+            self.count += 1;
+            self.drop_next_ns = self.control_law(self.drop_next_ns);
+            return true;
+        }
+
+        if !ok_to_drop {
+            return false;
+        }
+        self.dropping = true;
+        // Resume near the last drop rate if dropping stopped only recently
+        let delta = self.count.saturating_sub(self.last_count);
+        self.count = if delta > 1 && now_ns.saturating_sub(self.drop_next_ns) < 16 * INTERVAL_NS { delta } else { 1 };
+        self.last_count = self.count;
+        self.drop_next_ns = self.control_law(now_ns);
+        true
+    }
+
+    /// Next drop time: drops speed up with the square root of the count
+    fn control_law(&self, t_ns: u64) -> u64 {
+        t_ns + (INTERVAL_NS as f64 / (self.count.max(1) as f64).sqrt()) as u64
+    }
+}
+
+struct Flow {
+    // Allocated on first use, except flow 0
+    queue: Option<Arc<PepperRingBuffer>>,
+    codel: CoDel,
+    deficit: i64,
+    backlog: usize,
+    // On the new or old flow list
+    listed: bool,
+}
+
+impl Flow {
+    fn new(queue: Option<Arc<PepperRingBuffer>>) -> Self {
+        Self { queue, codel: CoDel::default(), deficit: 0, backlog: 0, listed: false }
+    }
+}
+
+/// A handle's transmit queue: timestamped chunks, CoDel per flow and, for
+/// TUN packets under FQ, flow queues served by deficit round robin with
+/// new flows first
+pub struct AqmQueue {
+    kind: QueueKind,
+    discipline: QueueDiscipline,
+    flows: Vec<Flow>,
+    new_flows: VecDeque<usize>,
+    old_flows: VecDeque<usize>,
+    backlog: usize,
+    // Streams: bytes that may be queued, and when it last changed
+    window: usize,
+    window_changed_ns: u64,
+}
+
+impl AqmQueue {
+    pub fn new(kind: QueueKind, discipline: QueueDiscipline, queue: Arc<PepperRingBuffer>) -> Self {
+        let mut flows = vec![Flow::new(Some(queue))];
+        if kind == QueueKind::Tun {
+            flows.extend((1..FQ_FLOWS).map(|_| Flow::new(None)));
+        }
+        Self {
+            kind,
+            discipline,
+            flows,
+            new_flows: VecDeque::new(),
+            old_flows: VecDeque::new(),
+            backlog: 0,
+            window: usize::MAX,
+            window_changed_ns: 0,
+        }
+    }
+
+    /// Apply a discipline change; queued chunks stay where they are
+    pub fn set_discipline(&mut self, discipline: QueueDiscipline) {
+        if discipline != self.discipline {
+            debug!("Queue discipline: {:?} -> {:?}", self.discipline, discipline);
+            self.discipline = discipline;
+            for flow in &mut self.flows {
+                flow.codel = CoDel::default();
+            }
+            self.window = usize::MAX;
+        }
+    }
+
+    /// Payload bytes queued
+    pub fn backlog(&self) -> usize {
+        self.backlog
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.backlog == 0
+    }
+
+    /// Largest stream chunk that fits right now, within the read-ahead window
+    pub fn space(&self) -> usize {
+        let free = self.flows[0].queue.as_ref().map_or(0, |q| q.available().saturating_sub(CHUNK_HEADER));
+        free.min(self.window.saturating_sub(self.backlog))
+    }
+
+    /// Queue one chunk; false if its flow queue is full
+    pub fn enqueue(&mut self, data: &[u8], now_ns: u64) -> bool {
+        let index = if self.kind == QueueKind::Tun && self.discipline == QueueDiscipline::Fq {
+            (flow_hash(data) % FQ_FLOWS as u64) as usize
+        } else {
+            0
+        };
+
+        let flow = &mut self.flows[index];
+        let queue = flow.queue.get_or_insert_with(|| Arc::new(PepperRingBuffer::new(FLOW_QUEUE_SIZE)));
+        if !queue.enqueue_chunk(data, now_ns) {
+            return false;
+        }
+        flow.backlog += data.len();
+        self.backlog += data.len();
+        if !flow.listed {
+            flow.listed = true;
+            flow.deficit = FQ_QUANTUM;
+            self.new_flows.push_back(index);
+        }
+        true
+    }
+
+    /// Take the next chunk into `out` (replacing its contents)
+    ///
+    /// Streams coalesce queued chunks up to `max_bytes`. Chunks CoDel drops
+    /// are counted in `stats` and skipped; returns false when nothing is left.
+    pub fn dequeue(&mut self, out: &mut Vec<u8>, max_bytes: usize, now_ns: u64, stats: &PumpStats) -> bool {
+        out.clear();
+        loop {
+            let (index, is_new) = match (self.new_flows.front(), self.old_flows.front()) {
+                (Some(&index), _) => (index, true),
+                (None, Some(&index)) => (index, false),
+                (None, None) => return false,
+            };
+
+            if self.flows[index].deficit <= 0 {
+                // Used its share this round: to the back of the old flows
+                self.flows[index].deficit += FQ_QUANTUM;
+                self.pop_list(is_new);
+                self.old_flows.push_back(index);
+                continue;
+            }
+
+            if self.take(index, out, max_bytes, now_ns, stats) {
+                self.flows[index].deficit -= out.len() as i64;
+                return true;
+            }
+
+            // Flow ran empty; a new flow passes through the old list once so
+            // it cannot come back ahead of the others right away
+            self.pop_list(is_new);
+            if is_new && !self.old_flows.is_empty() {
+                self.old_flows.push_back(index);
+            } else {
+                self.flows[index].listed = false;
+            }
+        }
+    }
+
+    fn pop_list(&mut self, is_new: bool) {
+        if is_new {
+            self.new_flows.pop_front();
+        } else {
+            self.old_flows.pop_front();
+        }
+    }
+
+    /// Dequeue from one flow through CoDel; false if it is empty
+    fn take(&mut self, index: usize, out: &mut Vec<u8>, max_bytes: usize, now_ns: u64, stats: &PumpStats) -> bool {
+        let kind = self.kind;
+        let aqm = self.discipline != QueueDiscipline::Simple;
+        let flow = &mut self.flows[index];
+        let Some(queue) = flow.queue.clone() else {
+            return false;
+        };
+
+        loop {
+            let Some(enqueued_ns) = queue.dequeue_chunk(out) else {
+                flow.codel.on_empty();
+                return false;
+            };
+            flow.backlog -= out.len();
+            self.backlog -= out.len();
+
+            let sojourn_ns = now_ns.saturating_sub(enqueued_ns);
+            stats.delay_histogram.record(sojourn_ns);
+
+            if aqm && flow.codel.should_drop(out.len(), sojourn_ns, flow.backlog, now_ns) {
+                match kind {
+                    QueueKind::Stream => {
+                        // Read less ahead; the producer feels it as back-pressure
+                        let min_window = MIN_WINDOW_CHUNKS * flow.codel.max_chunk;
+                        self.window = (self.window.min(flow.backlog + out.len()) / 2).max(min_window);
+                        self.window_changed_ns = now_ns;
+                        stats.ecn_marked.fetch_add(1, Ordering::Relaxed);
+                    }
+                    QueueKind::Tun if set_ecn_ce(out) => {
+                        stats.ecn_marked.fetch_add(1, Ordering::Relaxed);
+                    }
+                    _ => {
+                        stats.aqm_dropped.fetch_add(1, Ordering::Relaxed);
+                        out.clear();
+                        continue;
+                    }
+                }
+            }
+
+            if kind == QueueKind::Stream {
+                if self.window != usize::MAX && now_ns.saturating_sub(self.window_changed_ns) >= INTERVAL_NS {
+                    self.window += flow.codel.max_chunk;
+                    self.window_changed_ns = now_ns;
+                }
+                // Slices of a stream go out together while they fit
+                while let Some((len, _)) = queue.peek_chunk() {
+                    if out.len() + len > max_bytes {
+                        break;
+                    }
+                    queue.dequeue_chunk(out);
+                    flow.backlog -= len;
+                    self.backlog -= len;
+                }
+            }
+            return true;
+        }
+    }
+}
+
+/// Hash of an IP packet's addresses, protocol and (unfragmented) ports
+fn flow_hash(packet: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    match packet.first().map(|b| b >> 4) {
+        Some(4) if packet.len() >= 20 => {
+            let header_len = (packet[0] & 0x0f) as usize * 4;
+            let protocol = packet[9];
+            hasher.write(&packet[12..20]);
+            hasher.write_u8(protocol);
+            // Fragments after the first carry no ports: keep them with the flow
+            let fragmented = u16::from_be_bytes([packet[6], packet[7]]) & 0x3fff != 0;
+            if matches!(protocol, 6 | 17) && !fragmented && packet.len() >= header_len + 4 {
+                hasher.write(&packet[header_len..header_len + 4]);
+            }
+        }
+        Some(6) if packet.len() >= 40 => {
+            let next_header = packet[6];
+            hasher.write(&packet[8..40]);
+            hasher.write_u8(next_header);
+            if matches!(next_header, 6 | 17) && packet.len() >= 44 {
+                hasher.write(&packet[40..44]);
+            }
+        }
+        _ => {}
+    }
+    hasher.finish()
+}
+
+/// Mark an ECN-capable IP packet Congestion Experienced
+/// Returns false if it is not ECN-capable (and must be dropped instead)
+fn set_ecn_ce(packet: &mut [u8]) -> bool {
+    match packet.first().map(|b| b >> 4) {
+        Some(4) if packet.len() >= 20 => {
+            if packet[1] & 0x03 == 0 {
+                return false;
+            }
+            let old = u16::from_be_bytes([packet[0], packet[1]]);
+            packet[1] |= 0x03;
+            let new = u16::from_be_bytes([packet[0], packet[1]]);
+            // Incremental header checksum update (RFC 1624)
+            let checksum = u16::from_be_bytes([packet[10], packet[11]]);
+            let mut sum = !checksum as u32 + !old as u32 + new as u32;
+            sum = (sum & 0xffff) + (sum >> 16);
+            sum = (sum & 0xffff) + (sum >> 16);
+            packet[10..12].copy_from_slice(&(!(sum as u16)).to_be_bytes());
+            true
+        }
+        Some(6) if packet.len() >= 40 => {
+            // ECN bits are the low two bits of the traffic class
+            if packet[1] & 0x30 == 0 {
+                return false;
+            }
+            packet[1] |= 0x30;
+            true
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: u64 = 1_000_000;
+
+    fn ipv4_checksum(header: &[u8]) -> u16 {
+        let mut sum: u32 = header.chunks(2).map(|w| u16::from_be_bytes([w[0], w[1]]) as u32).sum();
+        while sum > 0xffff {
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+        !(sum as u16)
+    }
+
+    /// IPv4/UDP packet of `len` bytes with a valid header checksum
+    fn udp4(src_port: u16, dst_port: u16, len: usize, tos: u8, id: u16) -> Vec<u8> {
+        let mut packet = vec![0u8; len];
+        packet[0] = 0x45;
+        packet[1] = tos;
+        packet[2..4].copy_from_slice(&(len as u16).to_be_bytes());
+        packet[4..6].copy_from_slice(&id.to_be_bytes());
+        packet[8] = 64;
+        packet[9] = 17;
+        packet[12..16].copy_from_slice(&[10, 0, 0, 2]);
+        packet[16..20].copy_from_slice(&[10, 0, 0, 1]);
+        packet[20..22].copy_from_slice(&src_port.to_be_bytes());
+        packet[22..24].copy_from_slice(&dst_port.to_be_bytes());
+        let checksum = ipv4_checksum(&packet[..20]);
+        packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+        packet
+    }
+
+    fn fq_queue() -> AqmQueue {
+        AqmQueue::new(QueueKind::Tun, QueueDiscipline::Fq, Arc::new(PepperRingBuffer::new(64 * 1024)))
+    }
+
+    /// Source ports of two packets landing in different flow queues
+    fn two_flows() -> (u16, u16) {
+        let bucket = |port| flow_hash(&udp4(port, 53, 100, 0, 0)) % FQ_FLOWS as u64;
+        let other = (2000..).find(|&port| bucket(port) != bucket(1000)).unwrap();
+        (1000, other)
+    }
+
+    fn drain(queue: &mut AqmQueue, stats: &PumpStats) -> Vec<u16> {
+        let mut out = Vec::new();
+        let mut ports = Vec::new();
+        while queue.dequeue(&mut out, 2048, 0, stats) {
+            ports.push(u16::from_be_bytes([out[20], out[21]]));
+        }
+        ports
+    }
+
+    #[test]
+    fn codel_waits_an_interval_above_target() {
+        let mut codel = CoDel::default();
+        // Below target, or nothing queued behind the chunk: never
+        assert!(!codel.should_drop(1000, TARGET_NS - 1, 100_000, 0));
+        assert!(!codel.should_drop(1000, 50 * MS, 1000, MS));
+
+        assert!(!codel.should_drop(1000, 10 * MS, 100_000, 10 * MS));
+        assert!(!codel.should_drop(1000, 10 * MS, 100_000, 10 * MS + INTERVAL_NS - 1));
+        assert!(codel.should_drop(1000, 10 * MS, 100_000, 10 * MS + INTERVAL_NS));
+    }
+
+    #[test]
+    fn codel_drops_faster_with_the_count() {
+        let mut codel = CoDel::default();
+        assert!(!codel.should_drop(1000, 10 * MS, 100_000, 0));
+        let start = INTERVAL_NS;
+        assert!(codel.should_drop(1000, 10 * MS, 100_000, start));
+
+        // Next drops after interval / sqrt(count)
+        let second = start + INTERVAL_NS;
+        assert!(!codel.should_drop(1000, 10 * MS, 100_000, second - 1));
+        assert!(codel.should_drop(1000, 10 * MS, 100_000, second));
+        let third = second + (INTERVAL_NS as f64 / 2f64.sqrt()) as u64;
+        assert!(!codel.should_drop(1000, 10 * MS, 100_000, third - 1));
+        assert!(codel.should_drop(1000, 10 * MS, 100_000, third));
+
+        // Back below target: stop, and start over after a full interval
+        assert!(!codel.should_drop(1000, MS, 100_000, third + 1));
+        assert!(!codel.dropping);
+        let later = third + 20 * INTERVAL_NS;
+        assert!(!codel.should_drop(1000, 10 * MS, 100_000, later));
+        assert!(codel.should_drop(1000, 10 * MS, 100_000, later + INTERVAL_NS));
+        assert_eq!(codel.count, 1);
+    }
+
+    #[test]
+    fn codel_resumes_near_the_last_drop_rate() {
+        let mut codel = CoDel::default();
+        codel.should_drop(1000, 10 * MS, 100_000, 0);
+        let mut now = INTERVAL_NS;
+        while codel.count < 5 {
+            codel.should_drop(1000, 10 * MS, 100_000, now);
+            now += MS;
+        }
+        codel.on_empty();
+        // Above target again soon after: count restarts from the drops since last time
+        codel.should_drop(1000, 10 * MS, 100_000, now);
+        assert!(codel.should_drop(1000, 10 * MS, 100_000, now + INTERVAL_NS));
+        assert_eq!(codel.count, 4);
+    }
+
+    #[test]
+    fn sparse_flow_overtakes_a_bulk_flow() {
+        let (bulk, sparse) = two_flows();
+        let stats = PumpStats::default();
+        let mut queue = fq_queue();
+        for _ in 0..6 {
+            assert!(queue.enqueue(&udp4(bulk, 53, 1000, 0, 0), 0));
+        }
+        let mut out = Vec::new();
+        assert!(queue.dequeue(&mut out, 2048, 0, &stats));
+        assert!(queue.enqueue(&udp4(sparse, 53, 1000, 0, 0), 0));
+
+        // The bulk flow finishes its quantum, then the new flow goes first
+        let order = drain(&mut queue, &stats);
+        assert_eq!(order, [bulk, sparse, bulk, bulk, bulk, bulk]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn bulk_flows_share_by_quantum() {
+        let (a, b) = two_flows();
+        let stats = PumpStats::default();
+        let mut queue = fq_queue();
+        for _ in 0..10 {
+            assert!(queue.enqueue(&udp4(a, 53, 500, 0, 0), 0));
+            assert!(queue.enqueue(&udp4(b, 53, 500, 0, 0), 0));
+        }
+
+        let order = drain(&mut queue, &stats);
+        assert_eq!(order.len(), 20);
+        // Four 500 byte packets use up a 1514 byte quantum
+        assert_eq!(order[..8], [a, a, a, a, b, b, b, b]);
+        for i in 1..=order.len() {
+            let sent_a = order[..i].iter().filter(|&&port| port == a).count();
+            assert!(sent_a.abs_diff(i - sent_a) <= 4, "unfair after {}: {:?}", i, order);
+        }
+    }
+
+    #[test]
+    fn ecn_mark_keeps_the_checksum_valid() {
+        for id in 0..=u16::MAX {
+            let mut packet = udp4(1000, 53, 60, 0x01 | (id as u8 & 0xfc), id);
+            assert!(set_ecn_ce(&mut packet));
+            assert_eq!(packet[1] & 0x03, 0x03);
+            assert_eq!(ipv4_checksum(&packet[..20]), 0, "id {}", id);
+        }
+
+        let mut not_ect = udp4(1000, 53, 60, 0xb8, 1);
+        let original = not_ect.clone();
+        assert!(!set_ecn_ce(&mut not_ect));
+        assert_eq!(not_ect, original);
+
+        let mut ipv6 = vec![0u8; 60];
+        ipv6[0] = 0x60;
+        ipv6[1] = 0x20;
+        assert!(set_ecn_ce(&mut ipv6));
+        assert_eq!(ipv6[1], 0x30);
+        assert!(!set_ecn_ce(&mut ipv6[..39]));
+    }
+
+    #[test]
+    fn flow_hash_on_short_and_fragmented_packets() {
+        // Too short to parse: no panic, all in one flow
+        let empty = flow_hash(&[]);
+        assert_eq!(flow_hash(&[0x45; 19]), empty);
+        assert_eq!(flow_hash(&[0x60; 39]), empty);
+        // Header length past the end of the packet: ports are skipped
+        let mut bad_ihl = udp4(1000, 53, 40, 0, 0);
+        bad_ihl[0] = 0x4f;
+        flow_hash(&bad_ihl);
+
+        // Ports split flows, truncated transport headers do not panic
+        assert_ne!(flow_hash(&udp4(1000, 53, 100, 0, 0)), flow_hash(&udp4(1001, 53, 100, 0, 0)));
+        let truncated = &udp4(1000, 53, 100, 0, 0)[..22];
+        assert_eq!(flow_hash(truncated), flow_hash(&udp4(1001, 53, 100, 0, 0)[..22]));
+
+        // First and later fragments stay in one flow
+        let mut first = udp4(1000, 53, 100, 0, 7);
+        first[6] = 0x20; // More fragments
+        let mut later = udp4(0x4142, 0x4344, 100, 0, 7);
+        later[6..8].copy_from_slice(&185u16.to_be_bytes()); // Offset only
+        assert_eq!(flow_hash(&first), flow_hash(&later));
+        // Don't-fragment alone is not a fragment
+        let mut df = udp4(1000, 53, 100, 0, 7);
+        df[6] = 0x40;
+        assert_ne!(flow_hash(&df), flow_hash(&first));
+    }
+}
@@ -0,0 +1,370 @@
+/*
+ * Adaptive pacing for PepperShaper
+ * BBR-style bottleneck bandwidth / min RTT estimator with gain cycling
+ */
+
+use log::debug;
+use std::collections::VecDeque;
+
+// Startup grows the rate 2/ln(2) per round; drain empties the queue it built
+const STARTUP_GAIN: f64 = 2.885;
+const DRAIN_GAIN: f64 = 1.0 / STARTUP_GAIN;
+// ProbeBW: one probing round, one draining round, six cruising rounds
+const PROBE_BW_GAINS: [f64; 8] = [1.25, 0.75, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0];
+const PROBE_RTT_GAIN: f64 = 0.5;
+
+// Max filter length for bandwidth samples
+const BW_WINDOW_ROUNDS: u64 = 10;
+// Min RTT is re-probed if not seen again within this window
+const MIN_RTT_WINDOW_NS: u64 = 10_000_000_000;
+const PROBE_RTT_DURATION_NS: u64 = 200_000_000;
+// Round length until an RTT has been measured
+const DEFAULT_ROUND_NS: u64 = 100_000_000;
+
+// Without a congestion window, RTT inflation stands in for inflight data:
+// the queue counts as drained below DRAINED_RTT x min RTT, and above
+// QUEUE_CAP_RTT x min RTT (a BDP queued) ProbeBW drains before cruising on
+const DRAINED_RTT: f64 = 1.25;
+const QUEUE_CAP_RTT: f64 = 2.0;
+// Give up draining after this many rounds (e.g. a path whose RTT rose for good)
+const MAX_DRAIN_ROUNDS: u64 = 10;
+// ProbeBW cycle index of the draining phase
+const PROBE_BW_DRAIN_INDEX: usize = 1;
+
+// Startup ends after this many rounds without 25% bandwidth growth
+const FULL_BW_GROWTH: f64 = 1.25;
+const FULL_BW_ROUNDS: u32 = 3;
+
+// Rate used until the first delivery sample
+const INITIAL_RATE_BPS: u64 = 2_000_000;
+// Burst bounds: at least two full-size packets, at most 64 KiB
+const MIN_BURST_BYTES: u64 = 3000;
+const MAX_BURST_BYTES: u64 = 64 * 1024;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BbrPhase {
+    Startup,
+    Drain,
+    ProbeBw,
+    ProbeRtt,
+}
+
+impl BbrPhase {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BbrPhase::Startup => "startup",
+            BbrPhase::Drain => "drain",
+            BbrPhase::ProbeBw => "probe_bw",
+            BbrPhase::ProbeRtt => "probe_rtt",
+        }
+    }
+}
+
+/// Estimates bottleneck bandwidth and min RTT from delivery samples and
+/// derives a pacing rate and burst from them
+pub struct BbrEstimator {
+    phase: BbrPhase,
+    phase_start_ns: u64,
+    // (round, rate) samples inside the max filter window
+    bw_samples: VecDeque<(u64, u64)>,
+    btl_bw_bps: u64,
+    // u64::MAX until measured
+    min_rtt_ns: u64,
+    min_rtt_stamp_ns: u64,
+    last_rtt_ns: u64,
+    round: u64,
+    round_start_ns: u64,
+    full_bw_bps: u64,
+    full_bw_rounds: u32,
+    full_bw_reached: bool,
+    cycle_index: usize,
+    cycle_start_ns: u64,
+    // Upper bound for the pacing rate (0 = none)
+    ceiling_bps: u64,
+}
+
+impl BbrEstimator {
+    pub fn new(now_ns: u64, ceiling_bps: u64) -> Self {
+        Self {
+            phase: BbrPhase::Startup,
+            phase_start_ns: now_ns,
+            bw_samples: VecDeque::new(),
+            btl_bw_bps: 0,
+            min_rtt_ns: u64::MAX,
+            min_rtt_stamp_ns: now_ns,
+            last_rtt_ns: 0,
+            round: 0,
+            round_start_ns: now_ns,
+            full_bw_bps: 0,
+            full_bw_rounds: 0,
+            full_bw_reached: false,
+            cycle_index: 0,
+            cycle_start_ns: now_ns,
+            ceiling_bps,
+        }
+    }
+
+    pub fn set_ceiling(&mut self, ceiling_bps: u64) {
+        self.ceiling_bps = ceiling_bps;
+    }
+
+    #[cfg(test)]
+    pub fn phase(&self) -> BbrPhase {
+        self.phase
+    }
+
+    #[cfg(test)]
+    pub fn btl_bw_bps(&self) -> u64 {
+        self.btl_bw_bps
+    }
+
+    /// Min RTT (None until measured)
+    pub fn min_rtt_ns(&self) -> Option<u64> {
+        (self.min_rtt_ns != u64::MAX).then_some(self.min_rtt_ns)
+    }
+
+    fn round_ns(&self) -> u64 {
+        self.min_rtt_ns().unwrap_or(DEFAULT_ROUND_NS).max(1_000_000)
+    }
+
+    fn pacing_gain(&self) -> f64 {
+        match self.phase {
+            BbrPhase::Startup => STARTUP_GAIN,
+            BbrPhase::Drain => DRAIN_GAIN,
+            BbrPhase::ProbeBw => PROBE_BW_GAINS[self.cycle_index],
+            BbrPhase::ProbeRtt => PROBE_RTT_GAIN,
+        }
+    }
+
+    /// Current pacing rate in bits per second
+    pub fn pacing_rate_bps(&self) -> u64 {
+        let rate = if self.btl_bw_bps == 0 {
+            INITIAL_RATE_BPS
+        } else {
+            (self.btl_bw_bps as f64 * self.pacing_gain()) as u64
+        };
+        if self.ceiling_bps > 0 {
+            rate.min(self.ceiling_bps)
+        } else {
+            rate
+        }
+    }
+
+    /// Burst allowed at the current rate: about 1 ms of data, bounded
+    pub fn burst_bytes(&self) -> u64 {
+        (self.pacing_rate_bps() / 8 / 1000).clamp(MIN_BURST_BYTES, MAX_BURST_BYTES)
+    }
+
+    /// Take a delivery sample
+    ///
+    /// `app_limited` samples (the sender had nothing to send, so the rate says
+    /// little about the path) only raise the bandwidth estimate, never lower it.
+    pub fn on_sample(&mut self, now_ns: u64, delivery_rate_bps: u64, rtt_ns: u64, app_limited: bool) {
+        let round_start = now_ns.saturating_sub(self.round_start_ns) >= self.round_ns();
+        if round_start {
+            self.round += 1;
+            self.round_start_ns = now_ns;
+        }
+
+        if delivery_rate_bps > 0 && (!app_limited || delivery_rate_bps >= self.btl_bw_bps) {
+            self.bw_samples.push_back((self.round, delivery_rate_bps));
+        }
+        while self.bw_samples.front().is_some_and(|&(round, _)| round + BW_WINDOW_ROUNDS <= self.round) {
+            self.bw_samples.pop_front();
+        }
+        self.btl_bw_bps = self.bw_samples.iter().map(|&(_, rate)| rate).max().unwrap_or(self.btl_bw_bps);
+
+        let min_rtt_expired = now_ns.saturating_sub(self.min_rtt_stamp_ns) > MIN_RTT_WINDOW_NS;
+        if rtt_ns > 0 {
+            self.last_rtt_ns = rtt_ns;
+            if rtt_ns <= self.min_rtt_ns || min_rtt_expired {
+                self.min_rtt_ns = rtt_ns;
+                self.min_rtt_stamp_ns = now_ns;
+            }
+        }
+
+        if self.phase == BbrPhase::Startup && round_start && !app_limited {
+            self.check_full_bw(now_ns);
+        }
+        if min_rtt_expired && self.phase != BbrPhase::ProbeRtt {
+            self.enter(BbrPhase::ProbeRtt, now_ns);
+        }
+
+        self.advance(now_ns);
+    }
+
+    fn check_full_bw(&mut self, now_ns: u64) {
+        if self.btl_bw_bps as f64 >= self.full_bw_bps as f64 * FULL_BW_GROWTH {
+            self.full_bw_bps = self.btl_bw_bps;
+            self.full_bw_rounds = 0;
+            return;
+        }
+        self.full_bw_rounds += 1;
+        if self.full_bw_rounds >= FULL_BW_ROUNDS {
+            self.full_bw_reached = true;
+            self.enter(BbrPhase::Drain, now_ns);
+        }
+    }
+
+    /// Whether the last RTT sample shows more than `factor` x min RTT
+    fn rtt_above(&self, factor: f64) -> bool {
+        self.min_rtt_ns().is_some_and(|min_rtt| self.last_rtt_ns as f64 > min_rtt as f64 * factor)
+    }
+
+    /// Time-based transitions (drain exit, gain cycling, ProbeRTT exit)
+    pub fn advance(&mut self, now_ns: u64) {
+        let round_ns = self.round_ns();
+        match self.phase {
+            BbrPhase::Startup => {}
+            BbrPhase::Drain => {
+                let elapsed = now_ns.saturating_sub(self.phase_start_ns);
+                if elapsed >= round_ns && (!self.rtt_above(DRAINED_RTT) || elapsed >= round_ns * MAX_DRAIN_ROUNDS) {
+                    self.enter(BbrPhase::ProbeBw, now_ns);
+                }
+            }
+            BbrPhase::ProbeBw => {
+                let elapsed = now_ns.saturating_sub(self.cycle_start_ns);
+                let draining = self.cycle_index == PROBE_BW_DRAIN_INDEX
+                    && self.rtt_above(DRAINED_RTT)
+                    && elapsed < round_ns * MAX_DRAIN_ROUNDS;
+                if elapsed >= round_ns && !draining {
+                    self.cycle_index = if self.cycle_index != PROBE_BW_DRAIN_INDEX && self.rtt_above(QUEUE_CAP_RTT) {
+                        PROBE_BW_DRAIN_INDEX
+                    } else {
+                        (self.cycle_index + 1) % PROBE_BW_GAINS.len()
+                    };
+                    self.cycle_start_ns = now_ns;
+                }
+            }
+            BbrPhase::ProbeRtt => {
+                if now_ns.saturating_sub(self.phase_start_ns) >= PROBE_RTT_DURATION_NS.max(round_ns) {
+                    self.min_rtt_stamp_ns = now_ns;
+                    let next = if self.full_bw_reached { BbrPhase::ProbeBw } else { BbrPhase::Startup };
+                    self.enter(next, now_ns);
+                }
+            }
+        }
+    }
+
+    fn enter(&mut self, phase: BbrPhase, now_ns: u64) {
+        debug!("Adaptive pacing: {} -> {} (btl_bw={}bps, min_rtt={}us)", self.phase.name(), phase.name(),
+               self.btl_bw_bps, self.min_rtt_ns().unwrap_or(0) / 1000);
+        self.phase = phase;
+        self.phase_start_ns = now_ns;
+        if phase == BbrPhase::ProbeBw {
+            self.cycle_index = 0;
+            self.cycle_start_ns = now_ns;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: u64 = 1_000_000;
+
+    /// Feed a sample every 10 ms from `from_ms` until `to_ms`
+    fn feed(bbr: &mut BbrEstimator, from_ms: u64, to_ms: u64, rate_bps: u64, rtt_ms: u64) {
+        for t in (from_ms..to_ms).step_by(10) {
+            bbr.on_sample(t * MS, rate_bps, rtt_ms * MS, false);
+        }
+    }
+
+    #[test]
+    fn initial_rate_until_first_sample() {
+        let bbr = BbrEstimator::new(0, 0);
+        assert_eq!(bbr.phase(), BbrPhase::Startup);
+        assert_eq!(bbr.pacing_rate_bps(), INITIAL_RATE_BPS);
+        assert_eq!(bbr.min_rtt_ns(), None);
+        assert_eq!(bbr.burst_bytes(), MIN_BURST_BYTES);
+    }
+
+    #[test]
+    fn startup_drain_probe_bw() {
+        let mut bbr = BbrEstimator::new(0, 0);
+        // Growing delivery keeps startup going
+        for round in 0..5u64 {
+            feed(&mut bbr, round * 50, round * 50 + 50, 1_000_000 << round, 50);
+        }
+        assert_eq!(bbr.phase(), BbrPhase::Startup);
+        assert_eq!(bbr.pacing_rate_bps(), (16_000_000.0 * STARTUP_GAIN) as u64);
+
+        // Three rounds without 25% growth: the pipe is full
+        feed(&mut bbr, 250, 500, 16_000_000, 100);
+        assert_eq!(bbr.phase(), BbrPhase::Drain);
+        assert!(bbr.pacing_rate_bps() < bbr.btl_bw_bps());
+
+        // Drain ends once RTT is back near the minimum
+        let mut t = 500;
+        while bbr.phase() == BbrPhase::Drain {
+            assert!(t < 1_000, "drain did not end");
+            bbr.on_sample(t * MS, 16_000_000, 51 * MS, false);
+            t += 10;
+        }
+        assert_eq!(bbr.phase(), BbrPhase::ProbeBw);
+
+        // One round probing, one draining, then cruising at the estimate
+        let mut rates = vec![bbr.pacing_rate_bps()];
+        for t in (t..t + 300).step_by(10) {
+            bbr.on_sample(t * MS, 16_000_000, 50 * MS, false);
+            if rates.last() != Some(&bbr.pacing_rate_bps()) {
+                rates.push(bbr.pacing_rate_bps());
+            }
+        }
+        assert_eq!(rates, vec![20_000_000, 12_000_000, 16_000_000]);
+    }
+
+    #[test]
+    fn standing_queue_in_probe_bw_forces_drain() {
+        let mut bbr = BbrEstimator::new(0, 0);
+        feed(&mut bbr, 0, 400, 8_000_000, 20);
+        feed(&mut bbr, 400, 500, 8_000_000, 20);
+        assert_eq!(bbr.phase(), BbrPhase::ProbeBw);
+
+        // RTT above 2x min: the next cycle step is the draining one
+        feed(&mut bbr, 500, 560, 8_000_000, 50);
+        assert_eq!(bbr.pacing_rate_bps(), (8_000_000.0 * 0.75) as u64);
+    }
+
+    #[test]
+    fn probe_rtt_after_min_rtt_window() {
+        let mut bbr = BbrEstimator::new(0, 0);
+        feed(&mut bbr, 0, 1_000, 8_000_000, 20);
+        assert_eq!(bbr.phase(), BbrPhase::ProbeBw);
+
+        // No RTT at or below 20 ms for 10 s
+        feed(&mut bbr, 1_000, 10_980, 8_000_000, 22);
+        assert_eq!(bbr.phase(), BbrPhase::ProbeBw);
+        feed(&mut bbr, 10_980, 11_010, 8_000_000, 22);
+        assert_eq!(bbr.phase(), BbrPhase::ProbeRtt);
+        assert_eq!(bbr.pacing_rate_bps(), (8_000_000.0 * PROBE_RTT_GAIN) as u64);
+        assert_eq!(bbr.min_rtt_ns(), Some(22 * MS));
+
+        // Held for 200 ms, then back to probing bandwidth
+        bbr.advance(11_100 * MS);
+        assert_eq!(bbr.phase(), BbrPhase::ProbeRtt);
+        bbr.advance(11_210 * MS);
+        assert_eq!(bbr.phase(), BbrPhase::ProbeBw);
+    }
+
+    #[test]
+    fn app_limited_sample_does_not_lower_estimate() {
+        let mut bbr = BbrEstimator::new(0, 0);
+        feed(&mut bbr, 0, 100, 10_000_000, 20);
+        bbr.on_sample(100 * MS, 1_000_000, 20 * MS, true);
+        assert_eq!(bbr.btl_bw_bps(), 10_000_000);
+        // but a higher one still counts
+        bbr.on_sample(110 * MS, 12_000_000, 20 * MS, true);
+        assert_eq!(bbr.btl_bw_bps(), 12_000_000);
+    }
+
+    #[test]
+    fn ceiling_and_burst_bounds() {
+        let mut bbr = BbrEstimator::new(0, 5_000_000);
+        feed(&mut bbr, 0, 100, 10_000_000_000, 20);
+        assert_eq!(bbr.pacing_rate_bps(), 5_000_000);
+        bbr.set_ceiling(0);
+        assert_eq!(bbr.burst_bytes(), MAX_BURST_BYTES);
+    }
+}
@@ -0,0 +1,531 @@
+/*
+ * Hierarchical token bucket for PepperShaper
+ * Class tree splitting a shared budget between handles, DRR among borrowers
+ */
+
+use log::debug;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+// Bucket depth: this long at the class rate, at least two full-size packets
+const BURST_NS: f64 = 10_000_000.0;
+const MIN_BURST_BYTES: f64 = 3000.0;
+// Debt a bucket can run up by sending a chunk bigger than its tokens
+const MAX_DEBT_BURSTS: f64 = 4.0;
+// DRR quantum per unit of weight (a full pump chunk)
+const QUANTUM_BYTES: i64 = 16 * 1024;
+// A borrower counts as backlogged this long after its last request
+const ACTIVE_NS: u64 = 50_000_000;
+// Retry delay for a borrower waiting for its DRR turn
+const DRR_RETRY_NS: u64 = 1_000_000;
+
+/// A class configured by the app or a handle attached to one
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum NodeId {
+    Class(i32),
+    Handle(i64),
+}
+
+/// Rates of a class or handle
+#[derive(Clone, Copy)]
+pub struct ClassParams {
+    pub rate_bps: u64,  // Guaranteed rate (0 = none; unlimited for a top-level class)
+    pub ceil_bps: u64,  // Upper bound including borrowing (0 = none)
+    pub weight: u32,    // Share of the parent's spare rate among borrowing siblings
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct ClassStats {
+    pub sent_bytes: u64,
+    pub borrowed_bytes: u64,    // Sent above the guaranteed rate
+    pub delayed: u64,           // Requests held back
+}
+
+struct Bucket {
+    rate_bps: u64,
+    tokens: f64,
+    last_ns: u64,
+}
+
+impl Bucket {
+    fn new(rate_bps: u64, now_ns: u64) -> Option<Self> {
+        if rate_bps == 0 {
+            return None;
+        }
+        let mut bucket = Self { rate_bps, tokens: 0.0, last_ns: now_ns };
+        bucket.tokens = bucket.depth();
+        Some(bucket)
+    }
+
+    fn depth(&self) -> f64 {
+        (self.rate_bps as f64 * BURST_NS / 8e9).max(MIN_BURST_BYTES)
+    }
+
+    fn refill(&mut self, now_ns: u64) {
+        let elapsed_ns = now_ns.saturating_sub(self.last_ns) as f64;
+        self.tokens = (self.tokens + elapsed_ns * self.rate_bps as f64 / 8e9).min(self.depth());
+        self.last_ns = now_ns;
+    }
+
+    /// A send may start while any tokens are left; it can overdraw the bucket
+    fn ready(&self) -> bool {
+        self.tokens > 0.0
+    }
+
+    fn wait_ns(&self) -> u64 {
+        if self.ready() {
+            0
+        } else {
+            (-self.tokens * 8e9 / self.rate_bps as f64) as u64 + 1
+        }
+    }
+
+    fn charge(&mut self, bytes: usize) {
+        self.tokens = (self.tokens - bytes as f64).max(-self.depth() * MAX_DEBT_BURSTS);
+    }
+}
+
+/// Reconfigure a bucket live, keeping its fill
+fn update_bucket(bucket: &mut Option<Bucket>, rate_bps: u64, now_ns: u64) {
+    match bucket {
+        Some(b) if rate_bps > 0 => {
+            b.refill(now_ns);
+            b.rate_bps = rate_bps;
+            b.tokens = b.tokens.min(b.depth());
+        }
+        _ => *bucket = Bucket::new(rate_bps, now_ns),
+    }
+}
+
+struct Node {
+    // None: top level
+    parent: Option<i32>,
+    params: ClassParams,
+    rate: Option<Bucket>,
+    ceil: Option<Bucket>,
+    children: Vec<NodeId>,
+    // DRR state while borrowing from the parent
+    deficit: i64,
+    last_borrow_ns: u64,
+    stats: ClassStats,
+}
+
+impl Node {
+    fn new(parent: Option<i32>, params: ClassParams, now_ns: u64) -> Self {
+        Self {
+            parent,
+            params,
+            rate: Bucket::new(params.rate_bps, now_ns),
+            ceil: Bucket::new(params.ceil_bps, now_ns),
+            children: Vec::new(),
+            deficit: 0,
+            last_borrow_ns: 0,
+            stats: ClassStats::default(),
+        }
+    }
+
+    /// Whether this node can lend (or send on its own budget) right now
+    fn can_lend(&self) -> bool {
+        match &self.rate {
+            Some(bucket) => bucket.ready(),
+            // A top-level class without a rate is unlimited
+            None => self.parent.is_none(),
+        }
+    }
+
+    fn quantum(&self) -> i64 {
+        QUANTUM_BYTES * self.params.weight.max(1) as i64
+    }
+}
+
+/// Class tree shared by all handles
+///
+/// Classes form a tree under top-level classes; handles attach to a class as
+/// leaves. A send is allowed when some node on the path to the top has
+/// guaranteed rate left (the handle itself, or the nearest ancestor lending
+/// its spare rate) and neither it nor any node below it is over its ceiling.
+/// Children borrowing from the same parent take turns by deficit round robin,
+/// weighted.
+pub struct ClassTree {
+    nodes: HashMap<NodeId, Node>,
+}
+
+pub fn class_tree() -> &'static Mutex<ClassTree> {
+    static TREE: OnceLock<Mutex<ClassTree>> = OnceLock::new();
+    TREE.get_or_init(|| Mutex::new(ClassTree { nodes: HashMap::new() }))
+}
+
+impl ClassTree {
+    /// Create or reconfigure class `id` under `parent` (None = top level)
+    ///
+    /// Reconfiguring keeps the class's tokens, children and statistics.
+    pub fn set_class(&mut self, id: i32, parent: Option<i32>, params: ClassParams, now_ns: u64) -> bool {
+        if let Some(parent) = parent {
+            if !self.nodes.contains_key(&NodeId::Class(parent)) || self.is_in_subtree(NodeId::Class(parent), id) {
+                return false;
+            }
+        }
+        self.set_node(NodeId::Class(id), parent, params, now_ns);
+        debug!("Class {} set: parent={:?}, rate={}bps, ceil={}bps, weight={}", id, parent, params.rate_bps,
+               params.ceil_bps, params.weight);
+        true
+    }
+
+    /// Attach `handle` to class `class_id`, or reconfigure it
+    pub fn assign_handle(&mut self, handle: i64, class_id: i32, params: ClassParams, now_ns: u64) -> bool {
+        if !self.nodes.contains_key(&NodeId::Class(class_id)) {
+            return false;
+        }
+        self.set_node(NodeId::Handle(handle), Some(class_id), params, now_ns);
+        true
+    }
+
+    /// Remove class `id` with everything below it; its handles are no longer shaped by the tree
+    pub fn remove_class(&mut self, id: i32) -> bool {
+        let removed = self.remove_node(NodeId::Class(id));
+        if removed {
+            debug!("Class {} removed", id);
+        }
+        removed
+    }
+
+    pub fn remove_handle(&mut self, handle: i64) -> bool {
+        self.remove_node(NodeId::Handle(handle))
+    }
+
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+    }
+
+    /// Statistics and child count of class `id`
+    pub fn class_stats(&self, id: i32) -> Option<(ClassParams, ClassStats, usize)> {
+        self.nodes
+            .get(&NodeId::Class(id))
+            .map(|node| (node.params, node.stats, node.children.len()))
+    }
+
+    /// Ask to send `bytes` for `handle`
+    ///
+    /// On success the bytes are charged to the lender and everything above it
+    /// and to every ceiling on the path; otherwise returns how
+    /// long to wait before asking again. Handles outside the tree always pass.
+    pub fn request(&mut self, handle: i64, bytes: usize, now_ns: u64) -> Result<(), u64> {
+        let leaf = NodeId::Handle(handle);
+        if !self.nodes.contains_key(&leaf) {
+            return Ok(());
+        }
+
+        let path = self.path(leaf);
+        for id in &path {
+            let node = self.nodes.get_mut(id).expect("path node");
+            if let Some(b) = &mut node.rate {
+                b.refill(now_ns);
+            }
+            if let Some(b) = &mut node.ceil {
+                b.refill(now_ns);
+            }
+        }
+
+        let lender = match path.iter().position(|id| self.nodes[id].can_lend()) {
+            Some(i) => i,
+            None => {
+                // Everything on the path is out of rate: it all wants to borrow
+                for id in &path {
+                    self.nodes.get_mut(id).expect("path node").last_borrow_ns = now_ns;
+                }
+                // Wait for the first bucket on the path to refill
+                let wait = path
+                    .iter()
+                    .filter_map(|id| self.nodes[id].rate.as_ref().map(Bucket::wait_ns))
+                    .min()
+                    .unwrap_or(DRR_RETRY_NS);
+                return self.refuse(&path, wait.max(1));
+            }
+        };
+
+        // Ceilings hold back the lender and the borrowers below it; a class
+        // within its guaranteed rate never waits for its ancestors
+        let ceil_wait = path[..=lender]
+            .iter()
+            .filter_map(|id| self.nodes[id].ceil.as_ref().map(Bucket::wait_ns))
+            .max()
+            .unwrap_or(0);
+        if ceil_wait > 0 {
+            return self.refuse(&path, ceil_wait);
+        }
+
+        if lender > 0 && !self.take_turn(path[lender], path[lender - 1], bytes, now_ns) {
+            return self.refuse(&path, DRR_RETRY_NS);
+        }
+
+        for (i, id) in path.iter().enumerate() {
+            let node = self.nodes.get_mut(id).expect("path node");
+            if let Some(b) = &mut node.ceil {
+                b.charge(bytes);
+            }
+            node.stats.sent_bytes += bytes as u64;
+            if i < lender {
+                // Borrowed bytes do not eat into the borrower's own rate
+                node.stats.borrowed_bytes += bytes as u64;
+            } else if let Some(b) = &mut node.rate {
+                b.charge(bytes);
+            }
+        }
+        Ok(())
+    }
+
+    fn refuse(&mut self, path: &[NodeId], wait_ns: u64) -> Result<(), u64> {
+        for id in path {
+            self.nodes.get_mut(id).expect("path node").stats.delayed += 1;
+        }
+        Err(wait_ns)
+    }
+
+    /// DRR among the children of `lender`: may `child` borrow `bytes` now?
+    fn take_turn(&mut self, lender: NodeId, child: NodeId, bytes: usize, now_ns: u64) -> bool {
+        if let Some(node) = self.nodes.get_mut(&child) {
+            node.last_borrow_ns = now_ns;
+        }
+
+        if self.nodes[&child].deficit <= 0 {
+            let siblings = self.nodes[&lender].children.clone();
+            let mut any_left = false;
+            for id in &siblings {
+                let node = self.nodes.get_mut(id).expect("child node");
+                if let Some(b) = &mut node.ceil {
+                    b.refill(now_ns);
+                }
+                let over_ceil = node.ceil.as_ref().is_some_and(|b| !b.ready());
+                if now_ns.saturating_sub(node.last_borrow_ns) > ACTIVE_NS || over_ceil {
+                    // Not backlogged, or held by its ceiling: DRR forgets its
+                    // deficit so the round does not wait for it
+                    node.deficit = 0;
+                } else if node.deficit > 0 {
+                    any_left = true;
+                }
+            }
+            // Every backlogged borrower used its turn: start a new round
+            if !any_left {
+                for id in &siblings {
+                    let node = self.nodes.get_mut(id).expect("child node");
+                    if now_ns.saturating_sub(node.last_borrow_ns) <= ACTIVE_NS {
+                        node.deficit += node.quantum();
+                    }
+                }
+            }
+        }
+
+        let node = self.nodes.get_mut(&child).expect("child node");
+        if node.deficit <= 0 {
+            return false;
+        }
+        node.deficit -= bytes as i64;
+        true
+    }
+
+    /// `id` and its ancestors, leaf first
+    fn path(&self, id: NodeId) -> Vec<NodeId> {
+        let mut path = vec![id];
+        let mut current = self.nodes[&id].parent;
+        while let Some(parent) = current {
+            path.push(NodeId::Class(parent));
+            current = self.nodes[&NodeId::Class(parent)].parent;
+        }
+        path
+    }
+
+    /// Whether `id` is class `root` or below it
+    fn is_in_subtree(&self, id: NodeId, root: i32) -> bool {
+        self.nodes.contains_key(&id) && self.path(id).contains(&NodeId::Class(root))
+    }
+
+    fn set_node(&mut self, id: NodeId, parent: Option<i32>, params: ClassParams, now_ns: u64) {
+        let old_parent = match self.nodes.get_mut(&id) {
+            Some(node) => {
+                update_bucket(&mut node.rate, params.rate_bps, now_ns);
+                update_bucket(&mut node.ceil, params.ceil_bps, now_ns);
+                node.params = params;
+                let old = node.parent;
+                node.parent = parent;
+                old
+            }
+            None => {
+                self.nodes.insert(id, Node::new(parent, params, now_ns));
+                None
+            }
+        };
+
+        if old_parent != parent {
+            if let Some(old) = old_parent.and_then(|p| self.nodes.get_mut(&NodeId::Class(p))) {
+                old.children.retain(|c| *c != id);
+            }
+        }
+        if let Some(new) = parent.and_then(|p| self.nodes.get_mut(&NodeId::Class(p))) {
+            if !new.children.contains(&id) {
+                new.children.push(id);
+            }
+        }
+    }
+
+    fn remove_node(&mut self, id: NodeId) -> bool {
+        let node = match self.nodes.remove(&id) {
+            Some(n) => n,
+            None => return false,
+        };
+        if let Some(parent) = node.parent.and_then(|p| self.nodes.get_mut(&NodeId::Class(p))) {
+            parent.children.retain(|c| *c != id);
+        }
+        for child in node.children {
+            self.remove_subtree(child);
+        }
+        true
+    }
+
+    fn remove_subtree(&mut self, id: NodeId) {
+        if let Some(node) = self.nodes.remove(&id) {
+            for child in node.children {
+                self.remove_subtree(child);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: u64 = 1_000_000;
+
+    fn tree() -> ClassTree {
+        ClassTree { nodes: HashMap::new() }
+    }
+
+    fn params(rate_bps: u64, ceil_bps: u64, weight: u32) -> ClassParams {
+        ClassParams { rate_bps, ceil_bps, weight }
+    }
+
+    /// Every handle asks to send 1400 bytes whenever it may, in 100 us steps;
+    /// returns each handle's rate in bits per second
+    fn run(tree: &mut ClassTree, handles: &[i64], from_ms: u64, duration_ms: u64) -> Vec<u64> {
+        let mut sent = vec![0u64; handles.len()];
+        let mut next_ns = vec![0u64; handles.len()];
+        let mut now = from_ms * MS;
+        let mut step = 0;
+        while now < (from_ms + duration_ms) * MS {
+            // Rotate who asks first so no handle wins every race
+            step += 1;
+            for j in 0..handles.len() {
+                let i = (j + step) % handles.len();
+                if next_ns[i] <= now {
+                    match tree.request(handles[i], 1400, now) {
+                        Ok(()) => sent[i] += 1400,
+                        Err(wait_ns) => next_ns[i] = now + wait_ns,
+                    }
+                }
+            }
+            now += MS / 10;
+        }
+        sent.iter().map(|bytes| bytes * 8 * 1000 / duration_ms).collect()
+    }
+
+    fn assert_near(actual: u64, expected: u64) {
+        let error = (actual as f64 - expected as f64).abs() / expected as f64;
+        assert!(error < 0.05, "{} is not within 5% of {}", actual, expected);
+    }
+
+    /// Class 1 (10 Mbit/s) with children 2 and 3, one handle each (10 and 11)
+    fn two_children(rate_2: u64, rate_3: u64, weight_3: u32) -> ClassTree {
+        let mut tree = tree();
+        assert!(tree.set_class(1, None, params(10_000_000, 10_000_000, 1), 0));
+        assert!(tree.set_class(2, Some(1), params(rate_2, 10_000_000, 1), 0));
+        assert!(tree.set_class(3, Some(1), params(rate_3, 10_000_000, weight_3), 0));
+        assert!(tree.assign_handle(10, 2, params(0, 0, 1), 0));
+        assert!(tree.assign_handle(11, 3, params(0, 0, 1), 0));
+        tree
+    }
+
+    #[test]
+    fn spare_rate_is_shared_by_weight() {
+        let mut tree = two_children(2_000_000, 2_000_000, 3);
+        // 2 + 1.5 and 2 + 4.5 Mbit/s
+        let rates = run(&mut tree, &[10, 11], 0, 5_000);
+        assert_near(rates[0], 3_500_000);
+        assert_near(rates[1], 6_500_000);
+
+        let (_, stats_2, _) = tree.class_stats(2).unwrap();
+        let (_, stats_3, _) = tree.class_stats(3).unwrap();
+        assert_near(stats_2.borrowed_bytes * 3, stats_3.borrowed_bytes);
+        let (_, stats_1, children) = tree.class_stats(1).unwrap();
+        assert_eq!(children, 2);
+        assert_eq!(stats_1.borrowed_bytes, 0);
+        assert_eq!(stats_1.sent_bytes, stats_2.sent_bytes + stats_3.sent_bytes);
+    }
+
+    #[test]
+    fn guaranteed_rate_is_kept() {
+        let mut tree = two_children(6_000_000, 2_000_000, 1);
+        // 2 Mbit/s spare, split evenly
+        let rates = run(&mut tree, &[10, 11], 0, 5_000);
+        assert_near(rates[0], 7_000_000);
+        assert_near(rates[1], 3_000_000);
+    }
+
+    #[test]
+    fn lone_borrower_gets_the_parent_rate() {
+        let mut tree = two_children(2_000_000, 2_000_000, 1);
+        let rates = run(&mut tree, &[10], 0, 5_000);
+        assert_near(rates[0], 10_000_000);
+    }
+
+    #[test]
+    fn ceiling_limits_borrowing() {
+        let mut tree = two_children(2_000_000, 2_000_000, 1);
+        assert!(tree.set_class(2, Some(1), params(2_000_000, 3_000_000, 1), 0));
+        let rates = run(&mut tree, &[10, 11], 0, 5_000);
+        assert_near(rates[0], 3_000_000);
+        assert_near(rates[1], 7_000_000);
+    }
+
+    #[test]
+    fn rates_change_live() {
+        let mut tree = two_children(2_000_000, 2_000_000, 1);
+        let rates = run(&mut tree, &[10, 11], 0, 5_000);
+        assert_near(rates[0], 5_000_000);
+        assert!(tree.set_class(1, None, params(4_000_000, 4_000_000, 1), 5_000 * MS));
+        let rates = run(&mut tree, &[10, 11], 5_000, 5_000);
+        assert_near(rates[0], 2_000_000);
+        assert_near(rates[1], 2_000_000);
+    }
+
+    #[test]
+    fn tree_structure() {
+        let mut tree = two_children(1_000_000, 1_000_000, 1);
+        // Unknown parent, and a class under its own descendant
+        assert!(!tree.set_class(4, Some(9), params(1, 0, 1), 0));
+        assert!(!tree.set_class(1, Some(3), params(1, 0, 1), 0));
+        assert!(!tree.assign_handle(12, 9, params(0, 0, 1), 0));
+
+        // Handles outside the tree are never held back
+        assert_eq!(tree.request(99, 1_000_000, 0), Ok(()));
+
+        // Removing a class takes its subtree along
+        assert!(tree.remove_class(1));
+        assert!(tree.class_stats(2).is_none());
+        assert_eq!(tree.request(10, 1_000_000, 0), Ok(()));
+        assert!(!tree.remove_handle(10));
+    }
+
+    #[test]
+    fn refused_request_reports_the_wait() {
+        let mut tree = tree();
+        assert!(tree.set_class(1, None, params(8_000_000, 8_000_000, 1), 0));
+        assert!(tree.assign_handle(10, 1, params(0, 0, 1), 0));
+        // 10 ms of tokens at 1 MB/s, then a 10 KB overdraft
+        assert_eq!(tree.request(10, 20_000, 0), Ok(()));
+        let wait_ns = tree.request(10, 1400, 0).unwrap_err();
+        assert!((9 * MS..=11 * MS).contains(&wait_ns), "wait {}", wait_ns);
+        assert_eq!(tree.request(10, 1400, wait_ns), Ok(()));
+        assert_eq!(tree.class_stats(1).unwrap().1.delayed, 1);
+    }
+}
@@ -5,18 +5,30 @@
 
 mod queue;
 mod pacing;
+mod pump;
+mod metrics;
+mod bbr;
+mod htb;
+mod aqm;
+#[cfg(test)]
+mod sim;
 
 use jni::JNIEnv;
 use jni::objects::{JClass, JObject};
-use jni::sys::{jboolean, jint, jlong};
+use jni::sys::{jboolean, jfloat, jint, jlong, jlongArray};
 use std::sync::Arc;
 use parking_lot::Mutex;
 use log::{debug, error, info};
 use std::collections::HashMap;
 use std::sync::OnceLock;
+use std::thread::JoinHandle;
 
 use queue::PepperRingBuffer;
 use pacing::{PepperPacingState, PepperPacingParams};
+use metrics::PathSample;
+use pump::PumpStats;
+use htb::{class_tree, ClassParams};
+use aqm::QueueDiscipline;
 
 /// Shaper handle with ring buffers and pacing
 #[allow(dead_code)]
@@ -29,6 +41,20 @@ struct PepperShaperHandle {
     rx_queue: Arc<PepperRingBuffer>,
     pacing_state: Arc<Mutex<PepperPacingState>>,
     pacing_params: Arc<Mutex<PepperPacingParams>>,
+    stats: PumpStats,
+    pump: Mutex<Option<JoinHandle<()>>>,
+    // Last app-reported sample, taken by the pump for adaptive pacing
+    reported_sample: Mutex<Option<PathSample>>,
+}
+
+impl PepperShaperHandle {
+    /// Stop the pump and wait for it to exit
+    fn stop(&self) {
+        self.active.store(false, std::sync::atomic::Ordering::Release);
+        if let Some(pump) = self.pump.lock().take() {
+            let _ = pump.join();
+        }
+    }
 }
 
 // Handle storage
@@ -67,6 +93,13 @@ fn extract_params(env: &mut JNIEnv, params: &JObject) -> Option<PepperPacingPara
     let target_rate_bps = env.get_field(params, "targetRateBps", "J").ok()?.j().ok()?;
     let loss_aware_backoff = env.get_field(params, "lossAwareBackoff", "Z").ok()?.z().ok()?;
     let enable_pacing = env.get_field(params, "enablePacing", "Z").ok()?.z().ok()?;
+    let discipline = env
+        .get_field(params, "queueDiscipline", "Lcom/simplexray/an/chain/pepper/QueueDiscipline;")
+        .ok()?
+        .l()
+        .ok()?;
+    let discipline_ordinal = env.call_method(&discipline, "ordinal", "()I", &[]).ok()?.i().ok()?;
+    let queue_discipline = QueueDiscipline::from_ordinal(discipline_ordinal)?;
     
     Some(PepperPacingParams {
         target_rate_bps: target_rate_bps as u64,
@@ -74,10 +107,30 @@ fn extract_params(env: &mut JNIEnv, params: &JObject) -> Option<PepperPacingPara
         loss_aware_backoff,
         enable_pacing,
         min_pacing_interval_ns: 1000, // 1 microsecond default
+        queue_discipline,
+    })
+}
+
+/// Extract class rates from Java object
+fn extract_class_params(env: &mut JNIEnv, params: &JObject) -> Option<ClassParams> {
+    let rate_bps = env.get_field(params, "rateBps", "J").ok()?.j().ok()?;
+    let ceil_bps = env.get_field(params, "ceilBps", "J").ok()?.j().ok()?;
+    let weight = env.get_field(params, "weight", "I").ok()?.i().ok()?;
+
+    if rate_bps < 0 || ceil_bps < 0 || weight < 1 || (ceil_bps > 0 && ceil_bps < rate_bps) {
+        return None;
+    }
+    Some(ClassParams {
+        rate_bps: rate_bps as u64,
+        ceil_bps: ceil_bps as u64,
+        weight: weight as u32,
     })
 }
 
 /// Attach shaper to a socket/file descriptor pair
+///
+/// `mode` carries the SocketMode ordinal in the low byte and the PepperMode
+/// ordinal in the next; ADAPTIVE paces at the estimated bottleneck bandwidth.
 #[no_mangle]
 pub extern "system" fn Java_com_simplexray_an_chain_pepper_PepperShaper_nativeAttach(
     mut env: JNIEnv,
@@ -97,6 +150,13 @@ pub extern "system" fn Java_com_simplexray_an_chain_pepper_PepperShaper_nativeAt
         return 0;
     }
 
+    // The pump forwards read_fd to write_fd; on one fd (e.g. a TUN device) it
+    // would loop outgoing packets straight back in
+    if read_fd == write_fd {
+        error!("readFd and writeFd must differ: {}", read_fd);
+        return 0;
+    }
+
     debug!("Attaching shaper: readFd={}, writeFd={}, mode={}", read_fd, write_fd, mode);
 
     let pacing_params = match extract_params(&mut env, &params) {
@@ -126,8 +186,19 @@ pub extern "system" fn Java_com_simplexray_an_chain_pepper_PepperShaper_nativeAt
         rx_queue,
         pacing_state,
         pacing_params: Arc::new(Mutex::new(pacing_params)),
+        stats: PumpStats::default(),
+        pump: Mutex::new(None),
+        reported_sample: Mutex::new(None),
     });
 
+    match pump::start(handle_id, handle.clone()) {
+        Ok(pump) => *handle.pump.lock() = Some(pump),
+        Err(e) => {
+            error!("Failed to start pump: {}", e);
+            return 0;
+        }
+    }
+
     let mut handles = get_handles().lock();
     handles.insert(handle_id, handle);
 
@@ -148,9 +219,10 @@ pub extern "system" fn Java_com_simplexray_an_chain_pepper_PepperShaper_nativeDe
 
     debug!("Detaching shaper: handle={}", handle);
 
-    let mut handles = get_handles().lock();
-    if let Some(h) = handles.remove(&handle) {
-        h.active.store(false, std::sync::atomic::Ordering::Release);
+    let removed = get_handles().lock().remove(&handle);
+    if let Some(h) = removed {
+        h.stop();
+        class_tree().lock().remove_handle(handle);
         debug!("Shaper detached: handle={}", handle);
         jboolean::from(true)
     } else {
@@ -193,6 +265,229 @@ pub extern "system" fn Java_com_simplexray_an_chain_pepper_PepperShaper_nativeUp
     }
 }
 
+/// Report loss, RTT and delivery rate measured by the app (e.g. for UDP/QUIC flows)
+///
+/// On TCP handles the next TCP_INFO sample replaces these values.
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_chain_pepper_PepperShaper_nativeReportMetrics(
+    _env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+    loss_rate: jfloat,
+    rtt_us: jlong,
+    delivery_rate_bps: jlong,
+) -> jboolean {
+    if handle <= 0 || !(0.0..=1.0).contains(&loss_rate) || rtt_us < 0 || delivery_rate_bps < 0 {
+        return jboolean::from(false);
+    }
+
+    let handles = get_handles().lock();
+    if let Some(h) = handles.get(&handle) {
+        let sample = PathSample {
+            loss_rate,
+            rtt_ns: rtt_us as u64 * 1000,
+            delivery_rate_bps: delivery_rate_bps as u64,
+        };
+        {
+            let mut state = h.pacing_state.lock();
+            pacing::update_metrics(&mut state, sample.loss_rate, sample.rtt_ns);
+            if sample.delivery_rate_bps > 0 {
+                pacing::update_delivery_rate(&mut state, sample.delivery_rate_bps);
+            }
+        }
+        *h.reported_sample.lock() = Some(sample);
+        jboolean::from(true)
+    } else {
+        error!("Handle not found: {}", handle);
+        jboolean::from(false)
+    }
+}
+
+/// Get pump statistics for a handle
+/// Returns [queuedBytes, sentBytes, sentChunks, delayed, dropped, queueDepth, rttUs, lossPpm, deliveryRateBps,
+/// pacingRateBps, aqmDropped, ecnMarked]
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_chain_pepper_PepperShaper_nativeGetHandleStats(
+    env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+) -> jlongArray {
+    let h = match get_handles().lock().get(&handle) {
+        Some(h) => h.clone(),
+        None => return std::ptr::null_mut(),
+    };
+
+    let load = |counter: &std::sync::atomic::AtomicU64| counter.load(std::sync::atomic::Ordering::Relaxed) as jlong;
+    let (rtt_ns, loss_rate, delivery_rate_bps) = {
+        let state = h.pacing_state.lock();
+        (state.rtt_ns(), state.loss_rate(), state.delivery_rate_bps())
+    };
+    let values = [
+        load(&h.stats.queued_bytes),
+        load(&h.stats.sent_bytes),
+        load(&h.stats.sent_chunks),
+        load(&h.stats.delayed),
+        load(&h.stats.dropped),
+        load(&h.stats.queue_depth),
+        (rtt_ns / 1000) as jlong,
+        (loss_rate * 1_000_000.0) as jlong,
+        delivery_rate_bps as jlong,
+        load(&h.stats.pacing_rate_bps),
+        load(&h.stats.aqm_dropped),
+        load(&h.stats.ecn_marked),
+    ];
+
+    match env.new_long_array(values.len() as i32) {
+        Ok(result) => {
+            if env.set_long_array_region(&result, 0, &values).is_err() {
+                return std::ptr::null_mut();
+            }
+            result.into_raw()
+        }
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// Get the queue delay histogram for a handle
+/// Returns chunk counts per bucket: up to 1, 2, 5, 10, 20, 50, 100, 200, 500, 1000 ms, then above
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_chain_pepper_PepperShaper_nativeGetQueueDelayHistogram(
+    env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+) -> jlongArray {
+    let h = match get_handles().lock().get(&handle) {
+        Some(h) => h.clone(),
+        None => return std::ptr::null_mut(),
+    };
+
+    let values = h.stats.delay_histogram.snapshot().map(|count| count as jlong);
+
+    match env.new_long_array(values.len() as i32) {
+        Ok(result) => {
+            if env.set_long_array_region(&result, 0, &values).is_err() {
+                return std::ptr::null_mut();
+            }
+            result.into_raw()
+        }
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// Create or update a class in the bandwidth class tree (parentId 0 = top level)
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_chain_pepper_PepperShaper_nativeSetClass(
+    mut env: JNIEnv,
+    _class: JClass,
+    class_id: jint,
+    parent_id: jint,
+    params: JObject,
+) -> jboolean {
+    if class_id <= 0 || parent_id < 0 || class_id == parent_id {
+        return jboolean::from(false);
+    }
+
+    let class_params = match extract_class_params(&mut env, &params) {
+        Some(p) => p,
+        None => {
+            error!("Invalid class parameters: class={}", class_id);
+            return jboolean::from(false);
+        }
+    };
+
+    let parent = (parent_id > 0).then_some(parent_id);
+    let ok = class_tree().lock().set_class(class_id, parent, class_params, pacing::get_time_ns());
+    if !ok {
+        error!("Cannot place class {} under {}", class_id, parent_id);
+    }
+    jboolean::from(ok)
+}
+
+/// Remove a class and everything below it
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_chain_pepper_PepperShaper_nativeRemoveClass(
+    _env: JNIEnv,
+    _class: JClass,
+    class_id: jint,
+) -> jboolean {
+    jboolean::from(class_tree().lock().remove_class(class_id))
+}
+
+/// Put a handle under a class, or update its rates there
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_chain_pepper_PepperShaper_nativeAssignClass(
+    mut env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+    class_id: jint,
+    params: JObject,
+) -> jboolean {
+    let class_params = match extract_class_params(&mut env, &params) {
+        Some(p) => p,
+        None => {
+            error!("Invalid class parameters: handle={}", handle);
+            return jboolean::from(false);
+        }
+    };
+
+    // Held across the assignment so a concurrent detach cannot leave a stale leaf
+    let handles = get_handles().lock();
+    if handle <= 0 || !handles.contains_key(&handle) {
+        error!("Handle not found: {}", handle);
+        return jboolean::from(false);
+    }
+
+    let ok = class_tree().lock().assign_handle(handle, class_id, class_params, pacing::get_time_ns());
+    drop(handles);
+    if !ok {
+        error!("Class not found: {}", class_id);
+    }
+    jboolean::from(ok)
+}
+
+/// Take a handle out of the class tree
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_chain_pepper_PepperShaper_nativeUnassignClass(
+    _env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+) -> jboolean {
+    jboolean::from(class_tree().lock().remove_handle(handle))
+}
+
+/// Get class statistics
+/// Returns [rateBps, ceilBps, sentBytes, borrowedBytes, delayed, children]
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_chain_pepper_PepperShaper_nativeGetClassStats(
+    env: JNIEnv,
+    _class: JClass,
+    class_id: jint,
+) -> jlongArray {
+    let (params, stats, children) = match class_tree().lock().class_stats(class_id) {
+        Some(s) => s,
+        None => return std::ptr::null_mut(),
+    };
+
+    let values = [
+        params.rate_bps as jlong,
+        params.ceil_bps as jlong,
+        stats.sent_bytes as jlong,
+        stats.borrowed_bytes as jlong,
+        stats.delayed as jlong,
+        children as jlong,
+    ];
+
+    match env.new_long_array(values.len() as i32) {
+        Ok(result) => {
+            if env.set_long_array_region(&result, 0, &values).is_err() {
+                return std::ptr::null_mut();
+            }
+            result.into_raw()
+        }
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
 /// Shutdown PepperShaper
 #[no_mangle]
 pub extern "system" fn Java_com_simplexray_an_chain_pepper_PepperShaper_nativeShutdown(
@@ -205,11 +500,11 @@ pub extern "system" fn Java_com_simplexray_an_chain_pepper_PepperShaper_nativeSh
 
     info!("Shutting down PepperShaper");
 
-    let mut handles = get_handles().lock();
-    for (_, handle) in handles.iter() {
-        handle.active.store(false, std::sync::atomic::Ordering::Release);
+    let handles: Vec<_> = get_handles().lock().drain().map(|(_, h)| h).collect();
+    for handle in handles {
+        handle.stop();
     }
-    handles.clear();
+    class_tree().lock().clear();
     NEXT_HANDLE_ID.store(1, std::sync::atomic::Ordering::Release);
 
     info!("PepperShaper shutdown complete");
@@ -221,9 +516,12 @@ pub extern "C" fn JNI_OnUnload(_vm: jni::JavaVM, _reserved: *mut std::ffi::c_voi
     info!("PepperShaper JNI unloading - cleaning up handles");
 
     if let Some(handles) = HANDLES.get() {
-        let mut handles = handles.lock();
-        handles.clear();
+        let handles: Vec<_> = handles.lock().drain().map(|(_, h)| h).collect();
+        for handle in handles {
+            handle.stop();
+        }
     }
+    class_tree().lock().clear();
     NEXT_HANDLE_ID.store(1, std::sync::atomic::Ordering::Release);
     INITIALIZED.store(false, std::sync::atomic::Ordering::Release);
 
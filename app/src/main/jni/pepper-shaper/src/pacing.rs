@@ -3,20 +3,18 @@
  * Token bucket with loss-aware backoff
  */
 
-use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::aqm::QueueDiscipline;
 
 /// Pacing parameters
 #[derive(Clone)]
 pub struct PepperPacingParams {
-    #[allow(dead_code)]
     pub target_rate_bps: u64,      // Target rate in bits per second (0 = unlimited)
     pub max_burst_bytes: u64,      // Maximum burst size in bytes
-    #[allow(dead_code)]
     pub loss_aware_backoff: bool,   // Enable loss-aware backoff
-    #[allow(dead_code)]
     pub enable_pacing: bool,        // Enable pacing
-    #[allow(dead_code)]
     pub min_pacing_interval_ns: u64, // Minimum interval between packets (nanoseconds)
+    pub queue_discipline: QueueDiscipline, // Queue management for the transmit queue
 }
 
 /// Pacing state
@@ -27,11 +25,24 @@ pub struct PepperPacingState {
     last_update_ns: u64,        // Last token bucket update
     loss_rate: f32,             // Current loss rate estimate
     rtt_ns: u64,                // Current RTT estimate
+    delivery_rate_bps: u64,     // Last measured delivery rate (0 = unknown)
     in_backoff: bool,           // Currently in backoff mode
     backoff_until_ns: u64,      // Backoff end time
 }
 
 impl PepperPacingState {
+    pub fn loss_rate(&self) -> f32 {
+        self.loss_rate
+    }
+
+    pub fn rtt_ns(&self) -> u64 {
+        self.rtt_ns
+    }
+
+    pub fn delivery_rate_bps(&self) -> u64 {
+        self.delivery_rate_bps
+    }
+
     pub fn new(params: &PepperPacingParams) -> Self {
         let now_ns = get_time_ns();
         Self {
@@ -40,6 +51,7 @@ impl PepperPacingState {
             last_update_ns: now_ns,
             loss_rate: 0.0,
             rtt_ns: 0,
+            delivery_rate_bps: 0,
             in_backoff: false,
             backoff_until_ns: 0,
         }
@@ -47,7 +59,6 @@ impl PepperPacingState {
 }
 
 /// Check if packet can be sent now (pacing gate)
-#[allow(dead_code)]
 pub fn can_send(
     state: &mut PepperPacingState,
     params: &PepperPacingParams,
@@ -73,7 +84,7 @@ pub fn can_send(
     if params.target_rate_bps > 0 {
         // Refill tokens based on elapsed time
         let elapsed_ns = current_time_ns.saturating_sub(state.last_update_ns);
-        let tokens_to_add = (elapsed_ns * params.target_rate_bps) / (8 * 1_000_000_000);
+        let tokens_to_add = ((elapsed_ns as u128 * params.target_rate_bps as u128) / (8 * 1_000_000_000)) as u64;
         
         state.tokens = state.tokens
             .saturating_add(tokens_to_add)
@@ -90,7 +101,6 @@ pub fn can_send(
 }
 
 /// Update pacing state after sending
-#[allow(dead_code)]
 pub fn update_after_send(
     state: &mut PepperPacingState,
     params: &PepperPacingParams,
@@ -127,18 +137,50 @@ pub fn update_after_send(
     }
 }
 
+/// Nanoseconds until a packet of `packet_size` could pass the pacing gate (0 = now)
+pub fn time_until_send(
+    state: &PepperPacingState,
+    params: &PepperPacingParams,
+    packet_size: usize,
+    current_time_ns: u64,
+) -> u64 {
+    if !params.enable_pacing {
+        return 0;
+    }
+
+    let mut wait_ns = state.next_send_time_ns.saturating_sub(current_time_ns);
+    if state.in_backoff {
+        wait_ns = wait_ns.max(state.backoff_until_ns.saturating_sub(current_time_ns));
+    }
+
+    // Time for the token bucket to refill the shortfall
+    if params.target_rate_bps > 0 {
+        let missing = (packet_size as u64).saturating_sub(state.tokens);
+        let refill_ns = ((missing as u128 * 8 * 1_000_000_000) / params.target_rate_bps as u128) as u64;
+        let ready_ns = state.last_update_ns.saturating_add(refill_ns);
+        wait_ns = wait_ns.max(ready_ns.saturating_sub(current_time_ns));
+    }
+
+    wait_ns
+}
+
 /// Update loss and RTT estimates
-#[allow(dead_code)]
 pub fn update_metrics(state: &mut PepperPacingState, loss_rate: f32, rtt_ns: u64) {
     state.loss_rate = loss_rate;
     state.rtt_ns = rtt_ns;
 }
 
-/// Get high-resolution timestamp (nanoseconds)
+/// Update the measured delivery rate
+pub fn update_delivery_rate(state: &mut PepperPacingState, delivery_rate_bps: u64) {
+    state.delivery_rate_bps = delivery_rate_bps;
+}
+
+/// Get high-resolution monotonic timestamp (nanoseconds)
+///
+/// Wall-clock jumps (NTP, manual changes) would stall or burst the pacer.
 pub fn get_time_ns() -> u64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_nanos() as u64
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
 }
 
@@ -0,0 +1,514 @@
+/*
+ * Shaping pump for PepperShaper
+ * Moves bytes from read_fd to write_fd through the ring buffer, paced
+ */
+
+use log::{debug, error, warn};
+use nix::errno::Errno;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use crate::aqm::{AqmQueue, DelayHistogram, QueueKind};
+use crate::bbr::BbrEstimator;
+use crate::htb::class_tree;
+use crate::metrics::TcpInfoSampler;
+use crate::pacing::{
+    can_send, get_time_ns, time_until_send, update_after_send, update_delivery_rate, update_metrics, PepperPacingParams,
+};
+use crate::PepperShaperHandle;
+
+// Longest sleep before re-checking the active flag
+const TICK_NS: u64 = 50_000_000;
+// Largest stream chunk released at once (further capped by max_burst_bytes)
+const MAX_CHUNK: usize = 16 * 1024;
+// How often TCP_INFO is sampled for loss and RTT
+const SAMPLE_INTERVAL_NS: u64 = 200_000_000;
+// Adaptive pacing needs delivery samples more often
+const ADAPTIVE_SAMPLE_INTERVAL_NS: u64 = 50_000_000;
+// PepperMode ordinal from Kotlin
+const PEPPER_MODE_ADAPTIVE: i32 = 2;
+
+/// Per-handle pump counters
+#[derive(Default)]
+pub struct PumpStats {
+    pub queued_bytes: AtomicU64,
+    pub sent_bytes: AtomicU64,
+    pub sent_chunks: AtomicU64,
+    pub delayed: AtomicU64,     // Sends held back by the pacer
+    pub dropped: AtomicU64,     // Datagrams dropped (queue full or send error)
+    pub pacing_rate_bps: AtomicU64, // Rate the last chunk was paced at
+    pub aqm_dropped: AtomicU64, // Chunks dropped by CoDel
+    pub ecn_marked: AtomicU64,  // Chunks CoDel marked instead (ECN CE, or stream back-pressure)
+    pub queue_depth: AtomicU64, // Payload bytes queued
+    pub delay_histogram: DelayHistogram,
+}
+
+// nativeAttach `mode`: SocketMode ordinal in the low byte, PepperMode ordinal in the next
+
+/// SocketMode ordinals from Kotlin: TCP = 0, UDP = 1, TUN = 2
+fn is_datagram(mode: i32) -> bool {
+    mode & 0xff != 0
+}
+
+fn queue_kind(mode: i32) -> QueueKind {
+    match mode & 0xff {
+        0 => QueueKind::Stream,
+        1 => QueueKind::Datagram,
+        _ => QueueKind::Tun,
+    }
+}
+
+fn is_adaptive(mode: i32) -> bool {
+    (mode >> 8) & 0xff == PEPPER_MODE_ADAPTIVE
+}
+
+/// Start the pump thread for `handle`
+///
+/// Both fds are switched to non-blocking while the pump runs and restored
+/// when it stops (detach, shutdown or end of stream).
+pub fn start(handle_id: i64, handle: Arc<PepperShaperHandle>) -> io::Result<JoinHandle<()>> {
+    let read_flags = set_nonblocking(handle.read_fd)?;
+    let write_flags = match set_nonblocking(handle.write_fd) {
+        Ok(flags) => flags,
+        Err(e) => {
+            restore_flags(handle.read_fd, read_flags);
+            return Err(e);
+        }
+    };
+
+    let (read_fd, write_fd) = (handle.read_fd, handle.write_fd);
+    let result = std::thread::Builder::new()
+        .name(format!("pepper-pump-{}", handle_id))
+        .spawn(move || {
+            let mut pump = Pump::new(handle_id, &handle);
+            pump.run();
+            restore_flags(handle.write_fd, write_flags);
+            restore_flags(handle.read_fd, read_flags);
+            debug!("Pump stopped: handle={}", handle_id);
+        });
+    if result.is_err() {
+        restore_flags(write_fd, write_flags);
+        restore_flags(read_fd, read_flags);
+    }
+    result
+}
+
+fn set_nonblocking(fd: i32) -> io::Result<i32> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(flags)
+}
+
+fn restore_flags(fd: i32, flags: i32) {
+    unsafe { libc::fcntl(fd, libc::F_SETFL, flags) };
+}
+
+struct Pump<'a> {
+    handle_id: i64,
+    handle: &'a PepperShaperHandle,
+    datagram: bool,
+    queue: AqmQueue,
+    input: Vec<u8>,
+    // Chunk being released and how much of it is written
+    output: Vec<u8>,
+    output_pos: usize,
+    // Current chunk passed the pacer / was counted as delayed
+    paced: bool,
+    delay_counted: bool,
+    eof: bool,
+    // TCP_INFO source for loss/RTT feedback (TCP mode only)
+    sampler: Option<TcpInfoSampler>,
+    next_sample_ns: u64,
+    // Adaptive mode: rate and burst come from the estimator
+    bbr: Option<BbrEstimator>,
+    // Data waited on the pacer or the socket since the last sample
+    backlogged: bool,
+}
+
+impl<'a> Pump<'a> {
+    fn new(handle_id: i64, handle: &'a PepperShaperHandle) -> Self {
+        let datagram = is_datagram(handle.mode);
+        // Prefer the outgoing socket; the other side may be a pipe or local socket
+        let sampler = if datagram {
+            None
+        } else {
+            TcpInfoSampler::new(handle.write_fd).or_else(|| TcpInfoSampler::new(handle.read_fd))
+        };
+
+        let discipline = handle.pacing_params.lock().queue_discipline;
+        Self {
+            handle_id,
+            handle,
+            datagram,
+            queue: AqmQueue::new(queue_kind(handle.mode), discipline, handle.tx_queue.clone()),
+            input: vec![0u8; 64 * 1024],
+            output: Vec::with_capacity(MAX_CHUNK),
+            output_pos: 0,
+            paced: false,
+            delay_counted: false,
+            eof: false,
+            sampler,
+            next_sample_ns: 0,
+            bbr: is_adaptive(handle.mode)
+                .then(|| BbrEstimator::new(get_time_ns(), handle.pacing_params.lock().target_rate_bps)),
+            backlogged: false,
+        }
+    }
+
+    /// Pacing parameters in effect; in adaptive mode `target_rate_bps` is only a ceiling
+    fn effective_params(&mut self) -> PepperPacingParams {
+        let mut params = self.handle.pacing_params.lock().clone();
+        if let Some(bbr) = &mut self.bbr {
+            bbr.set_ceiling(params.target_rate_bps);
+            params.target_rate_bps = bbr.pacing_rate_bps();
+            params.max_burst_bytes = bbr.burst_bytes();
+        }
+        params
+    }
+
+    fn run(&mut self) {
+        while self.handle.active.load(Ordering::Acquire) {
+            if let Some(bbr) = &mut self.bbr {
+                bbr.advance(get_time_ns());
+            }
+            self.queue.set_discipline(self.handle.pacing_params.lock().queue_discipline);
+
+            let want_read = match self.fill() {
+                Ok(w) => w,
+                Err(e) => {
+                    error!("Pump read from fd {} failed: {}", self.handle.read_fd, e);
+                    return;
+                }
+            };
+            let (want_write, wait_ns) = match self.drain() {
+                Ok(r) => r,
+                Err(e) => {
+                    error!("Pump write to fd {} failed: {}", self.handle.write_fd, e);
+                    return;
+                }
+            };
+
+            self.handle.stats.queue_depth.store(self.queue.backlog() as u64, Ordering::Relaxed);
+
+            if self.eof && self.output_pos == self.output.len() && self.queue.is_empty() {
+                debug!("Pump input ended, fd {} drained", self.handle.read_fd);
+                if !self.datagram {
+                    // Pass the end of stream on
+                    unsafe { libc::shutdown(self.handle.write_fd, libc::SHUT_WR) };
+                }
+                return;
+            }
+
+            // A stream stopped reading for a full queue picks up again as
+            // soon as the drain made room
+            if !want_read && !want_write && self.stream_can_read() {
+                continue;
+            }
+
+            let sample_in_ns = self.sample_path();
+            self.wait(want_read, want_write, wait_ns.unwrap_or(TICK_NS).min(TICK_NS).min(sample_in_ns));
+        }
+    }
+
+    /// Feed path samples into the pacer when one is due; returns ns until the next one
+    ///
+    /// Samples come from TCP_INFO, or for other sockets from the app's reports.
+    fn sample_path(&mut self) -> u64 {
+        if self.sampler.is_none() && self.bbr.is_none() {
+            return u64::MAX;
+        }
+
+        let now = get_time_ns();
+        if now < self.next_sample_ns {
+            return self.next_sample_ns - now;
+        }
+        let interval_ns = if self.bbr.is_some() { ADAPTIVE_SAMPLE_INTERVAL_NS } else { SAMPLE_INTERVAL_NS };
+        self.next_sample_ns = now + interval_ns;
+
+        let sample = match &mut self.sampler {
+            Some(sampler) => match sampler.sample() {
+                Some(sample) => {
+                    let mut state = self.handle.pacing_state.lock();
+                    update_metrics(&mut state, sample.loss_rate, sample.rtt_ns);
+                    if sample.delivery_rate_bps > 0 {
+                        update_delivery_rate(&mut state, sample.delivery_rate_bps);
+                    }
+                    Some(sample)
+                }
+                None => {
+                    warn!("TCP_INFO sampling failed, disabling loss/RTT feedback");
+                    self.sampler = None;
+                    None
+                }
+            },
+            // Already applied to the pacing state when reported
+            None => self.handle.reported_sample.lock().take(),
+        };
+
+        if let (Some(bbr), Some(sample)) = (&mut self.bbr, sample) {
+            // Nothing waited to be sent: the app, not the path, set the delivery rate
+            bbr.on_sample(now, sample.delivery_rate_bps, sample.rtt_ns, !self.backlogged);
+            self.backlogged = false;
+        }
+        interval_ns
+    }
+
+    /// Read from read_fd into the queue; returns whether to wait for POLLIN
+    fn fill(&mut self) -> io::Result<bool> {
+        // Stream reads become queue chunks of at most one burst
+        let chunk_limit = if self.datagram {
+            self.input.len()
+        } else {
+            (self.effective_params().max_burst_bytes.max(1) as usize).min(MAX_CHUNK)
+        };
+
+        while !self.eof {
+            // Streams stop reading when the queue (or its CoDel window) is full: back-pressure
+            if !self.datagram && !self.stream_can_read() {
+                return Ok(false);
+            }
+
+            let limit = if self.datagram { self.input.len() } else { self.queue.space().min(chunk_limit) };
+            let n = match Errno::result(unsafe {
+                libc::read(self.handle.read_fd, self.input.as_mut_ptr().cast(), limit)
+            }) {
+                Ok(n) => n as usize,
+                Err(Errno::EAGAIN) => return Ok(true),
+                Err(Errno::EINTR) => continue,
+                Err(e) => return Err(e.into()),
+            };
+
+            if !self.datagram && n == 0 {
+                self.eof = true;
+                break;
+            }
+            if !self.queue.enqueue(&self.input[..n], get_time_ns()) {
+                // Datagrams are queued whole or dropped (tail drop); streams always fit
+                self.handle.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+            self.handle.stats.queued_bytes.fetch_add(n as u64, Ordering::Relaxed);
+        }
+        Ok(false)
+    }
+
+    fn stream_can_read(&self) -> bool {
+        !self.datagram && !self.eof && self.queue.space() > 0
+    }
+
+    /// Release queued data to write_fd as the pacer allows
+    ///
+    /// Returns whether to wait for POLLOUT and how long until the pacer opens.
+    fn drain(&mut self) -> io::Result<(bool, Option<u64>)> {
+        loop {
+            if self.output_pos == self.output.len() && !self.next_chunk() {
+                return Ok((false, None));
+            }
+
+            if !self.paced {
+                let params = self.effective_params();
+                self.handle.stats.pacing_rate_bps.store(params.target_rate_bps, Ordering::Relaxed);
+                let mut state = self.handle.pacing_state.lock();
+                // A chunk larger than the bucket could never pass
+                let size = self.output.len().min(params.max_burst_bytes.max(1) as usize);
+                let now = get_time_ns();
+                if !can_send(&mut state, &params, size, now) {
+                    let wait_ns = time_until_send(&state, &params, size, now).max(params.min_pacing_interval_ns);
+                    return Ok((false, Some(self.hold(wait_ns))));
+                }
+                // Then the share of the class budget, if the handle is in the class tree
+                if let Err(wait_ns) = class_tree().lock().request(self.handle_id, self.output.len(), now) {
+                    return Ok((false, Some(self.hold(wait_ns))));
+                }
+                update_after_send(&mut state, &params, size, now);
+                self.paced = true;
+            }
+
+            let pending = &self.output[self.output_pos..];
+            match Errno::result(unsafe {
+                libc::write(self.handle.write_fd, pending.as_ptr().cast(), pending.len())
+            }) {
+                Ok(n) => {
+                    let n = n as usize;
+                    self.output_pos += n;
+                    self.handle.stats.sent_bytes.fetch_add(n as u64, Ordering::Relaxed);
+                    if self.datagram {
+                        self.output_pos = self.output.len();
+                    }
+                    if self.output_pos == self.output.len() {
+                        self.handle.stats.sent_chunks.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+                Err(Errno::EAGAIN) => {
+                    self.backlogged = true;
+                    return Ok((true, None));
+                }
+                Err(Errno::EINTR) => {}
+                Err(e) if self.datagram => {
+                    warn!("Dropping datagram of {} bytes: {}", pending.len(), e);
+                    self.handle.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                    self.output_pos = self.output.len();
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Note the current chunk as held back; passes `wait_ns` through
+    fn hold(&mut self, wait_ns: u64) -> u64 {
+        self.backlogged = true;
+        if !self.delay_counted {
+            self.handle.stats.delayed.fetch_add(1, Ordering::Relaxed);
+            self.delay_counted = true;
+        }
+        wait_ns
+    }
+
+    /// Take the next chunk (a stream slice or one datagram) off the queue
+    fn next_chunk(&mut self) -> bool {
+        let burst = self.effective_params().max_burst_bytes.max(1) as usize;
+        let taken = self.queue.dequeue(&mut self.output, burst.min(MAX_CHUNK), get_time_ns(), &self.handle.stats);
+        self.output_pos = 0;
+        if !taken {
+            return false;
+        }
+
+        self.paced = false;
+        self.delay_counted = false;
+        true
+    }
+
+    /// Sleep until an fd is ready or `timeout_ns` passes
+    fn wait(&self, want_read: bool, want_write: bool, timeout_ns: u64) {
+        let mut fds = [
+            libc::pollfd { fd: self.handle.read_fd, events: libc::POLLIN, revents: 0 },
+            libc::pollfd { fd: self.handle.write_fd, events: libc::POLLOUT, revents: 0 },
+        ];
+        if !want_read {
+            fds[0].fd = -1;
+        }
+        if !want_write {
+            fds[1].fd = -1;
+        }
+        let timeout = libc::timespec {
+            tv_sec: (timeout_ns / 1_000_000_000) as libc::time_t,
+            tv_nsec: (timeout_ns % 1_000_000_000) as libc::c_long,
+        };
+        unsafe { libc::ppoll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, &timeout, std::ptr::null()) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aqm::QueueDiscipline;
+    use crate::pacing::PepperPacingState;
+    use crate::queue::PepperRingBuffer;
+    use parking_lot::Mutex;
+    use std::io::{Read, Write};
+    use std::os::fd::AsRawFd;
+    use std::os::unix::net::UnixStream;
+    use std::time::{Duration, Instant};
+
+    // Pump from `input` to `output` (stream mode); the test drives the other ends
+    struct Harness {
+        handle: Arc<PepperShaperHandle>,
+        input: UnixStream,
+        output: UnixStream,
+        _fds: (UnixStream, UnixStream),
+    }
+
+    fn attach(handle_id: i64, target_rate_bps: u64, max_burst_bytes: u64) -> Harness {
+        let (input, read_end) = UnixStream::pair().unwrap();
+        let (output, write_end) = UnixStream::pair().unwrap();
+        output.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        let params = PepperPacingParams {
+            target_rate_bps,
+            max_burst_bytes,
+            loss_aware_backoff: false,
+            enable_pacing: true,
+            min_pacing_interval_ns: 0,
+            queue_discipline: QueueDiscipline::Simple,
+        };
+        let handle = Arc::new(PepperShaperHandle {
+            read_fd: read_end.as_raw_fd(),
+            write_fd: write_end.as_raw_fd(),
+            mode: 0,
+            active: Arc::new(std::sync::atomic::AtomicBool::new(true)),
+            tx_queue: Arc::new(PepperRingBuffer::new(64 * 1024)),
+            rx_queue: Arc::new(PepperRingBuffer::new(64 * 1024)),
+            pacing_state: Arc::new(Mutex::new(PepperPacingState::new(&params))),
+            pacing_params: Arc::new(Mutex::new(params)),
+            stats: PumpStats::default(),
+            pump: Mutex::new(None),
+            reported_sample: Mutex::new(None),
+        });
+        *handle.pump.lock() = Some(start(handle_id, handle.clone()).unwrap());
+        Harness { handle, input, output, _fds: (read_end, write_end) }
+    }
+
+    fn flags(fd: i32) -> i32 {
+        unsafe { libc::fcntl(fd, libc::F_GETFL) }
+    }
+
+    fn wait_for(mut done: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !done() {
+            assert!(Instant::now() < deadline, "timed out");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn stream_arrives_intact_and_eof_is_passed_on() {
+        let mut harness = attach(9101, 0, 64 * 1024);
+        let data: Vec<u8> = (0..256 * 1024).map(|i| (i * 7 % 251) as u8).collect();
+
+        let mut input = harness.input.try_clone().unwrap();
+        let sent = data.clone();
+        let writer = std::thread::spawn(move || {
+            input.write_all(&sent).unwrap();
+            input.shutdown(std::net::Shutdown::Write).unwrap();
+        });
+
+        // read_to_end returns only once the pump shut down its write side
+        let mut received = Vec::new();
+        harness.output.read_to_end(&mut received).unwrap();
+        writer.join().unwrap();
+        assert!(received == data, "stream corrupted or reordered");
+
+        // The pump exits by itself at end of stream
+        let pump = harness.handle.pump.lock().take().unwrap();
+        wait_for(|| pump.is_finished());
+        assert_eq!(harness.handle.stats.sent_bytes.load(Ordering::Relaxed), data.len() as u64);
+    }
+
+    #[test]
+    fn low_rate_delays_sends_and_detach_restores_the_fds() {
+        let harness = attach(9102, 80_000, 1000);
+        let (read_fd, write_fd) = (harness.handle.read_fd, harness.handle.write_fd);
+        wait_for(|| flags(read_fd) & libc::O_NONBLOCK != 0 && flags(write_fd) & libc::O_NONBLOCK != 0);
+
+        // 10 KB/s with 1 KB bursts: 8 KB cannot leave at once
+        let mut input = harness.input.try_clone().unwrap();
+        input.write_all(&[0x5a; 8 * 1024]).unwrap();
+        wait_for(|| harness.handle.stats.delayed.load(Ordering::Relaxed) >= 2);
+        assert!(harness.handle.stats.sent_bytes.load(Ordering::Relaxed) < 8 * 1024);
+
+        let mut output = harness.output.try_clone().unwrap();
+        let mut chunk = [0u8; 1000];
+        output.read_exact(&mut chunk).unwrap();
+        assert!(chunk.iter().all(|&b| b == 0x5a));
+
+        // Detach: stop() joins the thread, and the fds are blocking again
+        harness.handle.stop();
+        assert!(harness.handle.pump.lock().is_none());
+        assert_eq!(flags(read_fd) & libc::O_NONBLOCK, 0);
+        assert_eq!(flags(write_fd) & libc::O_NONBLOCK, 0);
+    }
+}
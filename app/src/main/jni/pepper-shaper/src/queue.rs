@@ -1,23 +1,22 @@
 /*
  * Lock-free ring buffer implementation for PepperShaper
+ * Byte FIFO, or a queue of timestamped chunks for sojourn-time AQM
  */
 
-use std::sync::atomic::{AtomicU64, AtomicU32, Ordering};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::ptr;
 use std::alloc::{Layout, alloc, dealloc};
 
 const CACHE_LINE_SIZE: usize = 64;
 
+/// Bytes in front of each chunk: payload length (u32) and enqueue time (u64 ns)
+pub const CHUNK_HEADER: usize = 12;
+
 /// Lock-free ring buffer with cache locality
 pub struct PepperRingBuffer {
-    #[allow(dead_code)]
+    // Absolute positions; used = write_pos - read_pos
     write_pos: AtomicU64,
-    #[allow(dead_code)]
-    write_seq: AtomicU32,
-    #[allow(dead_code)]
     read_pos: AtomicU64,
-    #[allow(dead_code)]
-    read_seq: AtomicU32,
     capacity: usize,
     data: *mut u8,
 }
@@ -39,9 +38,7 @@ impl PepperRingBuffer {
 
         Self {
             write_pos: AtomicU64::new(0),
-            write_seq: AtomicU32::new(0),
             read_pos: AtomicU64::new(0),
-            read_seq: AtomicU32::new(0),
             capacity,
             data,
         }
@@ -56,22 +53,8 @@ impl PepperRingBuffer {
         }
 
         let write_pos = self.write_pos.load(Ordering::Relaxed);
-        let write_seq = self.write_seq.load(Ordering::Acquire);
         let read_pos = self.read_pos.load(Ordering::Acquire);
-        let read_seq = self.read_seq.load(Ordering::Acquire);
-
-        // Calculate available space
-        let available = if write_seq == read_seq {
-            // Same generation
-            if write_pos >= read_pos {
-                self.capacity - (write_pos - read_pos) as usize
-            } else {
-                (read_pos - write_pos) as usize
-            }
-        } else {
-            // Different generation (wrapped)
-            self.capacity - (read_pos - (write_pos % self.capacity as u64)) as usize
-        };
+        let available = self.capacity - (write_pos - read_pos) as usize;
 
         // Reserve one byte to distinguish full from empty
         if available <= 1 {
@@ -79,31 +62,10 @@ impl PepperRingBuffer {
         }
 
         let to_write = (data.len().min(available - 1)).min(self.capacity - 1);
-        let pos = (write_pos % self.capacity as u64) as usize;
+        self.copy_in(write_pos, &data[..to_write]);
 
-        // Write data (may wrap around)
-        let first_part = (pos + to_write).min(self.capacity) - pos;
-        unsafe {
-            ptr::copy_nonoverlapping(
-                data.as_ptr(),
-                self.data.add(pos),
-                first_part,
-            );
-            if first_part < to_write {
-                ptr::copy_nonoverlapping(
-                    data.as_ptr().add(first_part),
-                    self.data,
-                    to_write - first_part,
-                );
-            }
-        }
-
-        // Update write position and sequence
-        let new_write_pos = write_pos + to_write as u64;
-        self.write_pos.store(new_write_pos, Ordering::Release);
-        if new_write_pos / self.capacity as u64 > write_pos / self.capacity as u64 {
-            self.write_seq.fetch_add(1, Ordering::Release);
-        }
+        // Publish written data
+        self.write_pos.store(write_pos + to_write as u64, Ordering::Release);
 
         to_write
     }
@@ -117,95 +79,104 @@ impl PepperRingBuffer {
         }
 
         let read_pos = self.read_pos.load(Ordering::Relaxed);
-        let read_seq = self.read_seq.load(Ordering::Acquire);
         let write_pos = self.write_pos.load(Ordering::Acquire);
-        let write_seq = self.write_seq.load(Ordering::Acquire);
-
-        // Calculate used space
-        let used = if write_seq == read_seq {
-            // Same generation
-            if write_pos >= read_pos {
-                (write_pos - read_pos) as usize
-            } else {
-                0 // Empty
-            }
-        } else {
-            // Different generation (wrapped)
-            self.capacity - (read_pos % self.capacity as u64) as usize
-        };
+        let used = (write_pos - read_pos) as usize;
 
         if used == 0 {
             return 0; // Empty
         }
 
         let to_read = data.len().min(used);
-        let pos = (read_pos % self.capacity as u64) as usize;
+        self.copy_out(read_pos, &mut data[..to_read]);
 
-        // Read data (may wrap around)
-        let first_part = (pos + to_read).min(self.capacity) - pos;
-        unsafe {
-            ptr::copy_nonoverlapping(
-                self.data.add(pos),
-                data.as_mut_ptr(),
-                first_part,
-            );
-            if first_part < to_read {
-                ptr::copy_nonoverlapping(
-                    self.data,
-                    data.as_mut_ptr().add(first_part),
-                    to_read - first_part,
-                );
-            }
+        // Release the space
+        self.read_pos.store(read_pos + to_read as u64, Ordering::Release);
+
+        to_read
+    }
+
+    /// Enqueue one chunk stamped with its enqueue time, whole or not at all
+    /// Returns false if it does not fit
+    pub fn enqueue_chunk(&self, data: &[u8], enqueued_ns: u64) -> bool {
+        if data.len() > u32::MAX as usize || CHUNK_HEADER + data.len() > self.available() {
+            return false;
         }
 
-        // Update read position and sequence
-        let new_read_pos = read_pos + to_read as u64;
-        self.read_pos.store(new_read_pos, Ordering::Release);
-        if new_read_pos / self.capacity as u64 > read_pos / self.capacity as u64 {
-            self.read_seq.fetch_add(1, Ordering::Release);
+        let write_pos = self.write_pos.load(Ordering::Relaxed);
+        let mut header = [0u8; CHUNK_HEADER];
+        header[..4].copy_from_slice(&(data.len() as u32).to_ne_bytes());
+        header[4..].copy_from_slice(&enqueued_ns.to_ne_bytes());
+        self.copy_in(write_pos, &header);
+        self.copy_in(write_pos + CHUNK_HEADER as u64, data);
+
+        // Publish header and payload together
+        self.write_pos.store(write_pos + (CHUNK_HEADER + data.len()) as u64, Ordering::Release);
+        true
+    }
+
+    /// Length and enqueue time of the next chunk, without taking it
+    pub fn peek_chunk(&self) -> Option<(usize, u64)> {
+        let read_pos = self.read_pos.load(Ordering::Relaxed);
+        let write_pos = self.write_pos.load(Ordering::Acquire);
+        if ((write_pos - read_pos) as usize) < CHUNK_HEADER {
+            return None;
         }
 
-        to_read
+        let mut header = [0u8; CHUNK_HEADER];
+        self.copy_out(read_pos, &mut header);
+        let len = u32::from_ne_bytes(header[..4].try_into().unwrap()) as usize;
+        let enqueued_ns = u64::from_ne_bytes(header[4..].try_into().unwrap());
+        Some((len, enqueued_ns))
     }
 
-    /// Get available space
-    #[allow(dead_code)]
-    pub fn available(&self) -> usize {
-        let write_pos = self.write_pos.load(Ordering::Relaxed);
-        let write_seq = self.write_seq.load(Ordering::Acquire);
-        let read_pos = self.read_pos.load(Ordering::Acquire);
-        let read_seq = self.read_seq.load(Ordering::Acquire);
+    /// Dequeue the next chunk, appending its payload to `out`
+    /// Returns its enqueue time, None if empty
+    pub fn dequeue_chunk(&self, out: &mut Vec<u8>) -> Option<u64> {
+        let (len, enqueued_ns) = self.peek_chunk()?;
+        let read_pos = self.read_pos.load(Ordering::Relaxed);
+
+        let start = out.len();
+        out.resize(start + len, 0);
+        self.copy_out(read_pos + CHUNK_HEADER as u64, &mut out[start..]);
+
+        self.read_pos.store(read_pos + (CHUNK_HEADER + len) as u64, Ordering::Release);
+        Some(enqueued_ns)
+    }
+
+    /// Copy `data` in at absolute position `pos` (may wrap around)
+    fn copy_in(&self, pos: u64, data: &[u8]) {
+        let pos = (pos % self.capacity as u64) as usize;
+        let first_part = (pos + data.len()).min(self.capacity) - pos;
+        unsafe {
+            ptr::copy_nonoverlapping(data.as_ptr(), self.data.add(pos), first_part);
+            if first_part < data.len() {
+                ptr::copy_nonoverlapping(data.as_ptr().add(first_part), self.data, data.len() - first_part);
+            }
+        }
+    }
 
-        let used = if write_seq == read_seq {
-            if write_pos >= read_pos {
-                (write_pos - read_pos) as usize
-            } else {
-                0
+    /// Copy out from absolute position `pos` into `data` (may wrap around)
+    fn copy_out(&self, pos: u64, data: &mut [u8]) {
+        let pos = (pos % self.capacity as u64) as usize;
+        let first_part = (pos + data.len()).min(self.capacity) - pos;
+        unsafe {
+            ptr::copy_nonoverlapping(self.data.add(pos), data.as_mut_ptr(), first_part);
+            if first_part < data.len() {
+                ptr::copy_nonoverlapping(self.data, data.as_mut_ptr().add(first_part), data.len() - first_part);
             }
-        } else {
-            self.capacity - (read_pos % self.capacity as u64) as usize
-        };
+        }
+    }
 
-        self.capacity - used - 1 // Reserve one byte
+    /// Get available space
+    pub fn available(&self) -> usize {
+        self.capacity - self.used() - 1 // Reserve one byte
     }
 
     /// Get used space
-    #[allow(dead_code)]
     pub fn used(&self) -> usize {
-        let write_pos = self.write_pos.load(Ordering::Relaxed);
-        let write_seq = self.write_seq.load(Ordering::Acquire);
         let read_pos = self.read_pos.load(Ordering::Acquire);
-        let read_seq = self.read_seq.load(Ordering::Acquire);
-
-        if write_seq == read_seq {
-            if write_pos >= read_pos {
-                (write_pos - read_pos) as usize
-            } else {
-                0
-            }
-        } else {
-            self.capacity - (read_pos % self.capacity as u64) as usize
-        }
+        let write_pos = self.write_pos.load(Ordering::Acquire);
+        write_pos.saturating_sub(read_pos) as usize
     }
 }
 
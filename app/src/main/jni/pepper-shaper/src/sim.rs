@@ -0,0 +1,206 @@
+/*
+ * Simulated bottleneck link for PepperShaper adaptive pacing
+ * Deterministic harness for the estimator's unit tests
+ */
+
+use std::collections::VecDeque;
+
+use crate::bbr::BbrEstimator;
+
+/// A single-bottleneck path
+pub struct SimLink {
+    /// Bottleneck rate changes as (time ms, bits per second); the first applies from 0
+    pub bandwidth: Vec<(u64, u64)>,
+    pub base_rtt_ms: u64,
+    /// Bottleneck buffer; arrivals beyond it are dropped
+    pub buffer_bytes: u64,
+    /// Offered load of the application (0 = always has data)
+    pub app_rate_bps: u64,
+    /// Pacing ceiling, as `target_rate_bps` in adaptive mode (0 = none)
+    pub ceiling_bps: u64,
+    pub duration_ms: u64,
+    /// How often delivery is sampled (the pump samples TCP_INFO every 50 ms)
+    pub sample_interval_ms: u64,
+}
+
+/// State at the end of one sample interval
+pub struct SimStep {
+    pub time_ms: u64,
+    pub phase: &'static str,
+    pub pacing_rate_bps: u64,
+    pub btl_bw_bps: u64,
+    pub min_rtt_ms: f64,
+    pub link_bps: u64,
+    pub delivery_rate_bps: u64,
+    pub rtt_ms: f64,
+    pub queue_bytes: u64,
+    pub dropped_bytes: u64,
+}
+
+/// Run the estimator against `link` in 1 ms steps
+///
+/// Delivery and RTT samples reach the estimator one base RTT after they are
+/// taken, like ACK feedback would.
+pub fn simulate(link: &SimLink) -> Vec<SimStep> {
+    let mut bbr = BbrEstimator::new(0, link.ceiling_bps);
+    let interval_ms = link.sample_interval_ms.max(1);
+
+    let mut steps = Vec::new();
+    // (deliver at ms, delivery rate, rtt ns, app limited)
+    let mut feedback: VecDeque<(u64, u64, u64, bool)> = VecDeque::new();
+
+    // Byte budgets carry fractions over in millibytes
+    let (mut send_credit, mut app_credit, mut link_credit) = (0u64, 0u64, 0u64);
+    let mut app_backlog = 0u64;
+    let mut queue = 0u64;
+    let mut dropped = 0u64;
+    let mut delivered = 0u64;
+    let mut app_limited = false;
+
+    for t in 0..link.duration_ms {
+        let now_ns = t * 1_000_000;
+        let link_bps = link
+            .bandwidth
+            .iter()
+            .take_while(|&&(at, _)| at <= t)
+            .last()
+            .map_or(0, |&(_, bps)| bps);
+
+        while feedback.front().is_some_and(|&(at, ..)| at <= t) {
+            let (_, rate, rtt_ns, limited) = feedback.pop_front().unwrap();
+            bbr.on_sample(now_ns, rate, rtt_ns, limited);
+        }
+        bbr.advance(now_ns);
+
+        // Sender: paced bytes this millisecond, limited by what the app offered
+        send_credit += bbr.pacing_rate_bps() / 8;
+        let mut send = send_credit / 1000;
+        send_credit %= 1000;
+        if link.app_rate_bps > 0 {
+            app_credit += link.app_rate_bps / 8;
+            app_backlog += app_credit / 1000;
+            app_credit %= 1000;
+            if app_backlog < send {
+                app_limited = true;
+                send = app_backlog;
+            }
+            app_backlog -= send;
+        }
+
+        // Bottleneck queue
+        queue += send;
+        if queue > link.buffer_bytes {
+            dropped += queue - link.buffer_bytes;
+            queue = link.buffer_bytes;
+        }
+        link_credit += link_bps / 8;
+        let served = (link_credit / 1000).min(queue);
+        link_credit %= 1000;
+        if queue == served {
+            // An idle link cannot bank capacity
+            link_credit = 0;
+        }
+        queue -= served;
+        delivered += served;
+
+        if (t + 1) % interval_ms == 0 {
+            let delivery_rate_bps = delivered * 8 * 1000 / interval_ms;
+            let queue_delay_ns = (queue * 8 * 1_000_000_000).checked_div(link_bps).unwrap_or(0);
+            let rtt_ns = link.base_rtt_ms * 1_000_000 + queue_delay_ns;
+            feedback.push_back((t + link.base_rtt_ms, delivery_rate_bps, rtt_ns, app_limited));
+
+            steps.push(SimStep {
+                time_ms: t + 1,
+                phase: bbr.phase().name(),
+                pacing_rate_bps: bbr.pacing_rate_bps(),
+                btl_bw_bps: bbr.btl_bw_bps(),
+                min_rtt_ms: bbr.min_rtt_ns().map_or(0.0, |ns| ns as f64 / 1e6),
+                link_bps,
+                delivery_rate_bps,
+                rtt_ms: rtt_ns as f64 / 1e6,
+                queue_bytes: queue,
+                dropped_bytes: dropped,
+            });
+            delivered = 0;
+            app_limited = false;
+        }
+    }
+
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(bandwidth: Vec<(u64, u64)>, base_rtt_ms: u64, app_rate_bps: u64, duration_ms: u64) -> SimLink {
+        SimLink {
+            bandwidth,
+            base_rtt_ms,
+            buffer_bytes: 256 * 1024,
+            app_rate_bps,
+            ceiling_bps: 0,
+            duration_ms,
+            sample_interval_ms: 50,
+        }
+    }
+
+    /// Mean delivered / link rate and mean RTT over `steps`
+    fn summary(steps: &[SimStep]) -> (f64, f64) {
+        let utilization = steps.iter().map(|s| s.delivery_rate_bps as f64 / s.link_bps as f64).sum::<f64>()
+            / steps.len() as f64;
+        let rtt_ms = steps.iter().map(|s| s.rtt_ms).sum::<f64>() / steps.len() as f64;
+        (utilization, rtt_ms)
+    }
+
+    #[test]
+    fn steady_link_is_filled_without_a_standing_queue() {
+        let steps = simulate(&link(vec![(0, 20_000_000)], 40, 0, 15_000));
+        let (utilization, rtt_ms) = summary(&steps[steps.len() * 3 / 4..]);
+        println!("steady: utilization {:.3}, rtt {:.1} ms", utilization, rtt_ms);
+        assert!(utilization > 0.9, "utilization {}", utilization);
+        assert!(rtt_ms < 40.0 * 1.5, "rtt {} ms", rtt_ms);
+        // At most about a BDP (20 Mbit/s x 40 ms = 100 KB) ever builds up
+        assert!(steps[steps.len() * 3 / 4..].iter().all(|s| s.queue_bytes <= 100_000));
+
+        let last = steps.last().unwrap();
+        assert_eq!(last.min_rtt_ms, 40.0);
+        assert!((last.btl_bw_bps as f64 - 20e6).abs() < 20e6 * 0.1, "btl_bw {}", last.btl_bw_bps);
+    }
+
+    #[test]
+    fn step_down_converges_to_the_new_rate() {
+        let steps = simulate(&link(vec![(0, 50_000_000), (5_000, 10_000_000)], 30, 0, 10_000));
+        let tail: Vec<_> = steps.into_iter().filter(|s| s.time_ms > 7_500).collect();
+        let (utilization, rtt_ms) = summary(&tail);
+        println!("step-down: utilization {:.3}, rtt {:.1} ms", utilization, rtt_ms);
+        assert!(utilization > 0.9, "utilization {}", utilization);
+        assert!(rtt_ms < 30.0 * 2.0, "rtt {} ms", rtt_ms);
+        // The old 50 Mbit/s samples have aged out of the max filter
+        assert!(tail.iter().all(|s| s.btl_bw_bps < 15_000_000));
+        // and the overflow the drop caused is over
+        assert_eq!(tail.first().unwrap().dropped_bytes, tail.last().unwrap().dropped_bytes);
+    }
+
+    #[test]
+    fn app_limited_samples_do_not_lower_the_estimate() {
+        let steps = simulate(&link(vec![(0, 30_000_000)], 25, 3_000_000, 5_000));
+        // Delivery never exceeds what the app offers, yet the estimate stays put
+        let peak = steps.iter().map(|s| s.btl_bw_bps).max().unwrap();
+        let last = steps.last().unwrap();
+        println!("app-limited: peak btl_bw {}, last {}", peak, last.btl_bw_bps);
+        assert!(peak >= 3_000_000);
+        assert_eq!(last.btl_bw_bps, peak);
+        assert!(steps.iter().all(|s| s.phase == "startup"));
+    }
+
+    #[test]
+    fn ceiling_caps_the_pacing_rate() {
+        let mut sim = link(vec![(0, 20_000_000)], 40, 0, 5_000);
+        sim.ceiling_bps = 5_000_000;
+        let steps = simulate(&sim);
+        assert!(steps.iter().all(|s| s.pacing_rate_bps <= 5_000_000));
+        let (utilization, _) = summary(&steps[steps.len() / 2..]);
+        assert!((utilization - 0.25).abs() < 0.05, "utilization {}", utilization);
+    }
+}
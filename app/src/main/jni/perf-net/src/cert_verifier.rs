@@ -3,17 +3,23 @@
  * 
  * Features:
  * - rustls trust manager bridge
+ * - WebPKI chain validation
  * - Hostname mismatch handling
- * - Certificate pinning bypass (for isolated test env)
+ * - SPKI-SHA256 certificate pinning (bypassable for isolated test env)
  */
 
 use jni::JNIEnv;
-use jni::objects::{JClass, JString};
+use jni::objects::{JByteArray, JClass, JString};
 use jni::sys::{jboolean, jint, jlong};
-use log::debug;
-use rustls::client::danger::{ServerCertVerifier, ServerCertVerified};
-use rustls::pki_types::{CertificateDer, ServerName};
-use rustls::{Error, SignatureScheme};
+use log::{debug, warn};
+use parking_lot::RwLock;
+use ring::digest;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerifier, ServerCertVerified};
+use rustls::client::{verify_server_cert_signed_by_trust_anchor, verify_server_name};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::server::ParsedCertificate;
+use rustls::{CertificateError, ClientConfig as RustlsClientConfig, DigitallySignedStruct, Error, RootCertStore, SignatureScheme};
 use std::sync::Arc;
 
 /// Dummy certificate verifier (accepts all certificates)
@@ -82,8 +88,229 @@ impl ServerCertVerifier for NoCertificateVerification {
     }
 }
 
+/// Pinning-aware certificate verifier
+///
+/// Validates the chain against the WebPKI roots, checks the name against
+/// `expected_hostname` (falling back to the SNI name) unless a mismatch is
+/// allowed, and requires an SPKI pin match unless pinning is bypassed.
+#[derive(Debug)]
+pub struct PinningCertVerifier {
+    allow_hostname_mismatch: bool,
+    bypass_pinning: bool,
+    expected_hostname: Option<String>,
+    roots: RootCertStore,
+    provider: Arc<CryptoProvider>,
+    pins: RwLock<Vec<[u8; 32]>>,
+}
+
+impl PinningCertVerifier {
+    pub fn new(allow_hostname_mismatch: bool, bypass_pinning: bool, hostname: Option<String>) -> Self {
+        let mut roots = RootCertStore::empty();
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        Self::with_roots(allow_hostname_mismatch, bypass_pinning, hostname, roots)
+    }
+
+    /// Verifier trusting `roots` instead of the WebPKI roots
+    pub fn with_roots(
+        allow_hostname_mismatch: bool,
+        bypass_pinning: bool,
+        hostname: Option<String>,
+        roots: RootCertStore,
+    ) -> Self {
+        Self {
+            allow_hostname_mismatch,
+            bypass_pinning,
+            expected_hostname: hostname,
+            roots,
+            provider: Arc::new(rustls::crypto::ring::default_provider()),
+            pins: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Add an SPKI-SHA256 pin
+    pub fn add_pin(&self, pin: [u8; 32]) {
+        let mut pins = self.pins.write();
+        if !pins.contains(&pin) {
+            pins.push(pin);
+        }
+    }
+
+    fn sha256(spki_der: &[u8]) -> [u8; 32] {
+        let hash = digest::digest(&digest::SHA256, spki_der);
+        let mut pin = [0u8; 32];
+        pin.copy_from_slice(hash.as_ref());
+        pin
+    }
+
+    // Pins of every certificate on a validated path, trust anchor included
+    fn path_pins(path: &webpki::VerifiedPath<'_>) -> Vec<[u8; 32]> {
+        let mut pins = vec![Self::sha256(path.end_entity().subject_public_key_info().as_ref())];
+        pins.extend(
+            path.intermediate_certificates()
+                .map(|cert| Self::sha256(cert.subject_public_key_info().as_ref())),
+        );
+
+        // Anchors keep only the SPKI contents; pins cover the whole SEQUENCE
+        let anchor = path.anchor().subject_public_key_info.as_ref();
+        let mut spki = vec![0x30];
+        if anchor.len() < 0x80 {
+            spki.push(anchor.len() as u8);
+        } else {
+            let len = anchor.len().to_be_bytes();
+            let skip = len.iter().take_while(|&&b| b == 0).count();
+            spki.push(0x80 | (len.len() - skip) as u8);
+            spki.extend_from_slice(&len[skip..]);
+        }
+        spki.extend_from_slice(anchor);
+        pins.push(Self::sha256(&spki));
+        pins
+    }
+
+    fn check_hostname(&self, cert: &ParsedCertificate<'_>, server_name: &ServerName<'_>) -> Result<(), Error> {
+        if self.allow_hostname_mismatch {
+            return Ok(());
+        }
+
+        match &self.expected_hostname {
+            Some(hostname) => {
+                let expected = ServerName::try_from(hostname.as_str())
+                    .map_err(|_| Error::InvalidCertificate(CertificateError::NotValidForName))?;
+                verify_server_name(cert, &expected)
+            }
+            None => verify_server_name(cert, server_name),
+        }
+    }
+
+    // Require a validated path through a pinned key; certificates the peer
+    // appended that are not on that path do not count
+    fn check_pins(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        now: UnixTime,
+    ) -> Result<(), Error> {
+        if self.bypass_pinning {
+            return Ok(());
+        }
+
+        let pins = self.pins.read();
+        if pins.is_empty() {
+            return Ok(());
+        }
+
+        let pinned = |path: &webpki::VerifiedPath<'_>| {
+            if Self::path_pins(path).iter().any(|pin| pins.contains(pin)) {
+                Ok(())
+            } else {
+                Err(webpki::Error::UnknownIssuer)
+            }
+        };
+        let matched = webpki::EndEntityCert::try_from(end_entity).is_ok_and(|cert| {
+            cert.verify_for_usage(
+                self.provider.signature_verification_algorithms.all,
+                &self.roots.roots,
+                intermediates,
+                now,
+                webpki::KeyUsage::server_auth(),
+                None,
+                Some(&pinned),
+            )
+            .is_ok()
+        });
+
+        if matched {
+            Ok(())
+        } else {
+            warn!("Certificate pin mismatch ({} pins configured)", pins.len());
+            Err(Error::InvalidCertificate(CertificateError::ApplicationVerificationFailure))
+        }
+    }
+}
+
+impl ServerCertVerifier for PinningCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        _ocsp: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, Error> {
+        let cert = ParsedCertificate::try_from(end_entity)?;
+
+        verify_server_cert_signed_by_trust_anchor(
+            &cert,
+            &self.roots,
+            intermediates,
+            now,
+            self.provider.signature_verification_algorithms.all,
+        )?;
+        self.check_hostname(&cert, server_name)?;
+        self.check_pins(end_entity, intermediates, now)?;
+
+        debug!("Certificate verified for {:?}", server_name);
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// Client config verifying with a `PinningCertVerifier`
+///
+/// Strict policy: the name must match the SNI name and, when `pins` is not
+/// empty, one certificate in the chain must match a pin.
+pub fn pinned_client_config(pins: &[[u8; 32]], alpn: &str) -> Result<RustlsClientConfig, Error> {
+    client_config(PinningCertVerifier::new(false, false, None), pins, alpn)
+}
+
+/// `pinned_client_config` trusting `roots` instead of the WebPKI roots
+pub fn pinned_client_config_with_roots(
+    pins: &[[u8; 32]],
+    alpn: &str,
+    roots: RootCertStore,
+) -> Result<RustlsClientConfig, Error> {
+    client_config(PinningCertVerifier::with_roots(false, false, None, roots), pins, alpn)
+}
+
+fn client_config(verifier: PinningCertVerifier, pins: &[[u8; 32]], alpn: &str) -> Result<RustlsClientConfig, Error> {
+    let verifier = Arc::new(verifier);
+    for pin in pins {
+        verifier.add_pin(*pin);
+    }
+
+    let mut config = RustlsClientConfig::builder_with_provider(verifier.provider.clone())
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(verifier)
+        .with_no_client_auth();
+    if !alpn.is_empty() {
+        config.alpn_protocols = vec![alpn.as_bytes().to_vec()];
+    }
+    Ok(config)
+}
+
 struct VerifyContext {
-    verifier: Arc<NoCertificateVerification>,
+    verifier: Arc<PinningCertVerifier>,
 }
 
 /// Create certificate verifier context
@@ -104,7 +331,7 @@ pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nat
         None
     };
 
-    let verifier = Arc::new(NoCertificateVerification::new(
+    let verifier = Arc::new(PinningCertVerifier::new(
         allow_hostname_mismatch != 0,
         bypass_pinning != 0,
         hostname_str,
@@ -114,7 +341,39 @@ pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nat
     Box::into_raw(ctx) as jlong
 }
 
+/// Add SPKI-SHA256 pin (32-byte digest) to certificate verifier context
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nativeAddCertPin(
+    env: JNIEnv,
+    _class: JClass,
+    ctx_ptr: jlong,
+    spki_sha256: JByteArray,
+) -> jint {
+    if ctx_ptr == 0 || spki_sha256.is_null() {
+        return -1;
+    }
+
+    let bytes = match env.convert_byte_array(&spki_sha256) {
+        Ok(b) => b,
+        Err(_) => return -1,
+    };
+
+    if bytes.len() != 32 {
+        warn!("Invalid SPKI pin length: {}", bytes.len());
+        return -1;
+    }
+
+    let mut pin = [0u8; 32];
+    pin.copy_from_slice(&bytes);
+
+    let ctx = unsafe { &*(ctx_ptr as *const VerifyContext) };
+    ctx.verifier.add_pin(pin);
+    debug!("Certificate pin added");
+    0
+}
+
 /// Set certificate verify callback
+/// `ssl_ctx_ptr` is a rustls ClientConfig handle (see nativeCreateChromeMobileSSLContext)
 #[no_mangle]
 pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nativeSetCertVerifyCallback(
     _env: JNIEnv,
@@ -126,8 +385,10 @@ pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nat
         return -1;
     }
 
-    // Certificate verification is handled by rustls ServerCertVerifier
-    // This is a placeholder for callback setup
+    let ctx = unsafe { &*(ctx_ptr as *const VerifyContext) };
+    let config = unsafe { &mut *(ssl_ctx_ptr as *mut RustlsClientConfig) };
+    config.dangerous().set_certificate_verifier(ctx.verifier.clone());
+
     debug!("Certificate verify callback set");
     0
 }
@@ -164,3 +425,117 @@ pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nat
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{BasicConstraints, Certificate, CertificateParams, DnType, IsCa, KeyPair};
+
+    struct Issuer {
+        cert: Certificate,
+        key: KeyPair,
+    }
+
+    fn ca(name: &str, parent: Option<&Issuer>) -> Issuer {
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.distinguished_name.push(DnType::CommonName, name);
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let key = KeyPair::generate().unwrap();
+        let cert = match parent {
+            Some(parent) => params.signed_by(&key, &parent.cert, &parent.key).unwrap(),
+            None => params.self_signed(&key).unwrap(),
+        };
+        Issuer { cert, key }
+    }
+
+    fn leaf(issuer: &Issuer) -> CertificateDer<'static> {
+        let mut params = CertificateParams::new(vec!["pin.test".to_string()]).unwrap();
+        params.distinguished_name.push(DnType::CommonName, "pin.test");
+        let key = KeyPair::generate().unwrap();
+        params.signed_by(&key, &issuer.cert, &issuer.key).unwrap().der().clone()
+    }
+
+    fn pin(cert: &CertificateDer<'_>) -> [u8; 32] {
+        let parsed = webpki::EndEntityCert::try_from(cert).unwrap();
+        PinningCertVerifier::sha256(parsed.subject_public_key_info().as_ref())
+    }
+
+    fn verifier(root: &Issuer, pins: &[[u8; 32]]) -> PinningCertVerifier {
+        let mut roots = RootCertStore::empty();
+        roots.add(root.cert.der().clone()).unwrap();
+        let verifier = PinningCertVerifier::with_roots(false, false, None, roots);
+        for pin in pins {
+            verifier.add_pin(*pin);
+        }
+        verifier
+    }
+
+    fn verify(
+        verifier: &PinningCertVerifier,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+    ) -> Result<ServerCertVerified, Error> {
+        let name = ServerName::try_from("pin.test").unwrap();
+        verifier.verify_server_cert(end_entity, intermediates, &name, &[], UnixTime::now())
+    }
+
+    #[test]
+    fn pins_match_any_certificate_on_the_verified_path() {
+        let root = ca("Pin Test Root", None);
+        let intermediate = ca("Pin Test Intermediate", Some(&root));
+        let end_entity = leaf(&intermediate);
+        let chain = [intermediate.cert.der().clone()];
+
+        assert!(verify(&verifier(&root, &[]), &end_entity, &chain).is_ok());
+        assert!(verify(&verifier(&root, &[pin(&end_entity)]), &end_entity, &chain).is_ok());
+        assert!(verify(&verifier(&root, &[pin(intermediate.cert.der())]), &end_entity, &chain).is_ok());
+        // The root is never sent by the server; it matches as the trust anchor
+        assert!(verify(&verifier(&root, &[pin(root.cert.der())]), &end_entity, &chain).is_ok());
+    }
+
+    #[test]
+    fn unpinned_chain_is_rejected_unless_pinning_is_bypassed() {
+        let root = ca("Pin Test Root", None);
+        let intermediate = ca("Pin Test Intermediate", Some(&root));
+        let end_entity = leaf(&intermediate);
+        let chain = [intermediate.cert.der().clone()];
+
+        let err = verify(&verifier(&root, &[[7; 32]]), &end_entity, &chain).unwrap_err();
+        assert_eq!(err, Error::InvalidCertificate(CertificateError::ApplicationVerificationFailure));
+
+        let mut roots = RootCertStore::empty();
+        roots.add(root.cert.der().clone()).unwrap();
+        let bypass = PinningCertVerifier::with_roots(false, true, None, roots);
+        bypass.add_pin([7; 32]);
+        assert!(verify(&bypass, &end_entity, &chain).is_ok());
+    }
+
+    #[test]
+    fn appended_certificate_off_the_path_does_not_satisfy_pins() {
+        let root = ca("Pin Test Root", None);
+        let intermediate = ca("Pin Test Intermediate", Some(&root));
+        let end_entity = leaf(&intermediate);
+        let pinned = ca("Pinned Elsewhere", None);
+        let chain = [intermediate.cert.der().clone(), pinned.cert.der().clone()];
+
+        let err = verify(&verifier(&root, &[pin(pinned.cert.der())]), &end_entity, &chain).unwrap_err();
+        assert_eq!(err, Error::InvalidCertificate(CertificateError::ApplicationVerificationFailure));
+    }
+
+    #[test]
+    fn untrusted_chain_and_wrong_name_are_rejected() {
+        let root = ca("Pin Test Root", None);
+        let end_entity = leaf(&root);
+
+        let other = ca("Other Root", None);
+        let err = verify(&verifier(&other, &[]), &end_entity, &[]).unwrap_err();
+        assert_eq!(err, Error::InvalidCertificate(CertificateError::UnknownIssuer));
+
+        let named = PinningCertVerifier::with_roots(false, false, Some("other.test".into()), {
+            let mut roots = RootCertStore::empty();
+            roots.add(root.cert.der().clone()).unwrap();
+            roots
+        });
+        assert!(verify(&named, &end_entity, &[]).is_err());
+    }
+}
@@ -5,19 +5,28 @@
 
 use jni::JNIEnv;
 use jni::objects::{JClass, JString};
-use jni::sys::jint;
+use jni::sys::{jint, jlong, jlongArray};
 use parking_lot::Mutex;
-use nix::sys::socket::{socket, AddressFamily, SockType, SockFlag, SockProtocol, connect, setsockopt};
+use nix::errno::Errno;
+use nix::sys::socket::{socket, AddressFamily, SockType, SockFlag, SockProtocol, SockaddrIn, SockaddrIn6, connect, setsockopt};
+use nix::sys::socket::{getsockopt, sockopt::SocketError};
 use nix::sys::socket::sockopt::{ReuseAddr, KeepAlive};
-use nix::unistd::close;
+use nix::unistd::{close, dup2};
 use std::os::unix::io::RawFd;
-use std::os::fd::{AsRawFd, BorrowedFd};
-use std::net::Ipv4Addr;
+use std::os::fd::{AsRawFd, BorrowedFd, IntoRawFd};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 use log::{debug, error};
 
 const MAX_POOL_SIZE: usize = 16;
 const DEFAULT_POOL_SIZE: usize = 8;
 const MIN_POOL_SIZE: usize = 4;
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+const DEFAULT_MAX_LIFETIME: Duration = Duration::from_secs(600);
+const REAP_INTERVAL: Duration = Duration::from_secs(5);
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 enum PoolType {
@@ -41,13 +50,234 @@ struct ConnectionSlot {
     fd: Option<RawFd>,
     in_use: bool,
     connected: bool,
+    // Non-blocking connect in progress
+    connecting: bool,
+    connect_error: Option<Errno>,
+    // Bumped per connect so a stale completion is not applied
+    connect_seq: u64,
+    // Resolved addresses left to try if the current connect fails
+    pending_addrs: Vec<SocketAddr>,
     remote_addr: String,
     remote_port: u16,
+    family: AddressFamily,
+    // Last lease or return; idle time counts from here
+    last_used: Instant,
+    // Start of the current connection; max lifetime counts from here
+    connected_since: Option<Instant>,
     pool_type: PoolType,
 }
 
+/// Why an idle socket was closed
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Eviction {
+    Idle,
+    Lifetime,
+    // Peer closed or reset, unread data, or the connect failed
+    Unhealthy,
+}
+
+#[derive(Clone, Copy, Default)]
+struct PoolStats {
+    hits: u64,
+    misses: u64,
+    evicted_idle: u64,
+    evicted_lifetime: u64,
+    evicted_unhealthy: u64,
+}
+
+impl PoolStats {
+    fn record(&mut self, eviction: Eviction) {
+        match eviction {
+            Eviction::Idle => self.evicted_idle += 1,
+            Eviction::Lifetime => self.evicted_lifetime += 1,
+            Eviction::Unhealthy => self.evicted_unhealthy += 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct PoolLimits {
+    idle_timeout: Duration,
+    max_lifetime: Duration,
+}
+
+/// Whether an idle connected socket can be handed out again
+///
+/// The peer must not have closed or reset it, and nothing may be waiting to
+/// be read: an idle pooled connection has no reply pending.
+pub(crate) fn is_healthy(fd: RawFd) -> bool {
+    let mut pfd = libc::pollfd { fd, events: libc::POLLIN | libc::POLLRDHUP, revents: 0 };
+    let ready = unsafe { libc::poll(&mut pfd, 1, 0) };
+    if ready == 0 {
+        return true;
+    }
+    if ready < 0 {
+        return Errno::last() == Errno::EINTR;
+    }
+    if pfd.revents & (libc::POLLRDHUP | libc::POLLHUP | libc::POLLERR | libc::POLLNVAL) != 0 {
+        return false;
+    }
+
+    // Readable: EOF or stray data
+    let mut byte = 0u8;
+    let n = unsafe {
+        libc::recv(fd, &mut byte as *mut u8 as *mut libc::c_void, 1, libc::MSG_PEEK | libc::MSG_DONTWAIT)
+    };
+    n < 0 && Errno::last() == Errno::EAGAIN
+}
+
+/// Progress of a slot's non-blocking connect
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ConnectStatus {
+    Connected,
+    Connecting,
+    Failed(Errno),
+}
+
+impl ConnectStatus {
+    /// JNI result: 0 connected, 1 still connecting, negative errno on failure
+    fn to_jint(self) -> jint {
+        match self {
+            ConnectStatus::Connected => 0,
+            ConnectStatus::Connecting => 1,
+            ConnectStatus::Failed(e) => -(e as jint),
+        }
+    }
+}
+
+impl ConnectionSlot {
+    fn status(&self) -> ConnectStatus {
+        if self.connected {
+            ConnectStatus::Connected
+        } else if self.connecting {
+            ConnectStatus::Connecting
+        } else {
+            ConnectStatus::Failed(self.connect_error.unwrap_or(Errno::ENOTCONN))
+        }
+    }
+
+    /// Start a non-blocking connect, trying `addrs` in order
+    ///
+    /// A socket that already attempted a connect, or has the wrong address
+    /// family, is replaced first under the same fd number. Addresses after the
+    /// one left in progress are kept for `finish_connect` to fall back to.
+    fn connect_to(&mut self, fd: RawFd, addrs: &[SocketAddr]) -> Result<(), nix::Error> {
+        let mut used = !self.remote_addr.is_empty();
+        let mut last_error = Errno::EHOSTUNREACH;
+        self.connect_seq += 1;
+        self.connected_since = Some(Instant::now());
+        self.connected = false;
+        self.connecting = false;
+        self.pending_addrs.clear();
+
+        for (i, addr) in addrs.iter().enumerate() {
+            let family = if addr.is_ipv6() { AddressFamily::Inet6 } else { AddressFamily::Inet };
+            if used || family != self.family {
+                if let Err(e) = ConnectionPool::reopen_socket(fd, family) {
+                    last_error = e;
+                    continue;
+                }
+                self.family = family;
+            }
+            used = true;
+
+            let result = match addr {
+                SocketAddr::V4(a) => connect(fd, &SockaddrIn::from(*a)),
+                SocketAddr::V6(a) => connect(fd, &SockaddrIn6::from(*a)),
+            };
+            match result {
+                Ok(_) => {
+                    self.connected = true;
+                    self.connect_error = None;
+                    return Ok(());
+                }
+                Err(Errno::EINPROGRESS) => {
+                    self.connecting = true;
+                    self.connect_error = None;
+                    self.pending_addrs = addrs[i + 1..].to_vec();
+                    return Ok(());
+                }
+                Err(e) => {
+                    debug!("Connect to {} failed: {}", addr, e);
+                    last_error = e;
+                }
+            }
+        }
+
+        self.connect_error = Some(last_error);
+        Err(last_error)
+    }
+
+    /// Record the outcome of the connect in progress (SO_ERROR of the socket)
+    ///
+    /// On failure the remaining resolved addresses are tried.
+    fn finish_connect(&mut self, fd: RawFd, result: Result<(), Errno>) -> ConnectStatus {
+        self.connecting = false;
+        match result {
+            Ok(()) => {
+                self.connected = true;
+                debug!("Pooled socket connected: {}:{}", self.remote_addr, self.remote_port);
+            }
+            Err(e) => {
+                debug!("Pooled socket connect to {}:{} failed: {}", self.remote_addr, self.remote_port, e);
+                self.connect_error = Some(e);
+                let addrs = std::mem::take(&mut self.pending_addrs);
+                if !addrs.is_empty() {
+                    let _ = self.connect_to(fd, &addrs);
+                }
+            }
+        }
+        self.status()
+    }
+
+    /// Close this free slot's socket if it exceeded a limit or went bad
+    ///
+    /// Sockets that never started a connect are kept.
+    fn reap(&mut self, now: Instant, limits: &PoolLimits) -> Option<Eviction> {
+        let fd = self.fd?;
+        if self.in_use || self.remote_addr.is_empty() {
+            return None;
+        }
+        // Nobody waited for the connect; pick up its result
+        if self.connecting {
+            if let Some(result) = poll_connect(fd, now) {
+                self.finish_connect(fd, result);
+            }
+        }
+
+        let eviction = if self.connected_since.is_some_and(|at| now.duration_since(at) >= limits.max_lifetime) {
+            Eviction::Lifetime
+        } else if now.duration_since(self.last_used) >= limits.idle_timeout {
+            Eviction::Idle
+        } else if !(self.connecting || (self.connected && is_healthy(fd))) {
+            Eviction::Unhealthy
+        } else {
+            return None;
+        };
+
+        debug!("Evicting pooled socket to {}:{} ({:?})", self.remote_addr, self.remote_port, eviction);
+        self.evict();
+        Some(eviction)
+    }
+
+    fn evict(&mut self) {
+        if let Some(fd) = self.fd.take() {
+            let _ = close(fd);
+        }
+        self.connected = false;
+        self.connecting = false;
+        self.connect_error = None;
+        self.pending_addrs.clear();
+        self.remote_addr.clear();
+        self.remote_port = 0;
+        self.family = AddressFamily::Inet;
+        self.connected_since = None;
+    }
+}
+
 struct ConnectionPool {
     slots: Vec<ConnectionSlot>,
+    stats: PoolStats,
     initialized: bool,
 }
 
@@ -59,20 +289,40 @@ impl ConnectionPool {
                 fd: None,
                 in_use: false,
                 connected: false,
+                connecting: false,
+                connect_error: None,
+                connect_seq: 0,
+                pending_addrs: Vec::new(),
                 remote_addr: String::new(),
                 remote_port: 0,
+                family: AddressFamily::Inet,
+                last_used: Instant::now(),
+                connected_since: None,
                 pool_type,
             });
         }
         Self {
             slots,
+            stats: PoolStats::default(),
             initialized: true,
         }
     }
 
-    fn create_socket() -> Result<RawFd, nix::Error> {
+    /// Evict free sockets past their limits; returns how many were closed
+    fn reap(&mut self, now: Instant, limits: &PoolLimits) -> usize {
+        let mut evicted = 0;
+        for slot in &mut self.slots {
+            if let Some(eviction) = slot.reap(now, limits) {
+                self.stats.record(eviction);
+                evicted += 1;
+            }
+        }
+        evicted
+    }
+
+    fn create_socket(family: AddressFamily) -> Result<RawFd, nix::Error> {
         let fd = socket(
-            AddressFamily::Inet,
+            family,
             SockType::Stream,
             SockFlag::empty(),
             SockProtocol::Tcp,
@@ -92,9 +342,9 @@ impl ConnectionPool {
         #[cfg(not(target_os = "android"))]
         {
             use nix::fcntl::{fcntl, FcntlArg, OFlag};
-            if let Ok(flags) = fcntl(fd, FcntlArg::F_GETFL) {
+            if let Ok(flags) = fcntl(fd.as_raw_fd(), FcntlArg::F_GETFL) {
                 let flags = OFlag::from_bits_truncate(flags);
-                let _ = fcntl(fd, FcntlArg::F_SETFL(flags | OFlag::O_NONBLOCK));
+                let _ = fcntl(fd.as_raw_fd(), FcntlArg::F_SETFL(flags | OFlag::O_NONBLOCK));
             }
         }
 
@@ -117,7 +367,15 @@ impl ConnectionPool {
         }
         let _ = setsockopt(&fd, KeepAlive, &true);
 
-        Ok(fd.as_raw_fd())
+        Ok(fd.into_raw_fd())
+    }
+
+    /// Replace the socket behind `fd` with a new one, keeping the fd number
+    fn reopen_socket(fd: RawFd, family: AddressFamily) -> Result<(), nix::Error> {
+        let new_fd = Self::create_socket(family)?;
+        let result = dup2(new_fd, fd).map(|_| ());
+        let _ = close(new_fd);
+        result
     }
 
     fn find_slot_by_fd(&self, fd: RawFd) -> Option<usize> {
@@ -127,6 +385,58 @@ impl ConnectionPool {
 
 static POOLS: Mutex<[Option<ConnectionPool>; 3]> = Mutex::new([None, None, None]);
 static POOL_SIZE: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(DEFAULT_POOL_SIZE);
+static POOL_LIMITS: Mutex<PoolLimits> = Mutex::new(PoolLimits {
+    idle_timeout: DEFAULT_IDLE_TIMEOUT,
+    max_lifetime: DEFAULT_MAX_LIFETIME,
+});
+static REAPER: Mutex<Option<(Arc<AtomicBool>, JoinHandle<()>)>> = Mutex::new(None);
+
+/// Start the background thread evicting idle and dead sockets
+fn start_reaper() {
+    let mut reaper = REAPER.lock();
+    if reaper.is_some() {
+        return;
+    }
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let stop_flag = stop.clone();
+    let spawned = std::thread::Builder::new()
+        .name("pool-reaper".to_string())
+        .spawn(move || {
+            loop {
+                std::thread::park_timeout(REAP_INTERVAL);
+                if stop_flag.load(Ordering::Acquire) {
+                    break;
+                }
+
+                let limits = *POOL_LIMITS.lock();
+                let now = Instant::now();
+                let mut pools = POOLS.lock();
+                for (i, pool) in pools.iter_mut().enumerate() {
+                    if let Some(pool) = pool {
+                        let evicted = pool.reap(now, &limits);
+                        if evicted > 0 {
+                            debug!("Pool {} evicted {} socket(s)", i, evicted);
+                        }
+                    }
+                }
+            }
+        });
+
+    match spawned {
+        Ok(handle) => *reaper = Some((stop, handle)),
+        Err(e) => error!("Failed to start pool reaper: {}", e),
+    }
+}
+
+/// Stop the reaper thread (must not be called with POOLS locked)
+fn stop_reaper() {
+    if let Some((stop, handle)) = REAPER.lock().take() {
+        stop.store(true, Ordering::Release);
+        handle.thread().unpark();
+        let _ = handle.join();
+    }
+}
 
 /// Initialize connection pool
 #[no_mangle]
@@ -158,7 +468,9 @@ pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nat
         pools[i] = Some(ConnectionPool::new(*size, pool_type));
         debug!("Pool {} initialized with {} slots", i, size);
     }
+    drop(pools);
 
+    start_reaper();
     0
 }
 
@@ -183,28 +495,43 @@ pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nat
         }
     };
 
-    // Find available slot
-    for slot in &mut pool.slots {
-        if !slot.in_use {
-            if slot.fd.is_none() {
-                match ConnectionPool::create_socket() {
-                    Ok(fd) => slot.fd = Some(fd),
-                    Err(e) => {
-                        error!("Failed to create socket: {}", e);
-                        return -1;
-                    }
-                }
-            }
+    // Check free sockets before handing one out
+    let limits = *POOL_LIMITS.lock();
+    let now = Instant::now();
+    pool.reap(now, &limits);
+
+    // Prefer a live connection, then any free slot. Hits and misses are
+    // counted on connect, once it is known whether the connection is kept.
+    let live = |s: &ConnectionSlot| !s.in_use && s.fd.is_some() && (s.connected || s.connecting);
+    let idx = match pool.slots.iter().position(live).or_else(|| pool.slots.iter().position(|s| !s.in_use)) {
+        Some(idx) => idx,
+        None => {
+            pool.stats.misses += 1;
+            error!("Pool {} exhausted", pool_type);
+            return -1;
+        }
+    };
 
-            slot.in_use = true;
-            slot.connected = false;
-            debug!("Got socket from pool {}, fd={:?}", pool_type, slot.fd);
-            return slot.fd.unwrap() as jint;
+    let slot = &mut pool.slots[idx];
+    let live = live(slot);
+
+    if slot.fd.is_none() {
+        match ConnectionPool::create_socket(AddressFamily::Inet) {
+            Ok(fd) => {
+                slot.fd = Some(fd);
+                slot.family = AddressFamily::Inet;
+            }
+            Err(e) => {
+                error!("Failed to create socket: {}", e);
+                return -1;
+            }
         }
     }
 
-    error!("Pool {} exhausted", pool_type);
-    -1
+    slot.in_use = true;
+    slot.last_used = now;
+    debug!("Got socket from pool {}, fd={:?}, live={}", pool_type, slot.fd, live);
+    slot.fd.unwrap() as jint
 }
 
 /// Get slot index for a given file descriptor
@@ -231,62 +558,67 @@ pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nat
     }
 }
 
-/// Connect pooled socket
-#[no_mangle]
-pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nativeConnectPooledSocket(
-    mut env: JNIEnv,
-    _class: JClass,
-    pool_type: jint,
-    slot_index: jint,
-    host: JString,
-    port: jint,
+/// Resolve `host` and start connecting the in-use slot chosen by `find_slot`
+///
+/// `host_str` may be a hostname (resolved over DoH/DoQ, see dns_resolver) or an
+/// IPv4/IPv6 literal. The pool lock is not held while resolving.
+fn connect_slot(
+    pool_type: usize,
+    find_slot: impl Fn(&ConnectionPool) -> Option<usize>,
+    host_str: String,
+    port: u16,
 ) -> jint {
-    if pool_type < 0 || pool_type >= 3 || slot_index < 0 || port < 0 || port > 65535 {
-        error!("Invalid parameters");
-        return -1;
+    {
+        let mut pools = POOLS.lock();
+        let pool = match &mut pools[pool_type] {
+            Some(p) => p,
+            None => {
+                error!("Pool {} not initialized", pool_type);
+                return -1;
+            }
+        };
+        let slot = match find_slot(pool) {
+            Some(idx) if pool.slots[idx].in_use && pool.slots[idx].fd.is_some() => &pool.slots[idx],
+            _ => {
+                error!("Pooled socket not in use or invalid fd");
+                return -1;
+            }
+        };
+
+        // Check if already connected to same host:port
+        if slot.connected && slot.remote_addr == host_str && slot.remote_port == port {
+            debug!("Socket already connected to {}:{}, reusing", host_str, port);
+            pool.stats.hits += 1;
+            return 0;
+        }
     }
 
-    let host_str = match env.get_string(&host) {
-        Ok(s) => s.to_string_lossy().to_string(),
-        Err(_) => {
-            error!("Failed to get host string");
+    let addrs = match crate::dns_resolver::resolve_host(&host_str, port) {
+        Ok(addrs) => addrs,
+        Err(e) => {
+            error!("Failed to resolve {}: {}", host_str, e);
             return -1;
         }
     };
 
-    if host_str.is_empty() || host_str.len() > 255 {
-        error!("Invalid host string length: {}", host_str.len());
-        return -1;
-    }
-
     let mut pools = POOLS.lock();
-    let pool = match &mut pools[pool_type as usize] {
+    let pool = match &mut pools[pool_type] {
         Some(p) => p,
-        None => {
-            error!("Pool {} not initialized", pool_type);
-            return -1;
-        }
+        None => return -1,
     };
-
-    if slot_index as usize >= pool.slots.len() {
-        error!("Invalid slot index: {}", slot_index);
-        return -1;
-    }
-
-    let slot = &mut pool.slots[slot_index as usize];
-    let fd = match slot.fd {
-        Some(f) if slot.in_use => f,
+    // The slot may have been returned while resolving
+    let slot = match find_slot(pool) {
+        Some(idx) if pool.slots[idx].in_use => &mut pool.slots[idx],
         _ => {
-            error!("Slot {} not in use or invalid fd", slot_index);
+            error!("Pooled socket released while resolving {}", host_str);
             return -1;
         }
     };
-
-    // Check if already connected to same host:port
-    if slot.connected && slot.remote_addr == host_str && slot.remote_port == port as u16 {
-        debug!("Socket already connected to {}:{}, reusing", host_str, port);
-        return 0;
-    }
+    let fd = match slot.fd {
+        Some(f) => f,
+        None => return -1,
+    };
+    pool.stats.misses += 1;
 
     // Disconnect if connected to different host
     if slot.connected {
@@ -294,45 +626,62 @@ pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nat
         slot.connected = false;
     }
 
-    // Connect - resolve hostname to IP
-    let ip_addr = match host_str.parse::<Ipv4Addr>() {
-        Ok(ip) => ip,
-        Err(_) => {
-            // Try DNS resolution (simplified - in production use proper DNS)
-            error!("DNS resolution not implemented, use IP address: {}", host_str);
-            return -1;
-        }
-    };
-
-    // Convert to nix::SockaddrIn for connect
-    use nix::sys::socket::SockaddrIn;
-    let octets = ip_addr.octets();
-    let sockaddr = SockaddrIn::new(octets[0], octets[1], octets[2], octets[3], port as u16);
-
-    // connect expects RawFd
-    let borrowed_fd = unsafe { BorrowedFd::borrow_raw(fd) };
-    match connect(borrowed_fd.as_raw_fd(), &sockaddr) {
-        Ok(_) => {
-            slot.connected = true;
-            slot.remote_addr = host_str;
-            slot.remote_port = port as u16;
+    let result = slot.connect_to(fd, &addrs);
+    slot.remote_addr = host_str;
+    slot.remote_port = port;
+    match result {
+        Ok(()) if slot.connected => {
             debug!("Pooled socket connected: {}:{}", slot.remote_addr, port);
             0
         }
-        Err(nix::errno::Errno::EINPROGRESS) => {
-            slot.connected = false;
-            slot.remote_addr = host_str.clone();
-            slot.remote_port = port as u16;
-            debug!("Pooled socket connecting (non-blocking): {}:{}", host_str, port);
+        Ok(()) => {
+            debug!("Pooled socket connecting (non-blocking): {}:{}", slot.remote_addr, port);
             0
         }
         Err(e) => {
-            error!("Connect failed for {}:{}: {}", host_str, port, e);
+            error!("Connect failed for {}:{}: {}", slot.remote_addr, port, e);
             -1
         }
     }
 }
 
+/// Connect pooled socket
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nativeConnectPooledSocket(
+    mut env: JNIEnv,
+    _class: JClass,
+    pool_type: jint,
+    slot_index: jint,
+    host: JString,
+    port: jint,
+) -> jint {
+    if pool_type < 0 || pool_type >= 3 || slot_index < 0 || port < 0 || port > 65535 {
+        error!("Invalid parameters");
+        return -1;
+    }
+
+    let host_str = match env.get_string(&host) {
+        Ok(s) => s.to_string_lossy().to_string(),
+        Err(_) => {
+            error!("Failed to get host string");
+            return -1;
+        }
+    };
+
+    if host_str.is_empty() || host_str.len() > 255 {
+        error!("Invalid host string length: {}", host_str.len());
+        return -1;
+    }
+
+    let slot_index = slot_index as usize;
+    connect_slot(
+        pool_type as usize,
+        |pool| (slot_index < pool.slots.len()).then_some(slot_index),
+        host_str,
+        port as u16,
+    )
+}
+
 /// Connect pooled socket by file descriptor
 #[no_mangle]
 pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nativeConnectPooledSocketByFd(
@@ -352,55 +701,141 @@ pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nat
         Err(_) => return -1,
     };
 
-    let mut pools = POOLS.lock();
-    let pool = match &mut pools[pool_type as usize] {
-        Some(p) => p,
-        None => return -1,
-    };
+    if host_str.is_empty() || host_str.len() > 255 {
+        return -1;
+    }
 
-    let slot_idx = match pool.find_slot_by_fd(fd as RawFd) {
-        Some(idx) => idx,
-        None => return -1,
-    };
+    connect_slot(
+        pool_type as usize,
+        |pool| pool.find_slot_by_fd(fd as RawFd),
+        host_str,
+        port as u16,
+    )
+}
 
-    let slot = &mut pool.slots[slot_idx];
-    if !slot.in_use {
-        return -1;
+/// Wait until a connecting socket is writable, then read its SO_ERROR
+///
+/// Returns None if it is still connecting at `deadline`.
+fn poll_connect(fd: RawFd, deadline: Instant) -> Option<Result<(), Errno>> {
+    loop {
+        let timeout = deadline.saturating_duration_since(Instant::now()).as_millis().min(i32::MAX as u128) as i32;
+        let mut pfd = libc::pollfd { fd, events: libc::POLLOUT, revents: 0 };
+        let ready = unsafe { libc::poll(&mut pfd, 1, timeout) };
+        if ready > 0 {
+            break;
+        }
+        if ready == 0 {
+            return None;
+        }
+        match Errno::last() {
+            Errno::EINTR => continue,
+            e => return Some(Err(e)),
+        }
     }
 
-    if slot.connected && slot.remote_addr == host_str && slot.remote_port == port as u16 {
-        return 0;
+    let borrowed_fd = unsafe { BorrowedFd::borrow_raw(fd) };
+    match getsockopt(&borrowed_fd, SocketError) {
+        Ok(0) => Some(Ok(())),
+        Ok(err) => Some(Err(Errno::from_raw(err))),
+        Err(e) => Some(Err(e)),
     }
+}
 
-    let ip_addr = match host_str.parse::<Ipv4Addr>() {
-        Ok(ip) => ip,
-        Err(_) => return -1,
-    };
+/// Wait up to `timeout` for the in-use slot chosen by `find_slot` to finish connecting
+///
+/// A zero timeout only queries. The pool lock is not held while polling.
+fn wait_connect(
+    pool_type: usize,
+    find_slot: impl Fn(&ConnectionPool) -> Option<usize>,
+    timeout: Duration,
+) -> jint {
+    let deadline = Instant::now() + timeout;
 
-    // Convert to nix::SockaddrIn for connect
-    use nix::sys::socket::SockaddrIn;
-    let octets = ip_addr.octets();
-    let sockaddr = SockaddrIn::new(octets[0], octets[1], octets[2], octets[3], port as u16);
-
-    // Convert RawFd to BorrowedFd for connect
-    let borrowed_fd = unsafe { BorrowedFd::borrow_raw(fd as RawFd) };
-    match connect(borrowed_fd.as_raw_fd(), &sockaddr) {
-        Ok(_) => {
-            slot.connected = true;
-            slot.remote_addr = host_str;
-            slot.remote_port = port as u16;
-            0
+    loop {
+        let (fd, seq) = {
+            let pools = POOLS.lock();
+            let slot = match &pools[pool_type] {
+                Some(pool) => match find_slot(pool) {
+                    Some(idx) if pool.slots[idx].in_use => &pool.slots[idx],
+                    _ => return -(Errno::EBADF as jint),
+                },
+                None => return -(Errno::EBADF as jint),
+            };
+            match (slot.fd, slot.status()) {
+                (Some(fd), ConnectStatus::Connecting) => (fd, slot.connect_seq),
+                (_, status) => return status.to_jint(),
+            }
+        };
+
+        let result = match poll_connect(fd, deadline) {
+            Some(result) => result,
+            None => return ConnectStatus::Connecting.to_jint(),
+        };
+
+        let mut pools = POOLS.lock();
+        let pool = match &mut pools[pool_type] {
+            Some(p) => p,
+            None => return -(Errno::EBADF as jint),
+        };
+        let slot = match find_slot(pool) {
+            Some(idx) => &mut pool.slots[idx],
+            None => return -(Errno::EBADF as jint),
+        };
+        // Released or reconnected while polling: report the current state
+        if !slot.in_use || !slot.connecting || slot.connect_seq != seq {
+            continue;
         }
-        Err(nix::errno::Errno::EINPROGRESS) => {
-            slot.connected = false;
-            slot.remote_addr = host_str;
-            slot.remote_port = port as u16;
-            0
+        match slot.finish_connect(fd, result) {
+            // Falling back to the next resolved address
+            ConnectStatus::Connecting => continue,
+            status => return status.to_jint(),
         }
-        Err(_) => -1,
     }
 }
 
+/// Wait for or query the non-blocking connect of a pooled socket
+/// Returns 0 connected, 1 still connecting after `timeout_ms` (0 = query only),
+/// negative errno on failure
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nativeWaitPooledSocketConnect(
+    _env: JNIEnv,
+    _class: JClass,
+    pool_type: jint,
+    slot_index: jint,
+    timeout_ms: jint,
+) -> jint {
+    if pool_type < 0 || pool_type >= 3 || slot_index < 0 || timeout_ms < 0 {
+        return -(Errno::EINVAL as jint);
+    }
+
+    let slot_index = slot_index as usize;
+    wait_connect(
+        pool_type as usize,
+        |pool| (slot_index < pool.slots.len()).then_some(slot_index),
+        Duration::from_millis(timeout_ms as u64),
+    )
+}
+
+/// Wait for or query the non-blocking connect of a pooled socket by file descriptor
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nativeWaitPooledSocketConnectByFd(
+    _env: JNIEnv,
+    _class: JClass,
+    pool_type: jint,
+    fd: jint,
+    timeout_ms: jint,
+) -> jint {
+    if pool_type < 0 || pool_type >= 3 || fd < 0 || timeout_ms < 0 {
+        return -(Errno::EINVAL as jint);
+    }
+
+    wait_connect(
+        pool_type as usize,
+        |pool| pool.find_slot_by_fd(fd as RawFd),
+        Duration::from_millis(timeout_ms as u64),
+    )
+}
+
 /// Return socket to pool
 #[no_mangle]
 pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nativeReturnPooledSocket(
@@ -422,6 +857,7 @@ pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nat
     if (slot_index as usize) < pool.slots.len() {
         let slot = &mut pool.slots[slot_index as usize];
         slot.in_use = false;
+        slot.last_used = Instant::now();
         debug!("Returned socket to pool {}, slot {}", pool_type, slot_index);
     }
 }
@@ -445,17 +881,85 @@ pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nat
     };
 
     if let Some(slot_idx) = pool.find_slot_by_fd(fd as RawFd) {
-        pool.slots[slot_idx].in_use = false;
+        let slot = &mut pool.slots[slot_idx];
+        slot.in_use = false;
+        slot.last_used = Instant::now();
         debug!("Returned socket to pool {} by fd {}", pool_type, fd);
     }
 }
 
+/// Set idle timeout and max connection lifetime for free pooled sockets (0 = no limit)
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nativeSetConnectionPoolLimits(
+    _env: JNIEnv,
+    _class: JClass,
+    idle_timeout_ms: jlong,
+    max_lifetime_ms: jlong,
+) -> jint {
+    if idle_timeout_ms < 0 || max_lifetime_ms < 0 {
+        return -1;
+    }
+
+    let limit = |ms: jlong| if ms == 0 { Duration::MAX } else { Duration::from_millis(ms as u64) };
+    *POOL_LIMITS.lock() = PoolLimits {
+        idle_timeout: limit(idle_timeout_ms),
+        max_lifetime: limit(max_lifetime_ms),
+    };
+    debug!("Pool limits set: idle {} ms, lifetime {} ms", idle_timeout_ms, max_lifetime_ms);
+    0
+}
+
+/// Get pool statistics
+/// Returns [hits, misses, evictedIdle, evictedLifetime, evictedUnhealthy, inUse, idleConnected]
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nativeGetConnectionPoolStats(
+    env: JNIEnv,
+    _class: JClass,
+    pool_type: jint,
+) -> jlongArray {
+    if pool_type < 0 || pool_type >= 3 {
+        return std::ptr::null_mut();
+    }
+
+    let values = {
+        let pools = POOLS.lock();
+        let pool = match &pools[pool_type as usize] {
+            Some(p) => p,
+            None => return std::ptr::null_mut(),
+        };
+        let in_use = pool.slots.iter().filter(|s| s.in_use).count();
+        let idle_connected = pool.slots.iter().filter(|s| !s.in_use && s.connected).count();
+        let stats = pool.stats;
+        [
+            stats.hits as jlong,
+            stats.misses as jlong,
+            stats.evicted_idle as jlong,
+            stats.evicted_lifetime as jlong,
+            stats.evicted_unhealthy as jlong,
+            in_use as jlong,
+            idle_connected as jlong,
+        ]
+    };
+
+    match env.new_long_array(values.len() as i32) {
+        Ok(result) => {
+            if env.set_long_array_region(&result, 0, &values).is_err() {
+                return std::ptr::null_mut();
+            }
+            result.into_raw()
+        }
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
 /// Destroy connection pool
 #[no_mangle]
 pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nativeDestroyConnectionPool(
     _env: JNIEnv,
     _class: JClass,
 ) {
+    stop_reaper();
+
     let mut pools = POOLS.lock();
     for (i, pool) in pools.iter_mut().enumerate() {
         if let Some(p) = pool.take() {
@@ -468,3 +972,274 @@ pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nat
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    // Tests that install a pool in POOLS take this first
+    static GLOBAL_POOLS: Mutex<()> = Mutex::new(());
+
+    fn fresh_slot() -> (ConnectionSlot, RawFd) {
+        let mut slot = ConnectionPool::new(1, PoolType::Reserve).slots.remove(0);
+        let fd = ConnectionPool::create_socket(AddressFamily::Inet).unwrap();
+        slot.fd = Some(fd);
+        (slot, fd)
+    }
+
+    /// A loopback port nothing listens on
+    fn refused_addr() -> SocketAddr {
+        TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap()
+    }
+
+    /// Start connecting and record the target, as connect_slot does
+    fn start_connect(slot: &mut ConnectionSlot, fd: RawFd, addrs: &[SocketAddr]) -> Result<(), nix::Error> {
+        let result = slot.connect_to(fd, addrs);
+        slot.remote_addr = addrs[0].ip().to_string();
+        slot.remote_port = addrs[0].port();
+        result
+    }
+
+    /// Drive the slot's connect like wait_connect does, falling back as needed
+    fn settle(slot: &mut ConnectionSlot, fd: RawFd) -> ConnectStatus {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while slot.status() == ConnectStatus::Connecting {
+            let result = poll_connect(fd, deadline).expect("connect timed out");
+            slot.finish_connect(fd, result);
+        }
+        slot.status()
+    }
+
+    #[test]
+    fn connects_to_a_loopback_listener() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (mut slot, fd) = fresh_slot();
+
+        start_connect(&mut slot, fd, &[listener.local_addr().unwrap()]).unwrap();
+        assert_eq!(settle(&mut slot, fd), ConnectStatus::Connected);
+        assert_eq!(slot.connect_seq, 1);
+        assert!(slot.connected_since.is_some());
+
+        let (_peer, from) = listener.accept().unwrap();
+        assert_eq!(Some(from.port()), nix::sys::socket::getsockname::<SockaddrIn>(fd).ok().map(|a| a.port()));
+        slot.evict();
+    }
+
+    #[test]
+    fn refused_address_falls_back_to_the_next() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let live = listener.local_addr().unwrap();
+        let (mut slot, fd) = fresh_slot();
+
+        start_connect(&mut slot, fd, &[refused_addr(), live]).unwrap();
+        assert_eq!(settle(&mut slot, fd), ConnectStatus::Connected);
+        assert!(slot.pending_addrs.is_empty());
+        // The fallback reopened the socket under the same fd number
+        assert_eq!(slot.fd, Some(fd));
+        listener.accept().unwrap();
+        slot.evict();
+    }
+
+    #[test]
+    fn so_error_failure_tries_the_pending_addresses() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let live = listener.local_addr().unwrap();
+        let (mut slot, fd) = fresh_slot();
+        slot.remote_addr = "127.0.0.1".into();
+        slot.remote_port = live.port();
+
+        // As if the first address had returned EINPROGRESS, then ECONNREFUSED
+        slot.connecting = true;
+        slot.pending_addrs = vec![live];
+        let status = slot.finish_connect(fd, Err(Errno::ECONNREFUSED));
+        assert_ne!(status, ConnectStatus::Failed(Errno::ECONNREFUSED));
+        assert_eq!(settle(&mut slot, fd), ConnectStatus::Connected);
+        listener.accept().unwrap();
+        slot.evict();
+    }
+
+    #[test]
+    fn refused_connect_reports_the_error() {
+        let (mut slot, fd) = fresh_slot();
+
+        match start_connect(&mut slot, fd, &[refused_addr()]) {
+            // Loopback may refuse at once or through SO_ERROR
+            Ok(()) => assert_eq!(settle(&mut slot, fd), ConnectStatus::Failed(Errno::ECONNREFUSED)),
+            Err(e) => assert_eq!(e, Errno::ECONNREFUSED),
+        }
+        assert_eq!(slot.status(), ConnectStatus::Failed(Errno::ECONNREFUSED));
+        assert_eq!(slot.status().to_jint(), -(Errno::ECONNREFUSED as jint));
+        slot.evict();
+    }
+
+    /// An idle slot connected to a loopback peer
+    fn connected_slot(listener: &TcpListener) -> (ConnectionSlot, RawFd, std::net::TcpStream) {
+        let (mut slot, fd) = fresh_slot();
+        start_connect(&mut slot, fd, &[listener.local_addr().unwrap()]).unwrap();
+        assert_eq!(settle(&mut slot, fd), ConnectStatus::Connected);
+        let (peer, _) = listener.accept().unwrap();
+        (slot, fd, peer)
+    }
+
+    const LIMITS: PoolLimits = PoolLimits {
+        idle_timeout: Duration::from_secs(60),
+        max_lifetime: Duration::from_secs(600),
+    };
+
+    #[test]
+    fn reap_evicts_idle_and_expired_sockets() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let now = Instant::now();
+
+        let (mut slot, _fd, _peer) = connected_slot(&listener);
+        assert_eq!(slot.reap(now, &LIMITS), None);
+        assert_eq!(slot.reap(now + Duration::from_secs(61), &LIMITS), Some(Eviction::Idle));
+        assert!(slot.fd.is_none() && !slot.connected && slot.remote_addr.is_empty());
+
+        // Lifetime counts from the connect even if the socket was just used
+        let (mut slot, _fd, _peer) = connected_slot(&listener);
+        slot.last_used = now + Duration::from_secs(600);
+        assert_eq!(slot.reap(now + Duration::from_secs(601), &LIMITS), Some(Eviction::Lifetime));
+
+        // Leased sockets are never evicted
+        let (mut slot, _fd, _peer) = connected_slot(&listener);
+        slot.in_use = true;
+        assert_eq!(slot.reap(now + Duration::from_secs(601), &LIMITS), None);
+        slot.evict();
+    }
+
+    #[test]
+    fn reap_evicts_half_closed_and_dirty_sockets() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+        let (mut slot, fd, peer) = connected_slot(&listener);
+        assert!(is_healthy(fd));
+        drop(peer);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while is_healthy(fd) {
+            assert!(Instant::now() < deadline, "FIN never arrived");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(slot.reap(Instant::now(), &LIMITS), Some(Eviction::Unhealthy));
+
+        // Unread data means a reply nobody will read
+        let (mut slot, fd, mut peer) = connected_slot(&listener);
+        std::io::Write::write_all(&mut peer, b"stray").unwrap();
+        while is_healthy(fd) {
+            assert!(Instant::now() < deadline, "data never arrived");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(slot.reap(Instant::now(), &LIMITS), Some(Eviction::Unhealthy));
+
+        // A failed connect is picked up and evicted too
+        let (mut slot, fd) = fresh_slot();
+        let _ = start_connect(&mut slot, fd, &[refused_addr()]);
+        assert_eq!(slot.reap(Instant::now() + Duration::from_secs(1), &LIMITS), Some(Eviction::Unhealthy));
+    }
+
+    #[test]
+    fn pool_reap_counts_evictions() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut pool = ConnectionPool::new(0, PoolType::Reserve);
+        let mut peers = Vec::new();
+        for _ in 0..3 {
+            let (slot, _fd, peer) = connected_slot(&listener);
+            pool.slots.push(slot);
+            peers.push(peer);
+        }
+        // One never-connected slot stays
+        pool.slots.push(fresh_slot().0);
+        drop(peers.remove(0));
+        let closed = pool.slots[0].fd.unwrap();
+        while is_healthy(closed) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let limits = PoolLimits { idle_timeout: Duration::from_secs(60), max_lifetime: Duration::MAX };
+        assert_eq!(pool.reap(Instant::now(), &limits), 1);
+        assert_eq!(pool.reap(Instant::now() + Duration::from_secs(61), &limits), 2);
+        assert_eq!((pool.stats.evicted_unhealthy, pool.stats.evicted_idle, pool.stats.evicted_lifetime), (1, 2, 0));
+        assert!(pool.slots[3].fd.is_some());
+        pool.slots[3].evict();
+    }
+
+    /// Install a one-slot Reserve pool for the duration of `f`
+    fn with_global_pool(f: impl FnOnce()) {
+        let _guard = GLOBAL_POOLS.lock();
+        POOLS.lock()[PoolType::Reserve as usize] = Some(ConnectionPool::new(1, PoolType::Reserve));
+        f();
+        if let Some(pool) = POOLS.lock()[PoolType::Reserve as usize].take() {
+            for mut slot in pool.slots {
+                slot.evict();
+            }
+        }
+    }
+
+    fn lease_slot() -> RawFd {
+        let mut pools = POOLS.lock();
+        let slot = &mut pools[PoolType::Reserve as usize].as_mut().unwrap().slots[0];
+        if slot.fd.is_none() {
+            slot.fd = Some(ConnectionPool::create_socket(AddressFamily::Inet).unwrap());
+        }
+        slot.in_use = true;
+        slot.fd.unwrap()
+    }
+
+    #[test]
+    fn wait_connect_falls_back_and_reports_the_outcome() {
+        with_global_pool(|| {
+            let pool = PoolType::Reserve as usize;
+            let first = |_: &ConnectionPool| Some(0);
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let live = listener.local_addr().unwrap();
+            let fd = lease_slot();
+
+            {
+                let mut pools = POOLS.lock();
+                let slot = &mut pools[pool].as_mut().unwrap().slots[0];
+                start_connect(slot, fd, &[refused_addr(), live]).unwrap();
+            }
+            assert_eq!(wait_connect(pool, first, Duration::from_secs(5)), 0);
+            listener.accept().unwrap();
+
+            // A refused address alone fails with its errno
+            let refused = refused_addr();
+            assert_eq!(connect_slot(pool, first, refused.ip().to_string(), refused.port()), 0);
+            assert_eq!(wait_connect(pool, first, Duration::from_secs(5)), -(Errno::ECONNREFUSED as jint));
+        });
+    }
+
+    #[test]
+    fn only_kept_connections_count_as_hits() {
+        with_global_pool(|| {
+            let pool = PoolType::Reserve as usize;
+            let first = |_: &ConnectionPool| Some(0);
+            let stats = || {
+                let pools = POOLS.lock();
+                let stats = pools[pool].as_ref().unwrap().stats;
+                (stats.hits, stats.misses)
+            };
+            let a = TcpListener::bind("127.0.0.1:0").unwrap();
+            let b = TcpListener::bind("127.0.0.1:0").unwrap();
+            let (a_port, b_port) = (a.local_addr().unwrap().port(), b.local_addr().unwrap().port());
+
+            lease_slot();
+            assert_eq!(connect_slot(pool, first, "127.0.0.1".into(), a_port), 0);
+            assert_eq!(wait_connect(pool, first, Duration::from_secs(5)), 0);
+            assert_eq!(stats(), (0, 1));
+
+            // Leased again for the same host: the connection is kept
+            lease_slot();
+            assert_eq!(connect_slot(pool, first, "127.0.0.1".into(), a_port), 0);
+            assert_eq!(stats(), (1, 1));
+
+            // A live slot reconnected to another host is a miss
+            lease_slot();
+            assert_eq!(connect_slot(pool, first, "127.0.0.1".into(), b_port), 0);
+            assert_eq!(wait_connect(pool, first, Duration::from_secs(5)), 0);
+            assert_eq!(stats(), (1, 2));
+            b.accept().unwrap();
+        });
+    }
+}
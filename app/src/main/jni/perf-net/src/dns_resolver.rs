@@ -0,0 +1,1032 @@
+/*
+ * DNS Resolver (Rust Implementation)
+ * DNS-over-HTTPS / DNS-over-QUIC upstreams with an LRU answer cache
+ *
+ * Features:
+ * - DoH (RFC 8484, HTTP/1.1 POST over rustls) with connection reuse
+ * - DoQ (RFC 9250, over quinn) with connection reuse
+ * - A/AAAA lookups, answers ordered IPv6/IPv4 alternating
+ * - LRU cache honoring record TTLs, negative answers cached briefly
+ * - System resolver fallback when no upstream is configured
+ */
+
+use hashbrown::HashMap;
+use jni::JNIEnv;
+use jni::objects::{JClass, JObject, JObjectArray, JString};
+use jni::sys::{jint, jobjectArray};
+use log::{debug, error, info, warn};
+use parking_lot::{Mutex, RwLock};
+use quinn::crypto::rustls::QuicClientConfig;
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig as RustlsClientConfig, RootCertStore};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::runtime::Runtime;
+use crate::tls_stream::TlsStream;
+
+const DOH_PORT: u16 = 443;
+const DOQ_PORT: u16 = 853;
+const DOH_PATH: &str = "/dns-query";
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+const CACHE_CAPACITY: usize = 512;
+const MIN_TTL_SECS: u32 = 10;
+const MAX_TTL_SECS: u32 = 3600;
+const NEGATIVE_TTL_SECS: u32 = 30;
+// The system resolver reports no TTLs
+const SYSTEM_TTL_SECS: u32 = 60;
+const MAX_MESSAGE_SIZE: usize = 65535;
+const MAX_HTTP_HEADER_SIZE: usize = 8192;
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Protocol {
+    Https,
+    Quic,
+}
+
+/// One configured DoH or DoQ server
+struct Upstream {
+    protocol: Protocol,
+    host: String,
+    port: u16,
+    path: String,
+    // Bootstrap address when `host` is a name (resolved by the system once)
+    addr: Mutex<Option<SocketAddr>>,
+    https: tokio::sync::Mutex<Option<TlsStream>>,
+    quic: tokio::sync::Mutex<Option<(quinn::Endpoint, quinn::Connection)>>,
+}
+
+impl Upstream {
+    /// Parse `https://host[:port][/path]` (DoH) or `quic://host[:port]` (DoQ)
+    fn parse(url: &str) -> Option<Self> {
+        let url = url.trim();
+        let (protocol, rest) = if let Some(rest) = url.strip_prefix("https://") {
+            (Protocol::Https, rest)
+        } else if let Some(rest) = url.strip_prefix("quic://").or_else(|| url.strip_prefix("doq://")) {
+            (Protocol::Quic, rest)
+        } else {
+            return None;
+        };
+
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, ""),
+        };
+        let default_port = match protocol {
+            Protocol::Https => DOH_PORT,
+            Protocol::Quic => DOQ_PORT,
+        };
+
+        let (host, port) = if let Some(rest) = authority.strip_prefix('[') {
+            let (host, tail) = rest.split_once(']')?;
+            let port = match tail.strip_prefix(':') {
+                Some(port) => port.parse().ok()?,
+                None if tail.is_empty() => default_port,
+                None => return None,
+            };
+            (host, port)
+        } else {
+            match authority.rsplit_once(':') {
+                Some((host, port)) => (host, port.parse().ok()?),
+                None => (authority, default_port),
+            }
+        };
+        if host.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            protocol,
+            host: host.to_string(),
+            port,
+            path: if path.is_empty() { DOH_PATH.to_string() } else { path.to_string() },
+            addr: Mutex::new(None),
+            https: tokio::sync::Mutex::new(None),
+            quic: tokio::sync::Mutex::new(None),
+        })
+    }
+
+    async fn address(&self) -> io::Result<SocketAddr> {
+        if let Ok(ip) = self.host.parse::<IpAddr>() {
+            return Ok(SocketAddr::new(ip, self.port));
+        }
+        if let Some(addr) = *self.addr.lock() {
+            return Ok(addr);
+        }
+
+        let addr = tokio::net::lookup_host((self.host.as_str(), self.port))
+            .await?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("No address for upstream {}", self.host)))?;
+        *self.addr.lock() = Some(addr);
+        Ok(addr)
+    }
+}
+
+// One DNS response: addresses of the queried type and the smallest TTL
+struct Answer {
+    addrs: Vec<IpAddr>,
+    ttl: u32,
+}
+
+struct CacheEntry {
+    // Empty: negative answer
+    addrs: Vec<IpAddr>,
+    expires: Instant,
+    last_used: u64,
+}
+
+/// Answer cache evicting the least recently used name when full
+struct LruCache {
+    entries: HashMap<String, CacheEntry>,
+    capacity: usize,
+    tick: u64,
+}
+
+impl LruCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            capacity,
+            tick: 0,
+        }
+    }
+
+    fn get(&mut self, name: &str) -> Option<Vec<IpAddr>> {
+        self.tick += 1;
+        let entry = self.entries.get_mut(name)?;
+        if Instant::now() >= entry.expires {
+            self.entries.remove(name);
+            return None;
+        }
+        entry.last_used = self.tick;
+        Some(entry.addrs.clone())
+    }
+
+    fn insert(&mut self, name: String, addrs: Vec<IpAddr>, ttl: Duration) {
+        self.tick += 1;
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&name) {
+            let now = Instant::now();
+            self.entries.retain(|_, entry| entry.expires > now);
+            if self.entries.len() >= self.capacity {
+                // Linear scan; the cache is small
+                let oldest = self.entries.iter().min_by_key(|(_, entry)| entry.last_used).map(|(name, _)| name.clone());
+                if let Some(oldest) = oldest {
+                    self.entries.remove(&oldest);
+                }
+            }
+        }
+        self.entries.insert(name, CacheEntry {
+            addrs,
+            expires: Instant::now() + ttl,
+            last_used: self.tick,
+        });
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// Async A/AAAA resolver over DoH and DoQ upstreams
+pub struct DnsResolver {
+    upstreams: RwLock<Vec<Arc<Upstream>>>,
+    cache: Mutex<LruCache>,
+    https_config: Arc<RustlsClientConfig>,
+    quic_config: quinn::ClientConfig,
+}
+
+impl DnsResolver {
+    /// Resolver trusting `roots` for upstream certificates, with no upstreams
+    pub fn new(roots: RootCertStore) -> io::Result<Self> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+
+        let mut https = RustlsClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(io::Error::other)?
+            .with_root_certificates(roots.clone())
+            .with_no_client_auth();
+        https.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+        let mut quic = RustlsClientConfig::builder_with_provider(provider)
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .map_err(io::Error::other)?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        quic.alpn_protocols = vec![b"doq".to_vec()];
+        let quic = QuicClientConfig::try_from(Arc::new(quic)).map_err(io::Error::other)?;
+
+        Ok(Self {
+            upstreams: RwLock::new(Vec::new()),
+            cache: Mutex::new(LruCache::new(CACHE_CAPACITY)),
+            https_config: Arc::new(https),
+            quic_config: quinn::ClientConfig::new(Arc::new(quic)),
+        })
+    }
+
+    /// Replace the upstream list (tried in order); empty uses the system resolver
+    ///
+    /// Returns the number of upstreams, or None (keeping the old list) if any
+    /// URL is invalid.
+    pub fn set_upstreams(&self, urls: &[String]) -> Option<usize> {
+        let upstreams = urls
+            .iter()
+            .map(|url| Upstream::parse(url).map(Arc::new))
+            .collect::<Option<Vec<_>>>()?;
+        let count = upstreams.len();
+        *self.upstreams.write() = upstreams;
+        info!("DNS upstreams set: {:?}", urls);
+        Some(count)
+    }
+
+    pub fn clear_cache(&self) {
+        self.cache.lock().clear();
+    }
+
+    /// Addresses for `host` in connection-attempt order
+    ///
+    /// IP literals (optionally bracketed) are returned as is.
+    pub async fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        let literal = host.trim_start_matches('[').trim_end_matches(']');
+        if let Ok(ip) = literal.parse::<IpAddr>() {
+            return Ok(vec![SocketAddr::new(ip, port)]);
+        }
+
+        let name = host.trim_end_matches('.').to_ascii_lowercase();
+        let cached = self.cache.lock().get(&name);
+        let addrs = match cached {
+            Some(addrs) => {
+                debug!("{} resolved from cache ({} addresses)", name, addrs.len());
+                addrs
+            }
+            None => self.lookup(&name).await?,
+        };
+
+        if addrs.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("No addresses for {}", name)));
+        }
+        Ok(addrs.into_iter().map(|ip| SocketAddr::new(ip, port)).collect())
+    }
+
+    async fn lookup(&self, name: &str) -> io::Result<Vec<IpAddr>> {
+        let upstreams = self.upstreams.read().clone();
+        if upstreams.is_empty() {
+            let addrs = Self::lookup_system(name).await?;
+            self.cache.lock().insert(name.to_string(), addrs.clone(), Duration::from_secs(SYSTEM_TTL_SECS as u64));
+            return Ok(addrs);
+        }
+
+        let mut last_error = None;
+        for upstream in &upstreams {
+            match tokio::time::timeout(QUERY_TIMEOUT, self.query_upstream(upstream, name)).await {
+                Ok(Ok((addrs, ttl))) => {
+                    debug!("{} resolved via {} ({} addresses, ttl {}s)", name, upstream.host, addrs.len(), ttl);
+                    self.cache.lock().insert(name.to_string(), addrs.clone(), Duration::from_secs(ttl as u64));
+                    return Ok(addrs);
+                }
+                Ok(Err(e)) => {
+                    warn!("DNS query for {} via {} failed: {}", name, upstream.host, e);
+                    last_error = Some(e);
+                }
+                Err(_) => {
+                    warn!("DNS query for {} via {} timed out", name, upstream.host);
+                    last_error = Some(io::Error::new(io::ErrorKind::TimedOut, "DNS query timed out"));
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| io::Error::other("No DNS upstream")))
+    }
+
+    async fn lookup_system(name: &str) -> io::Result<Vec<IpAddr>> {
+        let mut v6 = Vec::new();
+        let mut v4 = Vec::new();
+        for addr in tokio::net::lookup_host((name, 0)).await? {
+            let family = if addr.is_ipv6() { &mut v6 } else { &mut v4 };
+            if !family.contains(&addr.ip()) {
+                family.push(addr.ip());
+            }
+        }
+        Ok(interleave(v6, v4))
+    }
+
+    // A and AAAA from one upstream, with the TTL to cache them for
+    async fn query_upstream(&self, upstream: &Upstream, name: &str) -> io::Result<(Vec<IpAddr>, u32)> {
+        let (v4, v6) = tokio::try_join!(
+            self.query(upstream, name, TYPE_A),
+            self.query(upstream, name, TYPE_AAAA),
+        )?;
+
+        let ttl = [&v4, &v6]
+            .iter()
+            .filter(|answer| !answer.addrs.is_empty())
+            .map(|answer| answer.ttl)
+            .min()
+            .map_or(NEGATIVE_TTL_SECS, |ttl| ttl.clamp(MIN_TTL_SECS, MAX_TTL_SECS));
+        Ok((interleave(v6.addrs, v4.addrs), ttl))
+    }
+
+    async fn query(&self, upstream: &Upstream, name: &str, qtype: u16) -> io::Result<Answer> {
+        let query = encode_query(name, qtype)?;
+        let response = match upstream.protocol {
+            Protocol::Https => self.exchange_https(upstream, &query).await?,
+            Protocol::Quic => self.exchange_quic(upstream, &query).await?,
+        };
+        decode_answer(&response, qtype)
+    }
+
+    async fn exchange_https(&self, upstream: &Upstream, query: &[u8]) -> io::Result<Vec<u8>> {
+        let mut conn = upstream.https.lock().await;
+
+        if let Some(stream) = conn.as_mut() {
+            match http_post(stream, upstream, query).await {
+                Ok((response, keep_alive)) => {
+                    if !keep_alive {
+                        *conn = None;
+                    }
+                    return Ok(response);
+                }
+                // Most likely closed by the server while idle
+                Err(e) => {
+                    debug!("Reused DoH connection to {} failed: {}", upstream.host, e);
+                    *conn = None;
+                }
+            }
+        }
+
+        let server_name = ServerName::try_from(upstream.host.clone())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let mut stream = TlsStream::connect(upstream.address().await?, server_name, self.https_config.clone()).await?;
+        let (response, keep_alive) = http_post(&mut stream, upstream, query).await?;
+        if keep_alive {
+            *conn = Some(stream);
+        }
+        Ok(response)
+    }
+
+    async fn exchange_quic(&self, upstream: &Upstream, query: &[u8]) -> io::Result<Vec<u8>> {
+        let conn = self.quic_connection(upstream).await?;
+        let (mut send, mut recv) = conn.open_bi().await?;
+
+        // One query per stream, with a 2-byte length prefix
+        let mut framed = Vec::with_capacity(query.len() + 2);
+        framed.extend_from_slice(&(query.len() as u16).to_be_bytes());
+        framed.extend_from_slice(query);
+        send.write_all(&framed).await?;
+        send.finish().map_err(io::Error::other)?;
+
+        let response = recv.read_to_end(MAX_MESSAGE_SIZE + 2).await.map_err(io::Error::other)?;
+        if response.len() < 2 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Truncated DoQ response"));
+        }
+        let len = u16::from_be_bytes([response[0], response[1]]) as usize;
+        response
+            .get(2..2 + len)
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Truncated DoQ response"))
+    }
+
+    async fn quic_connection(&self, upstream: &Upstream) -> io::Result<quinn::Connection> {
+        // Held while connecting so concurrent queries share one connection
+        let mut quic = upstream.quic.lock().await;
+        if let Some((_, conn)) = quic.as_ref() {
+            if conn.close_reason().is_none() {
+                return Ok(conn.clone());
+            }
+        }
+
+        let addr = upstream.address().await?;
+        let bind: SocketAddr = if addr.is_ipv6() { "[::]:0".parse().unwrap() } else { "0.0.0.0:0".parse().unwrap() };
+        let endpoint = quinn::Endpoint::client(bind)?;
+        let conn = endpoint
+            .connect_with(self.quic_config.clone(), addr, &upstream.host)
+            .map_err(io::Error::other)?
+            .await?;
+
+        debug!("DoQ connection to {} ({}) established", upstream.host, addr);
+        *quic = Some((endpoint, conn.clone()));
+        Ok(conn)
+    }
+}
+
+/// Alternate IPv6 and IPv4 addresses, starting with IPv6
+fn interleave(v6: Vec<IpAddr>, v4: Vec<IpAddr>) -> Vec<IpAddr> {
+    let mut addrs = Vec::with_capacity(v6.len() + v4.len());
+    let (mut v6, mut v4) = (v6.into_iter(), v4.into_iter());
+    loop {
+        match (v6.next(), v4.next()) {
+            (None, None) => break,
+            (a, b) => addrs.extend(a.into_iter().chain(b)),
+        }
+    }
+    addrs
+}
+
+// POST one message; returns the response body and whether the connection stays open
+async fn http_post(stream: &mut TlsStream, upstream: &Upstream, query: &[u8]) -> io::Result<(Vec<u8>, bool)> {
+    let mut request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/dns-message\r\n\
+         Accept: application/dns-message\r\nContent-Length: {}\r\n\r\n",
+        upstream.path, upstream.host, query.len()
+    )
+    .into_bytes();
+    request.extend_from_slice(query);
+    stream.write_all(&request).await?;
+
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+        if buf.len() > MAX_HTTP_HEADER_SIZE {
+            return Err(invalid("HTTP response header too large"));
+        }
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Connection closed"));
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+
+    let head = std::str::from_utf8(&buf[..header_end]).map_err(|_| invalid("Invalid HTTP response header"))?;
+    let mut lines = head.split("\r\n");
+    let status: u16 = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| invalid("Invalid HTTP status line"))?;
+
+    let mut content_length = None;
+    let mut keep_alive = true;
+    for line in lines {
+        if let Some((name, value)) = line.split_once(':') {
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.parse::<usize>().ok();
+            } else if name.eq_ignore_ascii_case("connection") && value.eq_ignore_ascii_case("close") {
+                keep_alive = false;
+            } else if name.eq_ignore_ascii_case("transfer-encoding") {
+                return Err(invalid("Chunked DoH responses are not supported"));
+            }
+        }
+    }
+
+    let length = content_length.ok_or_else(|| invalid("DoH response without Content-Length"))?;
+    if length > MAX_MESSAGE_SIZE {
+        return Err(invalid("DoH response too large"));
+    }
+    let mut body = buf.split_off(header_end);
+    while body.len() < length {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Connection closed"));
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+    body.truncate(length);
+
+    if status != 200 {
+        return Err(io::Error::other(format!("DoH server returned HTTP {}", status)));
+    }
+    Ok((body, keep_alive))
+}
+
+// Recursive query for `name`; ID 0 as DoH and DoQ recommend
+fn encode_query(name: &str, qtype: u16) -> io::Result<Vec<u8>> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid DNS name: {}", name));
+    if name.is_empty() || name.len() > 253 {
+        return Err(invalid());
+    }
+
+    let mut msg = Vec::with_capacity(name.len() + 18);
+    // ID, flags (RD), QDCOUNT=1, ANCOUNT, NSCOUNT, ARCOUNT
+    msg.extend_from_slice(&[0, 0, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(invalid());
+        }
+        msg.push(label.len() as u8);
+        msg.extend_from_slice(label.as_bytes());
+    }
+    msg.push(0);
+    msg.extend_from_slice(&qtype.to_be_bytes());
+    msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(msg)
+}
+
+// Records of `qtype` in the answer section (CNAME chains are flattened by the server)
+fn decode_answer(msg: &[u8], qtype: u16) -> io::Result<Answer> {
+    let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData, format!("Malformed DNS response: {}", what));
+    let u16_at = |pos: usize| -> io::Result<u16> {
+        msg.get(pos..pos + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .ok_or_else(|| invalid("truncated"))
+    };
+
+    if msg.len() < 12 {
+        return Err(invalid("short header"));
+    }
+    let flags = u16_at(2)?;
+    if flags & 0x8000 == 0 {
+        return Err(invalid("not a response"));
+    }
+    match flags & 0x000f {
+        0 => {}
+        // NXDOMAIN
+        3 => return Ok(Answer { addrs: Vec::new(), ttl: NEGATIVE_TTL_SECS }),
+        rcode => return Err(io::Error::other(format!("DNS server returned rcode {}", rcode))),
+    }
+
+    let questions = u16_at(4)?;
+    let answers = u16_at(6)?;
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = skip_name(msg, pos).ok_or_else(|| invalid("question name"))? + 4;
+    }
+
+    let mut addrs = Vec::new();
+    let mut ttl = u32::MAX;
+    for _ in 0..answers {
+        pos = skip_name(msg, pos).ok_or_else(|| invalid("answer name"))?;
+        let rtype = u16_at(pos)?;
+        let class = u16_at(pos + 2)?;
+        let record_ttl = msg
+            .get(pos + 4..pos + 8)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or_else(|| invalid("truncated"))?;
+        let len = u16_at(pos + 8)? as usize;
+        let data = msg.get(pos + 10..pos + 10 + len).ok_or_else(|| invalid("truncated record"))?;
+        pos += 10 + len;
+
+        if rtype != qtype || class != CLASS_IN {
+            continue;
+        }
+        let addr = match (rtype, len) {
+            (TYPE_A, 4) => IpAddr::from([data[0], data[1], data[2], data[3]]),
+            (TYPE_AAAA, 16) => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(data);
+                IpAddr::from(octets)
+            }
+            _ => return Err(invalid("bad address length")),
+        };
+        if !addrs.contains(&addr) {
+            addrs.push(addr);
+        }
+        ttl = ttl.min(record_ttl);
+    }
+
+    Ok(Answer { addrs, ttl })
+}
+
+// Position after the (possibly compressed) name at `pos`
+fn skip_name(msg: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *msg.get(pos)? as usize;
+        match len {
+            0 => return Some(pos + 1),
+            l if l & 0xc0 == 0xc0 => return msg.get(pos + 1).map(|_| pos + 2),
+            l if l <= 63 => pos += l + 1,
+            _ => return None,
+        }
+    }
+}
+
+/// Runtime for DNS lookups and other background network work
+pub(crate) fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .thread_name("perf-dns")
+            .enable_all()
+            .build()
+            .expect("Failed to create DNS runtime")
+    })
+}
+
+/// The resolver shared by the connection pool (WebPKI roots)
+pub fn dns_resolver() -> &'static DnsResolver {
+    static RESOLVER: OnceLock<DnsResolver> = OnceLock::new();
+    RESOLVER.get_or_init(|| {
+        let mut roots = RootCertStore::empty();
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        DnsResolver::new(roots).expect("Failed to create DNS resolver")
+    })
+}
+
+/// Resolve `host` on the DNS runtime, blocking the calling (non-runtime) thread
+pub fn resolve_host(host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+    runtime().block_on(dns_resolver().resolve(host, port))
+}
+
+/// Set DoH/DoQ upstreams (`https://host[:port][/path]`, `quic://host[:port]`)
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nativeSetDnsUpstreams(
+    mut env: JNIEnv,
+    _class: JClass,
+    upstreams: JObjectArray,
+) -> jint {
+    let count = match env.get_array_length(&upstreams) {
+        Ok(n) => n,
+        Err(_) => return -1,
+    };
+
+    let mut urls = Vec::with_capacity(count as usize);
+    for i in 0..count {
+        let url = match env.get_object_array_element(&upstreams, i) {
+            Ok(obj) => match env.get_string(&JString::from(obj)) {
+                Ok(s) => s.to_string_lossy().to_string(),
+                Err(_) => return -1,
+            },
+            Err(_) => return -1,
+        };
+        urls.push(url);
+    }
+
+    match dns_resolver().set_upstreams(&urls) {
+        Some(count) => count as jint,
+        None => {
+            error!("Invalid DNS upstream in {:?}", urls);
+            -1
+        }
+    }
+}
+
+/// Resolve a hostname to IP address strings (null on failure)
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nativeResolveHost(
+    mut env: JNIEnv,
+    _class: JClass,
+    host: JString,
+) -> jobjectArray {
+    let host_str = match env.get_string(&host) {
+        Ok(s) => s.to_string_lossy().to_string(),
+        Err(_) => return std::ptr::null_mut(),
+    };
+
+    let addrs = match resolve_host(&host_str, 0) {
+        Ok(addrs) => addrs,
+        Err(e) => {
+            error!("Failed to resolve {}: {}", host_str, e);
+            return std::ptr::null_mut();
+        }
+    };
+
+    let array = match env.new_object_array(addrs.len() as i32, "java/lang/String", JObject::null()) {
+        Ok(a) => a,
+        Err(_) => return std::ptr::null_mut(),
+    };
+    for (i, addr) in addrs.iter().enumerate() {
+        let ip = match env.new_string(addr.ip().to_string()) {
+            Ok(s) => s,
+            Err(_) => return std::ptr::null_mut(),
+        };
+        if env.set_object_array_element(&array, i as i32, ip).is_err() {
+            return std::ptr::null_mut();
+        }
+    }
+    array.into_raw()
+}
+
+/// Drop all cached DNS answers
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nativeClearDnsCache(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    dns_resolver().clear_cache();
+    debug!("DNS cache cleared");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const FLAGS_RESPONSE: u16 = 0x8180;
+    const TYPE_CNAME: u16 = 5;
+    // Pointer to the question name right after the header
+    const QNAME_PTR: [u8; 2] = [0xc0, 0x0c];
+
+    /// Response header and question section
+    fn response(flags: u16, name: &str, qtype: u16, answers: u16) -> Vec<u8> {
+        let mut msg = encode_query(name, qtype).unwrap();
+        msg[2..4].copy_from_slice(&flags.to_be_bytes());
+        msg[6..8].copy_from_slice(&answers.to_be_bytes());
+        msg
+    }
+
+    fn record(msg: &mut Vec<u8>, name: &[u8], rtype: u16, ttl: u32, data: &[u8]) {
+        msg.extend_from_slice(name);
+        msg.extend_from_slice(&rtype.to_be_bytes());
+        msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+        msg.extend_from_slice(&ttl.to_be_bytes());
+        msg.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        msg.extend_from_slice(data);
+    }
+
+    /// www.example.com: a CNAME to cdn.example.com, then A and AAAA records
+    /// for the alias, all names compressed
+    fn mixed_response(qtype: u16) -> Vec<u8> {
+        let mut msg = response(FLAGS_RESPONSE, "www.example.com", qtype, 5);
+        let alias_at = msg.len() + 12;
+        // cdn + pointer to "example.com" inside the question name
+        record(&mut msg, &QNAME_PTR, TYPE_CNAME, 600, &[3, b'c', b'd', b'n', 0xc0, 16]);
+        let alias = [0xc0, alias_at as u8];
+        record(&mut msg, &alias, TYPE_A, 300, &[192, 0, 2, 1]);
+        record(&mut msg, &alias, TYPE_AAAA, 60, &[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        record(&mut msg, &alias, TYPE_A, 120, &[192, 0, 2, 2]);
+        record(&mut msg, &alias, TYPE_A, 30, &[192, 0, 2, 1]);
+        msg
+    }
+
+    #[test]
+    fn encode_query_layout() {
+        let query = encode_query("www.example.com", TYPE_AAAA).unwrap();
+        let mut expected = vec![0, 0, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        expected.extend_from_slice(b"\x03www\x07example\x03com\x00\x00\x1c\x00\x01");
+        assert_eq!(query, expected);
+
+        let long_label = "a".repeat(64);
+        let long_name = ["a".repeat(63).as_str(); 4].join(".");
+        for name in ["", "a..b", ".a", long_label.as_str(), long_name.as_str()] {
+            assert_eq!(encode_query(name, TYPE_A).unwrap_err().kind(), io::ErrorKind::InvalidInput, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn decode_mixed_records_with_compression() {
+        let v4 = decode_answer(&mixed_response(TYPE_A), TYPE_A).unwrap();
+        // Duplicates collapse; the TTL is the smallest among the wanted records
+        assert_eq!(v4.addrs, ["192.0.2.1".parse::<IpAddr>().unwrap(), "192.0.2.2".parse().unwrap()]);
+        assert_eq!(v4.ttl, 30);
+
+        let v6 = decode_answer(&mixed_response(TYPE_AAAA), TYPE_AAAA).unwrap();
+        assert_eq!(v6.addrs, ["2001:db8::1".parse::<IpAddr>().unwrap()]);
+        assert_eq!(v6.ttl, 60);
+
+        // No records of the wanted type
+        let empty = decode_answer(&response(FLAGS_RESPONSE, "example.com", TYPE_A, 0), TYPE_A).unwrap();
+        assert!(empty.addrs.is_empty());
+    }
+
+    #[test]
+    fn decode_rcodes() {
+        let nxdomain = decode_answer(&response(FLAGS_RESPONSE | 3, "nx.example", TYPE_A, 0), TYPE_A).unwrap();
+        assert!(nxdomain.addrs.is_empty());
+        assert_eq!(nxdomain.ttl, NEGATIVE_TTL_SECS);
+
+        let servfail = decode_answer(&response(FLAGS_RESPONSE | 2, "example.com", TYPE_A, 0), TYPE_A);
+        assert_eq!(servfail.err().map(|e| e.kind()), Some(io::ErrorKind::Other));
+        let query = decode_answer(&response(0x0100, "example.com", TYPE_A, 0), TYPE_A);
+        assert_eq!(query.err().map(|e| e.kind()), Some(io::ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn decode_rejects_truncated_responses() {
+        let msg = mixed_response(TYPE_A);
+        for len in 0..msg.len() {
+            let result = decode_answer(&msg[..len], TYPE_A);
+            assert_eq!(result.err().map(|e| e.kind()), Some(io::ErrorKind::InvalidData), "cut at {}", len);
+        }
+
+        // An A record with an IPv6-sized address
+        let mut msg = response(FLAGS_RESPONSE, "example.com", TYPE_A, 1);
+        record(&mut msg, &QNAME_PTR, TYPE_A, 60, &[0; 16]);
+        assert!(decode_answer(&msg, TYPE_A).is_err());
+    }
+
+    #[test]
+    fn compression_pointer_loops_terminate() {
+        // Names are skipped without following pointers, so loops cannot spin
+        let mut msg = response(FLAGS_RESPONSE, "example.com", TYPE_A, 2);
+        let self_at = msg.len();
+        record(&mut msg, &[0xc0, self_at as u8], TYPE_A, 60, &[192, 0, 2, 7]);
+        let next_at = msg.len();
+        record(&mut msg, &[1, b'x', 0xc0, next_at as u8], TYPE_A, 60, &[192, 0, 2, 8]);
+        let answer = decode_answer(&msg, TYPE_A).unwrap();
+        assert_eq!(answer.addrs.len(), 2);
+
+        assert_eq!(skip_name(&[0xc0, 0], 0), Some(2));
+        assert_eq!(skip_name(&[0xc0], 0), None);
+        // Reserved label types and labels running off the end
+        assert_eq!(skip_name(&[0x80, 0], 0), None);
+        assert_eq!(skip_name(&[5, b'a', b'b'], 0), None);
+    }
+
+    #[test]
+    fn cache_expires_by_ttl_and_evicts_least_recently_used() {
+        let addr = |last: u8| vec![IpAddr::from([192, 0, 2, last])];
+        let mut cache = LruCache::new(2);
+        cache.insert("gone".into(), addr(1), Duration::ZERO);
+        assert_eq!(cache.get("gone"), None);
+        assert!(cache.entries.is_empty());
+
+        cache.insert("a".into(), addr(1), Duration::from_secs(60));
+        cache.insert("b".into(), addr(2), Duration::from_secs(60));
+        assert_eq!(cache.get("a"), Some(addr(1)));
+        cache.insert("c".into(), addr(3), Duration::from_secs(60));
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(addr(1)));
+        assert_eq!(cache.get("c"), Some(addr(3)));
+
+        // Expired entries go before the least recently used one ("a")
+        cache.insert("short".into(), Vec::new(), Duration::ZERO);
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.entries.len(), 2);
+        cache.insert("d".into(), addr(4), Duration::from_secs(60));
+        assert!(!cache.entries.contains_key("short"));
+        assert_eq!(cache.get("c"), Some(addr(3)));
+        assert_eq!(cache.get("d"), Some(addr(4)));
+    }
+
+    /// Stub upstream's answer: NXDOMAIN for missing.example, otherwise two
+    /// A records and one AAAA record with a short TTL
+    fn stub_answer(query: &[u8]) -> Vec<u8> {
+        // Question: name at 12, then type
+        let name_end = skip_name(query, 12).unwrap();
+        let qtype = u16::from_be_bytes([query[name_end], query[name_end + 1]]);
+        let mut msg = query.to_vec();
+        msg[2..4].copy_from_slice(&FLAGS_RESPONSE.to_be_bytes());
+        if &query[12..name_end] == b"\x07missing\x07example\x00" {
+            msg[3] |= 3;
+        } else if qtype == TYPE_A {
+            msg[6..8].copy_from_slice(&2u16.to_be_bytes());
+            record(&mut msg, &QNAME_PTR, TYPE_A, 300, &[192, 0, 2, 1]);
+            record(&mut msg, &QNAME_PTR, TYPE_A, 300, &[192, 0, 2, 2]);
+        } else {
+            msg[6..8].copy_from_slice(&1u16.to_be_bytes());
+            record(&mut msg, &QNAME_PTR, TYPE_AAAA, 5, &[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        }
+        msg
+    }
+
+    /// DoH server on loopback answering over one keep-alive connection;
+    /// returns its port and a count of requests served
+    fn doh_stub(cert: &rcgen::CertifiedKey) -> (u16, Arc<AtomicUsize>) {
+        let key = rustls::pki_types::PrivateKeyDer::Pkcs8(cert.key_pair.serialize_der().into());
+        let config = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.cert.der().clone()], key)
+            .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let served = Arc::new(AtomicUsize::new(0));
+        let counter = served.clone();
+
+        std::thread::spawn(move || {
+            let (tcp, _) = listener.accept().unwrap();
+            let tls = rustls::ServerConnection::new(Arc::new(config)).unwrap();
+            let mut stream = BufReader::new(rustls::StreamOwned::new(tls, tcp));
+            loop {
+                let mut request_line = String::new();
+                if stream.read_line(&mut request_line).unwrap_or(0) == 0 {
+                    return;
+                }
+                assert_eq!(request_line, "POST /dns HTTP/1.1\r\n");
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    stream.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    let (name, value) = line.split_once(':').unwrap();
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    } else if name.eq_ignore_ascii_case("content-type") {
+                        assert_eq!(value.trim(), "application/dns-message");
+                    }
+                }
+                let mut query = vec![0u8; length];
+                io::Read::read_exact(&mut stream, &mut query).unwrap();
+                counter.fetch_add(1, Ordering::SeqCst);
+                let msg = stub_answer(&query);
+
+                // Header and body in separate records, so the client reads twice
+                let out = stream.get_mut();
+                write!(out, "HTTP/1.1 200 OK\r\nContent-Type: application/dns-message\r\nContent-Length: {}\r\n\r\n", msg.len())
+                    .unwrap();
+                out.flush().unwrap();
+                out.write_all(&msg).unwrap();
+                out.flush().unwrap();
+            }
+        });
+        (port, served)
+    }
+
+    #[test]
+    fn doh_round_trip() {
+        let cert = rcgen::generate_simple_self_signed(vec!["127.0.0.1".into()]).unwrap();
+        let (port, served) = doh_stub(&cert);
+        let mut roots = RootCertStore::empty();
+        roots.add(cert.cert.der().clone()).unwrap();
+        let resolver = DnsResolver::new(roots).unwrap();
+        assert_eq!(resolver.set_upstreams(&[format!("https://127.0.0.1:{}/dns", port)]), Some(1));
+
+        let addrs = runtime().block_on(resolver.resolve("WWW.Example.com.", 443)).unwrap();
+        let expected: Vec<SocketAddr> = ["[2001:db8::1]:443", "192.0.2.1:443", "192.0.2.2:443"]
+            .iter()
+            .map(|a| a.parse().unwrap())
+            .collect();
+        assert_eq!(addrs, expected);
+        assert_eq!(served.load(Ordering::SeqCst), 2);
+
+        // Cached for the smallest TTL, raised to the minimum
+        let expires = resolver.cache.lock().entries["www.example.com"].expires;
+        let ttl = expires.saturating_duration_since(Instant::now());
+        assert!(ttl > Duration::from_secs(MIN_TTL_SECS as u64 - 2) && ttl <= Duration::from_secs(MIN_TTL_SECS as u64));
+        let again = runtime().block_on(resolver.resolve("www.example.com", 80)).unwrap();
+        assert_eq!(again.len(), 3);
+        assert_eq!(served.load(Ordering::SeqCst), 2);
+
+        // NXDOMAIN: an error now, and a negative entry for a while
+        let missing = runtime().block_on(resolver.resolve("missing.example", 80));
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(served.load(Ordering::SeqCst), 4);
+        let entry_ttl = resolver.cache.lock().entries["missing.example"].expires.saturating_duration_since(Instant::now());
+        assert!(entry_ttl > Duration::from_secs(NEGATIVE_TTL_SECS as u64 - 2));
+        assert!(runtime().block_on(resolver.resolve("missing.example", 80)).is_err());
+        assert_eq!(served.load(Ordering::SeqCst), 4);
+    }
+
+    /// DoQ server on loopback; returns its port and counts of queries and connections
+    fn doq_stub(cert: &rcgen::CertifiedKey) -> (u16, Arc<AtomicUsize>, Arc<AtomicUsize>) {
+        let key = rustls::pki_types::PrivateKeyDer::Pkcs8(cert.key_pair.serialize_der().into());
+        let mut tls = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.cert.der().clone()], key)
+            .unwrap();
+        tls.alpn_protocols = vec![b"doq".to_vec()];
+        let crypto = quinn::crypto::rustls::QuicServerConfig::try_from(tls).unwrap();
+        let config = quinn::ServerConfig::with_crypto(Arc::new(crypto));
+
+        let _guard = runtime().enter();
+        let endpoint = quinn::Endpoint::server(config, "127.0.0.1:0".parse().unwrap()).unwrap();
+        let port = endpoint.local_addr().unwrap().port();
+        let (served, connections) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let (query_counter, conn_counter) = (served.clone(), connections.clone());
+
+        runtime().spawn(async move {
+            while let Some(incoming) = endpoint.accept().await {
+                let Ok(conn) = incoming.await else { continue };
+                conn_counter.fetch_add(1, Ordering::SeqCst);
+                let query_counter = query_counter.clone();
+                tokio::spawn(async move {
+                    // One length-prefixed query per stream
+                    while let Ok((mut send, mut recv)) = conn.accept_bi().await {
+                        let framed = recv.read_to_end(MAX_MESSAGE_SIZE + 2).await.unwrap();
+                        let len = u16::from_be_bytes([framed[0], framed[1]]) as usize;
+                        assert_eq!(framed.len(), len + 2);
+                        query_counter.fetch_add(1, Ordering::SeqCst);
+
+                        let msg = stub_answer(&framed[2..]);
+                        send.write_all(&(msg.len() as u16).to_be_bytes()).await.unwrap();
+                        send.write_all(&msg).await.unwrap();
+                        send.finish().unwrap();
+                    }
+                });
+            }
+        });
+        (port, served, connections)
+    }
+
+    #[test]
+    fn doq_round_trip_reuses_the_connection() {
+        let cert = rcgen::generate_simple_self_signed(vec!["127.0.0.1".into()]).unwrap();
+        let (port, served, connections) = doq_stub(&cert);
+        let mut roots = RootCertStore::empty();
+        roots.add(cert.cert.der().clone()).unwrap();
+        let resolver = DnsResolver::new(roots).unwrap();
+        assert_eq!(resolver.set_upstreams(&[format!("quic://127.0.0.1:{}", port)]), Some(1));
+
+        let addrs = runtime().block_on(resolver.resolve("www.example.com", 853)).unwrap();
+        let expected: Vec<SocketAddr> = ["[2001:db8::1]:853", "192.0.2.1:853", "192.0.2.2:853"]
+            .iter()
+            .map(|a| a.parse().unwrap())
+            .collect();
+        assert_eq!(addrs, expected);
+        assert_eq!(served.load(Ordering::SeqCst), 2);
+
+        // Later queries go over the same connection
+        let missing = runtime().block_on(resolver.resolve("missing.example", 80));
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+        resolver.clear_cache();
+        assert_eq!(runtime().block_on(resolver.resolve("www.example.com", 80)).unwrap().len(), 3);
+        assert_eq!(served.load(Ordering::SeqCst), 6);
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+}
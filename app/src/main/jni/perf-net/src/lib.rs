@@ -6,6 +6,9 @@
 mod cpu_affinity;
 mod zero_copy;
 mod connection_pool;
+mod dns_resolver;
+mod tls_stream;
+mod warm_pool;
 mod crypto_accel;
 mod epoll_loop;
 mod kernel_pacing;
@@ -29,6 +32,8 @@ mod jni_bridge;
 pub use cpu_affinity::*;
 pub use zero_copy::*;
 pub use connection_pool::*;
+pub use dns_resolver::*;
+pub use warm_pool::*;
 pub use crypto_accel::*;
 pub use epoll_loop::*;
 pub use kernel_pacing::*;
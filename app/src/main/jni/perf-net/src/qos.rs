@@ -59,7 +59,8 @@ pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nat
     #[cfg(not(target_os = "android"))]
     {
         // Try nix first, fallback to libc
-        match setsockopt(fd, &sockopt::Priority, &(priority as i32)) {
+        let borrowed_fd = unsafe { BorrowedFd::borrow_raw(fd) };
+        match setsockopt(&borrowed_fd, sockopt::Priority, &priority) {
             Ok(_) => {
                 debug!("Socket priority set to {} for fd {}", priority, fd);
                 0
@@ -123,7 +124,8 @@ pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nat
     }
     #[cfg(not(target_os = "android"))]
     {
-        match setsockopt(fd, &sockopt::IpTos, &(tos as u8)) {
+        let borrowed_fd = unsafe { BorrowedFd::borrow_raw(fd) };
+        match setsockopt(&borrowed_fd, sockopt::IpTos, &tos) {
             Ok(_) => {
                 debug!("IP TOS set to 0x{:02x} for fd {}", tos, fd);
                 0
@@ -175,7 +177,8 @@ pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nat
         }
         #[cfg(not(target_os = "android"))]
         {
-            match setsockopt(fd, &sockopt::TcpNoDelay, &opt) {
+            let borrowed_fd = unsafe { BorrowedFd::borrow_raw(fd) };
+            match setsockopt(&borrowed_fd, sockopt::TcpNoDelay, &(opt != 0)) {
                 Ok(_) => 0,
                 Err(_) => {
                     use libc::{IPPROTO_TCP, TCP_NODELAY};
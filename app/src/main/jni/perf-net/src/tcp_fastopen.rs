@@ -40,36 +40,19 @@ pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nat
     }
 
     // TCP_FASTOPEN option - available on Linux 3.7+
-    // Use libc directly as TcpFastOpen may not be in nix 0.28
+    // Use libc directly as TcpFastOpen is not in nix 0.28
     let opt: i32 = 1;
-    #[cfg(target_os = "android")]
-    {
-        use libc::{IPPROTO_TCP, TCP_FASTOPEN};
-        let result = unsafe {
-            libc::setsockopt(fd, IPPROTO_TCP, TCP_FASTOPEN, &opt as *const _ as *const libc::c_void, std::mem::size_of::<i32>() as libc::socklen_t)
-        };
-        if result == 0 {
-            debug!("TCP Fast Open enabled for fd {}", fd);
-            0
-        } else {
-            // TFO may not be supported on all devices/Android versions
-            debug!("TCP Fast Open not available for fd {}", fd);
-            -1
-        }
-    }
-    #[cfg(not(target_os = "android"))]
-    {
-        use nix::sys::socket::{setsockopt, sockopt::TcpFastOpen};
-        match setsockopt(fd, &TcpFastOpen, &opt) {
-            Ok(_) => {
-                debug!("TCP Fast Open enabled for fd {}", fd);
-                0
-            }
-            Err(e) => {
-                debug!("TCP Fast Open not available for fd {}: {}", fd, e);
-                -1
-            }
-        }
+    use libc::{IPPROTO_TCP, TCP_FASTOPEN};
+    let result = unsafe {
+        libc::setsockopt(fd, IPPROTO_TCP, TCP_FASTOPEN, &opt as *const _ as *const libc::c_void, std::mem::size_of::<i32>() as libc::socklen_t)
+    };
+    if result == 0 {
+        debug!("TCP Fast Open enabled for fd {}", fd);
+        0
+    } else {
+        // TFO may not be supported on all devices/Android versions
+        debug!("TCP Fast Open not available for fd {}", fd);
+        -1
     }
 }
 
@@ -113,27 +96,16 @@ pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nat
 
     let opt: i32 = 1;
     let supported = {
-        #[cfg(target_os = "android")]
-        {
-            use libc::{IPPROTO_TCP, TCP_FASTOPEN};
-            let raw_fd = test_fd.as_raw_fd();
-            let result = unsafe {
-                libc::setsockopt(raw_fd, IPPROTO_TCP, TCP_FASTOPEN, &opt as *const _ as *const libc::c_void, std::mem::size_of::<i32>() as libc::socklen_t)
-            };
-            if result == 0 { 1 } else { 0 }
-        }
-        #[cfg(not(target_os = "android"))]
-        {
-            use nix::sys::socket::{setsockopt, sockopt::TcpFastOpen};
-            match setsockopt(&test_fd, TcpFastOpen, &opt) {
-                Ok(_) => 1,
-                Err(_) => 0,
-            }
-        }
+        use libc::{IPPROTO_TCP, TCP_FASTOPEN};
+        let raw_fd = test_fd.as_raw_fd();
+        let result = unsafe {
+            libc::setsockopt(raw_fd, IPPROTO_TCP, TCP_FASTOPEN, &opt as *const _ as *const libc::c_void, std::mem::size_of::<i32>() as libc::socklen_t)
+        };
+        if result == 0 { 1 } else { 0 }
     };
 
     // Close test socket
-    let _ = nix::unistd::close(test_fd.as_raw_fd());
+    drop(test_fd);
 
     // Update cache
     TFO_SUPPORTED.store(supported, Ordering::Release);
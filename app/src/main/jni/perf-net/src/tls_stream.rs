@@ -0,0 +1,88 @@
+/*
+ * Async TLS Stream (Rust Implementation)
+ * Drives a rustls client session over a tokio TcpStream
+ */
+
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig as RustlsClientConfig, ClientConnection};
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Minimal async driver for a rustls client session over a tokio TcpStream
+pub(crate) struct TlsStream {
+    tcp: TcpStream,
+    tls: ClientConnection,
+}
+
+impl TlsStream {
+    pub(crate) async fn connect(addr: SocketAddr, server_name: ServerName<'static>, config: Arc<RustlsClientConfig>) -> io::Result<Self> {
+        let tcp = TcpStream::connect(addr).await?;
+        let _ = tcp.set_nodelay(true);
+        Self::handshake(tcp, server_name, config).await
+    }
+
+    /// Run the client handshake over an already connected socket
+    pub(crate) async fn handshake(tcp: TcpStream, server_name: ServerName<'static>, config: Arc<RustlsClientConfig>) -> io::Result<Self> {
+        let tls = ClientConnection::new(config, server_name).map_err(io::Error::other)?;
+
+        let mut stream = Self { tcp, tls };
+        while stream.tls.is_handshaking() {
+            stream.flush().await?;
+            if stream.tls.wants_read() && stream.fill().await? == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Connection closed during TLS handshake"));
+            }
+        }
+        stream.flush().await?;
+        Ok(stream)
+    }
+
+    async fn flush(&mut self) -> io::Result<()> {
+        while self.tls.wants_write() {
+            let mut buf = Vec::new();
+            self.tls.write_tls(&mut buf)?;
+            self.tcp.write_all(&buf).await?;
+        }
+        Ok(())
+    }
+
+    // Feed one read from the socket into rustls; 0 on EOF
+    async fn fill(&mut self) -> io::Result<usize> {
+        let mut buf = [0u8; 8192];
+        let n = self.tcp.read(&mut buf).await?;
+        let mut data = &buf[..n];
+        while !data.is_empty() {
+            self.tls.read_tls(&mut data)?;
+            self.tls
+                .process_new_packets()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        }
+        Ok(n)
+    }
+
+    pub(crate) async fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        self.tls.writer().write_all(data)?;
+        self.flush().await
+    }
+
+    pub(crate) async fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.tls.reader().read(out) {
+                Ok(n) => return Ok(n),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    if self.fill().await? == 0 {
+                        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Connection closed"));
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// The socket and session after the handshake, for synchronous use
+    pub(crate) fn into_parts(self) -> (TcpStream, ClientConnection) {
+        (self.tcp, self.tls)
+    }
+}
@@ -0,0 +1,770 @@
+/*
+ * Warm Connection Pool (Rust Implementation)
+ * Spare connections keyed by (host, port, ALPN), pre-connected ahead of use
+ *
+ * Features:
+ * - Configurable number of spare connections per key, refilled in the background
+ * - Optional TLS handshake ahead of time (rustls, pinning-aware verifier, session resumption)
+ * - Lease and return by key; a miss connects on demand
+ * - Idle connections health-checked and evicted after an idle timeout
+ */
+
+use hashbrown::HashMap;
+use jni::JNIEnv;
+use jni::objects::{JByteArray, JClass, JString};
+use jni::sys::{jboolean, jint, jlong, jlongArray, jstring};
+use log::{debug, error, info, warn};
+use parking_lot::Mutex;
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig as RustlsClientConfig, ClientConnection};
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::os::fd::AsRawFd;
+use std::sync::{Arc, LazyLock, Once};
+use std::time::{Duration, Instant};
+use crate::cert_verifier::pinned_client_config;
+use crate::connection_pool::is_healthy;
+use crate::dns_resolver::{dns_resolver, runtime};
+use crate::tls_stream::TlsStream;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const MAINTAIN_INTERVAL: Duration = Duration::from_secs(5);
+const MAX_SPARES: usize = 16;
+
+/// Destination a warm connection is made for
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct WarmKey {
+    pub host: String,
+    pub port: u16,
+    // Empty: no ALPN
+    pub alpn: String,
+}
+
+/// A connected (and possibly TLS-established) socket
+pub struct WarmConnection {
+    tcp: TcpStream,
+    tls: Option<ClientConnection>,
+    idle_since: Instant,
+}
+
+impl WarmConnection {
+    pub fn fd(&self) -> i32 {
+        self.tcp.as_raw_fd()
+    }
+
+    /// Protocol agreed in the TLS handshake
+    pub fn alpn(&self) -> Option<String> {
+        self.tls
+            .as_ref()
+            .and_then(|tls| tls.alpn_protocol())
+            .map(|p| String::from_utf8_lossy(p).to_string())
+    }
+
+    /// Write application data (encrypted if TLS), blocking
+    pub fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        match &mut self.tls {
+            Some(tls) => {
+                let mut stream = rustls::Stream::new(tls, &mut self.tcp);
+                stream.write_all(data)?;
+                stream.flush()?;
+                Ok(data.len())
+            }
+            None => self.tcp.write(data),
+        }
+    }
+
+    /// Read application data (decrypted if TLS), blocking; 0 at end of stream
+    pub fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.tls {
+            Some(tls) => match rustls::Stream::new(tls, &mut self.tcp).read(buf) {
+                // Peer closed without close_notify
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(0),
+                result => result,
+            },
+            None => self.tcp.read(buf),
+        }
+    }
+
+    /// Whether an idle connection can still be handed out
+    ///
+    /// Post-handshake TLS messages (session tickets) are absorbed; application
+    /// data or a close from the peer makes the connection unusable.
+    fn check_idle(&mut self) -> bool {
+        let tls = match &mut self.tls {
+            Some(tls) => tls,
+            None => return is_healthy(self.tcp.as_raw_fd()),
+        };
+
+        loop {
+            match tls.read_tls(&mut self.tcp) {
+                Ok(0) => return false,
+                Ok(_) => match tls.process_new_packets() {
+                    Ok(state) if state.plaintext_bytes_to_read() == 0 && !state.peer_has_closed() => {}
+                    _ => return false,
+                },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return true,
+                Err(_) => return false,
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct WarmStats {
+    hits: u64,
+    misses: u64,
+    connects: u64,
+    connect_failures: u64,
+    evicted: u64,
+}
+
+struct Target {
+    spares: usize,
+    tls: Option<Arc<RustlsClientConfig>>,
+    idle_timeout: Duration,
+    // Newest last
+    idle: Vec<WarmConnection>,
+    // Background connects in flight
+    pending: usize,
+    // Bumped on reconfigure so stale connects are discarded
+    generation: u64,
+    stats: WarmStats,
+}
+
+impl Target {
+    fn new(spares: usize, tls: Option<Arc<RustlsClientConfig>>, idle_timeout: Duration) -> Self {
+        Self {
+            spares,
+            tls,
+            idle_timeout,
+            idle: Vec::new(),
+            pending: 0,
+            generation: 0,
+            stats: WarmStats::default(),
+        }
+    }
+
+    /// Drop idle connections that timed out or went bad
+    fn evict_stale(&mut self) {
+        let now = Instant::now();
+        let idle_timeout = self.idle_timeout;
+        let before = self.idle.len();
+        self.idle.retain_mut(|conn| now.duration_since(conn.idle_since) < idle_timeout && conn.check_idle());
+        self.stats.evicted += (before - self.idle.len()) as u64;
+    }
+
+    /// Start background connects up to the spare count
+    fn refill(&mut self, key: &WarmKey) {
+        let deficit = self.spares.saturating_sub(self.idle.len() + self.pending);
+        for _ in 0..deficit {
+            self.pending += 1;
+            let key = key.clone();
+            let tls = self.tls.clone();
+            let generation = self.generation;
+
+            runtime().spawn(async move {
+                let result = connect(&key, tls).await;
+                let mut targets = TARGETS.lock();
+                let target = match targets.get_mut(&key) {
+                    Some(t) if t.generation == generation => t,
+                    // Removed or reconfigured meanwhile; the connection is closed on drop
+                    _ => return,
+                };
+                target.pending -= 1;
+                match result {
+                    Ok(conn) => {
+                        target.stats.connects += 1;
+                        target.idle.push(conn);
+                    }
+                    Err(e) => {
+                        target.stats.connect_failures += 1;
+                        warn!("Warm connect to {}:{} failed: {}", key.host, key.port, e);
+                    }
+                }
+            });
+        }
+    }
+}
+
+static TARGETS: LazyLock<Mutex<HashMap<WarmKey, Target>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Connect to `key`, trying each resolved address, then handshake if `tls` is set
+async fn connect(key: &WarmKey, tls: Option<Arc<RustlsClientConfig>>) -> io::Result<WarmConnection> {
+    let addrs = dns_resolver().resolve(&key.host, key.port).await?;
+
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, "No address");
+    let mut tcp = None;
+    for addr in addrs {
+        match tokio::time::timeout(CONNECT_TIMEOUT, tokio::net::TcpStream::connect(addr)).await {
+            Ok(Ok(stream)) => {
+                tcp = Some(stream);
+                break;
+            }
+            Ok(Err(e)) => last_error = e,
+            Err(_) => last_error = io::Error::new(io::ErrorKind::TimedOut, format!("Connect to {} timed out", addr)),
+        }
+    }
+    let tcp = tcp.ok_or(last_error)?;
+    let _ = tcp.set_nodelay(true);
+
+    let (tcp, tls) = match tls {
+        Some(config) => {
+            let server_name = ServerName::try_from(key.host.clone())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            let stream = tokio::time::timeout(CONNECT_TIMEOUT, TlsStream::handshake(tcp, server_name, config))
+                .await
+                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "TLS handshake timed out"))??;
+            let (tcp, tls) = stream.into_parts();
+            (tcp, Some(tls))
+        }
+        None => (tcp, None),
+    };
+
+    // Idle connections stay non-blocking for health checks
+    Ok(WarmConnection {
+        tcp: tcp.into_std()?,
+        tls,
+        idle_since: Instant::now(),
+    })
+}
+
+/// Client config offering `alpn`, verified by `PinningCertVerifier` (WebPKI
+/// roots, hostname, and `pins` when not empty)
+pub fn tls_config(alpn: &str, pins: &[[u8; 32]]) -> io::Result<Arc<RustlsClientConfig>> {
+    pinned_client_config(pins, alpn).map(Arc::new).map_err(io::Error::other)
+}
+
+fn start_maintainer() {
+    static STARTED: Once = Once::new();
+    STARTED.call_once(|| {
+        runtime().spawn(async {
+            let mut ticker = tokio::time::interval(MAINTAIN_INTERVAL);
+            loop {
+                ticker.tick().await;
+                let mut targets = TARGETS.lock();
+                for (key, target) in targets.iter_mut() {
+                    target.evict_stale();
+                    target.refill(key);
+                }
+            }
+        });
+    });
+}
+
+/// Keep `spares` connections to `key` ready (TLS-established if `tls` is set)
+///
+/// Reconfiguring a key drops its idle connections.
+pub fn configure(key: WarmKey, spares: usize, tls: Option<Arc<RustlsClientConfig>>, idle_timeout: Duration) {
+    start_maintainer();
+
+    let mut targets = TARGETS.lock();
+    let target = targets
+        .entry(key.clone())
+        .or_insert_with(|| Target::new(spares, tls.clone(), idle_timeout));
+    target.spares = spares.min(MAX_SPARES);
+    target.tls = tls;
+    target.idle_timeout = idle_timeout;
+    target.idle.clear();
+    target.pending = 0;
+    target.generation += 1;
+    target.refill(&key);
+    info!("Warm pool for {}:{} ({}): {} spare(s), tls={}", key.host, key.port, key.alpn, target.spares,
+          target.tls.is_some());
+}
+
+/// Stop keeping connections for `key` and close its idle ones
+pub fn remove(key: &WarmKey) {
+    if TARGETS.lock().remove(key).is_some() {
+        debug!("Warm pool for {}:{} removed", key.host, key.port);
+    }
+}
+
+/// Take a ready connection for `key`, connecting on demand if none is idle
+///
+/// An unconfigured key connects on demand without TLS and is not kept.
+pub fn lease(key: &WarmKey, timeout: Duration) -> io::Result<WarmConnection> {
+    let tls = {
+        let mut targets = TARGETS.lock();
+        match targets.get_mut(key) {
+            Some(target) => {
+                let mut leased = None;
+                while let Some(mut conn) = target.idle.pop() {
+                    if conn.check_idle() {
+                        leased = Some(conn);
+                        break;
+                    }
+                    target.stats.evicted += 1;
+                }
+                target.refill(key);
+
+                if let Some(conn) = leased {
+                    target.stats.hits += 1;
+                    conn.tcp.set_nonblocking(false)?;
+                    return Ok(conn);
+                }
+                target.stats.misses += 1;
+                target.tls.clone()
+            }
+            None => None,
+        }
+    };
+
+    debug!("No warm connection for {}:{}, connecting", key.host, key.port);
+    let conn = runtime()
+        .block_on(async { tokio::time::timeout(timeout, connect(key, tls)).await })
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "Connect timed out"))??;
+    conn.tcp.set_nonblocking(false)?;
+    Ok(conn)
+}
+
+/// Give a leased connection back to `key` (closed if not reusable or not needed)
+pub fn give_back(key: &WarmKey, mut conn: WarmConnection, reusable: bool) {
+    if !reusable || conn.tcp.set_nonblocking(true).is_err() || !conn.check_idle() {
+        return;
+    }
+
+    let mut targets = TARGETS.lock();
+    if let Some(target) = targets.get_mut(key) {
+        if target.idle.len() < target.spares.max(1) {
+            conn.idle_since = Instant::now();
+            target.idle.push(conn);
+        }
+    }
+}
+
+struct Lease {
+    key: WarmKey,
+    conn: WarmConnection,
+}
+
+fn get_key(env: &mut JNIEnv, host: &JString, port: jint, alpn: &JString) -> Option<WarmKey> {
+    if !(0..=65535).contains(&port) {
+        return None;
+    }
+    let host = env.get_string(host).ok()?.to_string_lossy().to_string();
+    if host.is_empty() {
+        return None;
+    }
+    let alpn = if alpn.is_null() {
+        String::new()
+    } else {
+        env.get_string(alpn).ok()?.to_string_lossy().to_string()
+    };
+    Some(WarmKey { host, port: port as u16, alpn })
+}
+
+/// Configure spare connections for (host, port, alpn)
+/// `spki_pins` is a concatenation of 32-byte SPKI-SHA256 digests (may be null)
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nativeWarmPoolConfigure(
+    mut env: JNIEnv,
+    _class: JClass,
+    host: JString,
+    port: jint,
+    alpn: JString,
+    spares: jint,
+    tls: jboolean,
+    idle_timeout_ms: jlong,
+    spki_pins: JByteArray,
+) -> jint {
+    let key = match get_key(&mut env, &host, port, &alpn) {
+        Some(k) => k,
+        None => return -1,
+    };
+    if spares < 0 || idle_timeout_ms <= 0 {
+        return -1;
+    }
+
+    let mut pins: Vec<[u8; 32]> = Vec::new();
+    if !spki_pins.is_null() {
+        let raw = match env.convert_byte_array(&spki_pins) {
+            Ok(bytes) => bytes,
+            Err(_) => return -1,
+        };
+        if raw.len() % 32 != 0 {
+            warn!("Invalid SPKI pin array length: {}", raw.len());
+            return -1;
+        }
+        for chunk in raw.chunks_exact(32) {
+            let mut pin = [0u8; 32];
+            pin.copy_from_slice(chunk);
+            pins.push(pin);
+        }
+    }
+
+    let tls_config = if tls != 0 {
+        match tls_config(&key.alpn, &pins) {
+            Ok(config) => Some(config),
+            Err(e) => {
+                error!("Failed to create TLS config: {}", e);
+                return -1;
+            }
+        }
+    } else {
+        None
+    };
+
+    configure(key, spares as usize, tls_config, Duration::from_millis(idle_timeout_ms as u64));
+    0
+}
+
+/// Remove (host, port, alpn) from the warm pool
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nativeWarmPoolRemove(
+    mut env: JNIEnv,
+    _class: JClass,
+    host: JString,
+    port: jint,
+    alpn: JString,
+) {
+    if let Some(key) = get_key(&mut env, &host, port, &alpn) {
+        remove(&key);
+    }
+}
+
+/// Lease a connection for (host, port, alpn); returns a lease handle or 0
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nativeWarmPoolLease(
+    mut env: JNIEnv,
+    _class: JClass,
+    host: JString,
+    port: jint,
+    alpn: JString,
+    timeout_ms: jint,
+) -> jlong {
+    let key = match get_key(&mut env, &host, port, &alpn) {
+        Some(k) => k,
+        None => return 0,
+    };
+    if timeout_ms <= 0 {
+        return 0;
+    }
+
+    match lease(&key, Duration::from_millis(timeout_ms as u64)) {
+        Ok(conn) => Box::into_raw(Box::new(Lease { key, conn })) as jlong,
+        Err(e) => {
+            error!("Warm lease for {}:{} failed: {}", key.host, key.port, e);
+            0
+        }
+    }
+}
+
+/// Socket fd of a lease (owned by the lease; do not close)
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nativeWarmLeaseFd(
+    _env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+) -> jint {
+    if handle == 0 {
+        return -1;
+    }
+    let lease = unsafe { &*(handle as *const Lease) };
+    lease.conn.fd()
+}
+
+/// Negotiated ALPN protocol of a lease (null without TLS or ALPN)
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nativeWarmLeaseAlpn(
+    env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+) -> jstring {
+    if handle == 0 {
+        return std::ptr::null_mut();
+    }
+    let lease = unsafe { &*(handle as *const Lease) };
+    match lease.conn.alpn() {
+        Some(alpn) => env.new_string(alpn).map(|s| s.into_raw()).unwrap_or(std::ptr::null_mut()),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Write through a lease (TLS-encrypted if pre-handshaked); returns bytes written or -1
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nativeWarmLeaseWrite(
+    env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+    data: JByteArray,
+    offset: jint,
+    length: jint,
+) -> jint {
+    if handle == 0 || offset < 0 || length < 0 {
+        return -1;
+    }
+
+    let bytes = match env.convert_byte_array(&data) {
+        Ok(b) => b,
+        Err(_) => return -1,
+    };
+    let end = (offset as usize).saturating_add(length as usize);
+    if end > bytes.len() {
+        return -1;
+    }
+
+    let lease = unsafe { &mut *(handle as *mut Lease) };
+    match lease.conn.write(&bytes[offset as usize..end]) {
+        Ok(n) => n as jint,
+        Err(e) => {
+            debug!("Warm lease write failed: {}", e);
+            -1
+        }
+    }
+}
+
+/// Read through a lease, blocking; returns bytes read, 0 at end of stream, -1 on error
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nativeWarmLeaseRead(
+    env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+    data: JByteArray,
+    offset: jint,
+    length: jint,
+) -> jint {
+    if handle == 0 || offset < 0 || length < 0 {
+        return -1;
+    }
+
+    let capacity = match env.get_array_length(&data) {
+        Ok(n) => n,
+        Err(_) => return -1,
+    };
+    if offset.saturating_add(length) > capacity {
+        return -1;
+    }
+
+    let lease = unsafe { &mut *(handle as *mut Lease) };
+    let mut buf = vec![0u8; length as usize];
+    let n = match lease.conn.read(&mut buf) {
+        Ok(n) => n,
+        Err(e) => {
+            debug!("Warm lease read failed: {}", e);
+            return -1;
+        }
+    };
+
+    let signed: &[i8] = unsafe { std::slice::from_raw_parts(buf.as_ptr() as *const i8, n) };
+    if env.set_byte_array_region(&data, offset, signed).is_err() {
+        return -1;
+    }
+    n as jint
+}
+
+/// Return a lease to its key's pool (or close it) and free the handle
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nativeWarmPoolReturn(
+    _env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+    reusable: jboolean,
+) {
+    if handle == 0 {
+        return;
+    }
+    let lease = unsafe { Box::from_raw(handle as *mut Lease) };
+    give_back(&lease.key, lease.conn, reusable != 0);
+}
+
+/// Get warm pool statistics for (host, port, alpn)
+/// Returns [idle, pending, hits, misses, connects, connectFailures, evicted]
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nativeWarmPoolGetStats(
+    mut env: JNIEnv,
+    _class: JClass,
+    host: JString,
+    port: jint,
+    alpn: JString,
+) -> jlongArray {
+    let key = match get_key(&mut env, &host, port, &alpn) {
+        Some(k) => k,
+        None => return std::ptr::null_mut(),
+    };
+
+    let values = {
+        let targets = TARGETS.lock();
+        let target = match targets.get(&key) {
+            Some(t) => t,
+            None => return std::ptr::null_mut(),
+        };
+        let stats = target.stats;
+        [
+            target.idle.len() as jlong,
+            target.pending as jlong,
+            stats.hits as jlong,
+            stats.misses as jlong,
+            stats.connects as jlong,
+            stats.connect_failures as jlong,
+            stats.evicted as jlong,
+        ]
+    };
+
+    match env.new_long_array(values.len() as i32) {
+        Ok(result) => {
+            if env.set_long_array_region(&result, 0, &values).is_err() {
+                return std::ptr::null_mut();
+            }
+            result.into_raw()
+        }
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cert_verifier::pinned_client_config_with_roots;
+    use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, KeyPair};
+    use rustls::pki_types::PrivateKeyDer;
+    use rustls::RootCertStore;
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// TLS echo server for "localhost" offering h2; returns its port, its
+    /// accepted connection count and a client config trusting (and pinning) it
+    fn tls_echo_server() -> (u16, Arc<AtomicUsize>, Arc<RustlsClientConfig>) {
+        let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca_params.distinguished_name.push(DnType::CommonName, "Warm Pool Test Root");
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca_key = KeyPair::generate().unwrap();
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+        let leaf_key = KeyPair::generate().unwrap();
+        let leaf = CertificateParams::new(vec!["localhost".to_string()])
+            .unwrap()
+            .signed_by(&leaf_key, &ca, &ca_key)
+            .unwrap();
+
+        let mut server = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![leaf.der().clone()], PrivateKeyDer::Pkcs8(leaf_key.serialize_der().into()))
+            .unwrap();
+        server.alpn_protocols = vec![b"h2".to_vec()];
+        let server = Arc::new(server);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let counter = accepted.clone();
+        std::thread::spawn(move || {
+            for tcp in listener.incoming() {
+                let Ok(tcp) = tcp else { return };
+                counter.fetch_add(1, Ordering::SeqCst);
+                let conn = rustls::ServerConnection::new(server.clone()).unwrap();
+                std::thread::spawn(move || {
+                    let mut tls = rustls::StreamOwned::new(conn, tcp);
+                    let mut buf = [0u8; 1024];
+                    while let Ok(n @ 1..) = tls.read(&mut buf) {
+                        if tls.write_all(&buf[..n]).and_then(|_| tls.flush()).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        let mut roots = RootCertStore::empty();
+        roots.add(ca.der().clone()).unwrap();
+        let pin: [u8; 32] = ring::digest::digest(&ring::digest::SHA256, &leaf_key.public_key_der())
+            .as_ref()
+            .try_into()
+            .unwrap();
+        let config = pinned_client_config_with_roots(&[pin], "h2", roots).unwrap();
+        (port, accepted, Arc::new(config))
+    }
+
+    fn stats(key: &WarmKey) -> (usize, WarmStats) {
+        let targets = TARGETS.lock();
+        let target = &targets[key];
+        (target.idle.len(), target.stats)
+    }
+
+    #[test]
+    fn unconfigured_keys_connect_without_being_kept() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let key = WarmKey {
+            host: "127.0.0.1".into(),
+            port: listener.local_addr().unwrap().port(),
+            alpn: String::new(),
+        };
+
+        let conn = lease(&key, Duration::from_secs(5)).unwrap();
+        assert!(listener.accept().is_ok());
+        assert!(conn.alpn().is_none());
+        assert!(!TARGETS.lock().contains_key(&key));
+
+        // Nothing to give it back to, so it is closed
+        give_back(&key, conn, true);
+        assert!(!TARGETS.lock().contains_key(&key));
+    }
+
+    #[test]
+    fn pre_handshake_verifies_the_server_certificate() {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let key_der = rustls::pki_types::PrivateKeyDer::Pkcs8(cert.key_pair.serialize_der().into());
+        let server = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.cert.der().clone()], key_der)
+            .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (tcp, _) = listener.accept().unwrap();
+            let conn = rustls::ServerConnection::new(Arc::new(server)).unwrap();
+            let mut tls = rustls::StreamOwned::new(conn, tcp);
+            let _ = tls.read(&mut [0u8; 1]);
+        });
+
+        let key = WarmKey { host: "localhost".into(), port, alpn: "h2".into() };
+        let config = tls_config(&key.alpn, &[[7; 32]]).unwrap();
+        assert_eq!(config.alpn_protocols, vec![b"h2".to_vec()]);
+        configure(key.clone(), 0, Some(config), Duration::from_secs(60));
+
+        // Not signed by a WebPKI root
+        let err = lease(&key, Duration::from_secs(5)).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        remove(&key);
+    }
+
+    #[test]
+    fn warm_tls_lease_is_ready_for_application_data() {
+        let (port, accepted, config) = tls_echo_server();
+        let key = WarmKey { host: "localhost".into(), port, alpn: "h2".into() };
+        configure(key.clone(), 2, Some(config), Duration::from_secs(60));
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while stats(&key).0 < 2 {
+            assert!(Instant::now() < deadline, "spares were not refilled");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(stats(&key).1.connects, 2);
+        assert_eq!(accepted.load(Ordering::SeqCst), 2);
+
+        let mut conn = lease(&key, Duration::from_secs(5)).unwrap();
+        let (_, leased) = stats(&key);
+        assert_eq!((leased.hits, leased.misses), (1, 0));
+
+        // Already handshaked: the first bytes written are application data
+        let tls = conn.tls.as_ref().unwrap();
+        assert!(!tls.is_handshaking());
+        assert_eq!(conn.alpn().as_deref(), Some("h2"));
+        assert_eq!(conn.write(b"ping").unwrap(), 4);
+        let mut buf = [0u8; 4];
+        let mut read = 0;
+        while read < buf.len() {
+            let n = conn.read(&mut buf[read..]).unwrap();
+            assert!(n > 0, "server closed early");
+            read += n;
+        }
+        assert_eq!(&buf, b"ping");
+
+        // The lease itself opened no connection; only the refill may have
+        assert!(accepted.load(Ordering::SeqCst) <= 3);
+        give_back(&key, conn, true);
+        remove(&key);
+    }
+}
@@ -34,27 +34,20 @@ fn check_zerocopy_support() -> bool {
         };
 
         // Enable SO_ZEROCOPY option (required for MSG_ZEROCOPY)
-        // Use libc directly as SoZerocopy may not be in nix 0.28
+        // Use libc directly as SoZerocopy is not in nix 0.28
         let result = {
-            #[cfg(target_os = "android")]
-            {
-                // SO_ZEROCOPY is Linux 4.14+ only, may not be in libc on older Android
-                // Use numeric constant if not available
-                const SO_ZEROCOPY: i32 = 60; // Linux 4.14+
-                use libc::SOL_SOCKET;
-                let optval: i32 = 1;
-                let raw_fd = test_fd.as_raw_fd();
-                let r = unsafe {
-                    libc::setsockopt(raw_fd, SOL_SOCKET, SO_ZEROCOPY, &optval as *const _ as *const libc::c_void, std::mem::size_of::<i32>() as libc::socklen_t)
-                };
-                if r == 0 { Ok(()) } else { Err(nix::errno::Errno::last()) }
-            }
-            #[cfg(not(target_os = "android"))]
-            {
-                setsockopt(&test_fd, sockopt::SoZerocopy, &true)
-            }
+            // SO_ZEROCOPY is Linux 4.14+ only, may not be in libc on older Android
+            // Use numeric constant if not available
+            const SO_ZEROCOPY: i32 = 60; // Linux 4.14+
+            use libc::SOL_SOCKET;
+            let optval: i32 = 1;
+            let raw_fd = test_fd.as_raw_fd();
+            let r = unsafe {
+                libc::setsockopt(raw_fd, SOL_SOCKET, SO_ZEROCOPY, &optval as *const _ as *const libc::c_void, std::mem::size_of::<i32>() as libc::socklen_t)
+            };
+            if r == 0 { Ok(()) } else { Err(nix::errno::Errno::last()) }
         };
-        let _ = nix::unistd::close(test_fd.as_raw_fd());
+        drop(test_fd);
         
         let supported = result.is_ok();
         if supported {
@@ -182,22 +175,12 @@ pub extern "system" fn Java_com_simplexray_an_performance_PerformanceManager_nat
 
     // Enable SO_ZEROCOPY on socket if not already enabled
     if check_zerocopy_support() {
-        #[cfg(target_os = "android")]
-        {
-            const SO_ZEROCOPY: i32 = 60; // Linux 4.14+
-            use libc::SOL_SOCKET;
-            let optval: i32 = 1;
-            let _ = unsafe {
-                libc::setsockopt(fd, SOL_SOCKET, SO_ZEROCOPY, &optval as *const _ as *const libc::c_void, std::mem::size_of::<i32>() as libc::socklen_t)
-            };
-        }
-        #[cfg(not(target_os = "android"))]
-        {
-            use nix::sys::socket::{setsockopt, sockopt};
-            use std::os::fd::BorrowedFd;
-            let borrowed_fd = unsafe { BorrowedFd::borrow_raw(fd) };
-            let _ = setsockopt(borrowed_fd, sockopt::SoZerocopy, &true);
-        }
+        const SO_ZEROCOPY: i32 = 60; // Linux 4.14+
+        use libc::SOL_SOCKET;
+        let optval: i32 = 1;
+        let _ = unsafe {
+            libc::setsockopt(fd, SOL_SOCKET, SO_ZEROCOPY, &optval as *const _ as *const libc::c_void, std::mem::size_of::<i32>() as libc::socklen_t)
+        };
     }
 
     let flags = if check_zerocopy_support() {
@@ -0,0 +1,410 @@
+/*
+ * Server Certificate Verification (Rust Implementation)
+ * WebPKI chain validation with optional custom CA bundle and SPKI pinning
+ */
+
+use log::{error, warn};
+use ring::digest;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{AlertDescription, CertificateError, DigitallySignedStruct, Error, OtherError, RootCertStore, SignatureScheme};
+use std::fmt;
+use std::sync::Arc;
+
+/// SHA-256 digest of a DER-encoded SubjectPublicKeyInfo
+pub type SpkiPin = [u8; 32];
+
+/// Reason the server certificate was rejected
+///
+/// Surfaced from `QuicheClient::connect` (boxed) so callers can tell a bad
+/// certificate apart from a network failure.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CertVerifyError {
+    /// Chain does not lead to a trusted root
+    UnknownIssuer,
+    /// Certificate is expired or not yet valid
+    Expired,
+    /// Certificate is not valid for the requested server name
+    NameMismatch,
+    /// Chain validated but no certificate matched the configured SPKI pins
+    PinMismatch,
+    /// Custom CA bundle could not be parsed
+    InvalidCaBundle(String),
+    /// Any other validation failure
+    Other(String),
+}
+
+impl fmt::Display for CertVerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownIssuer => write!(f, "certificate issuer is not trusted"),
+            Self::Expired => write!(f, "certificate is expired or not yet valid"),
+            Self::NameMismatch => write!(f, "certificate is not valid for server name"),
+            Self::PinMismatch => write!(f, "no certificate in chain matches the SPKI pin set"),
+            Self::InvalidCaBundle(e) => write!(f, "invalid CA bundle: {}", e),
+            Self::Other(e) => write!(f, "certificate verification failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CertVerifyError {}
+
+impl CertVerifyError {
+    fn from_rustls(err: &Error) -> Self {
+        match err {
+            Error::InvalidCertificate(CertificateError::UnknownIssuer) => Self::UnknownIssuer,
+            Error::InvalidCertificate(CertificateError::Expired)
+            | Error::InvalidCertificate(CertificateError::ExpiredContext { .. })
+            | Error::InvalidCertificate(CertificateError::NotValidYet)
+            | Error::InvalidCertificate(CertificateError::NotValidYetContext { .. }) => Self::Expired,
+            Error::InvalidCertificate(CertificateError::NotValidForName)
+            | Error::InvalidCertificate(CertificateError::NotValidForNameContext { .. }) => {
+                Self::NameMismatch
+            }
+            other => Self::Other(format!("{:?}", other)),
+        }
+    }
+
+    // TLS error whose alert identifies the kind (inverse of `from_alert`)
+    fn to_rustls(&self) -> Error {
+        let cert_err = match self {
+            Self::UnknownIssuer => CertificateError::UnknownIssuer,
+            Self::Expired => CertificateError::Expired,
+            Self::NameMismatch => CertificateError::NotValidForName,
+            Self::PinMismatch => CertificateError::ApplicationVerificationFailure,
+            Self::InvalidCaBundle(_) | Self::Other(_) => CertificateError::Other(OtherError(Arc::new(self.clone()))),
+        };
+        Error::InvalidCertificate(cert_err)
+    }
+
+    /// Rejection behind a handshake that failed with TLS `alert`
+    ///
+    /// Only meaningful for alerts raised by `QuicCertVerifier`; `reason` is
+    /// the handshake error text, kept for the catch-all kind. None if the
+    /// alert is not about the certificate.
+    pub fn from_alert(alert: u8, reason: &str) -> Option<Self> {
+        match AlertDescription::from(alert) {
+            AlertDescription::UnknownCA => Some(Self::UnknownIssuer),
+            AlertDescription::CertificateExpired => Some(Self::Expired),
+            AlertDescription::BadCertificate => Some(Self::NameMismatch),
+            AlertDescription::AccessDenied => Some(Self::PinMismatch),
+            AlertDescription::CertificateUnknown
+            | AlertDescription::CertificateRevoked
+            | AlertDescription::UnsupportedCertificate => Some(Self::Other(reason.to_string())),
+            _ => None,
+        }
+    }
+}
+
+/// Compute the SPKI-SHA256 pin of a DER certificate
+pub fn spki_sha256(cert: &CertificateDer<'_>) -> Result<SpkiPin, CertVerifyError> {
+    let parsed = webpki::EndEntityCert::try_from(cert)
+        .map_err(|e| CertVerifyError::Other(format!("failed to parse certificate: {:?}", e)))?;
+    Ok(sha256_pin(parsed.subject_public_key_info().as_ref()))
+}
+
+fn sha256_pin(spki_der: &[u8]) -> SpkiPin {
+    let hash = digest::digest(&digest::SHA256, spki_der);
+    let mut pin = [0u8; 32];
+    pin.copy_from_slice(hash.as_ref());
+    pin
+}
+
+// Trust anchors keep only the SPKI contents; pins cover the whole SEQUENCE
+fn wrap_sequence(contents: &[u8]) -> Vec<u8> {
+    let len = contents.len();
+    let mut out = vec![0x30];
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let skip = bytes.iter().take_while(|&&b| b == 0).count();
+        out.push(0x80 | (bytes.len() - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+    out.extend_from_slice(contents);
+    out
+}
+
+/// SPKI pins of every certificate on a validated path, trust anchor included
+fn path_pins(path: &webpki::VerifiedPath<'_>) -> Vec<SpkiPin> {
+    let mut pins = vec![sha256_pin(path.end_entity().subject_public_key_info().as_ref())];
+    pins.extend(
+        path.intermediate_certificates()
+            .map(|cert| sha256_pin(cert.subject_public_key_info().as_ref())),
+    );
+    pins.push(sha256_pin(&wrap_sequence(path.anchor().subject_public_key_info.as_ref())));
+    pins
+}
+
+/// Build a root store from the WebPKI roots plus an optional PEM CA bundle
+fn build_root_store(ca_bundle_pem: Option<&str>) -> Result<RootCertStore, CertVerifyError> {
+    let mut roots = RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+
+    if let Some(pem) = ca_bundle_pem {
+        let mut reader = pem.as_bytes();
+        let mut added = 0usize;
+        for cert in rustls_pemfile::certs(&mut reader) {
+            let cert = cert.map_err(|e| CertVerifyError::InvalidCaBundle(e.to_string()))?;
+            roots
+                .add(cert)
+                .map_err(|e| CertVerifyError::InvalidCaBundle(format!("{:?}", e)))?;
+            added += 1;
+        }
+
+        if added == 0 {
+            return Err(CertVerifyError::InvalidCaBundle("no certificates found".to_string()));
+        }
+    }
+
+    Ok(roots)
+}
+
+/// Server certificate verifier used by `QuicheClient`
+///
+/// Performs WebPKI chain and hostname validation, then enforces the SPKI pin
+/// set (if any). Each kind of rejection is sent as its own TLS alert, so the
+/// failed handshake reports it (see `CertVerifyError::from_alert`); the
+/// verifier is shared by concurrent handshakes and keeps no failure state.
+#[derive(Debug)]
+pub struct QuicCertVerifier {
+    webpki: Arc<WebPkiServerVerifier>,
+    roots: Arc<RootCertStore>,
+    provider: Arc<CryptoProvider>,
+    spki_pins: Vec<SpkiPin>,
+}
+
+impl QuicCertVerifier {
+    pub fn new(
+        ca_bundle_pem: Option<&str>,
+        spki_pins: Vec<SpkiPin>,
+        provider: Arc<CryptoProvider>,
+    ) -> Result<Self, CertVerifyError> {
+        let roots = Arc::new(build_root_store(ca_bundle_pem)?);
+        let webpki = WebPkiServerVerifier::builder_with_provider(roots.clone(), provider.clone())
+            .build()
+            .map_err(|e| CertVerifyError::Other(format!("{:?}", e)))?;
+
+        Ok(Self { webpki, roots, provider, spki_pins })
+    }
+
+    fn reject(&self, err: CertVerifyError) -> Error {
+        error!("Server certificate rejected: {}", err);
+        err.to_rustls()
+    }
+
+    // True if some trusted path for the chain contains a pinned key
+    //
+    // Certificates the peer sent but that are not on the validated path
+    // (including the trust anchor, which is never sent) are not considered.
+    fn check_pins(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        now: UnixTime,
+    ) -> bool {
+        let Ok(cert) = webpki::EndEntityCert::try_from(end_entity) else {
+            return false;
+        };
+        let pinned = |path: &webpki::VerifiedPath<'_>| {
+            if path_pins(path).iter().any(|pin| self.spki_pins.contains(pin)) {
+                Ok(())
+            } else {
+                Err(webpki::Error::UnknownIssuer)
+            }
+        };
+        cert.verify_for_usage(
+            self.provider.signature_verification_algorithms.all,
+            &self.roots.roots,
+            intermediates,
+            now,
+            webpki::KeyUsage::server_auth(),
+            None,
+            Some(&pinned),
+        )
+        .is_ok()
+    }
+}
+
+impl ServerCertVerifier for QuicCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, Error> {
+        if let Err(e) = self
+            .webpki
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp, now)
+        {
+            return Err(self.reject(CertVerifyError::from_rustls(&e)));
+        }
+
+        if !self.spki_pins.is_empty() && !self.check_pins(end_entity, intermediates, now) {
+            return Err(self.reject(CertVerifyError::PinMismatch));
+        }
+
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        self.webpki.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        self.webpki.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.webpki.supported_verify_schemes()
+    }
+}
+
+// Accept-all certificate verifier
+// Only used when QuicConfig::allow_insecure is explicitly set
+#[derive(Debug)]
+pub(crate) struct NoCertificateVerification {
+    schemes: Vec<SignatureScheme>,
+}
+
+impl NoCertificateVerification {
+    pub(crate) fn new(provider: &CryptoProvider) -> Self {
+        warn!("TLS certificate verification is DISABLED (allow_insecure=true)");
+        Self {
+            schemes: provider.signature_verification_algorithms.supported_schemes(),
+        }
+    }
+}
+
+impl ServerCertVerifier for NoCertificateVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        _ocsp: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, Error> {
+        warn!("Accepting server certificate for {:?} without verification", server_name);
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.schemes.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{BasicConstraints, Certificate, CertificateParams, DnType, IsCa, KeyPair};
+
+    struct Issuer {
+        cert: Certificate,
+        key: KeyPair,
+    }
+
+    fn ca(name: &str, parent: Option<&Issuer>) -> Issuer {
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.distinguished_name.push(DnType::CommonName, name);
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let key = KeyPair::generate().unwrap();
+        let cert = match parent {
+            Some(parent) => params.signed_by(&key, &parent.cert, &parent.key).unwrap(),
+            None => params.self_signed(&key).unwrap(),
+        };
+        Issuer { cert, key }
+    }
+
+    fn leaf(issuer: &Issuer) -> CertificateDer<'static> {
+        let mut params = CertificateParams::new(vec!["pin.test".to_string()]).unwrap();
+        params.distinguished_name.push(DnType::CommonName, "pin.test");
+        let key = KeyPair::generate().unwrap();
+        params.signed_by(&key, &issuer.cert, &issuer.key).unwrap().der().clone()
+    }
+
+    fn verify(
+        root: &Issuer,
+        pins: Vec<SpkiPin>,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+    ) -> Result<ServerCertVerified, Error> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let verifier = QuicCertVerifier::new(Some(&root.cert.pem()), pins, provider).unwrap();
+        let name = ServerName::try_from("pin.test").unwrap();
+        verifier.verify_server_cert(end_entity, intermediates, &name, &[], UnixTime::now())
+    }
+
+    fn pin(issuer: &Issuer) -> SpkiPin {
+        spki_sha256(issuer.cert.der()).unwrap()
+    }
+
+    #[test]
+    fn pins_match_any_certificate_on_the_verified_path() {
+        let root = ca("Pin Test Root", None);
+        let intermediate = ca("Pin Test Intermediate", Some(&root));
+        let end_entity = leaf(&intermediate);
+        let chain = [intermediate.cert.der().clone()];
+
+        assert!(verify(&root, vec![spki_sha256(&end_entity).unwrap()], &end_entity, &chain).is_ok());
+        assert!(verify(&root, vec![pin(&intermediate)], &end_entity, &chain).is_ok());
+        // The root is never sent by the server; it matches as the trust anchor
+        assert!(verify(&root, vec![pin(&root)], &end_entity, &chain).is_ok());
+    }
+
+    #[test]
+    fn unpinned_chain_is_rejected() {
+        let root = ca("Pin Test Root", None);
+        let intermediate = ca("Pin Test Intermediate", Some(&root));
+        let end_entity = leaf(&intermediate);
+        let chain = [intermediate.cert.der().clone()];
+
+        let err = verify(&root, vec![[7; 32]], &end_entity, &chain).unwrap_err();
+        assert_eq!(err, CertVerifyError::PinMismatch.to_rustls());
+    }
+
+    #[test]
+    fn appended_certificate_off_the_path_does_not_satisfy_pins() {
+        let root = ca("Pin Test Root", None);
+        let intermediate = ca("Pin Test Intermediate", Some(&root));
+        let end_entity = leaf(&intermediate);
+        let pinned = ca("Pinned Elsewhere", None);
+        let chain = [intermediate.cert.der().clone(), pinned.cert.der().clone()];
+
+        let err = verify(&root, vec![pin(&pinned)], &end_entity, &chain).unwrap_err();
+        assert_eq!(err, CertVerifyError::PinMismatch.to_rustls());
+    }
+}
@@ -3,16 +3,66 @@
  * High-performance QUIC client using Quinn
  */
 
-use quinn::{Endpoint, Connection, ClientConfig};
+use quinn::{Endpoint, EndpointConfig, Connection, ConnectionError, ClientConfig, RecvStream, SendStream, TokioRuntime, IdleTimeout, MtuDiscoveryConfig, SendDatagramError, TransportConfig, VarInt, ZeroRttAccepted};
+use rustls::client::Resumption;
+use quinn::congestion::{BbrConfig, CubicConfig, NewRenoConfig};
 use quinn::crypto::rustls::QuicClientConfig;
-use std::sync::Arc;
-use std::net::ToSocketAddrs;
-use log::{info, warn, error};
-use tokio::runtime::{Runtime, Handle};
-use tokio::io::AsyncWriteExt;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, Instant};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use log::{debug, info, warn, error};
+use tokio::runtime::Handle;
+use tokio::sync::{watch, Semaphore};
+use tokio::task::{JoinHandle, JoinSet};
+use bytes::Bytes;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering};
 use parking_lot::Mutex;
 use std::panic::{catch_unwind, AssertUnwindSafe};
+use crate::cert_verifier::{CertVerifyError, SpkiPin};
+use crate::flow_header::{encode_stream_header, TargetAddr};
+use crate::flow_router::FlowRouter;
+use crate::metrics::MetricsSampler;
+use crate::resolver::dns_cache;
+use crate::runtime::runtime;
+use crate::scheduler::{ConnectionScheduler, LinkLoad};
+use crate::session_store::{insecure_verifier, no_client_auth, session_store, shared_verifier};
+use crate::utils::{NetUtils, TimeUtils};
+
+// Minimum UDP payload every QUIC path must support (RFC 9000 §14)
+const QUIC_MIN_MTU: u16 = 1200;
+
+// DATAGRAM buffer sizes (bytes)
+const DATAGRAM_RECV_BUFFER: usize = 2 * 1024 * 1024;
+const DATAGRAM_SEND_BUFFER: usize = 1024 * 1024;
+
+// How connect() finished the handshake
+enum Handshake {
+    Complete(Duration),
+    // `confirm`: address to mark good once the handshake completes
+    EarlyData { started: Instant, accepted: ZeroRttAccepted, confirm: Option<IpAddr> },
+}
+
+/// Error type for work that runs on the shared runtime
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+// Stream-fallback packets queued but not yet written
+const MAX_PENDING_STREAM_SENDS: usize = 1024;
+
+// Longest doubling step in the reconnect backoff (base << 16)
+const MAX_BACKOFF_SHIFT: u32 = 16;
+
+// How long a new proxied flow waits for a usable connection
+const FLOW_OPEN_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Upper bound for QuicConfig::connection_count
+const MAX_CONNECTIONS: u32 = 16;
+
+// Shortest gap between raced connection attempts (RFC 8305 §5)
+const MIN_ATTEMPT_DELAY: Duration = Duration::from_millis(10);
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum CongestionControl {
@@ -42,11 +92,41 @@ pub struct QuicConfig {
     pub initial_max_streams_bidi: u64,
     pub initial_max_streams_uni: u64,
     pub max_idle_timeout_ms: u64,
+    /// Keep-alive PING interval (0 disables keep-alive)
+    pub keep_alive_interval_ms: u64,
     pub max_udp_payload_size: u16,
     pub enable_early_data: bool,
     pub enable_pacing: bool,
     pub enable_dgram: bool,
     pub enable_hystart: bool,
+    /// UDP segmentation offload for sends on the QUIC socket
+    pub enable_gso: bool,
+    /// UDP receive coalescing on the QUIC socket
+    pub enable_gro: bool,
+    /// Extra trusted roots (PEM), added on top of the WebPKI roots
+    pub ca_bundle_pem: Option<String>,
+    /// SPKI-SHA256 pins; if non-empty, one certificate in the chain must match
+    pub spki_pins: Vec<SpkiPin>,
+    /// Skip certificate verification entirely (testing only, logged)
+    pub allow_insecure: bool,
+    /// Re-establish the connection automatically when it is lost
+    pub auto_reconnect: bool,
+    /// Reconnect attempts before giving up (0 = retry forever)
+    pub reconnect_max_attempts: u32,
+    /// First reconnect delay; doubles per attempt (with jitter)
+    pub reconnect_base_delay_ms: u64,
+    /// Upper bound for the reconnect delay
+    pub reconnect_max_delay_ms: u64,
+    /// Parallel connections to keep open (raised to one per server if lower)
+    pub connection_count: u32,
+    /// More servers to spread connections over, after server_host:server_port
+    pub additional_servers: Vec<(String, u16)>,
+    /// How new flows pick a connection when there are several
+    pub scheduler: ConnectionScheduler,
+    /// Head start of each server address over the next when racing them
+    pub happy_eyeballs_delay_ms: u64,
+    /// How long system DNS answers are reused (0 disables the cache)
+    pub dns_cache_ttl_ms: u64,
 }
 
 impl Default for QuicConfig {
@@ -62,11 +142,26 @@ impl Default for QuicConfig {
             initial_max_streams_bidi: 1000,
             initial_max_streams_uni: 1000,
             max_idle_timeout_ms: 300000,
+            keep_alive_interval_ms: 15000,
             max_udp_payload_size: 1350,
             enable_early_data: true,
             enable_pacing: false,
             enable_dgram: true,
             enable_hystart: true,
+            enable_gso: true,
+            enable_gro: true,
+            ca_bundle_pem: None,
+            spki_pins: Vec::new(),
+            allow_insecure: false,
+            auto_reconnect: true,
+            reconnect_max_attempts: 10,
+            reconnect_base_delay_ms: 500,
+            reconnect_max_delay_ms: 30000,
+            connection_count: 1,
+            additional_servers: Vec::new(),
+            scheduler: ConnectionScheduler::RoundRobin,
+            happy_eyeballs_delay_ms: 250,
+            dns_cache_ttl_ms: 60000,
         }
     }
 }
@@ -86,55 +181,401 @@ pub struct QuicMetrics {
     pub bytes_in_flight: u64,
     pub is_established: bool,
     pub is_in_early_data: bool,
+    pub zero_rtt_attempts: u64,
+    pub zero_rtt_accepted: u64,
+    pub zero_rtt_rejected: u64,
+    pub migration_count: u64,
+    pub last_migration_timestamp_ms: u64,
+    pub is_reconnecting: bool,
+    pub reconnect_attempts: u64,
+    pub reconnect_count: u64,
     pub handshake_duration_us: u64,
+    pub datagrams_sent: u64,
+    pub datagrams_received: u64,
+    pub datagrams_oversized: u64,
+    /// Connections in the pool / with an established handshake
+    pub connections: u64,
+    pub connections_established: u64,
 }
 
-pub struct QuicheClient {
-    config: QuicConfig,
-    endpoint: Option<Endpoint>,
-    connection: Option<Connection>,
-    runtime: Runtime,
-    connected: Arc<AtomicBool>,
+impl QuicMetrics {
+    /// Combine per-connection metrics into client-wide totals
+    ///
+    /// Counters, rates and windows add up; RTT and loss rate are averaged over
+    /// established connections; flags are set if any connection has them.
+    pub fn aggregate(parts: &[QuicMetrics]) -> QuicMetrics {
+        let mut total = QuicMetrics {
+            connections: parts.len() as u64,
+            ..Default::default()
+        };
+
+        for m in parts {
+            total.bytes_sent += m.bytes_sent;
+            total.bytes_received += m.bytes_received;
+            total.throughput_mbps += m.throughput_mbps;
+            total.packets_sent += m.packets_sent;
+            total.packets_received += m.packets_received;
+            total.packets_lost += m.packets_lost;
+            total.cwnd += m.cwnd;
+            total.bytes_in_flight += m.bytes_in_flight;
+            total.is_established |= m.is_established;
+            total.is_in_early_data |= m.is_in_early_data;
+            total.zero_rtt_attempts += m.zero_rtt_attempts;
+            total.zero_rtt_accepted += m.zero_rtt_accepted;
+            total.zero_rtt_rejected += m.zero_rtt_rejected;
+            total.migration_count += m.migration_count;
+            total.last_migration_timestamp_ms = total.last_migration_timestamp_ms.max(m.last_migration_timestamp_ms);
+            total.is_reconnecting |= m.is_reconnecting;
+            total.reconnect_attempts += m.reconnect_attempts;
+            total.reconnect_count += m.reconnect_count;
+            // The pool is usable once the slowest handshake is done
+            total.handshake_duration_us = total.handshake_duration_us.max(m.handshake_duration_us);
+            total.datagrams_sent += m.datagrams_sent;
+            total.datagrams_received += m.datagrams_received;
+            total.datagrams_oversized += m.datagrams_oversized;
+        }
+
+        let established: Vec<&QuicMetrics> = parts.iter().filter(|m| m.is_established).collect();
+        if !established.is_empty() {
+            let n = established.len();
+            total.connections_established = n as u64;
+            total.rtt_us = established.iter().map(|m| m.rtt_us).sum::<u64>() / n as u64;
+            total.min_rtt_us = established.iter().map(|m| m.min_rtt_us).min().unwrap_or(0);
+            total.packet_loss_rate = established.iter().map(|m| m.packet_loss_rate).sum::<f64>() / n as f64;
+        }
+
+        total
+    }
+}
+
+/// Packet exceeds the DATAGRAM size currently allowed by the path and peer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DatagramTooLarge {
+    pub size: usize,
+    pub max: usize,
+}
+
+impl fmt::Display for DatagramTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "datagram of {} bytes exceeds max_datagram_size {}", self.size, self.max)
+    }
+}
+
+impl std::error::Error for DatagramTooLarge {}
+
+/// Reader of a client's downlink DATAGRAMs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DatagramReader {
+    /// A packet-mode TUN forwarder: every DATAGRAM is an IP packet
+    Packets,
+    /// The flow router: DATAGRAMs carry a flow header
+    Flows,
+}
+
+/// Held by a packet-mode TUN forwarder while it reads the DATAGRAMs
+pub struct PacketDatagramClaim {
+    shared: Arc<Shared>,
+}
+
+impl Drop for PacketDatagramClaim {
+    fn drop(&mut self) {
+        *self.shared.datagram_reader.lock() = None;
+    }
+}
+
+/// Observes the client's connection across reconnects without locking the client
+#[derive(Clone)]
+pub struct ConnectionWatch {
+    connection: watch::Receiver<Option<Connection>>,
+    reconnecting: Arc<AtomicBool>,
+}
+
+impl ConnectionWatch {
+    /// True while a lost connection is being re-established
+    pub fn is_reconnecting(&self) -> bool {
+        self.reconnecting.load(Ordering::Acquire)
+    }
+
+    /// Wait until an open connection is available (None once the client is gone)
+    pub async fn open_connection(&mut self) -> Option<Connection> {
+        self.connection
+            .wait_for(|c| c.as_ref().is_some_and(|c| c.close_reason().is_none()))
+            .await
+            .ok()
+            .and_then(|c| c.clone())
+    }
+}
+
+// One pooled connection: its endpoint, connection and supervisor
+struct Link {
+    index: usize,
+    server_host: String,
+    server_port: u16,
+    endpoint: Mutex<Option<Endpoint>>,
+    // UDP socket behind the endpoint (-1 when there is none)
+    socket_fd: AtomicI32,
+    connection: watch::Sender<Option<Connection>>,
+    connected: AtomicBool,
+    reconnecting: Arc<AtomicBool>,
     metrics: Arc<Mutex<QuicMetrics>>,
+    sampler_task: Mutex<Option<JoinHandle<()>>>,
+    supervisor_task: Mutex<Option<JoinHandle<()>>>,
+    // Flows currently assigned here (see FlowLink)
+    active_flows: AtomicUsize,
+}
+
+impl Link {
+    fn new(index: usize, server_host: String, server_port: u16) -> Self {
+        let (connection, _) = watch::channel(None);
+        Self {
+            index,
+            server_host,
+            server_port,
+            endpoint: Mutex::new(None),
+            socket_fd: AtomicI32::new(-1),
+            connection,
+            connected: AtomicBool::new(false),
+            reconnecting: Arc::new(AtomicBool::new(false)),
+            metrics: Arc::new(Mutex::new(QuicMetrics::default())),
+            sampler_task: Mutex::new(None),
+            supervisor_task: Mutex::new(None),
+            active_flows: AtomicUsize::new(0),
+        }
+    }
+
+    // Connection if established and not yet closed
+    fn open_connection(&self) -> Option<Connection> {
+        if !self.connected.load(Ordering::Acquire) {
+            return None;
+        }
+        self.connection.borrow().clone().filter(|c| c.close_reason().is_none())
+    }
+
+    fn watch(&self) -> ConnectionWatch {
+        ConnectionWatch {
+            connection: self.connection.subscribe(),
+            reconnecting: self.reconnecting.clone(),
+        }
+    }
+
+    fn mark_disconnected(&self) {
+        self.connected.store(false, Ordering::Release);
+        if let Some(task) = self.sampler_task.lock().take() {
+            task.abort();
+        }
+        self.connection.send_replace(None);
+        self.metrics.lock().is_established = false;
+    }
+
+    fn send_datagram_on(&self, conn: &Connection, packet: Bytes) -> Result<usize, Box<dyn std::error::Error>> {
+        let len = packet.len();
+        let max = conn.max_datagram_size()
+            .ok_or("Peer does not support QUIC DATAGRAM")?;
+        if len > max {
+            return Err(self.drop_oversized(len, max));
+        }
+
+        match conn.send_datagram(packet) {
+            Ok(()) => Ok(len),
+            // Path MTU may shrink between the check above and the send
+            Err(SendDatagramError::TooLarge) => {
+                let max = conn.max_datagram_size().unwrap_or(0);
+                Err(self.drop_oversized(len, max))
+            }
+            Err(e) => Err(format!("Failed to send datagram: {}", e).into()),
+        }
+    }
+
+    fn drop_oversized(&self, size: usize, max: usize) -> Box<dyn std::error::Error> {
+        self.metrics.lock().datagrams_oversized += 1;
+        debug!("Dropping oversized datagram ({} > {} bytes)", size, max);
+        Box::new(DatagramTooLarge { size, max })
+    }
+}
+
+// Client state shared with the supervisor tasks
+struct Shared {
+    config: Mutex<QuicConfig>,
+    transport: Mutex<Arc<TransportConfig>>,
+    links: Vec<Arc<Link>>,
+    // Links with an open connection, so flows can wait for any of them
+    open_links: watch::Sender<usize>,
+    // Set by disconnect() so a deliberate close is not treated as a loss
+    closing: AtomicBool,
+    connecting: AtomicBool,
+    scheduler: ConnectionScheduler,
+    // Round-robin position
+    next_turn: AtomicUsize,
+    stream_sends: Arc<Semaphore>,
+    flow_router: OnceLock<Arc<FlowRouter>>,
+    // Which side reads downlink DATAGRAMs; quinn hands each one to a single reader
+    datagram_reader: Mutex<Option<DatagramReader>>,
+}
+
+impl Shared {
+    fn mark_disconnected(&self, link: &Link) {
+        link.mark_disconnected();
+        self.publish_open_links();
+    }
+
+    fn publish_open_links(&self) {
+        let open = self.links.iter().filter(|link| link.open_connection().is_some()).count();
+        self.open_links.send_replace(open);
+    }
+
+    // Scheduler's pick among links with an open connection
+    fn select_link(&self) -> Option<(Arc<Link>, Connection)> {
+        let mut open = Vec::with_capacity(self.links.len());
+        let mut candidates = Vec::with_capacity(self.links.len());
+        for link in &self.links {
+            if let Some(conn) = link.open_connection() {
+                candidates.push(LinkLoad {
+                    index: link.index,
+                    rtt: conn.rtt(),
+                    active_flows: link.active_flows.load(Ordering::Relaxed),
+                });
+                open.push((link.clone(), conn));
+            }
+        }
+
+        let turn = self.next_turn.fetch_add(1, Ordering::Relaxed);
+        let index = self.scheduler.pick(&candidates, turn)?;
+        open.into_iter().find(|(link, _)| link.index == index)
+    }
+
+    // Lowest-index open link; packets stay on one connection and keep their order
+    fn primary_connection(&self) -> Option<(&Link, Connection)> {
+        self.links.iter().find_map(|link| link.open_connection().map(|conn| (link.as_ref(), conn)))
+    }
+}
+
+// Clears Shared::connecting when a connect attempt ends (including by panic)
+struct ConnectingGuard<'a>(&'a AtomicBool);
+
+impl Drop for ConnectingGuard<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+/// A pooled connection assigned to one flow
+///
+/// Counts toward the connection's active flows (used by `LeastInflight`)
+/// until dropped, so hold it for as long as the flow lives.
+pub struct FlowLink {
+    link: Arc<Link>,
+}
+
+impl FlowLink {
+    fn new(link: Arc<Link>) -> Self {
+        link.active_flows.fetch_add(1, Ordering::Relaxed);
+        Self { link }
+    }
+
+    /// Position of the connection in the client's pool
+    pub fn index(&self) -> usize {
+        self.link.index
+    }
+
+    /// Send one flow datagram on this connection
+    pub fn send_datagram(&self, data: &[u8]) -> Result<usize, Box<dyn std::error::Error>> {
+        let conn = self.link.open_connection().ok_or("Not connected")?;
+        self.link.send_datagram_on(&conn, Bytes::copy_from_slice(data))
+    }
+}
+
+impl Drop for FlowLink {
+    fn drop(&mut self) {
+        self.link.active_flows.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// QUIC client
+///
+/// All methods take `&self`; connection work runs on the shared runtime, so a
+/// handle can be used from any thread without an outer lock. With
+/// `connection_count` > 1 or `additional_servers`, the client keeps a pool of
+/// connections: flows are spread by the scheduler, raw packets use the first
+/// open one.
+pub struct QuicheClient {
+    shared: Arc<Shared>,
+    config_warnings: Vec<String>,
+    dgram_enabled: bool,
 }
 
 impl QuicheClient {
     pub fn create(config: QuicConfig) -> Result<Self, Box<dyn std::error::Error>> {
         info!("Creating QUIC client for {}:{}", config.server_host, config.server_port);
 
-        // Try to use existing runtime handle first (if called from async context)
-        // Otherwise create a new current-thread runtime (safer for JNI)
-        let runtime = match Handle::try_current() {
-            Ok(_) => {
-                // We're in an async context, but we still need our own runtime for block_on
-                // Create a minimal current-thread runtime
-                Runtime::new()?
-            }
-            Err(_) => {
-                // Not in async context, create current-thread runtime (safer for JNI)
-                // Use Builder to create a current-thread runtime explicitly
-                tokio::runtime::Builder::new_current_thread()
-                    .enable_all()
-                    .build()
-                    .map_err(|e| format!("Failed to create Tokio runtime: {}", e))?
-            }
-        };
+        if config.connection_count > MAX_CONNECTIONS {
+            return Err(format!(
+                "connection_count {} exceeds the maximum of {}",
+                config.connection_count, MAX_CONNECTIONS
+            ).into());
+        }
+        // Every server gets a connection, so the server count is capped too
+        let server_count = 1 + config.additional_servers.len();
+        if server_count > MAX_CONNECTIONS as usize {
+            return Err(format!(
+                "{} servers exceed the maximum of {} connections",
+                server_count, MAX_CONNECTIONS
+            ).into());
+        }
 
         // Configure CPU affinity (non-fatal, continue even if it fails)
         if let Err(e) = Self::configure_cpu_affinity(&config) {
             warn!("Failed to configure CPU affinity: {} (non-fatal, continuing)", e);
         }
 
+        // Map QuicConfig onto quinn; invalid values fail creation, unsupported
+        // ones are downgraded and reported via config_warnings()
+        let mut config_warnings = Vec::new();
+        let transport = Arc::new(Self::build_transport_config(&config, &mut config_warnings)?);
+        for w in &config_warnings {
+            warn!("QUIC config: {}", w);
+        }
+
+        let links: Vec<Arc<Link>> = Self::link_targets(&config)
+            .into_iter()
+            .enumerate()
+            .map(|(index, (host, port))| Arc::new(Link::new(index, host, port)))
+            .collect();
+        if links.len() > 1 {
+            info!("Connection pool: {} connections, scheduler {:?}", links.len(), config.scheduler);
+        }
+
+        let (open_links, _) = watch::channel(0);
+        let dgram_enabled = config.enable_dgram;
+        let scheduler = config.scheduler;
+
         Ok(Self {
-            config,
-            endpoint: None,
-            connection: None,
-            runtime,
-            connected: Arc::new(AtomicBool::new(false)),
-            metrics: Arc::new(Mutex::new(QuicMetrics::default())),
+            shared: Arc::new(Shared {
+                config: Mutex::new(config),
+                transport: Mutex::new(transport),
+                links,
+                open_links,
+                closing: AtomicBool::new(false),
+                connecting: AtomicBool::new(false),
+                scheduler,
+                next_turn: AtomicUsize::new(0),
+                stream_sends: Arc::new(Semaphore::new(MAX_PENDING_STREAM_SENDS)),
+                flow_router: OnceLock::new(),
+                datagram_reader: Mutex::new(None),
+            }),
+            config_warnings,
+            dgram_enabled,
         })
     }
 
+    // Server of each pooled connection: connection_count spread over the server list
+    fn link_targets(config: &QuicConfig) -> Vec<(String, u16)> {
+        let servers: Vec<(String, u16)> = std::iter::once((config.server_host.clone(), config.server_port))
+            .chain(config.additional_servers.iter().cloned())
+            .collect();
+        let count = (config.connection_count.max(1) as usize).max(servers.len());
+        servers.into_iter().cycle().take(count).collect()
+    }
+
     fn configure_cpu_affinity(config: &QuicConfig) -> Result<(), Box<dyn std::error::Error>> {
         // Skip if None
         if matches!(config.cpu_affinity, CpuAffinity::None) {
@@ -184,192 +625,1262 @@ impl QuicheClient {
         }
     }
 
-    pub fn connect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        if self.connected.load(Ordering::Acquire) {
+    /// Connect and wait for the handshake (blocks the calling thread)
+    ///
+    /// Must not be called from a runtime thread; use `connect_async` there.
+    pub fn connect(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let shared = self.shared.clone();
+
+        // Wrap the entire connect logic in panic catching
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            runtime().block_on(Self::run_connect(shared))
+        }));
+
+        match result {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(e)) => Err(e),
+            Err(_) => {
+                let msg = "Panic occurred during connection (this should not happen)";
+                error!("{}", msg);
+                Err(msg.into())
+            }
+        }
+    }
+
+    /// Connect in the background; `done` runs on a runtime thread with the result
+    pub fn connect_async<F>(&self, done: F)
+    where
+        F: FnOnce(Result<(), BoxError>) + Send + 'static,
+    {
+        let shared = self.shared.clone();
+        runtime().spawn(async move {
+            // Separate task so a panic still reaches the callback
+            let result = match tokio::spawn(Self::run_connect(shared)).await {
+                Ok(result) => result,
+                Err(_) => {
+                    let msg = "Panic occurred during connection (this should not happen)";
+                    error!("{}", msg);
+                    Err(msg.into())
+                }
+            };
+            done(result);
+        });
+    }
+
+    async fn run_connect(shared: Arc<Shared>) -> Result<(), BoxError> {
+        if shared.links.iter().any(|link| link.connected.load(Ordering::Acquire)) {
             warn!("Already connected");
             return Ok(());
         }
+        if shared.connecting.swap(true, Ordering::AcqRel) {
+            return Err("Connect already in progress".into());
+        }
+        let _connecting = ConnectingGuard(&shared.connecting);
 
-        info!("Connecting to {}:{}...", self.config.server_host, self.config.server_port);
+        // A manual connect takes over from any reconnect in progress
+        for link in &shared.links {
+            if let Some(task) = link.supervisor_task.lock().take() {
+                task.abort();
+            }
+            link.reconnecting.store(false, Ordering::Release);
+            link.metrics.lock().is_reconnecting = false;
+        }
+        shared.closing.store(false, Ordering::Release);
 
-        // Wrap the entire connect logic in panic catching
-        let result = catch_unwind(AssertUnwindSafe(|| {
-            // Resolve server address
-            let addr = format!("{}:{}", self.config.server_host, self.config.server_port);
-            let mut addrs = addr.to_socket_addrs()
-                .map_err(|e| format!("DNS resolution failed: {}", e))?;
-            let server_addr = addrs.next()
-                .ok_or_else(|| "Failed to resolve server address".to_string())?;
-
-            info!("Resolved address: {:?}", server_addr);
-
-            // Create client config with rustls
-            // For now, use default config (accepts any certificate)
-            // In production, you should use proper certificate validation
-            // rustls 0.23 uses dangerous() instead of with_safe_defaults()
-            let crypto = rustls::ClientConfig::builder()
-                .dangerous()
-                .with_custom_certificate_verifier(Arc::new(NoCertificateVerification))
-                .with_no_client_auth();
-            
-            let quic_crypto = QuicClientConfig::try_from(Arc::new(crypto))
-                .map_err(|e| format!("Failed to create QUIC crypto config: {:?}", e))?;
-            let client_config = ClientConfig::new(Arc::new(quic_crypto));
-
-            // Create endpoint
-            let mut endpoint = Endpoint::client("[::]:0".parse()
-                .map_err(|e| format!("Failed to parse bind address: {:?}", e))?)
-                .map_err(|e| format!("Failed to create endpoint: {:?}", e))?;
-            endpoint.set_default_client_config(client_config);
-
-            info!("Endpoint created, initiating connection...");
-
-            // Connect with timeout protection
-            let new_conn = self.runtime.block_on(async {
-                let connecting = endpoint.connect(server_addr, &self.config.server_host)
-                    .map_err(|e| -> Box<dyn std::error::Error> { 
-                        error!("Connection initiation failed: {:?}", e);
-                        format!("Connection failed: {:?}", e).into() 
-                    })?;
-                
+        {
+            let config = shared.config.lock();
+            info!("Connecting to {}:{}...", config.server_host, config.server_port);
+        }
+
+        // Handshake every pooled connection at once
+        let mut attempts = JoinSet::new();
+        for link in &shared.links {
+            let (shared, link) = (shared.clone(), link.clone());
+            attempts.spawn(async move {
+                let result = Self::establish(&shared, &link).await;
+                (link, result)
+            });
+        }
+        let mut results = Vec::with_capacity(shared.links.len());
+        while let Some(joined) = attempts.join_next().await {
+            match joined {
+                Ok(result) => results.push(result),
+                Err(_) => error!("Panic occurred during connection (this should not happen)"),
+            }
+        }
+        results.sort_by_key(|(link, _)| link.index);
+
+        let established = results.iter().filter(|(_, result)| result.is_ok()).count();
+        if established == 0 {
+            let e = results
+                .into_iter()
+                .find_map(|(_, result)| result.err())
+                .unwrap_or_else(|| "Connect failed".into());
+            error!("Connect failed: {}", e);
+            return Err(e);
+        }
+        if established < shared.links.len() {
+            warn!("{} of {} connections established, retrying the rest in the background",
+                  established, shared.links.len());
+        }
+
+        for (link, result) in results {
+            let conn = match result {
+                Ok(conn) => Some(conn),
+                // Retrying will not fix a rejected certificate
+                Err(e) if e.downcast_ref::<CertVerifyError>().is_some() => {
+                    error!("Connection {} to {} rejected: {}", link.index, link.server_host, e);
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Connection {} to {} failed: {}", link.index, link.server_host, e);
+                    None
+                }
+            };
+            // Watch for connection loss and reconnect in the background
+            *link.supervisor_task.lock() = Some(tokio::spawn(Self::supervise(shared.clone(), link.clone(), conn)));
+        }
+        Ok(())
+    }
+
+    // Resolve, handshake and install a new connection (used by connect and reconnect)
+    async fn establish(shared: &Arc<Shared>, link: &Arc<Link>) -> Result<Connection, BoxError> {
+        let config = shared.config.lock().clone();
+
+        // Resolve server address (cached; app-provided addresses take precedence)
+        let candidates = dns_cache()
+            .resolve(&link.server_host, link.server_port, Duration::from_millis(config.dns_cache_ttl_ms))
+            .await?;
+
+        info!("Resolved addresses: {:?}", candidates);
+
+        // Create client config with rustls
+        let crypto = Self::build_tls_config(&config)?;
+        let verify = !config.allow_insecure;
+
+        let quic_crypto = QuicClientConfig::try_from(Arc::new(crypto))
+            .map_err(|e| format!("Failed to create QUIC crypto config: {:?}", e))?;
+        let mut client_config = ClientConfig::new(Arc::new(quic_crypto));
+        client_config.transport_config(shared.transport.lock().clone());
+
+        // Create endpoint (runs on the shared runtime, so quinn can spawn its driver)
+        let socket = UdpSocket::bind(SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)))
+            .map_err(|e| format!("Failed to bind UDP socket: {}", e))?;
+        socket.set_nonblocking(true)?;
+        let socket_fd = socket.as_raw_fd();
+        let mut endpoint = Endpoint::new(EndpointConfig::default(), None, socket, Arc::new(TokioRuntime))
+            .map_err(|e| format!("Failed to create endpoint: {:?}", e))?;
+        endpoint.set_default_client_config(client_config);
+        Self::configure_udp_offload(socket_fd, &config);
+
+        info!("Endpoint created, initiating connection...");
+
+        let handshake_start = Instant::now();
+        let attempt = match candidates[..] {
+            [addr] => {
+                Self::connect_single(&endpoint, addr, &link.server_host, config.enable_early_data, verify, handshake_start).await
+            }
+            [first, ..] => {
+                // Resume on the address that worked last time. Its preference is
+                // dropped until the handshake completes, so if it went away the
+                // next attempt races every address again.
+                let last_good = dns_cache().take_good(&link.server_host).filter(|ip| *ip == first.ip());
+                let early = match last_good {
+                    Some(_) if config.enable_early_data => Self::connect_early(&endpoint, first, &link.server_host),
+                    _ => None,
+                };
+                match early {
+                    Some((conn, accepted)) => Ok((conn, Handshake::EarlyData {
+                        started: handshake_start,
+                        accepted,
+                        confirm: Some(first.ip()),
+                    })),
+                    None => {
+                        let delay = Duration::from_millis(config.happy_eyeballs_delay_ms).max(MIN_ATTEMPT_DELAY);
+                        Self::race_candidates(&endpoint, &candidates, &link.server_host, verify, delay)
+                            .await
+                            .map(|(conn, addr)| {
+                                dns_cache().mark_good(&link.server_host, addr.ip());
+                                (conn, Handshake::Complete(handshake_start.elapsed()))
+                            })
+                    }
+                }
+            }
+            [] => Err("No address to connect to".into()),
+        };
+        let (conn, handshake) = attempt?;
+
+        info!("Connection established successfully");
+
+        // Install under the endpoint lock so a concurrent disconnect() wins
+        let mut endpoint_slot = link.endpoint.lock();
+        if shared.closing.load(Ordering::Acquire) {
+            conn.close(0u32.into(), b"");
+            return Err("Disconnected while connecting".into());
+        }
+
+        // Start sampling connection statistics into QuicMetrics
+        *link.sampler_task.lock() = Some(MetricsSampler::spawn(
+            conn.clone(),
+            link.metrics.clone(),
+            &Handle::current(),
+        ));
+
+        *endpoint_slot = Some(endpoint);
+        link.socket_fd.store(socket_fd, Ordering::Release);
+        link.connection.send_replace(Some(conn.clone()));
+        link.connected.store(true, Ordering::Release);
+        drop(endpoint_slot);
+        shared.publish_open_links();
+
+        // Update metrics
+        let mut metrics = link.metrics.lock();
+        metrics.is_established = true;
+        match handshake {
+            Handshake::Complete(duration) => {
+                metrics.is_in_early_data = false;
+                metrics.handshake_duration_us = duration.as_micros() as u64;
+                info!("Connected successfully (handshake {} ms)", duration.as_millis());
+            }
+            Handshake::EarlyData { started, accepted, confirm } => {
+                metrics.is_in_early_data = true;
+                metrics.zero_rtt_attempts += 1;
+                let confirm = confirm.map(|ip| (conn.clone(), link.server_host.clone(), ip));
+                tokio::spawn(Self::await_early_data(accepted, started, link.metrics.clone(), confirm));
+                info!("Connected with 0-RTT, handshake continuing in background");
+            }
+        }
+        drop(metrics);
+
+        Ok(conn)
+    }
+
+    // Connect to the only known address, resuming with 0-RTT when possible
+    async fn connect_single(
+        endpoint: &Endpoint,
+        server_addr: SocketAddr,
+        server_name: &str,
+        early_data: bool,
+        verify: bool,
+        started: Instant,
+    ) -> Result<(Connection, Handshake), BoxError> {
+        let connecting = endpoint.connect(server_addr, server_name)
+            .map_err(|e| -> BoxError {
+                error!("Connection initiation failed: {:?}", e);
+                format!("Connection failed: {:?}", e).into()
+            })?;
+
+        // Resume with 0-RTT when a ticket for this server is cached
+        let early = if early_data {
+            connecting.into_0rtt()
+        } else {
+            Err(connecting)
+        };
+
+        match early {
+            Ok((conn, accepted)) => {
+                info!("Resuming session, sending 0-RTT early data");
+                Ok((conn, Handshake::EarlyData { started, accepted, confirm: None }))
+            }
+            Err(connecting) => {
                 info!("Connection initiated, waiting for handshake...");
-                connecting.await
-                    .map_err(|e| -> Box<dyn std::error::Error> { 
+                let conn = connecting.await
+                    .map_err(|e| {
                         error!("Connection handshake failed: {:?}", e);
-                        format!("Connection handshake failed: {:?}", e).into() 
-                    })
-            })?;
+                        Self::handshake_error(&e, verify)
+                    })?;
+                Ok((conn, Handshake::Complete(started.elapsed())))
+            }
+        }
+    }
 
-            info!("Connection established successfully");
-            Ok((endpoint, new_conn))
-        }));
+    // Start a 0-RTT handshake to `addr`, or None without a usable ticket
+    //
+    // The connection is usable at once; its handshake finishes in the background.
+    fn connect_early(endpoint: &Endpoint, addr: SocketAddr, server_name: &str) -> Option<(Connection, ZeroRttAccepted)> {
+        let connecting = endpoint.connect(addr, server_name)
+            .inspect_err(|e| warn!("Connection to {} failed: {:?}", addr, e))
+            .ok()?;
+        match connecting.into_0rtt() {
+            Ok(early) => {
+                info!("Resuming session with {}, sending 0-RTT early data", addr);
+                Some(early)
+            }
+            Err(_) => {
+                debug!("No session ticket for {}, racing all addresses", server_name);
+                None
+            }
+        }
+    }
 
-        match result {
-            Ok(Ok((endpoint, new_conn))) => {
-                self.endpoint = Some(endpoint);
-                self.connection = Some(new_conn);
-                self.connected.store(true, Ordering::Release);
+    // Happy eyeballs (RFC 8305 §5): start a handshake per candidate, the next
+    // one `delay` after the previous or as soon as it fails; the first
+    // completed handshake wins and dropping the rest abandons them.
+    //
+    // 0-RTT would hide which address is reachable, so racing uses full
+    // handshakes (the session is still resumed); establish() sends 0-RTT only
+    // to the address that worked last time.
+    async fn race_candidates(
+        endpoint: &Endpoint,
+        candidates: &[SocketAddr],
+        server_name: &str,
+        verify: bool,
+        delay: Duration,
+    ) -> Result<(Connection, SocketAddr), BoxError> {
+        let mut attempts = JoinSet::new();
+        let mut pending = candidates.iter().copied().peekable();
+        let mut last_error: Option<BoxError> = None;
+        // A certificate rejection is reported over network errors
+        let mut cert_error: Option<BoxError> = None;
 
-                // Update metrics
-                let mut metrics = self.metrics.lock();
-                metrics.is_established = true;
-                drop(metrics);
+        loop {
+            // Every wakeup (delay elapsed or an attempt failed) starts the next address
+            if let Some(addr) = pending.next() {
+                debug!("Attempting {}", addr);
+                match endpoint.connect(addr, server_name) {
+                    Ok(connecting) => {
+                        attempts.spawn(async move { (addr, connecting.await) });
+                    }
+                    Err(e) => {
+                        last_error = Some(format!("Connection to {} failed: {:?}", addr, e).into());
+                        continue;
+                    }
+                }
+            }
 
-                info!("Connected successfully");
-                Ok(())
+            if attempts.is_empty() {
+                error!("Connection handshake failed on all {} addresses", candidates.len());
+                return Err(cert_error.or(last_error).unwrap_or_else(|| "No address to connect to".into()));
             }
-            Ok(Err(e)) => {
-                error!("Connect failed: {}", e);
-                Err(e)
+
+            let more = pending.peek().is_some();
+            tokio::select! {
+                Some(joined) = attempts.join_next() => match joined {
+                    Ok((addr, Ok(conn))) => {
+                        info!("Handshake with {} won the race", addr);
+                        return Ok((conn, addr));
+                    }
+                    Ok((addr, Err(e))) => {
+                        warn!("Connection handshake with {} failed: {:?}", addr, e);
+                        let err = Self::handshake_error(&e, verify);
+                        if err.is::<CertVerifyError>() {
+                            cert_error = Some(err);
+                        } else {
+                            last_error = Some(err);
+                        }
+                    }
+                    Err(_) => {}
+                },
+                _ = tokio::time::sleep(delay), if more => {}
             }
-            Err(_) => {
-                let msg = "Panic occurred during connection (this should not happen)";
-                error!("{}", msg);
-                Err(msg.into())
+        }
+    }
+
+    // Error for a failed handshake; certificate rejections by our verifier
+    // (`verify`) come back as CertVerifyError, read from this handshake's alert
+    fn handshake_error(e: &ConnectionError, verify: bool) -> BoxError {
+        if let ConnectionError::TransportError(err) = e {
+            let code = u64::from(err.code);
+            // Crypto errors carry the TLS alert (RFC 9001 §4.8)
+            if verify && code & !0xff == 0x100 {
+                if let Some(cert_err) = CertVerifyError::from_alert(code as u8, &err.reason) {
+                    return Box::new(cert_err);
+                }
             }
         }
+        format!("Connection handshake failed: {:?}", e).into()
     }
 
-    pub fn disconnect(&mut self) {
-        if !self.connected.load(Ordering::Acquire) {
-            return;
+    // Wait for the connection to close, then reconnect unless disconnect() closed it
+    //
+    // Starts reconnecting right away when the initial connect of this link failed.
+    async fn supervise(shared: Arc<Shared>, link: Arc<Link>, mut conn: Option<Connection>) {
+        loop {
+            if let Some(conn) = conn.take() {
+                let reason = conn.closed().await;
+                if shared.closing.load(Ordering::Acquire) {
+                    return;
+                }
+
+                warn!("QUIC connection {} lost: {}", link.index, reason);
+                shared.mark_disconnected(&link);
+            }
+
+            match Self::reconnect(&shared, &link).await {
+                Some(new_conn) => conn = Some(new_conn),
+                None => return,
+            }
         }
+    }
 
-        info!("Disconnecting...");
-        self.connected.store(false, Ordering::Release);
+    // Retry establish() with jittered exponential backoff
+    async fn reconnect(shared: &Arc<Shared>, link: &Arc<Link>) -> Option<Connection> {
+        let (enabled, max_attempts, base_ms, max_ms) = {
+            let config = shared.config.lock();
+            (config.auto_reconnect, config.reconnect_max_attempts,
+             config.reconnect_base_delay_ms, config.reconnect_max_delay_ms)
+        };
+        if !enabled {
+            info!("Auto-reconnect disabled, staying disconnected");
+            return None;
+        }
 
-        if let Some(conn) = &self.connection {
-            conn.close(0u32.into(), b"");
+        link.reconnecting.store(true, Ordering::Release);
+        link.metrics.lock().is_reconnecting = true;
+
+        let mut attempt = 0u32;
+        let result = loop {
+            if max_attempts != 0 && attempt >= max_attempts {
+                error!("Giving up after {} reconnect attempts", attempt);
+                break None;
+            }
+
+            let delay = backoff_delay(attempt, base_ms, max_ms);
+            attempt += 1;
+            info!("Reconnecting connection {} in {} ms (attempt {})", link.index, delay.as_millis(), attempt);
+            tokio::time::sleep(delay).await;
+
+            if shared.closing.load(Ordering::Acquire) {
+                break None;
+            }
+
+            link.metrics.lock().reconnect_attempts += 1;
+            match Self::establish(shared, link).await {
+                Ok(conn) => {
+                    info!("Reconnected after {} attempt(s)", attempt);
+                    link.metrics.lock().reconnect_count += 1;
+                    break Some(conn);
+                }
+                // Retrying will not fix a rejected certificate
+                Err(e) if e.downcast_ref::<CertVerifyError>().is_some() => {
+                    error!("Reconnect aborted: {}", e);
+                    break None;
+                }
+                Err(e) => warn!("Reconnect attempt {} failed: {}", attempt, e),
+            }
+        };
+
+        link.reconnecting.store(false, Ordering::Release);
+        link.metrics.lock().is_reconnecting = false;
+        result
+    }
+
+    // Record the server's verdict on 0-RTT once the handshake completes
+    async fn await_early_data(
+        accepted: ZeroRttAccepted,
+        started: Instant,
+        metrics: Arc<Mutex<QuicMetrics>>,
+        confirm: Option<(Connection, String, IpAddr)>,
+    ) {
+        let was_accepted = accepted.await;
+        if let Some((conn, host, ip)) = confirm {
+            if conn.close_reason().is_none() {
+                dns_cache().mark_good(&host, ip);
+            }
+        }
+        let mut m = metrics.lock();
+        m.is_in_early_data = false;
+        m.handshake_duration_us = started.elapsed().as_micros() as u64;
+        if was_accepted {
+            m.zero_rtt_accepted += 1;
+            info!("0-RTT early data accepted");
+        } else {
+            m.zero_rtt_rejected += 1;
+            warn!("0-RTT early data rejected by server, data was retransmitted as 1-RTT");
         }
+    }
 
-        self.connection = None;
-        self.endpoint = None;
+    fn build_transport_config(
+        config: &QuicConfig,
+        warnings: &mut Vec<String>,
+    ) -> Result<TransportConfig, Box<dyn std::error::Error>> {
+        let mut transport = TransportConfig::default();
 
-        let mut metrics = self.metrics.lock();
-        metrics.is_established = false;
-        drop(metrics);
+        // Congestion control
+        match config.cc_algorithm {
+            CongestionControl::Reno => {
+                transport.congestion_controller_factory(Arc::new(NewRenoConfig::default()));
+            }
+            CongestionControl::Cubic => {
+                transport.congestion_controller_factory(Arc::new(CubicConfig::default()));
+            }
+            CongestionControl::Bbr => {
+                transport.congestion_controller_factory(Arc::new(BbrConfig::default()));
+            }
+            CongestionControl::Bbr2 => {
+                warnings.push("BBRv2 is not supported by quinn, using BBR".to_string());
+                transport.congestion_controller_factory(Arc::new(BbrConfig::default()));
+            }
+        }
+
+        if config.enable_hystart && config.cc_algorithm != CongestionControl::Cubic {
+            warnings.push("HyStart only applies to CUBIC, ignored".to_string());
+        }
+        if !config.enable_pacing {
+            warnings.push("quinn always paces sends, enable_pacing=false ignored".to_string());
+        }
+
+        transport.enable_segmentation_offload(config.enable_gso);
+
+        // Flow control windows
+        let max_data = VarInt::from_u64(config.initial_max_data)
+            .map_err(|_| format!("initial_max_data out of range: {}", config.initial_max_data))?;
+        let max_stream_data = VarInt::from_u64(config.initial_max_stream_data)
+            .map_err(|_| format!("initial_max_stream_data out of range: {}", config.initial_max_stream_data))?;
+        transport.receive_window(max_data);
+        transport.send_window(config.initial_max_data);
+        transport.stream_receive_window(max_stream_data);
+
+        // Stream limits
+        let max_bidi = VarInt::from_u64(config.initial_max_streams_bidi)
+            .map_err(|_| format!("initial_max_streams_bidi out of range: {}", config.initial_max_streams_bidi))?;
+        let max_uni = VarInt::from_u64(config.initial_max_streams_uni)
+            .map_err(|_| format!("initial_max_streams_uni out of range: {}", config.initial_max_streams_uni))?;
+        transport.max_concurrent_bidi_streams(max_bidi);
+        transport.max_concurrent_uni_streams(max_uni);
 
-        info!("Disconnected");
+        // Idle timeout and keep-alive
+        if config.max_idle_timeout_ms == 0 {
+            transport.max_idle_timeout(None);
+        } else {
+            let timeout = IdleTimeout::try_from(Duration::from_millis(config.max_idle_timeout_ms))
+                .map_err(|_| format!("max_idle_timeout_ms out of range: {}", config.max_idle_timeout_ms))?;
+            transport.max_idle_timeout(Some(timeout));
+        }
+
+        if config.keep_alive_interval_ms == 0 {
+            transport.keep_alive_interval(None);
+        } else {
+            if config.max_idle_timeout_ms != 0 && config.keep_alive_interval_ms >= config.max_idle_timeout_ms {
+                warnings.push(format!(
+                    "keep_alive_interval_ms ({}) >= max_idle_timeout_ms ({}), connection may idle out",
+                    config.keep_alive_interval_ms, config.max_idle_timeout_ms
+                ));
+            }
+            transport.keep_alive_interval(Some(Duration::from_millis(config.keep_alive_interval_ms)));
+        }
+
+        // MTU: start at the QUIC minimum and probe up to max_udp_payload_size
+        if config.max_udp_payload_size < QUIC_MIN_MTU {
+            return Err(format!(
+                "max_udp_payload_size {} is below the QUIC minimum of {}",
+                config.max_udp_payload_size, QUIC_MIN_MTU
+            ).into());
+        }
+        transport.initial_mtu(QUIC_MIN_MTU);
+        transport.min_mtu(QUIC_MIN_MTU);
+        if config.max_udp_payload_size > QUIC_MIN_MTU {
+            let mut mtu_discovery = MtuDiscoveryConfig::default();
+            mtu_discovery.upper_bound(config.max_udp_payload_size);
+            transport.mtu_discovery_config(Some(mtu_discovery));
+        } else {
+            transport.mtu_discovery_config(None);
+        }
+
+        // DATAGRAM (RFC 9221): advertise support only when enabled
+        if config.enable_dgram {
+            transport.datagram_receive_buffer_size(Some(DATAGRAM_RECV_BUFFER));
+            transport.datagram_send_buffer_size(DATAGRAM_SEND_BUFFER);
+        } else {
+            transport.datagram_receive_buffer_size(None);
+        }
+
+        Ok(transport)
     }
 
-    pub fn is_connected(&self) -> bool {
-        self.connected.load(Ordering::Acquire) && 
-        self.connection.is_some()
+    /// Warnings for config values quinn could not honor as requested
+    pub fn config_warnings(&self) -> &[String] {
+        &self.config_warnings
     }
 
-    pub fn send(&mut self, data: &[u8]) -> Result<usize, Box<dyn std::error::Error>> {
-        if !self.is_connected() {
-            return Err("Not connected".into());
+    /// Update certificate validation options (applied on next connect)
+    pub fn set_tls_options(
+        &self,
+        ca_bundle_pem: Option<String>,
+        spki_pins: Vec<SpkiPin>,
+        allow_insecure: bool,
+    ) {
+        info!("TLS options updated: custom_ca={}, pins={}, insecure={}",
+              ca_bundle_pem.is_some(), spki_pins.len(), allow_insecure);
+        let mut config = self.shared.config.lock();
+        config.ca_bundle_pem = ca_bundle_pem;
+        config.spki_pins = spki_pins;
+        config.allow_insecure = allow_insecure;
+    }
+
+    /// Update the automatic reconnect policy (applies to the next connection loss)
+    pub fn set_reconnect_policy(
+        &self,
+        enabled: bool,
+        max_attempts: u32,
+        base_delay_ms: u64,
+        max_delay_ms: u64,
+    ) {
+        info!("Reconnect policy updated: enabled={}, max_attempts={}, delay={}..{} ms",
+              enabled, max_attempts, base_delay_ms, max_delay_ms);
+        let mut config = self.shared.config.lock();
+        config.auto_reconnect = enabled;
+        config.reconnect_max_attempts = max_attempts;
+        config.reconnect_base_delay_ms = base_delay_ms;
+        config.reconnect_max_delay_ms = max_delay_ms.max(base_delay_ms);
+    }
+
+    /// Toggle UDP GSO/GRO on the QUIC socket
+    ///
+    /// GRO changes apply to the current socket immediately; GSO is part of the
+    /// transport config and takes effect from the next (re)connect.
+    pub fn set_udp_offload(&self, gso: bool, gro: bool) -> Result<(), Box<dyn std::error::Error>> {
+        let mut config = self.shared.config.lock();
+        if config.enable_gso == gso && config.enable_gro == gro {
+            return Ok(());
         }
+        config.enable_gso = gso;
+        config.enable_gro = gro;
 
-        let conn = self.connection.as_ref().unwrap();
-        
-        self.runtime.block_on(async {
-            let mut send_stream = conn.open_uni().await?;
-            send_stream.write_all(data).await?;
-            send_stream.finish()?;
-            Ok(data.len())
-        })
+        let transport = Self::build_transport_config(&config, &mut Vec::new())?;
+        *self.shared.transport.lock() = Arc::new(transport);
+
+        for link in &self.shared.links {
+            // The endpoint lock keeps the socket open while it is reconfigured
+            let _endpoint = link.endpoint.lock();
+            let fd = link.socket_fd.load(Ordering::Acquire);
+            if fd >= 0 {
+                Self::configure_udp_offload(fd, &config);
+            }
+        }
+
+        info!("UDP offload updated: gso={}, gro={}", gso, gro);
+        Ok(())
     }
 
-    pub fn get_metrics(&self) -> QuicMetrics {
-        self.metrics.lock().clone()
+    // Apply the GSO/GRO settings to a QUIC socket (failures only degrade performance)
+    fn configure_udp_offload(fd: RawFd, config: &QuicConfig) {
+        // quinn negotiates GSO per send; probe so unsupported kernels are visible in logs
+        if config.enable_gso {
+            if let Err(e) = NetUtils::enable_udp_gso(fd, 0) {
+                warn!("UDP GSO unavailable on this kernel: {} (sending unsegmented)", e);
+            }
+        }
+
+        // quinn turns GRO on for every socket it wraps, so only disabling needs work
+        if let Err(e) = NetUtils::enable_udp_gro(fd, config.enable_gro) {
+            if config.enable_gro {
+                warn!("UDP GRO unavailable on this kernel: {}", e);
+            } else {
+                warn!("Failed to disable UDP GRO: {}", e);
+            }
+        }
+    }
+
+    fn build_tls_config(
+        config: &QuicConfig,
+    ) -> Result<rustls::ClientConfig, BoxError> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = rustls::ClientConfig::builder_with_provider(provider.clone())
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .map_err(|e| format!("Failed to configure TLS versions: {:?}", e))?;
+
+        // Verifier and client-cert resolver are shared so session tickets resume
+        let mut crypto = if config.allow_insecure {
+            builder
+                .dangerous()
+                .with_custom_certificate_verifier(insecure_verifier(&provider))
+                .with_client_cert_resolver(no_client_auth())
+        } else {
+            builder
+                .dangerous()
+                .with_custom_certificate_verifier(shared_verifier(config.ca_bundle_pem.as_deref(), &config.spki_pins, &provider)?)
+                .with_client_cert_resolver(no_client_auth())
+        };
+
+        // Session resumption (tickets shared by all clients in this process)
+        crypto.resumption = Resumption::store(session_store());
+        crypto.enable_early_data = config.enable_early_data;
+
+        Ok(crypto)
+    }
+
+    pub fn disconnect(&self) {
+        Self::shutdown(&self.shared);
+    }
+
+    /// Disconnect on the shared runtime; `done` runs once the connection is closed
+    pub fn disconnect_async<F>(&self, done: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let shared = self.shared.clone();
+        runtime().spawn(async move {
+            Self::shutdown(&shared);
+            done();
+        });
+    }
+
+    fn shutdown(shared: &Shared) {
+        // Flag the close first so the supervisors do not treat it as a loss;
+        // establish() checks it under each link's endpoint lock
+        shared.closing.store(true, Ordering::Release);
+
+        let mut disconnected = false;
+        for link in &shared.links {
+            let (endpoint, conn) = {
+                let mut endpoint_slot = link.endpoint.lock();
+                link.socket_fd.store(-1, Ordering::Release);
+                (endpoint_slot.take(), link.connection.borrow().clone())
+            };
+            let was_reconnecting = link.reconnecting.swap(false, Ordering::AcqRel);
+
+            if let Some(task) = link.supervisor_task.lock().take() {
+                task.abort();
+            }
+
+            if endpoint.is_none() && !was_reconnecting {
+                continue;
+            }
+
+            if !disconnected {
+                info!("Disconnecting...");
+                disconnected = true;
+            }
+            link.mark_disconnected();
+
+            if let Some(conn) = conn {
+                conn.close(0u32.into(), b"");
+            }
+            drop(endpoint);
+
+            link.metrics.lock().is_reconnecting = false;
+        }
+
+        if disconnected {
+            shared.publish_open_links();
+            info!("Disconnected");
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.shared.links.iter().any(|link| link.open_connection().is_some())
+    }
+
+    /// True while a lost connection is being re-established
+    pub fn is_reconnecting(&self) -> bool {
+        self.shared.links.iter().any(|link| link.reconnecting.load(Ordering::Acquire))
+    }
+
+    /// Number of pooled connections (open or not)
+    pub fn connection_count(&self) -> usize {
+        self.shared.links.len()
     }
-}
 
-use rustls::client::danger::{ServerCertVerifier, ServerCertVerified};
-use rustls::pki_types::{CertificateDer, ServerName};
-use rustls::Error;
+    /// Watch handles, one per pooled connection, for tasks that follow each
+    /// connection across reconnects
+    pub fn watches(&self) -> Vec<ConnectionWatch> {
+        self.shared.links.iter().map(|link| link.watch()).collect()
+    }
+
+    /// Datagram router shared by UDP flow users; created on first use
+    ///
+    /// Fails while a packet-mode TUN forwarder reads the DATAGRAMs; once
+    /// created, the router keeps them for the life of the client.
+    pub fn flow_router(&self) -> Result<Arc<FlowRouter>, Box<dyn std::error::Error>> {
+        let mut reader = self.shared.datagram_reader.lock();
+        if *reader == Some(DatagramReader::Packets) {
+            return Err("DATAGRAMs are read by a packet-mode TUN forwarder".into());
+        }
+        *reader = Some(DatagramReader::Flows);
+        Ok(self.shared.flow_router.get_or_init(|| FlowRouter::spawn(self.watches())).clone())
+    }
 
-// Dummy certificate verifier (accepts all certificates)
-// In production, use proper certificate validation
-#[derive(Debug)]
-struct NoCertificateVerification;
+    /// Reserve the downlink DATAGRAMs for whole IP packets (packet-mode TUN)
+    ///
+    /// Fails if the flow router or another forwarder already reads them;
+    /// released when the returned claim is dropped.
+    pub fn claim_packet_datagrams(&self) -> Result<PacketDatagramClaim, Box<dyn std::error::Error>> {
+        let mut reader = self.shared.datagram_reader.lock();
+        match *reader {
+            Some(DatagramReader::Flows) => Err("DATAGRAMs are read by the flow router".into()),
+            Some(DatagramReader::Packets) => Err("DATAGRAMs are read by another TUN forwarder".into()),
+            None => {
+                *reader = Some(DatagramReader::Packets);
+                Ok(PacketDatagramClaim { shared: self.shared.clone() })
+            }
+        }
+    }
 
-impl ServerCertVerifier for NoCertificateVerification {
-    fn verify_server_cert(
+    /// Assign a connection to a new flow with the configured scheduler
+    ///
+    /// When none is open, falls back to taking turns over all connections so
+    /// the flow still sticks to one once it is back.
+    pub fn flow_link(&self) -> FlowLink {
+        let link = match self.shared.select_link() {
+            Some((link, _)) => link,
+            None => {
+                let turn = self.shared.next_turn.fetch_add(1, Ordering::Relaxed);
+                self.shared.links[turn % self.shared.links.len()].clone()
+            }
+        };
+        FlowLink::new(link)
+    }
+
+    /// Open a bidirectional stream carrying a proxied TCP flow to `target`
+    ///
+    /// The scheduler picks the connection, waiting out a reconnect (up to
+    /// FLOW_OPEN_TIMEOUT), and the flow header is written. Keep the returned
+    /// `FlowLink` until the flow ends.
+    pub async fn open_flow_stream(
         &self,
-        _end_entity: &CertificateDer<'_>,
-        _intermediates: &[CertificateDer<'_>],
-        _server_name: &ServerName<'_>,
-        _ocsp: &[u8],
-        _now: rustls::pki_types::UnixTime,
-    ) -> Result<ServerCertVerified, Error> {
-        Ok(ServerCertVerified::assertion())
+        target: &TargetAddr,
+    ) -> Result<(SendStream, RecvStream, FlowLink), BoxError> {
+        let mut open_links = self.shared.open_links.subscribe();
+        let wait_for_link = async {
+            loop {
+                if let Some(selected) = self.shared.select_link() {
+                    return Ok::<_, BoxError>(selected);
+                }
+                open_links.changed().await.map_err(|_| "QUIC client destroyed")?;
+            }
+        };
+        let (link, conn) = tokio::time::timeout(FLOW_OPEN_TIMEOUT, wait_for_link)
+            .await
+            .map_err(|_| "Timed out waiting for QUIC connection")??;
+
+        let link = FlowLink::new(link);
+        let (mut send, recv) = conn.open_bi().await?;
+        send.write_all(&encode_stream_header(target)).await?;
+        Ok((send, recv, link))
+    }
+
+    /// Send one IP packet
+    ///
+    /// Uses a QUIC DATAGRAM when enabled and negotiated by the peer, otherwise
+    /// queues a unidirectional stream per packet on the runtime. Never blocks.
+    /// Pooled clients send on the first open connection so packets stay in order.
+    pub fn send(&self, data: &[u8]) -> Result<usize, Box<dyn std::error::Error>> {
+        self.send_packet(Bytes::copy_from_slice(data))
+    }
+
+    /// Same as `send`, taking ownership of the packet so it is not copied
+    pub fn send_packet(&self, packet: Bytes) -> Result<usize, Box<dyn std::error::Error>> {
+        let (link, conn) = self.shared.primary_connection().ok_or("Not connected")?;
+
+        if self.dgram_enabled && conn.max_datagram_size().is_some() {
+            return link.send_datagram_on(&conn, packet);
+        }
+
+        let len = packet.len();
+        let permit = self.shared.stream_sends.clone().try_acquire_owned()
+            .map_err(|_| "Stream send backlog full")?;
+        runtime().spawn(async move {
+            let result: Result<(), BoxError> = async {
+                let mut send_stream = conn.open_uni().await?;
+                send_stream.write_chunk(packet).await?;
+                send_stream.finish()?;
+                Ok(())
+            }.await;
+            if let Err(e) = result {
+                debug!("Stream send failed: {}", e);
+            }
+            drop(permit);
+        });
+
+        Ok(len)
+    }
+
+    /// Send one packet as a QUIC DATAGRAM
+    ///
+    /// Packets larger than `max_datagram_size` are dropped and counted in
+    /// `QuicMetrics::datagrams_oversized`; the error is `DatagramTooLarge`.
+    pub fn send_datagram(&self, data: &[u8]) -> Result<usize, Box<dyn std::error::Error>> {
+        let (link, conn) = self.shared.primary_connection().ok_or("Not connected")?;
+        link.send_datagram_on(&conn, Bytes::copy_from_slice(data))
     }
 
-    fn verify_tls12_signature(
+    /// Wait for the next QUIC DATAGRAM from the peer
+    ///
+    /// Refused while a TUN forwarder or the flow router reads them.
+    pub fn read_datagram(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        if self.shared.datagram_reader.lock().is_some() {
+            return Err("DATAGRAMs are read by a forwarder".into());
+        }
+        let conn = self.connection().ok_or("Not connected")?;
+
+        let datagram = runtime().block_on(conn.read_datagram())?;
+        Ok(datagram.to_vec())
+    }
+
+    /// Move the connections to new local UDP sockets (network change)
+    ///
+    /// Binds to `local_addr` (wildcard if None), or adopts `socket_fd` when the
+    /// app supplies an already-bound/network-bound socket; ownership of the fd
+    /// passes to the client, which closes it if no connection takes it. The
+    /// QUIC sessions migrate to the new path. Endpoints are dual-stack IPv6
+    /// sockets, so an IPv4 `local_addr` is bound in its mapped form and an
+    /// adopted socket must be IPv6. In a pool the first connection gets the
+    /// given socket and the others bind an ephemeral port on its address.
+    /// Returns the first connection's address.
+    ///
+    /// Fails without moving anything if the first connection cannot be
+    /// rebound. A later connection that fails keeps its old socket (logged);
+    /// migrations already done are not undone, since the old sockets are gone.
+    pub fn rebind(
         &self,
-        _message: &[u8],
-        _cert: &CertificateDer<'_>,
-        _dss: &rustls::DigitallySignedStruct,
-    ) -> Result<rustls::client::danger::HandshakeSignatureValid, Error> {
-        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
+        local_addr: Option<SocketAddr>,
+        socket_fd: Option<RawFd>,
+    ) -> Result<SocketAddr, Box<dyn std::error::Error>> {
+        if let Some(fd) = socket_fd.filter(|fd| *fd < 0) {
+            return Err(format!("Invalid socket fd: {}", fd).into());
+        }
+
+        // Owned from here on, so every early return closes it
+        let mut adopted = socket_fd.map(|fd| unsafe { UdpSocket::from_raw_fd(fd) });
+        if let Some(socket) = &adopted {
+            if socket.local_addr()?.is_ipv4() {
+                return Err("Adopted socket must be IPv6 (dual-stack)".into());
+            }
+        }
+        let local_addr = local_addr.map(|addr| match addr {
+            SocketAddr::V4(v4) => SocketAddr::new(v4.ip().to_ipv6_mapped().into(), v4.port()),
+            v6 => v6,
+        });
+        let mut first_addr: Option<SocketAddr> = None;
+        for link in &self.shared.links {
+            let endpoint_slot = link.endpoint.lock();
+            let Some(endpoint) = endpoint_slot.as_ref() else {
+                continue;
+            };
+
+            let socket = match adopted.take() {
+                Some(socket) => Ok(socket),
+                None => {
+                    let addr = match first_addr {
+                        Some(first) => SocketAddr::new(first.ip(), 0),
+                        None => local_addr.unwrap_or_else(|| SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0))),
+                    };
+                    UdpSocket::bind(addr).map_err(|e| format!("Failed to bind {}: {}", addr, e).into())
+                }
+            };
+            match (socket.and_then(|socket| self.rebind_link(link, endpoint, socket)), first_addr) {
+                (Ok(new_addr), _) => {
+                    first_addr.get_or_insert(new_addr);
+                }
+                (Err(e), None) => return Err(e),
+                (Err(e), Some(_)) => warn!("Endpoint {} keeps its old socket: {}", link.index, e),
+            }
+        }
+
+        first_addr.ok_or_else(|| "Not connected".into())
     }
 
-    fn verify_tls13_signature(
+    fn rebind_link(
         &self,
-        _message: &[u8],
-        _cert: &CertificateDer<'_>,
-        _dss: &rustls::DigitallySignedStruct,
-    ) -> Result<rustls::client::danger::HandshakeSignatureValid, Error> {
-        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
-    }
-
-    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
-        // Return default supported schemes
-        vec![
-            rustls::SignatureScheme::RSA_PSS_SHA512,
-            rustls::SignatureScheme::RSA_PSS_SHA384,
-            rustls::SignatureScheme::RSA_PSS_SHA256,
-            rustls::SignatureScheme::ECDSA_NISTP521_SHA512,
-            rustls::SignatureScheme::ECDSA_NISTP384_SHA384,
-            rustls::SignatureScheme::ECDSA_NISTP256_SHA256,
-            rustls::SignatureScheme::RSA_PKCS1_SHA512,
-            rustls::SignatureScheme::RSA_PKCS1_SHA384,
-            rustls::SignatureScheme::RSA_PKCS1_SHA256,
-            rustls::SignatureScheme::ED25519,
-        ]
+        link: &Link,
+        endpoint: &Endpoint,
+        socket: UdpSocket,
+    ) -> Result<SocketAddr, Box<dyn std::error::Error>> {
+        socket.set_nonblocking(true)?;
+        let new_fd = socket.as_raw_fd();
+
+        let old_addr = endpoint.local_addr().ok();
+        {
+            let _guard = runtime().enter();
+            endpoint.rebind(socket).map_err(|e| format!("Endpoint rebind failed: {}", e))?;
+        }
+        let new_addr = endpoint.local_addr()?;
+        link.socket_fd.store(new_fd, Ordering::Release);
+        Self::configure_udp_offload(new_fd, &self.shared.config.lock());
+
+        let mut metrics = link.metrics.lock();
+        metrics.migration_count += 1;
+        metrics.last_migration_timestamp_ms = TimeUtils::get_timestamp_ms();
+        drop(metrics);
+
+        info!("Endpoint {} rebound {:?} -> {}, connection migrating", link.index, old_addr, new_addr);
+        Ok(new_addr)
+    }
+
+    /// Current connection, if established (the first open one in a pool)
+    pub fn connection(&self) -> Option<Connection> {
+        self.shared.primary_connection().map(|(_, conn)| conn)
+    }
+
+    /// Metrics summed over all pooled connections (see `QuicMetrics::aggregate`)
+    pub fn get_metrics(&self) -> QuicMetrics {
+        QuicMetrics::aggregate(&self.connection_metrics())
+    }
+
+    /// Metrics of each pooled connection, in pool order
+    pub fn connection_metrics(&self) -> Vec<QuicMetrics> {
+        self.shared.links
+            .iter()
+            .map(|link| {
+                let mut metrics = link.metrics.lock().clone();
+                metrics.connections = 1;
+                metrics.connections_established = metrics.is_established as u64;
+                metrics
+            })
+            .collect()
+    }
+}
+
+impl Drop for QuicheClient {
+    fn drop(&mut self) {
+        // Tasks on the shared runtime outlive the client; stop them here
+        Self::shutdown(&self.shared);
     }
 }
 
+// Exponential backoff with equal jitter: half of each step is fixed, half random
+fn backoff_delay(attempt: u32, base_ms: u64, max_ms: u64) -> Duration {
+    let step = base_ms
+        .saturating_mul(1u64 << attempt.min(MAX_BACKOFF_SHIFT))
+        .min(max_ms)
+        .max(1);
+    let half = step / 2;
+    // RandomState is randomly keyed, which is enough for jitter without a rand dependency
+    let jitter = RandomState::new().build_hasher().finish() % (step - half + 1);
+    Duration::from_millis(half + jitter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{client_for, echo_server, echo_server_with_cert, ECHO_SERVER_NAME};
+    use std::os::fd::IntoRawFd;
+    use std::os::unix::net::UnixDatagram;
+
+    #[test]
+    fn rebind_without_endpoint_closes_the_fd() {
+        let client = client_for("127.0.0.1:9".parse().unwrap(), 1);
+        // The peer of a closed datagram socket sees ECONNREFUSED, whatever reuses the fd number
+        let (adopted, peer) = UnixDatagram::pair().unwrap();
+        assert!(client.rebind(None, Some(adopted.into_raw_fd())).is_err());
+        assert_eq!(peer.send(b"x").unwrap_err().kind(), std::io::ErrorKind::ConnectionRefused);
+    }
+
+    #[test]
+    fn rebind_migrates_the_pool() {
+        let client = client_for(echo_server(), 2);
+        client.connect().unwrap();
+
+        // IPv4 sockets cannot reach the server the endpoints address in IPv6 form
+        let v4 = UdpSocket::bind("127.0.0.1:0").unwrap();
+        assert!(client.rebind(None, Some(v4.into_raw_fd())).is_err());
+        assert!(client.connection_metrics().iter().all(|m| m.migration_count == 0));
+
+        let socket = UdpSocket::bind("[::ffff:127.0.0.1]:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        assert_eq!(client.rebind(None, Some(socket.into_raw_fd())).unwrap(), addr);
+
+        let metrics = client.connection_metrics();
+        assert!(metrics.iter().all(|m| m.migration_count == 1));
+        let fds: Vec<RawFd> = client.shared.links.iter().map(|link| link.socket_fd.load(Ordering::Acquire)).collect();
+        assert_ne!(fds[0], fds[1]);
+
+        // Still talking to the server over the new path
+        let conn = client.connection().unwrap();
+        conn.send_datagram(Bytes::from_static(b"ping")).unwrap();
+        let echo = runtime()
+            .block_on(async { tokio::time::timeout(Duration::from_secs(5), conn.read_datagram()).await })
+            .unwrap()
+            .unwrap();
+        assert_eq!(&echo[..], b"ping");
+
+        // An IPv4 address is bound as IPv6-mapped
+        let rebound = client.rebind(Some("127.0.0.1:0".parse().unwrap()), None).unwrap();
+        assert_eq!(rebound.ip(), "::ffff:127.0.0.1".parse::<std::net::IpAddr>().unwrap());
+        client.disconnect();
+    }
+
+    #[test]
+    fn concurrent_links_each_report_their_certificate_rejection() {
+        let (server, pem) = echo_server_with_cert();
+        let attempt = |host: &str, ca_bundle_pem: Option<String>, spki_pins: Vec<SpkiPin>| {
+            dns_cache().inject(host, vec![server.ip()], None);
+            let client = QuicheClient::create(QuicConfig {
+                server_host: host.into(),
+                server_port: server.port(),
+                connection_count: 3,
+                ca_bundle_pem,
+                spki_pins,
+                enable_early_data: false,
+                enable_gso: false,
+                enable_gro: false,
+                ..QuicConfig::default()
+            }).unwrap();
+            let shared = client.shared.clone();
+            let results = runtime().block_on(async move {
+                let mut attempts = JoinSet::new();
+                for link in shared.links.clone() {
+                    let shared = shared.clone();
+                    attempts.spawn(async move { QuicheClient::establish(&shared, &link).await.map_err(|e| e.to_string()) });
+                }
+                attempts.join_all().await
+            });
+            dns_cache().clear(Some(host));
+            results
+        };
+        let rejected = |results: Vec<Result<Connection, String>>, expected: CertVerifyError| {
+            assert_eq!(results.len(), 3);
+            for result in results {
+                assert_eq!(result.err(), Some(expected.to_string()));
+            }
+        };
+
+        rejected(attempt(ECHO_SERVER_NAME, None, Vec::new()), CertVerifyError::UnknownIssuer);
+        rejected(attempt(ECHO_SERVER_NAME, Some(pem.clone()), vec![[0; 32]]), CertVerifyError::PinMismatch);
+        rejected(attempt("other.test", Some(pem.clone()), Vec::new()), CertVerifyError::NameMismatch);
+
+        // Trusted, so every link connects with no rejection left behind
+        for result in attempt(ECHO_SERVER_NAME, Some(pem), Vec::new()) {
+            result.unwrap().close(0u32.into(), b"");
+        }
+    }
+
+    #[test]
+    fn link_count_is_capped() {
+        let servers = |n: usize| (0..n).map(|i| (format!("s{}.test", i), 443)).collect::<Vec<_>>();
+        let config = |connection_count, additional| QuicConfig {
+            server_host: "server.test".into(),
+            connection_count,
+            additional_servers: servers(additional),
+            ..QuicConfig::default()
+        };
+
+        assert!(QuicheClient::create(config(MAX_CONNECTIONS + 1, 0)).is_err());
+        assert!(QuicheClient::create(config(1, MAX_CONNECTIONS as usize)).is_err());
+
+        let links = QuicheClient::link_targets(&config(MAX_CONNECTIONS, MAX_CONNECTIONS as usize - 1));
+        assert_eq!(links.len(), MAX_CONNECTIONS as usize);
+        let links = QuicheClient::link_targets(&config(1, 2));
+        assert_eq!(links.len(), 3);
+    }
+
+    #[test]
+    fn reconnect_resumes_with_0rtt_on_the_last_good_address() {
+        let server = echo_server();
+        // Nothing listens on 127.0.0.2, so the first race is won by the second address
+        let host = "zero-rtt.test";
+        dns_cache().inject(host, vec!["127.0.0.2".parse().unwrap(), server.ip()], None);
+        let config = QuicConfig {
+            server_host: host.into(),
+            server_port: server.port(),
+            allow_insecure: true,
+            enable_gso: false,
+            enable_gro: false,
+            happy_eyeballs_delay_ms: 50,
+            ..QuicConfig::default()
+        };
+
+        let first = QuicheClient::create(config.clone()).unwrap();
+        first.connect().unwrap();
+        assert_eq!(first.connection_metrics()[0].zero_rtt_attempts, 0);
+        // A round trip lets the session ticket arrive
+        let conn = first.connection().unwrap();
+        conn.send_datagram(Bytes::from_static(b"ping")).unwrap();
+        runtime()
+            .block_on(async { tokio::time::timeout(Duration::from_secs(5), conn.read_datagram()).await })
+            .unwrap()
+            .unwrap();
+        first.disconnect();
+
+        let second = QuicheClient::create(config).unwrap();
+        second.connect().unwrap();
+        assert_eq!(second.connection_metrics()[0].zero_rtt_attempts, 1);
+        let conn = second.connection().unwrap();
+        conn.send_datagram(Bytes::from_static(b"early")).unwrap();
+        let echo = runtime()
+            .block_on(async { tokio::time::timeout(Duration::from_secs(5), conn.read_datagram()).await })
+            .unwrap()
+            .unwrap();
+        assert_eq!(&echo[..], b"early");
+        // Once the handshake completes the address is preferred again
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let good = loop {
+            match dns_cache().take_good(host) {
+                Some(ip) => break Some(ip),
+                None if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(10)),
+                None => break None,
+            }
+        };
+        assert_eq!(good, Some(server.ip()));
+        second.disconnect();
+        dns_cache().clear(Some(host));
+    }
+
+    #[test]
+    fn backoff_grows_to_the_cap_with_bounded_jitter() {
+        let in_range = |delay: Duration, step: u64| (step / 2..=step).contains(&(delay.as_millis() as u64));
+        for attempt in 0..7 {
+            let step = 100 << attempt;
+            for _ in 0..32 {
+                let delay = backoff_delay(attempt, 100, 10_000);
+                assert!(in_range(delay, step), "attempt {}: {:?}", attempt, delay);
+            }
+        }
+
+        // Capped, including shifts and products that would overflow
+        for attempt in [7, MAX_BACKOFF_SHIFT, MAX_BACKOFF_SHIFT + 1, u32::MAX] {
+            assert!(in_range(backoff_delay(attempt, 100, 10_000), 10_000));
+            assert!(in_range(backoff_delay(attempt, u64::MAX / 2, 10_000), 10_000));
+        }
+        assert!(backoff_delay(3, 0, 10_000) <= Duration::from_millis(1));
+
+        // Equal jitter: the random half actually varies
+        let delays: std::collections::HashSet<_> = (0..64).map(|_| backoff_delay(10, 1, 1_000)).collect();
+        assert!(delays.len() > 1);
+    }
+
+    #[test]
+    fn metrics_aggregate_over_the_pool() {
+        let established = QuicMetrics {
+            bytes_sent: 1000,
+            throughput_mbps: 1.5,
+            rtt_us: 10_000,
+            min_rtt_us: 8_000,
+            packet_loss_rate: 0.02,
+            cwnd: 100,
+            is_established: true,
+            zero_rtt_accepted: 1,
+            last_migration_timestamp_ms: 500,
+            handshake_duration_us: 30_000,
+            datagrams_oversized: 2,
+            ..Default::default()
+        };
+        let slower = QuicMetrics {
+            bytes_sent: 500,
+            throughput_mbps: 2.5,
+            rtt_us: 30_000,
+            min_rtt_us: 20_000,
+            packet_loss_rate: 0.04,
+            cwnd: 50,
+            is_in_early_data: true,
+            last_migration_timestamp_ms: 900,
+            handshake_duration_us: 60_000,
+            ..established.clone()
+        };
+        // Not established: counted, but its RTT and loss are left out
+        let reconnecting = QuicMetrics {
+            bytes_sent: 1,
+            rtt_us: 1_000_000,
+            min_rtt_us: 1,
+            packet_loss_rate: 1.0,
+            is_reconnecting: true,
+            reconnect_attempts: 3,
+            ..Default::default()
+        };
+
+        let total = QuicMetrics::aggregate(&[established, slower, reconnecting]);
+        assert_eq!((total.connections, total.connections_established), (3, 2));
+        assert_eq!((total.bytes_sent, total.cwnd, total.zero_rtt_accepted), (1501, 150, 2));
+        assert_eq!(total.throughput_mbps, 4.0);
+        assert_eq!((total.rtt_us, total.min_rtt_us), (20_000, 8_000));
+        assert!((total.packet_loss_rate - 0.03).abs() < 1e-9);
+        assert!(total.is_established && total.is_in_early_data && total.is_reconnecting);
+        assert_eq!(total.reconnect_attempts, 3);
+        assert_eq!((total.last_migration_timestamp_ms, total.handshake_duration_us), (900, 60_000));
+        assert_eq!(total.datagrams_oversized, 4);
+
+        let empty = QuicMetrics::aggregate(&[]);
+        assert_eq!((empty.connections, empty.rtt_us, empty.is_established), (0, 0, false));
+    }
+}
@@ -0,0 +1,210 @@
+/*
+ * Flow Header (Rust Implementation)
+ * Destination framing for proxied TCP streams and UDP datagrams
+ */
+
+use bytes::{BufMut, Bytes};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// Version byte leading every header
+pub const FLOW_HEADER_VERSION: u8 = 1;
+
+const CMD_TCP: u8 = 1;
+const CMD_UDP: u8 = 2;
+
+// Address types (same values as SOCKS5)
+const ATYP_IPV4: u8 = 1;
+const ATYP_DOMAIN: u8 = 3;
+const ATYP_IPV6: u8 = 4;
+
+/// Proxy destination: an address or a hostname the server resolves
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TargetAddr {
+    Ip(SocketAddr),
+    Domain(String, u16),
+}
+
+impl fmt::Display for TargetAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ip(addr) => write!(f, "{}", addr),
+            Self::Domain(host, port) => write!(f, "{}:{}", host, port),
+        }
+    }
+}
+
+impl From<SocketAddr> for TargetAddr {
+    fn from(addr: SocketAddr) -> Self {
+        Self::Ip(addr)
+    }
+}
+
+impl TargetAddr {
+    fn encoded_len(&self) -> usize {
+        match self {
+            Self::Ip(SocketAddr::V4(_)) => 1 + 4 + 2,
+            Self::Ip(SocketAddr::V6(_)) => 1 + 16 + 2,
+            Self::Domain(host, _) => 1 + 1 + host.len() + 2,
+        }
+    }
+
+    /// Append `[atyp][addr][port]` (the SOCKS5 address layout)
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Self::Ip(SocketAddr::V4(addr)) => {
+                buf.put_u8(ATYP_IPV4);
+                buf.put_slice(&addr.ip().octets());
+                buf.put_u16(addr.port());
+            }
+            Self::Ip(SocketAddr::V6(addr)) => {
+                buf.put_u8(ATYP_IPV6);
+                buf.put_slice(&addr.ip().octets());
+                buf.put_u16(addr.port());
+            }
+            Self::Domain(host, port) => {
+                buf.put_u8(ATYP_DOMAIN);
+                buf.put_u8(host.len() as u8);
+                buf.put_slice(host.as_bytes());
+                buf.put_u16(*port);
+            }
+        }
+    }
+
+    /// Parse an address at the start of `buf`; returns it and the bytes consumed
+    pub fn decode(buf: &[u8]) -> Option<(Self, usize)> {
+        let port_at = |i: usize| buf.get(i..i + 2).map(|p| u16::from_be_bytes([p[0], p[1]]));
+        match *buf.first()? {
+            ATYP_IPV4 => {
+                let ip: [u8; 4] = buf.get(1..5)?.try_into().ok()?;
+                let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::from(ip)), port_at(5)?);
+                Some((Self::Ip(addr), 7))
+            }
+            ATYP_IPV6 => {
+                let ip: [u8; 16] = buf.get(1..17)?.try_into().ok()?;
+                let addr = SocketAddr::new(IpAddr::V6(Ipv6Addr::from(ip)), port_at(17)?);
+                Some((Self::Ip(addr), 19))
+            }
+            ATYP_DOMAIN => {
+                let len = *buf.get(1)? as usize;
+                let host = std::str::from_utf8(buf.get(2..2 + len)?).ok()?;
+                Some((Self::Domain(host.to_string(), port_at(2 + len)?), 2 + len + 2))
+            }
+            _ => None,
+        }
+    }
+
+    /// Hostnames must fit the one-byte length field
+    pub fn is_valid(&self) -> bool {
+        match self {
+            Self::Ip(_) => true,
+            Self::Domain(host, _) => !host.is_empty() && host.len() <= u8::MAX as usize,
+        }
+    }
+}
+
+/// Preamble written at the start of a TCP flow's bidirectional stream
+///
+/// `[version][CMD_TCP][atyp][addr][port]`
+pub fn encode_stream_header(target: &TargetAddr) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(2 + target.encoded_len());
+    buf.put_u8(FLOW_HEADER_VERSION);
+    buf.put_u8(CMD_TCP);
+    target.encode(&mut buf);
+    buf
+}
+
+/// One UDP payload as a QUIC datagram
+///
+/// `[version][CMD_UDP][flow_id u32][atyp][addr][port][payload]`. Uplink
+/// carries the destination, downlink the peer the payload came from.
+pub fn encode_datagram(flow_id: u32, target: &TargetAddr, payload: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(6 + target.encoded_len() + payload.len());
+    buf.put_u8(FLOW_HEADER_VERSION);
+    buf.put_u8(CMD_UDP);
+    buf.put_u32(flow_id);
+    target.encode(&mut buf);
+    buf.put_slice(payload);
+    buf
+}
+
+/// Split a datagram into (flow_id, peer, payload); None if malformed
+pub fn decode_datagram(datagram: &Bytes) -> Option<(u32, TargetAddr, Bytes)> {
+    if datagram.len() < 6 || datagram[0] != FLOW_HEADER_VERSION || datagram[1] != CMD_UDP {
+        return None;
+    }
+    let flow_id = u32::from_be_bytes([datagram[2], datagram[3], datagram[4], datagram[5]]);
+    let (target, used) = TargetAddr::decode(&datagram[6..])?;
+    Some((flow_id, target, datagram.slice(6 + used..)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn targets() -> Vec<TargetAddr> {
+        vec![
+            TargetAddr::Ip("192.0.2.1:53".parse().unwrap()),
+            TargetAddr::Ip("[2001:db8::1]:443".parse().unwrap()),
+            TargetAddr::Domain("example.com".into(), 8080),
+        ]
+    }
+
+    #[test]
+    fn addresses_round_trip() {
+        for target in targets() {
+            let mut buf = Vec::new();
+            target.encode(&mut buf);
+            assert_eq!(buf.len(), target.encoded_len());
+            buf.extend_from_slice(b"tail");
+            assert_eq!(TargetAddr::decode(&buf), Some((target.clone(), target.encoded_len())));
+        }
+    }
+
+    #[test]
+    fn stream_header_layout() {
+        let header = encode_stream_header(&TargetAddr::Domain("a.test".into(), 80));
+        assert_eq!(header, [&[FLOW_HEADER_VERSION, CMD_TCP, ATYP_DOMAIN, 6][..], b"a.test", &[0, 80]].concat());
+    }
+
+    #[test]
+    fn datagrams_round_trip() {
+        for target in targets() {
+            let datagram = Bytes::from(encode_datagram(0x01020304, &target, b"payload"));
+            assert_eq!(&datagram[..6], &[FLOW_HEADER_VERSION, CMD_UDP, 1, 2, 3, 4]);
+            let (flow_id, peer, payload) = decode_datagram(&datagram).unwrap();
+            assert_eq!((flow_id, peer, &payload[..]), (0x01020304, target, &b"payload"[..]));
+        }
+
+        // Empty payloads are allowed
+        let target = TargetAddr::Ip("192.0.2.1:53".parse().unwrap());
+        let (_, _, payload) = decode_datagram(&Bytes::from(encode_datagram(7, &target, b""))).unwrap();
+        assert!(payload.is_empty());
+    }
+
+    #[test]
+    fn malformed_input_is_rejected() {
+        let datagram = encode_datagram(1, &TargetAddr::Domain("example.com".into(), 53), b"x");
+        for len in 0..datagram.len() - 1 {
+            assert!(decode_datagram(&Bytes::copy_from_slice(&datagram[..len])).is_none(), "len {}", len);
+        }
+
+        let mut wrong_version = datagram.clone();
+        wrong_version[0] = FLOW_HEADER_VERSION + 1;
+        assert!(decode_datagram(&Bytes::from(wrong_version)).is_none());
+        let mut stream_cmd = datagram;
+        stream_cmd[1] = CMD_TCP;
+        assert!(decode_datagram(&Bytes::from(stream_cmd)).is_none());
+
+        assert!(TargetAddr::decode(&[2, 0, 0]).is_none());
+        assert!(TargetAddr::decode(&[ATYP_DOMAIN, 2, 0xff, 0xfe, 0, 80]).is_none());
+        assert!(TargetAddr::decode(&[ATYP_IPV4, 127, 0, 0, 1, 0]).is_none());
+    }
+
+    #[test]
+    fn hostnames_must_fit_the_length_byte() {
+        assert!(TargetAddr::Domain("a".repeat(255), 1).is_valid());
+        assert!(!TargetAddr::Domain("a".repeat(256), 1).is_valid());
+        assert!(!TargetAddr::Domain(String::new(), 1).is_valid());
+    }
+}
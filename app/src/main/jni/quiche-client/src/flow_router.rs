@@ -0,0 +1,190 @@
+/*
+ * Flow Datagram Router (Rust Implementation)
+ * Shares one connection's DATAGRAM channel between UDP flow users
+ */
+
+use crate::client::ConnectionWatch;
+use crate::flow_header::{self, TargetAddr};
+use crate::runtime::runtime;
+use bytes::Bytes;
+use log::debug;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::task::JoinHandle;
+
+/// A downlink datagram for one flow
+#[derive(Debug)]
+pub struct FlowDatagram {
+    pub flow_id: u32,
+    /// Where the payload came from
+    pub peer: TargetAddr,
+    pub payload: Bytes,
+}
+
+/// Allocates flow ids and delivers each downlink datagram to its flow's owner
+///
+/// The flow stack and local proxy can then run on the same client without
+/// reading each other's datagrams. Created per client by `QuicheClient::flow_router`.
+pub struct FlowRouter {
+    flows: Mutex<HashMap<u32, mpsc::Sender<FlowDatagram>>>,
+    next_id: AtomicU32,
+    tasks: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl FlowRouter {
+    /// Start routing datagrams from each of the client's connections (across reconnects)
+    pub fn spawn(watches: Vec<ConnectionWatch>) -> Arc<Self> {
+        let router = Arc::new(Self {
+            flows: Mutex::new(HashMap::new()),
+            next_id: AtomicU32::new(0),
+            tasks: Mutex::new(Vec::new()),
+        });
+        let tasks = watches
+            .into_iter()
+            .map(|watch| runtime().spawn(Self::route(Arc::downgrade(&router), watch)))
+            .collect();
+        *router.tasks.lock() = tasks;
+        router
+    }
+
+    /// Allocate a flow id whose datagrams go to `sender`
+    pub fn register(&self, sender: mpsc::Sender<FlowDatagram>) -> u32 {
+        let mut flows = self.flows.lock();
+        loop {
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+            if let std::collections::hash_map::Entry::Vacant(entry) = flows.entry(id) {
+                entry.insert(sender);
+                return id;
+            }
+        }
+    }
+
+    pub fn unregister(&self, flow_id: u32) {
+        self.flows.lock().remove(&flow_id);
+    }
+
+    /// Flows currently registered
+    pub fn flow_count(&self) -> usize {
+        self.flows.lock().len()
+    }
+
+    // Hand a datagram to its flow; full queues drop it like a congested link
+    fn dispatch(&self, datagram: &Bytes) {
+        let Some((flow_id, peer, payload)) = flow_header::decode_datagram(datagram) else {
+            debug!("Dropping malformed flow datagram ({} bytes)", datagram.len());
+            return;
+        };
+
+        let mut flows = self.flows.lock();
+        let Some(sender) = flows.get(&flow_id) else {
+            return;
+        };
+        match sender.try_send(FlowDatagram { flow_id, peer, payload }) {
+            Ok(()) | Err(TrySendError::Full(_)) => {}
+            Err(TrySendError::Closed(_)) => {
+                flows.remove(&flow_id);
+            }
+        }
+    }
+
+    async fn route(router: std::sync::Weak<Self>, mut watch: ConnectionWatch) {
+        while let Some(conn) = watch.open_connection().await {
+            while let Ok(datagram) = conn.read_datagram().await {
+                let Some(router) = router.upgrade() else {
+                    return;
+                };
+                router.dispatch(&datagram);
+            }
+        }
+    }
+}
+
+impl Drop for FlowRouter {
+    fn drop(&mut self) {
+        for task in self.tasks.lock().drain(..) {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flow_header::encode_datagram;
+    use crate::test_server::{client_for, echo_server};
+    use crate::tun_forwarder::{ForwarderConfig, ForwarderMode, QuicheTunForwarder};
+    use std::os::fd::AsRawFd;
+    use std::os::unix::net::UnixDatagram;
+    use std::time::Duration;
+
+    fn recv(rx: &mut mpsc::Receiver<FlowDatagram>) -> FlowDatagram {
+        runtime()
+            .block_on(async { tokio::time::timeout(Duration::from_secs(5), rx.recv()).await })
+            .expect("timed out waiting for a datagram")
+            .expect("flow closed")
+    }
+
+    #[test]
+    fn datagrams_reach_their_flow_over_loopback() {
+        let client = client_for(echo_server(), 1);
+        client.connect().unwrap();
+
+        let router = client.flow_router().unwrap();
+        let (tx_a, mut rx_a) = mpsc::channel(8);
+        let (tx_b, mut rx_b) = mpsc::channel(8);
+        let flow_a = router.register(tx_a);
+        let flow_b = router.register(tx_b);
+        assert_ne!(flow_a, flow_b);
+        assert_eq!(router.flow_count(), 2);
+
+        let peer_a = TargetAddr::Ip("192.0.2.1:53".parse().unwrap());
+        let peer_b = TargetAddr::Domain("example.com".into(), 443);
+        let link = client.flow_link();
+        link.send_datagram(&encode_datagram(flow_b, &peer_b, b"to b")).unwrap();
+        link.send_datagram(&encode_datagram(flow_a, &peer_a, b"to a")).unwrap();
+
+        let a = recv(&mut rx_a);
+        assert_eq!((a.flow_id, a.peer, &a.payload[..]), (flow_a, peer_a, &b"to a"[..]));
+        let b = recv(&mut rx_b);
+        assert_eq!((b.flow_id, b.peer, &b.payload[..]), (flow_b, peer_b.clone(), &b"to b"[..]));
+
+        // The router owns the DATAGRAMs from now on
+        assert!(client.claim_packet_datagrams().is_err());
+        assert!(client.read_datagram().is_err());
+
+        // Unregistered flows no longer get theirs; the others still do
+        router.unregister(flow_a);
+        link.send_datagram(&encode_datagram(flow_a, &peer_b, b"gone")).unwrap();
+        link.send_datagram(&encode_datagram(flow_b, &peer_b, b"again")).unwrap();
+        assert_eq!(&recv(&mut rx_b).payload[..], b"again");
+        assert!(rx_a.try_recv().is_err());
+
+        client.disconnect();
+    }
+
+    #[test]
+    fn packet_forwarder_and_flow_router_exclude_each_other() {
+        let client = client_for("127.0.0.1:9".parse().unwrap(), 1);
+
+        let claim = client.claim_packet_datagrams().unwrap();
+        assert!(client.claim_packet_datagrams().is_err());
+        assert!(client.flow_router().is_err());
+        assert!(client.read_datagram().is_err());
+        drop(claim);
+
+        client.flow_router().unwrap();
+        assert!(client.claim_packet_datagrams().is_err());
+
+        // A packet-mode forwarder refuses to start; the TUN is never touched
+        let (tun, _peer) = UnixDatagram::pair().unwrap();
+        let config = ForwarderConfig { tun_fd: tun.as_raw_fd(), mode: ForwarderMode::Packet, ..ForwarderConfig::default() };
+        let mut forwarder = QuicheTunForwarder::create(config, client.clone()).unwrap();
+        assert!(forwarder.start().is_err());
+        forwarder.stop();
+        assert!(client.flow_router().is_ok());
+    }
+}
@@ -3,16 +3,65 @@
  * Java/Kotlin bindings for QUICHE native client
  */
 
-use jni::JNIEnv;
-use jni::objects::{JClass, JByteArray};
-use jni::sys::{jboolean, jbooleanArray, jdoubleArray, jint, jlong, jlongArray};
-use std::sync::Arc;
+use jni::{JNIEnv, JavaVM};
+use jni::objects::{GlobalRef, JClass, JByteArray, JObject, JValue};
+use jni::sys::{jboolean, jbooleanArray, jbyteArray, jdoubleArray, jint, jlong, jlongArray, jobjectArray, JNI_VERSION_1_6};
+use std::sync::{Arc, OnceLock};
 use parking_lot::Mutex;
 use log::{error, info, warn};
 
-use crate::client::{QuicheClient, QuicConfig, CongestionControl, CpuAffinity};
-use crate::tun_forwarder::{QuicheTunForwarder, ForwarderConfig};
+use crate::client::{QuicheClient, QuicConfig, QuicMetrics, CongestionControl, CpuAffinity};
+use crate::resolver::dns_cache;
+use crate::session_store::session_store;
+use crate::scheduler::ConnectionScheduler;
+use crate::tun_forwarder::{QuicheTunForwarder, ForwarderConfig, ForwarderMode};
+use crate::local_proxy::{LocalProxy, LocalProxyConfig};
 use crate::crypto::QuicheCrypto;
+use crate::cert_verifier::{CertVerifyError, SpkiPin};
+
+// JavaVM cached at load time, used to deliver callbacks from runtime threads
+static JVM: OnceLock<JavaVM> = OnceLock::new();
+
+#[no_mangle]
+pub extern "C" fn JNI_OnLoad(vm: JavaVM, _reserved: *mut std::ffi::c_void) -> jint {
+    let _ = JVM.set(vm);
+    JNI_VERSION_1_6
+}
+
+// Map a connect failure to the code returned to Kotlin
+fn connect_error_code(e: &(dyn std::error::Error + 'static)) -> jint {
+    if let Some(cert_err) = e.downcast_ref::<CertVerifyError>() {
+        error!("Certificate validation failed: {}", cert_err);
+        return -2;
+    }
+    error!("Connection failed: {}", e);
+    -1
+}
+
+// Call `QuicheCallback.onResult(code)` from a runtime thread
+fn deliver_result(callback: GlobalRef, code: jint) {
+    let Some(vm) = JVM.get() else {
+        error!("JavaVM not cached, dropping callback result {}", code);
+        return;
+    };
+
+    // Runtime workers live as long as the process, so attach them once
+    let mut env = match vm.attach_current_thread_as_daemon() {
+        Ok(env) => env,
+        Err(e) => {
+            error!("Failed to attach runtime thread to JVM: {}", e);
+            return;
+        }
+    };
+
+    if let Err(e) = env.call_method(&callback, "onResult", "(I)V", &[JValue::Int(code)]) {
+        error!("Callback onResult({}) failed: {}", code, e);
+        if env.exception_check().unwrap_or(false) {
+            let _ = env.exception_describe();
+            let _ = env.exception_clear();
+        }
+    }
+}
 
 // Helper to convert Java string to Rust string
 fn jstring_to_string(env: &mut JNIEnv, jstr: jni::sys::jstring) -> String {
@@ -31,6 +80,38 @@ fn jstring_to_string(env: &mut JNIEnv, jstr: jni::sys::jstring) -> String {
     }
 }
 
+// Strings of a Java String[] (empty if null)
+fn jstring_array_to_vec(env: &mut JNIEnv, array: jobjectArray) -> Vec<String> {
+    if array.is_null() {
+        return Vec::new();
+    }
+
+    let array = unsafe { jni::objects::JObjectArray::from_raw(array) };
+    let len = env.get_array_length(&array).unwrap_or(0);
+    let mut strings = Vec::with_capacity(len as usize);
+    for i in 0..len {
+        if let Ok(element) = env.get_object_array_element(&array, i) {
+            strings.push(jstring_to_string(env, element.into_raw()));
+        }
+    }
+    strings
+}
+
+// Split "host:port" / "[v6]:port"; IPv6 addresses must be bracketed
+fn parse_host_port(entry: &str) -> Option<(String, u16)> {
+    let (host, port) = entry.trim().rsplit_once(':')?;
+    let host = match host.strip_prefix('[') {
+        Some(bracketed) => bracketed.strip_suffix(']')?,
+        None if host.contains(':') => return None,
+        None => host,
+    };
+    let port: u16 = port.parse().ok()?;
+    if host.is_empty() || port == 0 {
+        return None;
+    }
+    Some((host.to_string(), port))
+}
+
 /// Create QUIC client
 /// Note: Function name includes $Companion (encoded as 00024) because the native method
 /// is declared in Kotlin's companion object without @JvmStatic
@@ -43,6 +124,9 @@ pub extern "system" fn Java_com_simplexray_an_quiche_QuicheClient_00024Companion
     congestion_control: jint,
     enable_zero_copy: jboolean,
     cpu_affinity: jint,
+    connection_count: jint,
+    scheduler: jint,
+    additional_servers: jobjectArray,
 ) -> jlong {
     // Initialize logger if not already done
     android_logger::init_once(
@@ -86,8 +170,30 @@ pub extern "system" fn Java_com_simplexray_an_quiche_QuicheClient_00024Companion
         }
     };
 
-    info!("nativeCreate: Config - host={}, port={}, cc={:?}, cpu_aff={:?}", 
-          host, server_port, cc, cpu_aff);
+    let scheduler = match scheduler {
+        0 => ConnectionScheduler::RoundRobin,
+        1 => ConnectionScheduler::LeastRtt,
+        2 => ConnectionScheduler::LeastInflight,
+        _ => {
+            warn!("nativeCreate: Unknown scheduler {}, using round-robin", scheduler);
+            ConnectionScheduler::RoundRobin
+        }
+    };
+
+    // "host:port" entries ("[v6]:port" for IPv6 literals)
+    let mut servers = Vec::new();
+    for entry in jstring_array_to_vec(&mut env, additional_servers) {
+        match parse_host_port(&entry) {
+            Some(server) => servers.push(server),
+            None => {
+                error!("nativeCreate: Invalid server address: {}", entry);
+                return 0;
+            }
+        }
+    }
+
+    info!("nativeCreate: Config - host={}, port={}, cc={:?}, cpu_aff={:?}, connections={}, servers={}, scheduler={:?}", 
+          host, server_port, cc, cpu_aff, connection_count, servers.len() + 1, scheduler);
 
     let config = QuicConfig {
         server_host: host.clone(),
@@ -95,6 +201,9 @@ pub extern "system" fn Java_com_simplexray_an_quiche_QuicheClient_00024Companion
         cc_algorithm: cc,
         enable_zero_copy: enable_zero_copy != 0,
         cpu_affinity: cpu_aff,
+        connection_count: connection_count.max(1) as u32,
+        additional_servers: servers,
+        scheduler,
         ..Default::default()
     };
 
@@ -102,7 +211,7 @@ pub extern "system" fn Java_com_simplexray_an_quiche_QuicheClient_00024Companion
     let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
         match QuicheClient::create(config) {
             Ok(client) => {
-                let client = Arc::new(Mutex::new(client));
+                let client = Arc::new(client);
                 let handle = Box::into_raw(Box::new(client)) as jlong;
                 info!("nativeCreate: QUIC client created successfully, handle={}", handle);
                 Ok(handle)
@@ -128,6 +237,7 @@ pub extern "system" fn Java_com_simplexray_an_quiche_QuicheClient_00024Companion
 }
 
 /// Connect to server
+/// Returns 0 on success, -2 if the server certificate was rejected, -1 on any other failure
 #[no_mangle]
 pub extern "system" fn Java_com_simplexray_an_quiche_QuicheClient_00024Companion_nativeConnect(
     _env: JNIEnv,
@@ -148,18 +258,14 @@ pub extern "system" fn Java_com_simplexray_an_quiche_QuicheClient_00024Companion
 
     // Use catch_unwind to prevent panics from crashing the JVM
     let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-        let client = unsafe { &*(client_handle as *const Arc<Mutex<QuicheClient>>) };
-        let mut client = client.lock();
+        let client = unsafe { &*(client_handle as *const Arc<QuicheClient>) };
         
         match client.connect() {
             Ok(_) => {
                 info!("nativeConnect: Connection successful");
                 0
             }
-            Err(e) => {
-                error!("nativeConnect: Connection failed: {}", e);
-                -1
-            }
+            Err(e) => connect_error_code(e.as_ref()),
         }
     }));
 
@@ -172,6 +278,117 @@ pub extern "system" fn Java_com_simplexray_an_quiche_QuicheClient_00024Companion
     }
 }
 
+/// Connect without blocking the caller
+/// Returns 0 if the attempt started; `callback.onResult` later receives the
+/// same codes as nativeConnect, on a native thread
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_quiche_QuicheClient_00024Companion_nativeConnectAsync(
+    env: JNIEnv,
+    _class: JClass,
+    client_handle: jlong,
+    callback: JObject,
+) -> jint {
+    if client_handle == 0 || callback.is_null() {
+        error!("nativeConnectAsync: Invalid client handle or callback");
+        return -1;
+    }
+
+    let callback = match env.new_global_ref(&callback) {
+        Ok(callback) => callback,
+        Err(e) => {
+            error!("nativeConnectAsync: Failed to reference callback: {}", e);
+            return -1;
+        }
+    };
+
+    let client = unsafe { &*(client_handle as *const Arc<QuicheClient>) };
+    client.connect_async(move |result| {
+        let code = match result {
+            Ok(()) => {
+                info!("nativeConnectAsync: Connection successful");
+                0
+            }
+            Err(e) => connect_error_code(e.as_ref()),
+        };
+        deliver_result(callback, code);
+    });
+    0
+}
+
+/// Set certificate validation options
+/// `spki_pins` is a concatenation of 32-byte SPKI-SHA256 digests (may be null)
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_quiche_QuicheClient_00024Companion_nativeSetTlsOptions(
+    mut env: JNIEnv,
+    _class: JClass,
+    client_handle: jlong,
+    ca_bundle_pem: jni::sys::jstring,
+    spki_pins: JByteArray,
+    allow_insecure: jboolean,
+) -> jint {
+    if client_handle == 0 {
+        error!("nativeSetTlsOptions: Invalid client handle (0)");
+        return -1;
+    }
+
+    let pem = jstring_to_string(&mut env, ca_bundle_pem);
+    let ca_bundle = if pem.is_empty() { None } else { Some(pem) };
+
+    let mut pins: Vec<SpkiPin> = Vec::new();
+    if !spki_pins.is_null() {
+        let raw = match env.convert_byte_array(&spki_pins) {
+            Ok(bytes) => bytes,
+            Err(_) => {
+                error!("nativeSetTlsOptions: Failed to read pin array");
+                return -1;
+            }
+        };
+
+        if raw.len() % 32 != 0 {
+            error!("nativeSetTlsOptions: Pin array length {} is not a multiple of 32", raw.len());
+            return -1;
+        }
+
+        for chunk in raw.chunks_exact(32) {
+            let mut pin = [0u8; 32];
+            pin.copy_from_slice(chunk);
+            pins.push(pin);
+        }
+    }
+
+    let client = unsafe { &*(client_handle as *const Arc<QuicheClient>) };
+    client.set_tls_options(ca_bundle, pins, allow_insecure != 0);
+    0
+}
+
+/// Configure automatic reconnect after connection loss
+/// `max_attempts` 0 retries forever; delays are in milliseconds
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_quiche_QuicheClient_00024Companion_nativeSetReconnectPolicy(
+    _env: JNIEnv,
+    _class: JClass,
+    client_handle: jlong,
+    enabled: jboolean,
+    max_attempts: jint,
+    base_delay_ms: jlong,
+    max_delay_ms: jlong,
+) -> jint {
+    if client_handle == 0 {
+        error!("nativeSetReconnectPolicy: Invalid client handle (0)");
+        return -1;
+    }
+
+    if max_attempts < 0 || base_delay_ms <= 0 || max_delay_ms <= 0 {
+        error!("nativeSetReconnectPolicy: Invalid policy (attempts={}, base={} ms, max={} ms)",
+               max_attempts, base_delay_ms, max_delay_ms);
+        return -1;
+    }
+
+    let client = unsafe { &*(client_handle as *const Arc<QuicheClient>) };
+    client.set_reconnect_policy(enabled != 0, max_attempts as u32, base_delay_ms as u64, max_delay_ms as u64);
+    0
+}
+
 /// Disconnect from server
 #[no_mangle]
 pub extern "system" fn Java_com_simplexray_an_quiche_QuicheClient_00024Companion_nativeDisconnect(
@@ -183,11 +400,37 @@ pub extern "system" fn Java_com_simplexray_an_quiche_QuicheClient_00024Companion
         return;
     }
 
-    let client = unsafe { &*(client_handle as *const Arc<Mutex<QuicheClient>>) };
-    let mut client = client.lock();
+    let client = unsafe { &*(client_handle as *const Arc<QuicheClient>) };
     client.disconnect();
 }
 
+/// Disconnect without blocking the caller
+/// Returns 0 if started; `callback.onResult(0)` runs once the connection is closed
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_quiche_QuicheClient_00024Companion_nativeDisconnectAsync(
+    env: JNIEnv,
+    _class: JClass,
+    client_handle: jlong,
+    callback: JObject,
+) -> jint {
+    if client_handle == 0 || callback.is_null() {
+        error!("nativeDisconnectAsync: Invalid client handle or callback");
+        return -1;
+    }
+
+    let callback = match env.new_global_ref(&callback) {
+        Ok(callback) => callback,
+        Err(e) => {
+            error!("nativeDisconnectAsync: Failed to reference callback: {}", e);
+            return -1;
+        }
+    };
+
+    let client = unsafe { &*(client_handle as *const Arc<QuicheClient>) };
+    client.disconnect_async(move || deliver_result(callback, 0));
+    0
+}
+
 /// Destroy client
 #[no_mangle]
 pub extern "system" fn Java_com_simplexray_an_quiche_QuicheClient_00024Companion_nativeDestroy(
@@ -197,7 +440,7 @@ pub extern "system" fn Java_com_simplexray_an_quiche_QuicheClient_00024Companion
 ) {
     if client_handle != 0 {
         unsafe {
-            let _ = Box::from_raw(client_handle as *mut Arc<Mutex<QuicheClient>>);
+            let _ = Box::from_raw(client_handle as *mut Arc<QuicheClient>);
         }
     }
 }
@@ -213,8 +456,7 @@ pub extern "system" fn Java_com_simplexray_an_quiche_QuicheClient_00024Companion
         return jboolean::from(false);
     }
 
-    let client = unsafe { &*(client_handle as *const Arc<Mutex<QuicheClient>>) };
-    let client = client.lock();
+    let client = unsafe { &*(client_handle as *const Arc<QuicheClient>) };
     jboolean::from(client.is_connected())
 }
 
@@ -246,8 +488,7 @@ pub extern "system" fn Java_com_simplexray_an_quiche_QuicheClient_00024Companion
         }
     };
 
-    let client = unsafe { &*(client_handle as *const Arc<Mutex<QuicheClient>>) };
-    let mut client = client.lock();
+    let client = unsafe { &*(client_handle as *const Arc<QuicheClient>) };
     
     let data_slice: &[u8] = unsafe {
         std::slice::from_raw_parts(src.as_ptr() as *const u8, array_length as usize)
@@ -262,7 +503,7 @@ pub extern "system" fn Java_com_simplexray_an_quiche_QuicheClient_00024Companion
     result
 }
 
-/// Get metrics
+/// Get metrics (summed over all pooled connections)
 #[no_mangle]
 pub extern "system" fn Java_com_simplexray_an_quiche_QuicheClient_00024Companion_nativeGetMetrics(
     env: JNIEnv,
@@ -273,15 +514,30 @@ pub extern "system" fn Java_com_simplexray_an_quiche_QuicheClient_00024Companion
         return std::ptr::null_mut();
     }
 
-    let client = unsafe { &*(client_handle as *const Arc<Mutex<QuicheClient>>) };
-    let client = client.lock();
-    let metrics = client.get_metrics();
+    let client = unsafe { &*(client_handle as *const Arc<QuicheClient>) };
+    metrics_to_array(&env, &client.get_metrics())
+}
 
-    let result = match env.new_double_array(8) {
-        Ok(arr) => arr,
-        Err(_) => return std::ptr::null_mut(),
-    };
+/// Get metrics of one pooled connection (same layout as nativeGetMetrics)
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_quiche_QuicheClient_00024Companion_nativeGetConnectionMetrics(
+    env: JNIEnv,
+    _class: JClass,
+    client_handle: jlong,
+    index: jint,
+) -> jdoubleArray {
+    if client_handle == 0 || index < 0 {
+        return std::ptr::null_mut();
+    }
+
+    let client = unsafe { &*(client_handle as *const Arc<QuicheClient>) };
+    match client.connection_metrics().get(index as usize) {
+        Some(metrics) => metrics_to_array(&env, metrics),
+        None => std::ptr::null_mut(),
+    }
+}
 
+fn metrics_to_array(env: &JNIEnv, metrics: &QuicMetrics) -> jdoubleArray {
     let values = [
         metrics.throughput_mbps,
         metrics.rtt_us as f64,
@@ -291,15 +547,206 @@ pub extern "system" fn Java_com_simplexray_an_quiche_QuicheClient_00024Companion
         metrics.packets_sent as f64,
         metrics.packets_received as f64,
         metrics.cwnd as f64,
+        metrics.datagrams_sent as f64,
+        metrics.datagrams_received as f64,
+        metrics.datagrams_oversized as f64,
+        metrics.min_rtt_us as f64,
+        metrics.packets_lost as f64,
+        metrics.handshake_duration_us as f64,
+        if metrics.is_in_early_data { 1.0 } else { 0.0 },
+        metrics.zero_rtt_attempts as f64,
+        metrics.zero_rtt_accepted as f64,
+        metrics.zero_rtt_rejected as f64,
+        metrics.migration_count as f64,
+        metrics.last_migration_timestamp_ms as f64,
+        if metrics.is_reconnecting { 1.0 } else { 0.0 },
+        metrics.reconnect_attempts as f64,
+        metrics.reconnect_count as f64,
+        metrics.connections as f64,
+        metrics.connections_established as f64,
     ];
 
-    if let Err(_) = env.set_double_array_region(&result, 0, &values) {
+    let result = match env.new_double_array(values.len() as jint) {
+        Ok(arr) => arr,
+        Err(_) => return std::ptr::null_mut(),
+    };
+
+    if env.set_double_array_region(&result, 0, &values).is_err() {
         return std::ptr::null_mut();
     }
 
     result.into_raw() as jni::sys::jdoubleArray
 }
 
+/// Rebind the client to a new local socket after a network change
+/// `socket_fd` >= 0 adopts an app-provided UDP socket (ownership is transferred);
+/// otherwise binds to `local_address`:`local_port` (wildcard if empty)
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_quiche_QuicheClient_00024Companion_nativeRebind(
+    mut env: JNIEnv,
+    _class: JClass,
+    client_handle: jlong,
+    local_address: jni::sys::jstring,
+    local_port: jint,
+    socket_fd: jint,
+) -> jint {
+    if client_handle == 0 {
+        error!("nativeRebind: Invalid client handle (0)");
+        // The fd was handed over either way
+        if socket_fd >= 0 {
+            unsafe { libc::close(socket_fd) };
+        }
+        return -1;
+    }
+
+    let local_addr = if socket_fd >= 0 {
+        None
+    } else {
+        let addr_str = jstring_to_string(&mut env, local_address);
+        if addr_str.is_empty() {
+            None
+        } else {
+            match addr_str.parse::<std::net::IpAddr>() {
+                Ok(ip) => Some(std::net::SocketAddr::new(ip, local_port.clamp(0, 65535) as u16)),
+                Err(e) => {
+                    error!("nativeRebind: Invalid local address {}: {}", addr_str, e);
+                    return -1;
+                }
+            }
+        }
+    };
+    let fd = if socket_fd >= 0 { Some(socket_fd) } else { None };
+
+    let client = unsafe { &*(client_handle as *const Arc<QuicheClient>) };
+
+    match client.rebind(local_addr, fd) {
+        Ok(addr) => {
+            info!("nativeRebind: Rebound to {}", addr);
+            0
+        }
+        Err(e) => {
+            error!("nativeRebind: Rebind failed: {}", e);
+            -1
+        }
+    }
+}
+
+/// Provide server addresses resolved by the app (process-wide, used by every client)
+/// An empty array clears them (and cached answers) so the system resolver is used again;
+/// `ttl_ms` <= 0 keeps them until cleared. Returns 0, or -1 on invalid input
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_quiche_QuicheClient_00024Companion_nativeSetResolvedAddresses(
+    mut env: JNIEnv,
+    _class: JClass,
+    host: jni::sys::jstring,
+    addresses: jobjectArray,
+    ttl_ms: jlong,
+) -> jint {
+    let host = jstring_to_string(&mut env, host);
+    if host.is_empty() {
+        error!("nativeSetResolvedAddresses: Empty host");
+        return -1;
+    }
+
+    let mut addrs = Vec::new();
+    for entry in jstring_array_to_vec(&mut env, addresses) {
+        match entry.trim().parse::<std::net::IpAddr>() {
+            Ok(ip) => addrs.push(ip),
+            Err(_) => {
+                error!("nativeSetResolvedAddresses: Invalid address {}", entry);
+                return -1;
+            }
+        }
+    }
+
+    if addrs.is_empty() {
+        dns_cache().clear(Some(&host));
+    } else {
+        let ttl = if ttl_ms > 0 { Some(std::time::Duration::from_millis(ttl_ms as u64)) } else { None };
+        dns_cache().inject(&host, addrs, ttl);
+    }
+    0
+}
+
+/// Export each server's TLS key exchange group for the app to persist
+/// Session tickets are not included, so 0-RTT does not survive a restart. Returns null on failure
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_quiche_QuicheClient_00024Companion_nativeExportSessionState(
+    env: JNIEnv,
+    _class: JClass,
+) -> jbyteArray {
+    let state = session_store().export_state();
+    match env.byte_array_from_slice(&state) {
+        Ok(arr) => arr.into_raw(),
+        Err(_) => {
+            error!("nativeExportSessionState: Failed to allocate byte array");
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Restore key exchange groups saved from nativeExportSessionState (before connecting)
+/// Returns the number of servers restored, or -1 on invalid input
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_quiche_QuicheClient_00024Companion_nativeImportSessionState(
+    env: JNIEnv,
+    _class: JClass,
+    state: JByteArray,
+) -> jint {
+    if state.is_null() {
+        error!("nativeImportSessionState: Null state");
+        return -1;
+    }
+
+    let raw = match env.convert_byte_array(&state) {
+        Ok(bytes) => bytes,
+        Err(_) => {
+            error!("nativeImportSessionState: Failed to read state array");
+            return -1;
+        }
+    };
+
+    match session_store().import_state(&raw) {
+        Ok(count) => count as jint,
+        Err(e) => {
+            error!("nativeImportSessionState: {}", e);
+            -1
+        }
+    }
+}
+
+/// Get config warnings (values downgraded or ignored when mapping to quinn)
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_quiche_QuicheClient_00024Companion_nativeGetConfigWarnings(
+    mut env: JNIEnv,
+    _class: JClass,
+    client_handle: jlong,
+) -> jobjectArray {
+    if client_handle == 0 {
+        return std::ptr::null_mut();
+    }
+
+    let client = unsafe { &*(client_handle as *const Arc<QuicheClient>) };
+    let warnings = client.config_warnings().to_vec();
+
+    let result = match env.new_object_array(warnings.len() as jint, "java/lang/String", jni::objects::JObject::null()) {
+        Ok(arr) => arr,
+        Err(_) => return std::ptr::null_mut(),
+    };
+
+    for (i, warning) in warnings.iter().enumerate() {
+        let jstr = match env.new_string(warning) {
+            Ok(s) => s,
+            Err(_) => return std::ptr::null_mut(),
+        };
+        if env.set_object_array_element(&result, i as jint, jstr).is_err() {
+            return std::ptr::null_mut();
+        }
+    }
+
+    result.into_raw()
+}
+
 /// Create TUN forwarder
 #[no_mangle]
 pub extern "system" fn Java_com_simplexray_an_quiche_QuicheTunForwarder_00024Companion_nativeCreate(
@@ -310,20 +757,29 @@ pub extern "system" fn Java_com_simplexray_an_quiche_QuicheTunForwarder_00024Com
     batch_size: jint,
     use_gso: jboolean,
     use_gro: jboolean,
+    tun_mtu: jint,
+    use_vnet_hdr: jboolean,
+    mode: jint,
 ) -> jlong {
-    if client_handle == 0 || tun_fd < 0 {
+    if client_handle == 0 || tun_fd < 0 || batch_size < 1 || !(576..=65535).contains(&tun_mtu) {
         error!("Invalid parameters");
         return 0;
     }
 
-    let client = unsafe { &*(client_handle as *const Arc<Mutex<QuicheClient>>) };
+    let client = unsafe { &*(client_handle as *const Arc<QuicheClient>) };
     let client_clone = client.clone();
 
     let config = ForwarderConfig {
         tun_fd: tun_fd as std::os::unix::io::RawFd,
+        tun_mtu: tun_mtu as usize,
         batch_size: batch_size as usize,
         use_gso: use_gso != 0,
         use_gro: use_gro != 0,
+        use_vnet_hdr: use_vnet_hdr != 0,
+        mode: match mode {
+            1 => ForwarderMode::Flow,
+            _ => ForwarderMode::Packet,
+        },
         ..Default::default()
     };
 
@@ -407,19 +863,35 @@ pub extern "system" fn Java_com_simplexray_an_quiche_QuicheTunForwarder_00024Com
     let forwarder = forwarder.lock();
     let stats = forwarder.get_stats();
 
-    let result = match env.new_long_array(5) {
-        Ok(arr) => arr,
-        Err(_) => return std::ptr::null_mut(),
-    };
-
     let values = [
         stats.packets_received as jlong,
         stats.packets_sent as jlong,
         stats.packets_dropped as jlong,
         stats.bytes_received as jlong,
         stats.bytes_sent as jlong,
+        stats.packets_to_tun as jlong,
+        stats.bytes_to_tun as jlong,
+        stats.tun_write_errors as jlong,
+        // Rates are exported in kbps to fit the long array
+        (stats.tx_rate_mbps * 1000.0) as jlong,
+        (stats.rx_rate_mbps * 1000.0) as jlong,
+        stats.packets_oversized as jlong,
+        stats.read_batches as jlong,
+        stats.pool_exhausted as jlong,
+        stats.gso_frames as jlong,
+        stats.gso_segments as jlong,
+        stats.tcp_flows_active as jlong,
+        stats.tcp_flows_total as jlong,
+        stats.udp_flows_active as jlong,
+        stats.udp_flows_total as jlong,
+        stats.flow_errors as jlong,
     ];
 
+    let result = match env.new_long_array(values.len() as jint) {
+        Ok(arr) => arr,
+        Err(_) => return std::ptr::null_mut(),
+    };
+
     if let Err(_) = env.set_long_array_region(&result, 0, &values) {
         return std::ptr::null_mut();
     }
@@ -427,6 +899,135 @@ pub extern "system" fn Java_com_simplexray_an_quiche_QuicheTunForwarder_00024Com
     result.into_raw() as jni::sys::jlongArray
 }
 
+/// Start local SOCKS5/HTTP CONNECT proxy
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_quiche_QuicheLocalProxy_00024Companion_nativeStart(
+    mut env: JNIEnv,
+    _class: JClass,
+    client_handle: jlong,
+    bind_host: jni::sys::jstring,
+    port: jint,
+    max_connections: jint,
+) -> jlong {
+    if client_handle == 0 || !(0..=65535).contains(&port) || max_connections < 1 {
+        error!("Invalid parameters");
+        return 0;
+    }
+
+    let bind_host = jstring_to_string(&mut env, bind_host);
+    let bind_ip: std::net::IpAddr = match bind_host.parse() {
+        Ok(ip) => ip,
+        Err(_) => {
+            error!("Invalid proxy bind address: {}", bind_host);
+            return 0;
+        }
+    };
+
+    let client = unsafe { &*(client_handle as *const Arc<QuicheClient>) };
+
+    let config = LocalProxyConfig {
+        bind_addr: std::net::SocketAddr::new(bind_ip, port as u16),
+        max_connections: max_connections as usize,
+    };
+
+    match LocalProxy::start(config, client.clone()) {
+        Ok(proxy) => {
+            let proxy = Arc::new(Mutex::new(proxy));
+            Box::into_raw(Box::new(proxy)) as jlong
+        }
+        Err(e) => {
+            error!("Failed to start local proxy: {}", e);
+            0
+        }
+    }
+}
+
+/// Stop local proxy (closes the listener and all proxied connections)
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_quiche_QuicheLocalProxy_00024Companion_nativeStop(
+    _env: JNIEnv,
+    _class: JClass,
+    proxy_handle: jlong,
+) {
+    if proxy_handle == 0 {
+        return;
+    }
+
+    let proxy = unsafe { &*(proxy_handle as *const Arc<Mutex<LocalProxy>>) };
+    proxy.lock().stop();
+}
+
+/// Destroy local proxy
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_quiche_QuicheLocalProxy_00024Companion_nativeDestroy(
+    _env: JNIEnv,
+    _class: JClass,
+    proxy_handle: jlong,
+) {
+    if proxy_handle != 0 {
+        unsafe {
+            let _ = Box::from_raw(proxy_handle as *mut Arc<Mutex<LocalProxy>>);
+        }
+    }
+}
+
+/// Get the port the proxy is listening on
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_quiche_QuicheLocalProxy_00024Companion_nativeGetPort(
+    _env: JNIEnv,
+    _class: JClass,
+    proxy_handle: jlong,
+) -> jint {
+    if proxy_handle == 0 {
+        return -1;
+    }
+
+    let proxy = unsafe { &*(proxy_handle as *const Arc<Mutex<LocalProxy>>) };
+    let port = proxy.lock().local_addr().port();
+    port as jint
+}
+
+/// Get local proxy statistics
+#[no_mangle]
+pub extern "system" fn Java_com_simplexray_an_quiche_QuicheLocalProxy_00024Companion_nativeGetStats(
+    env: JNIEnv,
+    _class: JClass,
+    proxy_handle: jlong,
+) -> jlongArray {
+    if proxy_handle == 0 {
+        return std::ptr::null_mut();
+    }
+
+    let proxy = unsafe { &*(proxy_handle as *const Arc<Mutex<LocalProxy>>) };
+    let stats = proxy.lock().get_stats();
+
+    let values = [
+        stats.connections_total as jlong,
+        stats.connections_active as jlong,
+        stats.connections_rejected as jlong,
+        stats.socks_connects as jlong,
+        stats.socks_udp_associates as jlong,
+        stats.http_connects as jlong,
+        stats.handshake_errors as jlong,
+        stats.stream_errors as jlong,
+        stats.bytes_up as jlong,
+        stats.bytes_down as jlong,
+        stats.udp_datagrams_up as jlong,
+        stats.udp_datagrams_down as jlong,
+    ];
+
+    let result = match env.new_long_array(values.len() as jint) {
+        Ok(arr) => arr,
+        Err(_) => return std::ptr::null_mut(),
+    };
+
+    if env.set_long_array_region(&result, 0, &values).is_err() {
+        return std::ptr::null_mut();
+    }
+
+    result.into_raw() as jni::sys::jlongArray
+}
+
 /// Get crypto capabilities
 #[no_mangle]
 pub extern "system" fn Java_com_simplexray_an_quiche_QuicheCrypto_nativeGetCapabilities(
@@ -463,6 +1064,23 @@ pub extern "system" fn Java_com_simplexray_an_quiche_QuicheCrypto_nativePrintCap
     QuicheCrypto::print_capabilities();
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
+    #[test]
+    fn host_port_entries_parse() {
+        assert_eq!(parse_host_port(" example.com:443 "), Some(("example.com".into(), 443)));
+        assert_eq!(parse_host_port("192.0.2.1:8443"), Some(("192.0.2.1".into(), 8443)));
+        assert_eq!(parse_host_port("[2001:db8::1]:443"), Some(("2001:db8::1".into(), 443)));
 
-
+        // Unbracketed IPv6 would split inside the address
+        assert_eq!(parse_host_port("2001:db8::1"), None);
+        assert_eq!(parse_host_port("2001:db8::1:443"), None);
+        assert_eq!(parse_host_port("[2001:db8::1:443"), None);
+        assert_eq!(parse_host_port("example.com"), None);
+        assert_eq!(parse_host_port(":443"), None);
+        assert_eq!(parse_host_port("example.com:0"), None);
+        assert_eq!(parse_host_port("example.com:http"), None);
+    }
+}
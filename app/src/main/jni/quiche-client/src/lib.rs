@@ -4,13 +4,39 @@
  */
 
 mod client;
+mod runtime;
+mod resolver;
+mod scheduler;
+mod cert_verifier;
+mod session_store;
 mod tun_forwarder;
+mod packet_pool;
+mod vnet;
+mod flow_header;
+mod flow_router;
+mod netstack;
+mod local_proxy;
+mod metrics;
 mod crypto;
 mod utils;
 mod jni_bridge;
+#[cfg(test)]
+mod test_server;
 
 pub use client::*;
+pub use runtime::*;
+pub use resolver::*;
+pub use scheduler::*;
+pub use cert_verifier::*;
+pub use session_store::*;
 pub use tun_forwarder::*;
+pub use packet_pool::*;
+pub use vnet::*;
+pub use flow_header::*;
+pub use flow_router::*;
+pub use netstack::*;
+pub use local_proxy::*;
+pub use metrics::*;
 pub use crypto::*;
 pub use utils::*;
 pub use jni_bridge::*;
@@ -0,0 +1,681 @@
+/*
+ * Local Proxy Inbound (Rust Implementation)
+ * SOCKS5 and HTTP CONNECT listener tunnelling over QuicheClient streams
+ */
+
+use crate::client::{BoxError, QuicheClient};
+use crate::flow_header::{encode_datagram, TargetAddr};
+use crate::flow_router::FlowRouter;
+use crate::runtime::runtime;
+use log::{debug, info, warn};
+use parking_lot::Mutex;
+use quinn::{RecvStream, SendStream};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::mpsc;
+use tokio::task::{JoinHandle, JoinSet};
+
+const SOCKS_VERSION: u8 = 5;
+const SOCKS_AUTH_NONE: u8 = 0x00;
+const SOCKS_AUTH_UNACCEPTABLE: u8 = 0xff;
+const SOCKS_CMD_CONNECT: u8 = 1;
+const SOCKS_CMD_UDP_ASSOCIATE: u8 = 3;
+const SOCKS_REPLY_SUCCEEDED: u8 = 0;
+const SOCKS_REPLY_GENERAL_FAILURE: u8 = 1;
+const SOCKS_REPLY_COMMAND_NOT_SUPPORTED: u8 = 7;
+const SOCKS_REPLY_ADDRESS_NOT_SUPPORTED: u8 = 8;
+
+// Client must finish the SOCKS/HTTP handshake within this time
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_HTTP_HEADER_SIZE: usize = 8 * 1024;
+// Datagrams queued from the router to one UDP association
+const UDP_RELAY_DEPTH: usize = 256;
+const MAX_UDP_PACKET_SIZE: usize = 65535;
+
+#[derive(Clone, Debug)]
+pub struct LocalProxyConfig {
+    /// Listen address; port 0 picks a free one (see `LocalProxy::local_addr`)
+    pub bind_addr: SocketAddr,
+    /// Connections beyond this are closed on accept
+    pub max_connections: usize,
+}
+
+impl Default for LocalProxyConfig {
+    fn default() -> Self {
+        Self {
+            bind_addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 1080),
+            max_connections: 1024,
+        }
+    }
+}
+
+/// Per-listener counters
+///
+/// `bytes_up`/`bytes_down` count proxied TCP payload once a connection ends
+/// (client -> QUIC and back); UDP ASSOCIATE traffic is in `udp_datagrams_*`.
+#[derive(Clone, Debug, Default)]
+pub struct LocalProxyStats {
+    pub connections_total: u64,
+    pub connections_active: u64,
+    pub connections_rejected: u64,
+    pub socks_connects: u64,
+    pub socks_udp_associates: u64,
+    pub http_connects: u64,
+    pub handshake_errors: u64,
+    pub stream_errors: u64,
+    pub bytes_up: u64,
+    pub bytes_down: u64,
+    pub udp_datagrams_up: u64,
+    pub udp_datagrams_down: u64,
+}
+
+/// SOCKS5 (CONNECT, UDP ASSOCIATE) and HTTP CONNECT on one port
+///
+/// The protocol is picked from the first byte. TCP connections each get a
+/// bidirectional stream with a flow header; UDP associations become a
+/// `FlowRouter` datagram flow (refused while a packet-mode TUN forwarder
+/// reads the client's DATAGRAMs).
+pub struct LocalProxy {
+    local_addr: SocketAddr,
+    stats: Arc<Mutex<LocalProxyStats>>,
+    accept_task: Option<JoinHandle<()>>,
+}
+
+impl LocalProxy {
+    /// Bind the listener and start accepting on the shared runtime
+    pub fn start(
+        config: LocalProxyConfig,
+        quic_client: Arc<QuicheClient>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let listener = std::net::TcpListener::bind(config.bind_addr)
+            .map_err(|e| format!("Failed to bind {}: {}", config.bind_addr, e))?;
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+
+        let listener = {
+            let _guard = runtime().enter();
+            TcpListener::from_std(listener)?
+        };
+
+        let stats = Arc::new(Mutex::new(LocalProxyStats::default()));
+        let accept_task = runtime().spawn(accept_loop(listener, config, quic_client, stats.clone()));
+
+        info!("Local proxy listening on {} (SOCKS5 + HTTP CONNECT)", local_addr);
+        Ok(Self {
+            local_addr,
+            stats,
+            accept_task: Some(accept_task),
+        })
+    }
+
+    /// Address actually bound
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Close the listener and every proxied connection
+    pub fn stop(&mut self) {
+        if let Some(task) = self.accept_task.take() {
+            task.abort();
+            info!("Local proxy on {} stopped", self.local_addr);
+        }
+    }
+
+    pub fn get_stats(&self) -> LocalProxyStats {
+        self.stats.lock().clone()
+    }
+}
+
+impl Drop for LocalProxy {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// Sessions live in the JoinSet, so aborting this task closes them too
+async fn accept_loop(
+    listener: TcpListener,
+    config: LocalProxyConfig,
+    client: Arc<QuicheClient>,
+    stats: Arc<Mutex<LocalProxyStats>>,
+) {
+    let mut sessions = JoinSet::new();
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, peer)) => {
+                    if sessions.len() >= config.max_connections {
+                        debug!("Rejecting proxy connection from {}: limit reached", peer);
+                        stats.lock().connections_rejected += 1;
+                        continue;
+                    }
+                    let _ = stream.set_nodelay(true);
+                    sessions.spawn(session(stream, config.bind_addr.ip(), client.clone(), stats.clone()));
+                }
+                Err(e) => {
+                    warn!("Proxy accept failed: {}", e);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+            },
+            Some(_) = sessions.join_next() => {}
+        }
+    }
+}
+
+// Tracks connections_active for a session, including aborted ones
+struct ActiveSession(Arc<Mutex<LocalProxyStats>>);
+
+impl ActiveSession {
+    fn new(stats: Arc<Mutex<LocalProxyStats>>) -> Self {
+        {
+            let mut stats = stats.lock();
+            stats.connections_total += 1;
+            stats.connections_active += 1;
+        }
+        Self(stats)
+    }
+}
+
+impl Drop for ActiveSession {
+    fn drop(&mut self) {
+        self.0.lock().connections_active -= 1;
+    }
+}
+
+// What a handshake asked for
+enum ProxyRequest {
+    Connect { target: TargetAddr, protocol: ProxyProtocol, leftover: Vec<u8> },
+    UdpAssociate,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ProxyProtocol {
+    Socks5,
+    Http,
+}
+
+async fn session(
+    mut stream: TcpStream,
+    listen_ip: IpAddr,
+    client: Arc<QuicheClient>,
+    stats: Arc<Mutex<LocalProxyStats>>,
+) {
+    let _active = ActiveSession::new(stats.clone());
+
+    let request = tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake(&mut stream))
+        .await
+        .map_err(|_| "handshake timed out".into())
+        .and_then(|r| r);
+    let request = match request {
+        Ok(request) => request,
+        Err(e) => {
+            debug!("Proxy handshake failed: {}", e);
+            stats.lock().handshake_errors += 1;
+            return;
+        }
+    };
+
+    let result = match request {
+        ProxyRequest::Connect { target, protocol, leftover } => {
+            match protocol {
+                ProxyProtocol::Socks5 => stats.lock().socks_connects += 1,
+                ProxyProtocol::Http => stats.lock().http_connects += 1,
+            }
+            connect(stream, target, protocol, leftover, &client, &stats).await
+        }
+        ProxyRequest::UdpAssociate => {
+            stats.lock().socks_udp_associates += 1;
+            udp_associate(stream, listen_ip, &client, &stats).await
+        }
+    };
+
+    if let Err(e) = result {
+        debug!("Proxy session ended with error: {}", e);
+        stats.lock().stream_errors += 1;
+    }
+}
+
+async fn handshake(stream: &mut TcpStream) -> Result<ProxyRequest, BoxError> {
+    let mut first = [0u8; 1];
+    if stream.peek(&mut first).await? == 0 {
+        return Err("connection closed before handshake".into());
+    }
+
+    if first[0] == SOCKS_VERSION {
+        socks5_handshake(stream).await
+    } else {
+        http_handshake(stream).await
+    }
+}
+
+async fn socks5_handshake(stream: &mut TcpStream) -> Result<ProxyRequest, BoxError> {
+    // Greeting: VER NMETHODS METHODS...
+    let mut greeting = [0u8; 2];
+    stream.read_exact(&mut greeting).await?;
+    let mut methods = vec![0u8; greeting[1] as usize];
+    stream.read_exact(&mut methods).await?;
+    if !methods.contains(&SOCKS_AUTH_NONE) {
+        stream.write_all(&[SOCKS_VERSION, SOCKS_AUTH_UNACCEPTABLE]).await?;
+        return Err("client offered no supported SOCKS5 auth method".into());
+    }
+    stream.write_all(&[SOCKS_VERSION, SOCKS_AUTH_NONE]).await?;
+
+    // Request: VER CMD RSV ATYP DST.ADDR DST.PORT
+    let mut head = [0u8; 4];
+    stream.read_exact(&mut head).await?;
+    if head[0] != SOCKS_VERSION {
+        return Err(format!("bad SOCKS version {}", head[0]).into());
+    }
+    let target = match read_socks_addr(stream, head[3]).await? {
+        Some(target) => target,
+        None => {
+            socks5_reply(stream, SOCKS_REPLY_ADDRESS_NOT_SUPPORTED, None).await?;
+            return Err(format!("unsupported SOCKS address type {}", head[3]).into());
+        }
+    };
+
+    match head[1] {
+        SOCKS_CMD_CONNECT => Ok(ProxyRequest::Connect {
+            target,
+            protocol: ProxyProtocol::Socks5,
+            leftover: Vec::new(),
+        }),
+        SOCKS_CMD_UDP_ASSOCIATE => Ok(ProxyRequest::UdpAssociate),
+        cmd => {
+            socks5_reply(stream, SOCKS_REPLY_COMMAND_NOT_SUPPORTED, None).await?;
+            Err(format!("unsupported SOCKS command {}", cmd).into())
+        }
+    }
+}
+
+// Read ATYP-specific address bytes; None for an unknown ATYP
+async fn read_socks_addr(stream: &mut TcpStream, atyp: u8) -> Result<Option<TargetAddr>, BoxError> {
+    let mut buf = vec![atyp];
+    let remaining = match atyp {
+        1 => 4 + 2,
+        4 => 16 + 2,
+        3 => {
+            let len = stream.read_u8().await?;
+            buf.push(len);
+            len as usize + 2
+        }
+        _ => return Ok(None),
+    };
+    let start = buf.len();
+    buf.resize(start + remaining, 0);
+    stream.read_exact(&mut buf[start..]).await?;
+    Ok(TargetAddr::decode(&buf).map(|(target, _)| target))
+}
+
+// VER REP RSV ATYP BND.ADDR BND.PORT
+async fn socks5_reply(stream: &mut TcpStream, reply: u8, bound: Option<SocketAddr>) -> Result<(), BoxError> {
+    let bound = bound.unwrap_or_else(|| SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0));
+    let mut buf = vec![SOCKS_VERSION, reply, 0];
+    TargetAddr::Ip(bound).encode(&mut buf);
+    stream.write_all(&buf).await?;
+    Ok(())
+}
+
+async fn http_handshake(stream: &mut TcpStream) -> Result<ProxyRequest, BoxError> {
+    // Read the request head; anything after it is early tunnel data
+    let mut buf = Vec::with_capacity(1024);
+    let head_len = loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+        if buf.len() >= MAX_HTTP_HEADER_SIZE {
+            http_reply(stream, "431 Request Header Fields Too Large").await?;
+            return Err("HTTP request head too large".into());
+        }
+        let mut chunk = [0u8; 1024];
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err("connection closed during HTTP request".into());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+
+    let head = String::from_utf8_lossy(&buf[..head_len]);
+    let mut request_line = head.lines().next().unwrap_or_default().split_whitespace();
+    let (method, authority) = (request_line.next().unwrap_or_default(), request_line.next().unwrap_or_default());
+
+    if !method.eq_ignore_ascii_case("CONNECT") {
+        http_reply(stream, "405 Method Not Allowed\r\nAllow: CONNECT").await?;
+        return Err(format!("unsupported HTTP method {:?}", method).into());
+    }
+    let Some(target) = parse_authority(authority) else {
+        http_reply(stream, "400 Bad Request").await?;
+        return Err(format!("bad CONNECT target {:?}", authority).into());
+    };
+
+    Ok(ProxyRequest::Connect {
+        target,
+        protocol: ProxyProtocol::Http,
+        leftover: buf[head_len..].to_vec(),
+    })
+}
+
+async fn http_reply(stream: &mut TcpStream, status: &str) -> Result<(), BoxError> {
+    let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+    stream.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+// host:port or [v6]:port
+fn parse_authority(authority: &str) -> Option<TargetAddr> {
+    if let Ok(addr) = authority.parse::<SocketAddr>() {
+        return Some(TargetAddr::Ip(addr));
+    }
+    let (host, port) = authority.rsplit_once(':')?;
+    let port = port.parse().ok()?;
+    if host.contains(':') || host.starts_with('[') {
+        return None;
+    }
+    Some(TargetAddr::Domain(host.to_string(), port)).filter(TargetAddr::is_valid)
+}
+
+async fn connect(
+    mut stream: TcpStream,
+    target: TargetAddr,
+    protocol: ProxyProtocol,
+    leftover: Vec<u8>,
+    client: &QuicheClient,
+    stats: &Mutex<LocalProxyStats>,
+) -> Result<(), BoxError> {
+    debug!("Proxy CONNECT {}", target);
+
+    let (send, recv, _link) = match client.open_flow_stream(&target).await {
+        Ok(streams) => streams,
+        Err(e) => {
+            // Best effort: the client may already be gone
+            let _ = match protocol {
+                ProxyProtocol::Socks5 => socks5_reply(&mut stream, SOCKS_REPLY_GENERAL_FAILURE, None).await,
+                ProxyProtocol::Http => http_reply(&mut stream, "502 Bad Gateway").await,
+            };
+            return Err(e);
+        }
+    };
+
+    match protocol {
+        ProxyProtocol::Socks5 => socks5_reply(&mut stream, SOCKS_REPLY_SUCCEEDED, None).await?,
+        ProxyProtocol::Http => stream.write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n").await?,
+    }
+
+    relay(stream, &leftover, send, recv, stats).await
+}
+
+// Copy both directions, half-closing each side when the other finishes
+async fn relay(
+    stream: TcpStream,
+    leftover: &[u8],
+    mut send: SendStream,
+    mut recv: RecvStream,
+    stats: &Mutex<LocalProxyStats>,
+) -> Result<(), BoxError> {
+    let (mut reader, mut writer) = stream.into_split();
+
+    let uplink = async {
+        send.write_all(leftover).await?;
+        let copied = tokio::io::copy(&mut reader, &mut send).await?;
+        send.finish()?;
+        Ok::<_, BoxError>(copied + leftover.len() as u64)
+    };
+    let downlink = async {
+        let copied = tokio::io::copy(&mut recv, &mut writer).await?;
+        writer.shutdown().await?;
+        Ok::<_, BoxError>(copied)
+    };
+
+    let (up, down) = tokio::try_join!(uplink, downlink)?;
+    let mut stats = stats.lock();
+    stats.bytes_up += up;
+    stats.bytes_down += down;
+    Ok(())
+}
+
+// Unregisters a UDP association's flow id
+struct FlowRegistration(Arc<FlowRouter>, u32);
+
+impl Drop for FlowRegistration {
+    fn drop(&mut self) {
+        self.0.unregister(self.1);
+    }
+}
+
+// Relay SOCKS5 UDP for as long as the control connection stays open
+async fn udp_associate(
+    mut control: TcpStream,
+    listen_ip: IpAddr,
+    client: &QuicheClient,
+    stats: &Mutex<LocalProxyStats>,
+) -> Result<(), BoxError> {
+    // Unavailable while a packet-mode TUN forwarder reads the client's DATAGRAMs
+    let router = match client.flow_router().map_err(|e| e.to_string()) {
+        Ok(router) => router,
+        Err(e) => {
+            socks5_reply(&mut control, SOCKS_REPLY_COMMAND_NOT_SUPPORTED, None).await?;
+            return Err(e.into());
+        }
+    };
+    let socket = match UdpSocket::bind(SocketAddr::new(listen_ip, 0)).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            socks5_reply(&mut control, SOCKS_REPLY_GENERAL_FAILURE, None).await?;
+            return Err(e.into());
+        }
+    };
+    socks5_reply(&mut control, SOCKS_REPLY_SUCCEEDED, Some(socket.local_addr()?)).await?;
+
+    let link = client.flow_link();
+    let (tx, mut rx) = mpsc::channel(UDP_RELAY_DEPTH);
+    let flow_id = router.register(tx);
+    let _registration = FlowRegistration(router, flow_id);
+    debug!("Proxy UDP ASSOCIATE on {} (flow {}, connection {})", socket.local_addr()?, flow_id, link.index());
+
+    // Only the control connection's host may use the relay (RFC 1928 section 7);
+    // replies go to the port it sent the first datagram from
+    let app_ip = control.peer_addr()?.ip();
+    let mut app_addr: Option<SocketAddr> = None;
+    let mut buf = vec![0u8; MAX_UDP_PACKET_SIZE];
+    let mut control_buf = [0u8; 64];
+
+    loop {
+        tokio::select! {
+            received = socket.recv_from(&mut buf) => {
+                let (len, from) = received?;
+                if from.ip() != app_ip || *app_addr.get_or_insert(from) != from {
+                    continue;
+                }
+                // RSV RSV FRAG ATYP DST.ADDR DST.PORT DATA; fragments are not supported
+                if len < 4 || buf[2] != 0 {
+                    continue;
+                }
+                let Some((target, used)) = TargetAddr::decode(&buf[3..len]) else {
+                    continue;
+                };
+                let datagram = encode_datagram(flow_id, &target, &buf[3 + used..len]);
+                match link.send_datagram(&datagram) {
+                    Ok(_) => stats.lock().udp_datagrams_up += 1,
+                    Err(e) => debug!("Dropping SOCKS UDP datagram to {}: {}", target, e),
+                }
+            }
+            Some(reply) = rx.recv() => {
+                let Some(to) = app_addr else {
+                    continue;
+                };
+                let mut packet = vec![0, 0, 0];
+                reply.peer.encode(&mut packet);
+                packet.extend_from_slice(&reply.payload);
+                if socket.send_to(&packet, to).await.is_ok() {
+                    stats.lock().udp_datagrams_down += 1;
+                }
+            }
+            read = control.read(&mut control_buf) => match read {
+                Ok(0) | Err(_) => return Ok(()),
+                Ok(_) => {}
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{client_for, echo_server};
+    use std::io::{Read, Write};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn proxy() -> (LocalProxy, Arc<QuicheClient>) {
+        let client = client_for(echo_server(), 1);
+        client.connect().unwrap();
+        let config = LocalProxyConfig {
+            bind_addr: "127.0.0.1:0".parse().unwrap(),
+            ..LocalProxyConfig::default()
+        };
+        (LocalProxy::start(config, client.clone()).unwrap(), client)
+    }
+
+    fn dial(proxy: &LocalProxy) -> std::net::TcpStream {
+        let stream = std::net::TcpStream::connect(proxy.local_addr()).unwrap();
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+        stream
+    }
+
+    // Greeting plus a request for `cmd`; returns the reply's bound address
+    fn socks5_request(stream: &mut std::net::TcpStream, cmd: u8, target: &TargetAddr) -> SocketAddr {
+        stream.write_all(&[SOCKS_VERSION, 1, SOCKS_AUTH_NONE]).unwrap();
+        let mut method = [0u8; 2];
+        stream.read_exact(&mut method).unwrap();
+        assert_eq!(method, [SOCKS_VERSION, SOCKS_AUTH_NONE]);
+
+        let mut request = vec![SOCKS_VERSION, cmd, 0];
+        target.encode(&mut request);
+        stream.write_all(&request).unwrap();
+        let mut reply = [0u8; 10];
+        stream.read_exact(&mut reply).unwrap();
+        assert_eq!(reply[..2], [SOCKS_VERSION, SOCKS_REPLY_SUCCEEDED]);
+        match TargetAddr::decode(&reply[3..]) {
+            Some((TargetAddr::Ip(bound), _)) => bound,
+            other => panic!("unexpected bound address {:?}", other),
+        }
+    }
+
+    fn wait_for(mut done: impl FnMut() -> bool) {
+        let deadline = std::time::Instant::now() + TIMEOUT;
+        while !done() {
+            assert!(std::time::Instant::now() < deadline, "timed out");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn socks5_connect_round_trip() {
+        let (proxy, client) = proxy();
+        let mut stream = dial(&proxy);
+        socks5_request(&mut stream, SOCKS_CMD_CONNECT, &TargetAddr::Domain("example.com".into(), 80));
+
+        stream.write_all(b"ping").unwrap();
+        let mut echoed = [0u8; 4];
+        stream.read_exact(&mut echoed).unwrap();
+        assert_eq!(&echoed, b"ping");
+
+        // Half-closing the client finishes the stream, and the echo ends too
+        stream.shutdown(std::net::Shutdown::Write).unwrap();
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).unwrap();
+        assert!(rest.is_empty());
+
+        wait_for(|| proxy.get_stats().connections_active == 0);
+        let stats = proxy.get_stats();
+        assert_eq!((stats.socks_connects, stats.bytes_up, stats.bytes_down), (1, 4, 4));
+        client.disconnect();
+    }
+
+    #[test]
+    fn http_connect_round_trip_keeps_early_data() {
+        let (proxy, client) = proxy();
+        let mut stream = dial(&proxy);
+        stream
+            .write_all(b"CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\nearly")
+            .unwrap();
+
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            let mut byte = [0u8; 1];
+            stream.read_exact(&mut byte).unwrap();
+            head.push(byte[0]);
+        }
+        assert!(head.starts_with(b"HTTP/1.1 200"));
+
+        stream.write_all(b" and late").unwrap();
+        let mut echoed = [0u8; 14];
+        stream.read_exact(&mut echoed).unwrap();
+        assert_eq!(&echoed, b"early and late");
+
+        drop(stream);
+        wait_for(|| proxy.get_stats().connections_active == 0);
+        assert_eq!(proxy.get_stats().http_connects, 1);
+        client.disconnect();
+    }
+
+    #[test]
+    fn socks5_udp_associate_round_trip() {
+        let (proxy, client) = proxy();
+        let mut control = dial(&proxy);
+        let relay = socks5_request(&mut control, SOCKS_CMD_UDP_ASSOCIATE, &TargetAddr::Ip("0.0.0.0:0".parse().unwrap()));
+
+        let app = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        app.set_read_timeout(Some(TIMEOUT)).unwrap();
+        let target = TargetAddr::Ip("192.0.2.1:53".parse().unwrap());
+        let mut packet = vec![0, 0, 0];
+        target.encode(&mut packet);
+        packet.extend_from_slice(b"query");
+        app.send_to(&packet, relay).unwrap();
+
+        // The echo server returns it as coming from the target
+        let mut buf = [0u8; 64];
+        let (len, from) = app.recv_from(&mut buf).unwrap();
+        assert_eq!(from, relay);
+        assert_eq!(&buf[..len], &packet[..]);
+
+        // The association ends with its control connection
+        drop(control);
+        wait_for(|| proxy.get_stats().connections_active == 0);
+        let stats = proxy.get_stats();
+        assert_eq!((stats.socks_udp_associates, stats.udp_datagrams_up, stats.udp_datagrams_down), (1, 1, 1));
+        assert_eq!(client.flow_router().unwrap().flow_count(), 0);
+        client.disconnect();
+    }
+
+    #[test]
+    fn udp_associate_ignores_other_hosts() {
+        let (proxy, client) = proxy();
+        let mut control = dial(&proxy);
+        let relay = socks5_request(&mut control, SOCKS_CMD_UDP_ASSOCIATE, &TargetAddr::Ip("0.0.0.0:0".parse().unwrap()));
+
+        let mut packet = vec![0, 0, 0];
+        TargetAddr::Ip("192.0.2.1:53".parse().unwrap()).encode(&mut packet);
+        packet.extend_from_slice(b"query");
+
+        // Another loopback address is another host to the relay
+        let stranger = std::net::UdpSocket::bind("127.0.0.2:0").unwrap();
+        stranger.set_read_timeout(Some(Duration::from_millis(300))).unwrap();
+        stranger.send_to(&packet, relay).unwrap();
+
+        let app = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        app.set_read_timeout(Some(TIMEOUT)).unwrap();
+        app.send_to(&packet, relay).unwrap();
+        let mut buf = [0u8; 64];
+        let (len, _) = app.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &packet[..]);
+
+        assert!(stranger.recv_from(&mut buf).is_err());
+        assert_eq!(proxy.get_stats().udp_datagrams_up, 1);
+        client.disconnect();
+    }
+}
@@ -0,0 +1,102 @@
+/*
+ * QUIC Metrics Sampler (Rust Implementation)
+ * Periodically folds quinn connection statistics into QuicMetrics
+ */
+
+use crate::client::QuicMetrics;
+use log::debug;
+use parking_lot::Mutex;
+use quinn::Connection;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+
+/// Interval between samples
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+/// Number of samples in the rolling window (5s at the default interval)
+const WINDOW_SAMPLES: usize = 10;
+
+// Cumulative counters captured at one sample point
+#[derive(Clone, Copy)]
+struct Sample {
+    at: Instant,
+    bytes: u64,
+    sent_packets: u64,
+    lost_packets: u64,
+}
+
+pub struct MetricsSampler {
+    window: VecDeque<Sample>,
+    min_rtt: Option<Duration>,
+}
+
+impl MetricsSampler {
+    /// Spawn the sampler on `runtime`; it exits when the connection closes
+    pub fn spawn(conn: Connection, metrics: Arc<Mutex<QuicMetrics>>, runtime: &Handle) -> JoinHandle<()> {
+        runtime.spawn(async move {
+            let mut sampler = Self {
+                window: VecDeque::with_capacity(WINDOW_SAMPLES + 1),
+                min_rtt: None,
+            };
+            let mut ticker = tokio::time::interval(SAMPLE_INTERVAL);
+
+            while conn.close_reason().is_none() {
+                ticker.tick().await;
+                sampler.sample(&conn, &metrics);
+            }
+
+            debug!("Metrics sampler stopped (connection closed)");
+        })
+    }
+
+    fn sample(&mut self, conn: &Connection, metrics: &Mutex<QuicMetrics>) {
+        let stats = conn.stats();
+        let rtt = conn.rtt();
+        let now = Instant::now();
+
+        let min_rtt = match self.min_rtt {
+            Some(min) if min <= rtt => min,
+            _ => rtt,
+        };
+        self.min_rtt = Some(min_rtt);
+
+        let current = Sample {
+            at: now,
+            bytes: stats.udp_tx.bytes + stats.udp_rx.bytes,
+            sent_packets: stats.path.sent_packets,
+            lost_packets: stats.path.lost_packets,
+        };
+        self.window.push_back(current);
+        if self.window.len() > WINDOW_SAMPLES {
+            self.window.pop_front();
+        }
+
+        // Rolling rates over the window (oldest -> newest)
+        let oldest = self.window.front().copied().unwrap_or(current);
+        let elapsed = current.at.duration_since(oldest.at).as_secs_f64();
+        let throughput_mbps = if elapsed > 0.0 {
+            (current.bytes - oldest.bytes) as f64 * 8.0 / elapsed / 1_000_000.0
+        } else {
+            0.0
+        };
+        let sent = current.sent_packets - oldest.sent_packets;
+        let lost = current.lost_packets - oldest.lost_packets;
+        let packet_loss_rate = if sent > 0 { lost as f64 / sent as f64 } else { 0.0 };
+
+        let mut m = metrics.lock();
+        m.bytes_sent = stats.udp_tx.bytes;
+        m.bytes_received = stats.udp_rx.bytes;
+        m.packets_sent = stats.udp_tx.datagrams;
+        m.packets_received = stats.udp_rx.datagrams;
+        m.packets_lost = stats.path.lost_packets;
+        m.datagrams_sent = stats.frame_tx.datagram;
+        m.datagrams_received = stats.frame_rx.datagram;
+        m.rtt_us = rtt.as_micros() as u64;
+        m.min_rtt_us = min_rtt.as_micros() as u64;
+        m.cwnd = stats.path.cwnd;
+        m.throughput_mbps = throughput_mbps;
+        m.packet_loss_rate = packet_loss_rate;
+    }
+}
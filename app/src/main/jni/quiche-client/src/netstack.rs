@@ -0,0 +1,991 @@
+/*
+ * Userspace TCP/IP Stack (Rust Implementation)
+ * Terminates TUN flows locally and proxies them over QUIC (tun2quic)
+ */
+
+use crate::client::{BoxError, FlowLink, QuicheClient};
+use crate::flow_header::{self, TargetAddr};
+use crate::flow_router::{FlowDatagram, FlowRouter};
+use crate::tun_forwarder::ForwarderStats;
+use bytes::Bytes;
+use log::{debug, error, info};
+use parking_lot::Mutex;
+use smoltcp::iface::{Config, Interface, SocketHandle, SocketSet};
+use smoltcp::phy::{self, ChecksumCapabilities, Device, DeviceCapabilities, Medium};
+use smoltcp::socket::tcp;
+use smoltcp::wire::{
+    HardwareAddress, IpAddress, IpCidr, IpProtocol, IpRepr, Ipv4Address, Ipv4Packet,
+    Ipv6Address, Ipv6Packet, TcpPacket, UdpPacket, UdpRepr,
+};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{BuildHasher, Hasher};
+use std::net::{IpAddr, SocketAddr};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
+use tokio::sync::mpsc::error::{TryRecvError, TrySendError};
+use tokio::sync::{mpsc, Notify};
+use tokio::task::JoinHandle;
+
+// Per-direction TCP socket buffer for each flow
+const TCP_BUFFER_SIZE: usize = 128 * 1024;
+// Largest chunk moved between a socket and its QUIC stream at once
+const MAX_CHUNK_SIZE: usize = 64 * 1024;
+// Chunks queued per direction between the stack and a flow task
+const FLOW_CHANNEL_DEPTH: usize = 16;
+const MAX_TCP_FLOWS: usize = 2048;
+const MAX_UDP_FLOWS: usize = 4096;
+// A listener whose SYN never completed the handshake
+const LISTEN_TIMEOUT: Duration = Duration::from_secs(10);
+// UDP flows are forgotten after this long without traffic
+const UDP_FLOW_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+const UDP_EXPIRE_INTERVAL: Duration = Duration::from_secs(5);
+// Longest sleep between stack polls
+const MAX_POLL_INTERVAL: Duration = Duration::from_millis(500);
+// TUN packets read per wakeup
+const MAX_READS_PER_WAKEUP: usize = 256;
+// UDP replies queued between the datagram router and the stack
+const UDP_DOWNLINK_DEPTH: usize = 1024;
+const DEFAULT_HOP_LIMIT: u8 = 64;
+
+// Connection 4-tuple as seen from the TUN: (local app, remote destination)
+type FlowKey = (SocketAddr, SocketAddr);
+
+/// Run the flow stack on `tun_fd` until the task is aborted or the TUN closes
+///
+/// TCP connections are accepted by a smoltcp interface that answers for any
+/// address, and each one is relayed over its own bidirectional stream opened
+/// with a `flow_header` preamble. UDP is handled without the stack: each
+/// (local, remote) pair becomes a `FlowRouter` datagram flow. Other protocols
+/// are dropped.
+pub async fn run_flow_stack(
+    tun_fd: RawFd,
+    mtu: usize,
+    client: Arc<QuicheClient>,
+    router: Arc<FlowRouter>,
+    stats: Arc<Mutex<ForwarderStats>>,
+) {
+    let tun = match AsyncFd::with_interest(TunFd(tun_fd), Interest::READABLE) {
+        Ok(tun) => tun,
+        Err(e) => {
+            error!("Failed to register TUN with the runtime: {}", e);
+            return;
+        }
+    };
+
+    let (udp_tx, mut udp_rx) = mpsc::channel(UDP_DOWNLINK_DEPTH);
+    let mut stack = FlowStack::new(tun_fd, mtu, client, router, udp_tx, stats);
+    let notify = stack.notify.clone();
+    let mut buffer = vec![0u8; mtu];
+
+    info!("Flow stack started (mtu={})", mtu);
+
+    loop {
+        let delay = stack.poll_delay();
+        tokio::select! {
+            ready = tun.readable() => match ready {
+                Ok(mut guard) => match stack.read_tun(&mut buffer) {
+                    TunRead::Drained => guard.clear_ready(),
+                    TunRead::More => {}
+                    TunRead::Closed => {
+                        info!("TUN closed, flow stack stopping");
+                        break;
+                    }
+                },
+                Err(e) => {
+                    error!("Wait on TUN failed: {}", e);
+                    break;
+                }
+            },
+            Some(datagram) = udp_rx.recv() => {
+                stack.deliver_udp(datagram);
+                while let Ok(datagram) = udp_rx.try_recv() {
+                    stack.deliver_udp(datagram);
+                }
+            }
+            _ = notify.notified() => {}
+            _ = tokio::time::sleep(delay) => {}
+        }
+        stack.process();
+    }
+}
+
+// Non-owning TUN fd for AsyncFd registration
+struct TunFd(RawFd);
+
+impl AsRawFd for TunFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
+
+enum TunRead {
+    /// Read until EAGAIN
+    Drained,
+    /// Stopped at the per-wakeup limit
+    More,
+    Closed,
+}
+
+// smoltcp device over packet queues; the run loop moves them to and from the TUN
+struct QueueDevice {
+    rx: VecDeque<Vec<u8>>,
+    tx: VecDeque<Vec<u8>>,
+    mtu: usize,
+}
+
+struct QueueRxToken(Vec<u8>);
+
+struct QueueTxToken<'a>(&'a mut VecDeque<Vec<u8>>);
+
+impl phy::RxToken for QueueRxToken {
+    fn consume<R, F>(self, f: F) -> R
+    where
+        F: FnOnce(&[u8]) -> R,
+    {
+        f(&self.0)
+    }
+}
+
+impl phy::TxToken for QueueTxToken<'_> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let mut packet = vec![0u8; len];
+        let result = f(&mut packet);
+        self.0.push_back(packet);
+        result
+    }
+}
+
+impl Device for QueueDevice {
+    type RxToken<'a> = QueueRxToken;
+    type TxToken<'a> = QueueTxToken<'a>;
+
+    fn receive(&mut self, _timestamp: smoltcp::time::Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let packet = self.rx.pop_front()?;
+        Some((QueueRxToken(packet), QueueTxToken(&mut self.tx)))
+    }
+
+    fn transmit(&mut self, _timestamp: smoltcp::time::Instant) -> Option<Self::TxToken<'_>> {
+        Some(QueueTxToken(&mut self.tx))
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.medium = Medium::Ip;
+        caps.max_transmission_unit = self.mtu;
+        caps
+    }
+}
+
+// What the stack does with a packet read from the TUN
+enum Inbound<'a> {
+    Udp { src: SocketAddr, dst: SocketAddr, payload: &'a [u8] },
+    TcpSyn { src: SocketAddr, dst: SocketAddr },
+    Tcp,
+    Other,
+}
+
+fn classify(packet: &[u8]) -> Inbound<'_> {
+    let (src_ip, dst_ip, protocol, l4) = match packet.first().map(|b| b >> 4) {
+        Some(4) => {
+            let Ok(ip) = Ipv4Packet::new_checked(packet) else {
+                return Inbound::Other;
+            };
+            // Fragments would need reassembly; leave them to the stack's drop path
+            if ip.more_frags() || ip.frag_offset() != 0 {
+                return Inbound::Other;
+            }
+            let range = ip.header_len() as usize..ip.total_len() as usize;
+            (IpAddr::V4(ip.src_addr()), IpAddr::V4(ip.dst_addr()), ip.next_header(), &packet[range])
+        }
+        Some(6) => {
+            let Ok(ip) = Ipv6Packet::new_checked(packet) else {
+                return Inbound::Other;
+            };
+            let start = ip.header_len();
+            let range = start..start + ip.payload_len() as usize;
+            (IpAddr::V6(ip.src_addr()), IpAddr::V6(ip.dst_addr()), ip.next_header(), &packet[range])
+        }
+        _ => return Inbound::Other,
+    };
+
+    match protocol {
+        IpProtocol::Udp => match UdpPacket::new_checked(l4) {
+            Ok(udp) => Inbound::Udp {
+                src: SocketAddr::new(src_ip, udp.src_port()),
+                dst: SocketAddr::new(dst_ip, udp.dst_port()),
+                payload: &l4[8..udp.len() as usize],
+            },
+            Err(_) => Inbound::Other,
+        },
+        IpProtocol::Tcp => match TcpPacket::new_checked(l4) {
+            Ok(tcp) if tcp.syn() && !tcp.ack() => Inbound::TcpSyn {
+                src: SocketAddr::new(src_ip, tcp.src_port()),
+                dst: SocketAddr::new(dst_ip, tcp.dst_port()),
+            },
+            Ok(_) => Inbound::Tcp,
+            Err(_) => Inbound::Other,
+        },
+        _ => Inbound::Other,
+    }
+}
+
+// IP/UDP packet from `src` to `dst`; None if the address families differ
+fn build_udp_packet(src: SocketAddr, dst: SocketAddr, payload: &[u8]) -> Option<Vec<u8>> {
+    if src.is_ipv4() != dst.is_ipv4() {
+        return None;
+    }
+
+    let src_ip = IpAddress::from(src.ip());
+    let dst_ip = IpAddress::from(dst.ip());
+    let udp = UdpRepr { src_port: src.port(), dst_port: dst.port() };
+    let ip = IpRepr::new(src_ip, dst_ip, IpProtocol::Udp, udp.header_len() + payload.len(), DEFAULT_HOP_LIMIT);
+    let caps = ChecksumCapabilities::default();
+
+    let mut packet = vec![0u8; ip.buffer_len()];
+    ip.emit(&mut packet[..], &caps);
+    udp.emit(
+        &mut UdpPacket::new_unchecked(&mut packet[ip.header_len()..]),
+        &src_ip,
+        &dst_ip,
+        payload.len(),
+        |buf| buf.copy_from_slice(payload),
+        &caps,
+    );
+    Some(packet)
+}
+
+fn endpoint_addr(endpoint: smoltcp::wire::IpEndpoint) -> SocketAddr {
+    SocketAddr::new(endpoint.addr.into(), endpoint.port)
+}
+
+fn write_packet(tun_fd: RawFd, packet: &[u8]) -> bool {
+    use std::os::fd::BorrowedFd;
+    let fd = unsafe { BorrowedFd::borrow_raw(tun_fd) };
+
+    match nix::unistd::write(fd, packet) {
+        Ok(written) => written == packet.len(),
+        Err(e) => {
+            debug!("Write to TUN failed: {}", e);
+            false
+        }
+    }
+}
+
+// UDP flows and their router registrations
+struct UdpFlows {
+    by_key: HashMap<FlowKey, UdpFlow>,
+    by_id: HashMap<u32, FlowKey>,
+    router: Arc<FlowRouter>,
+    sender: mpsc::Sender<FlowDatagram>,
+    total: u64,
+}
+
+struct UdpFlow {
+    id: u32,
+    // Connection the flow's datagrams go out on
+    link: FlowLink,
+    last_active: Instant,
+}
+
+impl UdpFlows {
+    // Flow for a (local, remote) pair, creating one if there is room
+    fn flow(&mut self, key: FlowKey, client: &QuicheClient) -> Option<&UdpFlow> {
+        if self.by_key.contains_key(&key) {
+            let flow = self.by_key.get_mut(&key)?;
+            flow.last_active = Instant::now();
+            return Some(flow);
+        }
+        if self.by_key.len() >= MAX_UDP_FLOWS {
+            return None;
+        }
+
+        let id = self.router.register(self.sender.clone());
+        self.by_id.insert(id, key);
+        self.total += 1;
+        let flow = UdpFlow { id, link: client.flow_link(), last_active: Instant::now() };
+        Some(self.by_key.entry(key).or_insert(flow))
+    }
+
+    // Local address a downlink datagram belongs to
+    fn local_addr(&mut self, id: u32) -> Option<SocketAddr> {
+        let key = *self.by_id.get(&id)?;
+        let flow = self.by_key.get_mut(&key)?;
+        flow.last_active = Instant::now();
+        Some(key.0)
+    }
+
+    fn expire(&mut self) {
+        let by_id = &mut self.by_id;
+        let router = &self.router;
+        self.by_key.retain(|_, flow| {
+            let keep = flow.last_active.elapsed() < UDP_FLOW_IDLE_TIMEOUT;
+            if !keep {
+                by_id.remove(&flow.id);
+                router.unregister(flow.id);
+            }
+            keep
+        });
+    }
+}
+
+impl Drop for UdpFlows {
+    fn drop(&mut self) {
+        for id in self.by_id.keys() {
+            self.router.unregister(*id);
+        }
+    }
+}
+
+enum TcpSlot {
+    /// Listener created for a SYN, not yet past the handshake
+    Listening { since: Instant, key: FlowKey },
+    Active(TcpFlow),
+}
+
+// Stack side of a TCP flow; the relay task owns the QUIC stream
+struct TcpFlow {
+    key: FlowKey,
+    /// Socket -> stream; dropped once the local side has sent FIN
+    up_tx: Option<mpsc::Sender<Bytes>>,
+    /// Stream -> socket; an empty chunk marks a clean end of stream
+    down_rx: mpsc::Receiver<Bytes>,
+    /// Downlink bytes the socket had no room for yet
+    pending: Option<Bytes>,
+    down_done: bool,
+    task: JoinHandle<()>,
+}
+
+impl Drop for TcpFlow {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+// Counters accumulated during one iteration, then folded into ForwarderStats
+#[derive(Default)]
+struct StackCounters {
+    packets_in: u64,
+    bytes_in: u64,
+    packets_dropped: u64,
+    datagrams_sent: u64,
+    bytes_sent: u64,
+    packets_out: u64,
+    bytes_out: u64,
+    write_errors: u64,
+    tcp_flows_opened: u64,
+    flow_errors: u64,
+}
+
+enum PumpResult {
+    Open,
+    Closed,
+    Failed,
+}
+
+impl TcpFlow {
+    // Move data between the socket and the relay task in both directions
+    fn pump(&mut self, socket: &mut tcp::Socket, counters: &mut StackCounters) -> PumpResult {
+        // Local app -> QUIC
+        if let Some(up_tx) = &self.up_tx {
+            while socket.can_recv() {
+                let permit = match up_tx.try_reserve() {
+                    Ok(permit) => permit,
+                    Err(TrySendError::Full(_)) | Err(TrySendError::Closed(_)) => break,
+                };
+                let chunk = socket.recv(|data| {
+                    let len = data.len().min(MAX_CHUNK_SIZE);
+                    (len, Bytes::copy_from_slice(&data[..len]))
+                });
+                match chunk {
+                    Ok(chunk) => {
+                        counters.bytes_sent += chunk.len() as u64;
+                        permit.send(chunk);
+                    }
+                    Err(_) => break,
+                }
+            }
+
+            // Local FIN received and everything before it handed over
+            let remote_closed = matches!(
+                socket.state(),
+                tcp::State::CloseWait | tcp::State::LastAck | tcp::State::Closing | tcp::State::TimeWait
+            );
+            if remote_closed && !socket.can_recv() {
+                self.up_tx = None;
+            }
+        }
+
+        // QUIC -> local app
+        while !self.down_done {
+            if self.pending.is_none() {
+                match self.down_rx.try_recv() {
+                    Ok(chunk) if chunk.is_empty() => {
+                        self.down_done = true;
+                        socket.close();
+                        break;
+                    }
+                    Ok(chunk) => self.pending = Some(chunk),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        // Relay failed before the stream finished
+                        self.down_done = true;
+                        socket.abort();
+                        return PumpResult::Failed;
+                    }
+                }
+            }
+            if !socket.can_send() {
+                break;
+            }
+
+            let Some(chunk) = self.pending.take() else {
+                break;
+            };
+            match socket.send_slice(&chunk) {
+                Ok(sent) if sent < chunk.len() => {
+                    self.pending = Some(chunk.slice(sent..));
+                    break;
+                }
+                Ok(_) => {}
+                Err(_) => {
+                    self.pending = Some(chunk);
+                    break;
+                }
+            }
+        }
+
+        match socket.state() {
+            tcp::State::Closed | tcp::State::TimeWait => PumpResult::Closed,
+            _ => PumpResult::Open,
+        }
+    }
+}
+
+struct FlowStack {
+    iface: Interface,
+    device: QueueDevice,
+    sockets: SocketSet<'static>,
+    tcp: HashMap<SocketHandle, TcpSlot>,
+    // Flows with a socket already, so retransmitted SYNs don't add listeners
+    tcp_keys: HashSet<FlowKey>,
+    udp: UdpFlows,
+    client: Arc<QuicheClient>,
+    notify: Arc<Notify>,
+    stats: Arc<Mutex<ForwarderStats>>,
+    counters: StackCounters,
+    tun_fd: RawFd,
+    epoch: Instant,
+    last_udp_expire: Instant,
+}
+
+impl FlowStack {
+    fn new(
+        tun_fd: RawFd,
+        mtu: usize,
+        client: Arc<QuicheClient>,
+        router: Arc<FlowRouter>,
+        udp_tx: mpsc::Sender<FlowDatagram>,
+        stats: Arc<Mutex<ForwarderStats>>,
+    ) -> Self {
+        let mut device = QueueDevice {
+            rx: VecDeque::new(),
+            tx: VecDeque::new(),
+            mtu,
+        };
+
+        let mut config = Config::new(HardwareAddress::Ip);
+        config.random_seed = RandomState::new().build_hasher().finish();
+        let mut iface = Interface::new(config, &mut device, smoltcp::time::Instant::ZERO);
+
+        // Answer for every destination: any_ip accepts packets whose route
+        // points at one of the interface's own addresses
+        let gateway_v4 = Ipv4Address::new(0, 0, 0, 1);
+        let gateway_v6 = Ipv6Address::new(0, 0, 0, 0, 0, 0, 0, 1);
+        iface.update_ip_addrs(|addrs| {
+            let _ = addrs.push(IpCidr::new(IpAddress::Ipv4(gateway_v4), 0));
+            let _ = addrs.push(IpCidr::new(IpAddress::Ipv6(gateway_v6), 0));
+        });
+        let _ = iface.routes_mut().add_default_ipv4_route(gateway_v4);
+        let _ = iface.routes_mut().add_default_ipv6_route(gateway_v6);
+        iface.set_any_ip(true);
+
+        Self {
+            iface,
+            device,
+            sockets: SocketSet::new(Vec::new()),
+            tcp: HashMap::new(),
+            tcp_keys: HashSet::new(),
+            udp: UdpFlows {
+                by_key: HashMap::new(),
+                by_id: HashMap::new(),
+                router,
+                sender: udp_tx,
+                total: 0,
+            },
+            client,
+            notify: Arc::new(Notify::new()),
+            stats,
+            counters: StackCounters::default(),
+            tun_fd,
+            epoch: Instant::now(),
+            last_udp_expire: Instant::now(),
+        }
+    }
+
+    fn now(&self) -> smoltcp::time::Instant {
+        smoltcp::time::Instant::from_micros(self.epoch.elapsed().as_micros() as i64)
+    }
+
+    fn poll_delay(&mut self) -> Duration {
+        let now = self.now();
+        self.iface
+            .poll_delay(now, &self.sockets)
+            .map(|d| Duration::from_micros(d.total_micros()))
+            .unwrap_or(MAX_POLL_INTERVAL)
+            .min(MAX_POLL_INTERVAL)
+    }
+
+    fn read_tun(&mut self, buffer: &mut [u8]) -> TunRead {
+        for _ in 0..MAX_READS_PER_WAKEUP {
+            match nix::unistd::read(self.tun_fd, buffer) {
+                Ok(0) => return TunRead::Closed,
+                Ok(len) => self.ingest(&buffer[..len]),
+                Err(nix::errno::Errno::EAGAIN) => return TunRead::Drained,
+                Err(nix::errno::Errno::EINTR) => {}
+                Err(e) => {
+                    error!("Read from TUN failed: {}", e);
+                    return TunRead::Closed;
+                }
+            }
+        }
+        TunRead::More
+    }
+
+    fn ingest(&mut self, packet: &[u8]) {
+        self.counters.packets_in += 1;
+        self.counters.bytes_in += packet.len() as u64;
+
+        match classify(packet) {
+            Inbound::Udp { src, dst, payload } => self.send_udp(src, dst, payload),
+            Inbound::TcpSyn { src, dst } => {
+                self.listen_for((src, dst));
+                self.device.rx.push_back(packet.to_vec());
+            }
+            Inbound::Tcp => self.device.rx.push_back(packet.to_vec()),
+            Inbound::Other => self.counters.packets_dropped += 1,
+        }
+    }
+
+    fn send_udp(&mut self, src: SocketAddr, dst: SocketAddr, payload: &[u8]) {
+        let Some(flow) = self.udp.flow((src, dst), &self.client) else {
+            self.counters.packets_dropped += 1;
+            return;
+        };
+
+        let datagram = flow_header::encode_datagram(flow.id, &TargetAddr::Ip(dst), payload);
+        match flow.link.send_datagram(&datagram) {
+            Ok(_) => {
+                self.counters.datagrams_sent += 1;
+                self.counters.bytes_sent += payload.len() as u64;
+            }
+            Err(e) => {
+                debug!("Dropping UDP packet to {}: {}", dst, e);
+                self.counters.packets_dropped += 1;
+            }
+        }
+    }
+
+    // Add a listener for a new connection's SYN; without one smoltcp answers RST
+    fn listen_for(&mut self, key: FlowKey) {
+        if self.tcp_keys.contains(&key) {
+            return;
+        }
+        if self.tcp.len() >= MAX_TCP_FLOWS {
+            debug!("Too many TCP flows, refusing {} -> {}", key.0, key.1);
+            self.counters.flow_errors += 1;
+            return;
+        }
+
+        let mut socket = tcp::Socket::new(
+            tcp::SocketBuffer::new(vec![0u8; TCP_BUFFER_SIZE]),
+            tcp::SocketBuffer::new(vec![0u8; TCP_BUFFER_SIZE]),
+        );
+        socket.set_nagle_enabled(false);
+        if let Err(e) = socket.listen(key.1) {
+            debug!("Failed to listen on {}: {}", key.1, e);
+            return;
+        }
+
+        let handle = self.sockets.add(socket);
+        self.tcp.insert(handle, TcpSlot::Listening { since: Instant::now(), key });
+        self.tcp_keys.insert(key);
+    }
+
+    // Poll the interface, service flows, and write the result to the TUN
+    fn process(&mut self) {
+        let now = self.now();
+        self.iface.poll(now, &mut self.device, &mut self.sockets);
+        self.service_flows();
+        // Push out what the flows just queued on their sockets
+        self.iface.poll(now, &mut self.device, &mut self.sockets);
+
+        while let Some(packet) = self.device.tx.pop_front() {
+            if write_packet(self.tun_fd, &packet) {
+                self.counters.packets_out += 1;
+                self.counters.bytes_out += packet.len() as u64;
+            } else {
+                self.counters.write_errors += 1;
+            }
+        }
+
+        if self.last_udp_expire.elapsed() >= UDP_EXPIRE_INTERVAL {
+            self.udp.expire();
+            self.last_udp_expire = Instant::now();
+        }
+
+        self.flush_stats();
+    }
+
+    fn service_flows(&mut self) {
+        let mut finished = Vec::new();
+
+        for (&handle, slot) in self.tcp.iter_mut() {
+            let socket = self.sockets.get_mut::<tcp::Socket>(handle);
+            match slot {
+                TcpSlot::Listening { since, key } => match socket.state() {
+                    tcp::State::Listen => {
+                        if since.elapsed() >= LISTEN_TIMEOUT {
+                            finished.push(handle);
+                        }
+                    }
+                    tcp::State::Closed => finished.push(handle),
+                    _ => {
+                        let (Some(local), Some(remote)) = (socket.local_endpoint(), socket.remote_endpoint()) else {
+                            continue;
+                        };
+                        // Listeners are per destination; the SYN that landed here may
+                        // not be the one this listener was created for
+                        self.tcp_keys.remove(key);
+                        let key = (endpoint_addr(remote), endpoint_addr(local));
+                        self.tcp_keys.insert(key);
+                        *slot = TcpSlot::Active(Self::spawn_tcp_flow(key, &self.client, &self.notify));
+                        self.counters.tcp_flows_opened += 1;
+                    }
+                },
+                TcpSlot::Active(flow) => match flow.pump(socket, &mut self.counters) {
+                    PumpResult::Open => {}
+                    PumpResult::Closed => finished.push(handle),
+                    PumpResult::Failed => {
+                        self.counters.flow_errors += 1;
+                        finished.push(handle);
+                    }
+                },
+            }
+        }
+
+        for handle in finished {
+            match self.tcp.remove(&handle) {
+                Some(TcpSlot::Listening { key, .. }) => {
+                    self.tcp_keys.remove(&key);
+                }
+                Some(TcpSlot::Active(flow)) => {
+                    self.tcp_keys.remove(&flow.key);
+                }
+                None => {}
+            }
+            self.sockets.remove(handle);
+        }
+    }
+
+    // Write a UDP reply to the TUN as coming from the peer
+    fn deliver_udp(&mut self, datagram: FlowDatagram) {
+        let packet = match (datagram.peer, self.udp.local_addr(datagram.flow_id)) {
+            (TargetAddr::Ip(peer), Some(local)) => build_udp_packet(peer, local, &datagram.payload),
+            _ => None,
+        };
+
+        match packet {
+            Some(packet) if write_packet(self.tun_fd, &packet) => {
+                self.counters.packets_out += 1;
+                self.counters.bytes_out += packet.len() as u64;
+            }
+            Some(_) => self.counters.write_errors += 1,
+            None => self.counters.packets_dropped += 1,
+        }
+    }
+
+    fn spawn_tcp_flow(key: FlowKey, client: &Arc<QuicheClient>, notify: &Arc<Notify>) -> TcpFlow {
+        let (up_tx, up_rx) = mpsc::channel(FLOW_CHANNEL_DEPTH);
+        let (down_tx, down_rx) = mpsc::channel(FLOW_CHANNEL_DEPTH);
+        debug!("TCP flow {} -> {}", key.0, key.1);
+
+        let task = tokio::spawn(relay_tcp_flow(
+            client.clone(),
+            TargetAddr::Ip(key.1),
+            up_rx,
+            down_tx,
+            notify.clone(),
+        ));
+
+        TcpFlow {
+            key,
+            up_tx: Some(up_tx),
+            down_rx,
+            pending: None,
+            down_done: false,
+            task,
+        }
+    }
+
+    fn flush_stats(&mut self) {
+        let counters = std::mem::take(&mut self.counters);
+        let tcp_active = self.tcp.values().filter(|s| matches!(s, TcpSlot::Active(_))).count();
+        let (udp_active, udp_total) = (self.udp.by_key.len(), self.udp.total);
+
+        let mut stats = self.stats.lock();
+        stats.packets_received += counters.packets_in;
+        stats.bytes_received += counters.bytes_in;
+        stats.packets_dropped += counters.packets_dropped;
+        stats.packets_sent += counters.datagrams_sent;
+        stats.bytes_sent += counters.bytes_sent;
+        stats.packets_to_tun += counters.packets_out;
+        stats.bytes_to_tun += counters.bytes_out;
+        stats.tun_write_errors += counters.write_errors;
+        stats.tcp_flows_total += counters.tcp_flows_opened;
+        stats.flow_errors += counters.flow_errors;
+        stats.tcp_flows_active = tcp_active as u64;
+        stats.udp_flows_active = udp_active as u64;
+        stats.udp_flows_total = udp_total;
+    }
+}
+
+// Relay one TCP flow over a bidirectional stream
+async fn relay_tcp_flow(
+    client: Arc<QuicheClient>,
+    target: TargetAddr,
+    mut up_rx: mpsc::Receiver<Bytes>,
+    down_tx: mpsc::Sender<Bytes>,
+    notify: Arc<Notify>,
+) {
+    let result: Result<(), BoxError> = async {
+        let (mut send, mut recv, _link) = client.open_flow_stream(&target).await?;
+
+        let uplink = async {
+            while let Some(chunk) = up_rx.recv().await {
+                // The socket may have more buffered now that there is room
+                notify.notify_one();
+                send.write_chunk(chunk).await?;
+            }
+            send.finish()?;
+            Ok::<_, BoxError>(())
+        };
+        let downlink = async {
+            while let Some(chunk) = recv.read_chunk(MAX_CHUNK_SIZE, true).await? {
+                if chunk.bytes.is_empty() {
+                    continue;
+                }
+                down_tx.send(chunk.bytes).await.map_err(|_| "flow closed")?;
+                notify.notify_one();
+            }
+            down_tx.send(Bytes::new()).await.map_err(|_| "flow closed")?;
+            notify.notify_one();
+            Ok::<_, BoxError>(())
+        };
+
+        tokio::try_join!(uplink, downlink)?;
+        Ok(())
+    }
+    .await;
+
+    // Dropping down_tx without the end-of-stream marker resets the socket
+    if let Err(e) = result {
+        debug!("TCP flow to {} failed: {}", target, e);
+    }
+    drop(down_tx);
+    notify.notify_one();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::runtime;
+    use crate::test_server::{client_for, echo_server};
+    use smoltcp::wire::IpEndpoint;
+    use std::os::unix::net::UnixDatagram;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    // The app's side of the TUN, as a smoltcp device
+    struct AppDevice(UnixDatagram);
+
+    struct AppTxToken<'a>(&'a UnixDatagram);
+
+    impl phy::TxToken for AppTxToken<'_> {
+        fn consume<R, F>(self, len: usize, f: F) -> R
+        where
+            F: FnOnce(&mut [u8]) -> R,
+        {
+            let mut packet = vec![0u8; len];
+            let result = f(&mut packet);
+            self.0.send(&packet).unwrap();
+            result
+        }
+    }
+
+    impl Device for AppDevice {
+        type RxToken<'a> = QueueRxToken;
+        type TxToken<'a> = AppTxToken<'a>;
+
+        fn receive(&mut self, _timestamp: smoltcp::time::Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+            let mut packet = vec![0u8; 2048];
+            let len = self.0.recv(&mut packet).ok()?;
+            packet.truncate(len);
+            Some((QueueRxToken(packet), AppTxToken(&self.0)))
+        }
+
+        fn transmit(&mut self, _timestamp: smoltcp::time::Instant) -> Option<Self::TxToken<'_>> {
+            Some(AppTxToken(&self.0))
+        }
+
+        fn capabilities(&self) -> DeviceCapabilities {
+            let mut caps = DeviceCapabilities::default();
+            caps.medium = Medium::Ip;
+            caps.max_transmission_unit = 1500;
+            caps
+        }
+    }
+
+    struct Harness {
+        app: UnixDatagram,
+        client: Arc<QuicheClient>,
+        stats: Arc<Mutex<ForwarderStats>>,
+        task: JoinHandle<()>,
+        _tun: UnixDatagram,
+    }
+
+    impl Drop for Harness {
+        fn drop(&mut self) {
+            self.task.abort();
+            self.client.disconnect();
+        }
+    }
+
+    fn harness() -> Harness {
+        let client = client_for(echo_server(), 1);
+        client.connect().unwrap();
+        let (tun, app) = UnixDatagram::pair().unwrap();
+        tun.set_nonblocking(true).unwrap();
+        app.set_nonblocking(true).unwrap();
+
+        let stats = Arc::new(Mutex::new(ForwarderStats::default()));
+        let task = runtime().spawn(run_flow_stack(
+            tun.as_raw_fd(),
+            1500,
+            client.clone(),
+            client.flow_router().unwrap(),
+            stats.clone(),
+        ));
+        Harness { app, client, stats, task, _tun: tun }
+    }
+
+    fn wait_for(mut poll: impl FnMut() -> bool) {
+        let deadline = Instant::now() + TIMEOUT;
+        while !poll() {
+            assert!(Instant::now() < deadline, "timed out");
+            std::thread::sleep(Duration::from_millis(2));
+        }
+    }
+
+    #[test]
+    fn tcp_flow_is_relayed_over_a_stream() {
+        let harness = harness();
+        let mut device = AppDevice(harness.app.try_clone().unwrap());
+        let mut iface = Interface::new(Config::new(HardwareAddress::Ip), &mut device, smoltcp::time::Instant::now());
+        iface.update_ip_addrs(|addrs| {
+            let _ = addrs.push(IpCidr::new(IpAddress::v4(10, 0, 0, 2), 24));
+        });
+        iface.routes_mut().add_default_ipv4_route(Ipv4Address::new(10, 0, 0, 1)).unwrap();
+
+        let mut sockets = SocketSet::new(Vec::new());
+        let socket = tcp::Socket::new(
+            tcp::SocketBuffer::new(vec![0u8; 4096]),
+            tcp::SocketBuffer::new(vec![0u8; 4096]),
+        );
+        let handle = sockets.add(socket);
+        let remote = IpEndpoint::new(IpAddress::v4(192, 0, 2, 1), 80);
+        sockets.get_mut::<tcp::Socket>(handle).connect(iface.context(), remote, 49152).unwrap();
+
+        let mut poll = |until: &mut dyn FnMut(&mut tcp::Socket) -> bool| {
+            wait_for(|| {
+                iface.poll(smoltcp::time::Instant::now(), &mut device, &mut sockets);
+                until(sockets.get_mut::<tcp::Socket>(handle))
+            })
+        };
+
+        poll(&mut |socket| socket.may_send());
+        poll(&mut |socket| socket.send_slice(b"hello over quic").is_ok());
+        let mut echoed = Vec::new();
+        poll(&mut |socket| {
+            let _ = socket.recv(|data| {
+                echoed.extend_from_slice(data);
+                (data.len(), ())
+            });
+            echoed.len() >= 15
+        });
+        assert_eq!(echoed, b"hello over quic");
+
+        // Our FIN finishes the stream; the echo's end comes back as a FIN
+        poll(&mut |socket| {
+            socket.close();
+            !socket.may_recv()
+        });
+        wait_for(|| harness.stats.lock().tcp_flows_total == 1);
+        assert_eq!(harness.stats.lock().flow_errors, 0);
+    }
+
+    #[test]
+    fn udp_flow_is_relayed_over_datagrams() {
+        let harness = harness();
+        let app_addr: SocketAddr = "10.0.0.2:5353".parse().unwrap();
+        let dst: SocketAddr = "192.0.2.1:53".parse().unwrap();
+        harness.app.send(&build_udp_packet(app_addr, dst, b"query").unwrap()).unwrap();
+
+        // The echo server returns the payload as coming from the destination
+        let mut packet = vec![0u8; 2048];
+        wait_for(|| match harness.app.recv(&mut packet) {
+            Ok(len) => {
+                packet.truncate(len);
+                true
+            }
+            Err(_) => false,
+        });
+        match classify(&packet) {
+            Inbound::Udp { src, dst: to, payload } => {
+                assert_eq!((src, to, payload), (dst, app_addr, &b"query"[..]));
+            }
+            _ => panic!("expected a UDP reply"),
+        }
+
+        let stats = harness.stats.lock();
+        assert_eq!((stats.udp_flows_total, stats.udp_flows_active), (1, 1));
+        assert_eq!(harness.client.flow_router().unwrap().flow_count(), 1);
+    }
+
+    #[test]
+    fn udp_packets_build_and_classify() {
+        let src: SocketAddr = "[2001:db8::2]:1000".parse().unwrap();
+        let dst: SocketAddr = "[2001:db8::1]:2000".parse().unwrap();
+        let packet = build_udp_packet(src, dst, b"v6").unwrap();
+        assert!(matches!(classify(&packet), Inbound::Udp { payload: b"v6", .. }));
+
+        assert!(build_udp_packet("192.0.2.1:1".parse().unwrap(), dst, b"").is_none());
+        assert!(matches!(classify(&[0x45, 0, 0]), Inbound::Other));
+    }
+}
@@ -0,0 +1,84 @@
+/*
+ * Packet Buffer Pool (Rust Implementation)
+ * Reusable TUN packet buffers that are sent without copying
+ */
+
+use bytes::{Bytes, BytesMut};
+use std::collections::VecDeque;
+
+/// Fixed-size packet buffers, recycled once the QUIC stack releases them
+///
+/// Packets are handed out as `Bytes`; the pool keeps a reference to each and
+/// takes the buffer back when that reference is the last one left.
+pub struct PacketPool {
+    free: Vec<BytesMut>,
+    in_flight: VecDeque<Bytes>,
+    slot_size: usize,
+    allocated: usize,
+    max_slots: usize,
+}
+
+impl PacketPool {
+    /// Pre-allocate `initial` slots of `slot_size` bytes, growing up to `max_slots`
+    pub fn new(initial: usize, max_slots: usize, slot_size: usize) -> Self {
+        let max_slots = max_slots.max(1);
+        let initial = initial.min(max_slots);
+
+        Self {
+            free: (0..initial).map(|_| BytesMut::zeroed(slot_size)).collect(),
+            in_flight: VecDeque::with_capacity(max_slots),
+            slot_size,
+            allocated: initial,
+            max_slots,
+        }
+    }
+
+    /// Take an empty slot (`slot_size` bytes long), or None if all are in flight
+    pub fn take(&mut self) -> Option<BytesMut> {
+        if self.free.is_empty() {
+            self.reclaim();
+        }
+        if let Some(slot) = self.free.pop() {
+            return Some(slot);
+        }
+        if self.allocated < self.max_slots {
+            self.allocated += 1;
+            return Some(BytesMut::zeroed(self.slot_size));
+        }
+        None
+    }
+
+    /// Return a slot that was not used
+    pub fn put(&mut self, slot: BytesMut) {
+        self.free.push(slot);
+    }
+
+    /// Turn the first `len` bytes of a filled slot into a packet
+    pub fn freeze(&mut self, mut slot: BytesMut, len: usize) -> Bytes {
+        slot.truncate(len);
+        let packet = slot.freeze();
+        self.in_flight.push_back(packet.clone());
+        packet
+    }
+
+    /// Slots currently allocated (free and in flight)
+    pub fn allocated(&self) -> usize {
+        self.allocated
+    }
+
+    // Move released packets back to the free list
+    fn reclaim(&mut self) {
+        for _ in 0..self.in_flight.len() {
+            let Some(packet) = self.in_flight.pop_front() else {
+                break;
+            };
+            match packet.try_into_mut() {
+                Ok(mut slot) => {
+                    slot.resize(self.slot_size, 0);
+                    self.free.push(slot);
+                }
+                Err(packet) => self.in_flight.push_back(packet),
+            }
+        }
+    }
+}
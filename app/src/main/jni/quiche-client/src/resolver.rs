@@ -0,0 +1,275 @@
+/*
+ * Server Address Resolver (Rust Implementation)
+ * DNS cache, app-provided addresses and RFC 8305 candidate ordering
+ */
+
+use crate::client::BoxError;
+use log::{debug, info, warn};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+// Addresses known for one host
+struct CacheEntry {
+    addrs: Vec<IpAddr>,
+    // None: never expires (injected without a TTL)
+    expires: Option<Instant>,
+    injected: bool,
+}
+
+impl CacheEntry {
+    fn is_fresh(&self) -> bool {
+        self.expires.is_none_or(|at| Instant::now() < at)
+    }
+}
+
+/// Process-wide server address cache
+///
+/// The system resolver reports no TTLs, so its answers are kept for the
+/// caller's TTL. Addresses injected by the app (e.g. resolved outside the VPN)
+/// take precedence over the system resolver until they expire or are cleared.
+pub struct DnsCache {
+    entries: Mutex<HashMap<String, CacheEntry>>,
+    // Address of the last successful connection per host, tried first
+    preferred: Mutex<HashMap<String, IpAddr>>,
+}
+
+/// The shared cache used by every QuicheClient
+pub fn dns_cache() -> &'static DnsCache {
+    static CACHE: OnceLock<DnsCache> = OnceLock::new();
+    CACHE.get_or_init(|| DnsCache {
+        entries: Mutex::new(HashMap::new()),
+        preferred: Mutex::new(HashMap::new()),
+    })
+}
+
+impl DnsCache {
+    /// Use `addrs` for `host` instead of the system resolver (`ttl` None = until cleared)
+    pub fn inject(&self, host: &str, addrs: Vec<IpAddr>, ttl: Option<Duration>) {
+        info!("Using {} app-provided address(es) for {}", addrs.len(), host);
+        self.entries.lock().insert(host.to_ascii_lowercase(), CacheEntry {
+            addrs,
+            expires: ttl.map(|ttl| Instant::now() + ttl),
+            injected: true,
+        });
+    }
+
+    /// Forget cached and injected addresses for `host` (every host if None)
+    pub fn clear(&self, host: Option<&str>) {
+        match host {
+            Some(host) => {
+                let host = host.to_ascii_lowercase();
+                self.entries.lock().remove(&host);
+                self.preferred.lock().remove(&host);
+            }
+            None => {
+                self.entries.lock().clear();
+                self.preferred.lock().clear();
+            }
+        }
+    }
+
+    /// Addresses for `host`, from the cache or the system resolver
+    ///
+    /// IP literals are returned as is. System answers are cached for `ttl`
+    /// (zero disables caching); a stale entry is still used if a fresh lookup
+    /// fails. The result is in connection-attempt order (see `sort_candidates`).
+    pub async fn resolve(&self, host: &str, port: u16, ttl: Duration) -> Result<Vec<SocketAddr>, BoxError> {
+        if let Ok(ip) = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+            return Ok(vec![SocketAddr::new(ip, port)]);
+        }
+
+        let key = host.to_ascii_lowercase();
+        let cached = self.entries.lock().get(&key).map(|entry| {
+            (entry.is_fresh(), entry.injected, entry.addrs.clone())
+        });
+
+        let addrs = match cached {
+            Some((true, injected, addrs)) => {
+                debug!("{} resolved from {} ({} addresses)", host,
+                       if injected { "app-provided addresses" } else { "cache" }, addrs.len());
+                addrs
+            }
+            _ => match Self::lookup(host, port).await {
+                Ok(addrs) => {
+                    if !ttl.is_zero() {
+                        self.entries.lock().insert(key.clone(), CacheEntry {
+                            addrs: addrs.clone(),
+                            expires: Some(Instant::now() + ttl),
+                            injected: false,
+                        });
+                    }
+                    addrs
+                }
+                Err(e) => match cached {
+                    Some((_, _, stale)) if !stale.is_empty() => {
+                        warn!("DNS resolution for {} failed ({}), using stale addresses", host, e);
+                        stale
+                    }
+                    _ => return Err(e),
+                },
+            },
+        };
+
+        let preferred = self.preferred.lock().get(&key).copied();
+        let addrs = addrs.into_iter().map(|ip| SocketAddr::new(ip, port)).collect();
+        Ok(sort_candidates(addrs, preferred))
+    }
+
+    /// Remember the address a connection to `host` succeeded on
+    pub fn mark_good(&self, host: &str, addr: IpAddr) {
+        self.preferred.lock().insert(host.to_ascii_lowercase(), addr);
+    }
+
+    /// Forget and return the address last marked good for `host`
+    pub fn take_good(&self, host: &str) -> Option<IpAddr> {
+        self.preferred.lock().remove(&host.to_ascii_lowercase())
+    }
+
+    async fn lookup(host: &str, port: u16) -> Result<Vec<IpAddr>, BoxError> {
+        let mut addrs: Vec<IpAddr> = Vec::new();
+        for addr in tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|e| format!("DNS resolution failed: {}", e))?
+        {
+            if !addrs.contains(&addr.ip()) {
+                addrs.push(addr.ip());
+            }
+        }
+        if addrs.is_empty() {
+            return Err("Failed to resolve server address".into());
+        }
+        Ok(addrs)
+    }
+}
+
+/// Order addresses for connection attempts (RFC 8305 §4)
+///
+/// The last address that worked goes first; the rest alternate between
+/// families, starting with IPv6, keeping the resolver's order within a family.
+pub fn sort_candidates(addrs: Vec<SocketAddr>, preferred: Option<IpAddr>) -> Vec<SocketAddr> {
+    let (mut v6, mut v4): (Vec<SocketAddr>, Vec<SocketAddr>) = addrs.into_iter().partition(|a| a.is_ipv6());
+    let mut sorted = Vec::with_capacity(v6.len() + v4.len());
+
+    if let Some(ip) = preferred {
+        for family in [&mut v6, &mut v4] {
+            if let Some(pos) = family.iter().position(|a| a.ip() == ip) {
+                sorted.push(family.remove(pos));
+            }
+        }
+    }
+
+    // Continue with the other family after a preferred address
+    let v4_first = sorted.first().is_some_and(|a| a.is_ipv6());
+    let (first, second) = if v4_first { (v4, v6) } else { (v6, v4) };
+    let (mut first, mut second) = (first.into_iter(), second.into_iter());
+    loop {
+        match (first.next(), second.next()) {
+            (None, None) => break,
+            (a, b) => sorted.extend(a.into_iter().chain(b)),
+        }
+    }
+    sorted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::runtime;
+
+    fn cache() -> DnsCache {
+        DnsCache {
+            entries: Mutex::new(HashMap::new()),
+            preferred: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn ips(addrs: &[&str]) -> Vec<IpAddr> {
+        addrs.iter().map(|a| a.parse().unwrap()).collect()
+    }
+
+    fn sockets(addrs: &[&str]) -> Vec<SocketAddr> {
+        ips(addrs).into_iter().map(|ip| SocketAddr::new(ip, 443)).collect()
+    }
+
+    fn resolve(cache: &DnsCache, host: &str, ttl: Duration) -> Result<Vec<SocketAddr>, BoxError> {
+        runtime().block_on(cache.resolve(host, 443, ttl))
+    }
+
+    #[test]
+    fn injected_addresses_take_precedence_until_they_expire() {
+        let cache = cache();
+        cache.inject("Server.Invalid", ips(&["192.0.2.1", "2001:db8::1"]), Some(Duration::from_millis(50)));
+        let expected = sockets(&["2001:db8::1", "192.0.2.1"]);
+        assert_eq!(resolve(&cache, "server.invalid", Duration::from_secs(60)).unwrap(), expected);
+        assert!(cache.entries.lock()["server.invalid"].is_fresh());
+
+        // Expired: the lookup fails, so the stale addresses are still used
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(!cache.entries.lock()["server.invalid"].is_fresh());
+        assert_eq!(resolve(&cache, "server.invalid", Duration::from_secs(60)).unwrap(), expected);
+
+        cache.clear(Some("SERVER.invalid"));
+        assert!(resolve(&cache, "server.invalid", Duration::from_secs(60)).is_err());
+
+        // Without a TTL they never expire
+        cache.inject("pinned.invalid", ips(&["192.0.2.7"]), None);
+        assert!(cache.entries.lock()["pinned.invalid"].is_fresh());
+    }
+
+    #[test]
+    fn system_answers_are_cached_for_the_ttl() {
+        let cache = cache();
+        let literal = resolve(&cache, "[2001:db8::5]", Duration::from_secs(60)).unwrap();
+        assert_eq!(literal, sockets(&["2001:db8::5"]));
+
+        assert!(!resolve(&cache, "localhost", Duration::ZERO).unwrap().is_empty());
+        assert!(cache.entries.lock().is_empty());
+
+        let resolved = resolve(&cache, "localhost", Duration::from_secs(60)).unwrap();
+        let entries = cache.entries.lock();
+        let entry = &entries["localhost"];
+        assert!(!entry.injected && entry.is_fresh());
+        assert_eq!(entry.addrs.len(), resolved.len());
+    }
+
+    #[test]
+    fn good_address_is_tried_first_until_taken() {
+        let cache = cache();
+        cache.inject("server.invalid", ips(&["2001:db8::1", "192.0.2.1", "192.0.2.2"]), None);
+        cache.mark_good("SERVER.invalid", "192.0.2.2".parse().unwrap());
+        assert_eq!(
+            resolve(&cache, "server.invalid", Duration::ZERO).unwrap(),
+            sockets(&["192.0.2.2", "2001:db8::1", "192.0.2.1"])
+        );
+
+        assert_eq!(cache.take_good("server.invalid"), Some("192.0.2.2".parse().unwrap()));
+        assert_eq!(cache.take_good("server.invalid"), None);
+        assert_eq!(
+            resolve(&cache, "server.invalid", Duration::ZERO).unwrap(),
+            sockets(&["2001:db8::1", "192.0.2.1", "192.0.2.2"])
+        );
+    }
+
+    #[test]
+    fn candidates_alternate_families() {
+        let addrs = sockets(&["192.0.2.1", "192.0.2.2", "192.0.2.3", "2001:db8::1", "2001:db8::2"]);
+        assert_eq!(
+            sort_candidates(addrs.clone(), None),
+            sockets(&["2001:db8::1", "192.0.2.1", "2001:db8::2", "192.0.2.2", "192.0.2.3"])
+        );
+
+        // A preferred IPv6 address is followed by IPv4
+        assert_eq!(
+            sort_candidates(addrs.clone(), Some("2001:db8::2".parse().unwrap())),
+            sockets(&["2001:db8::2", "192.0.2.1", "2001:db8::1", "192.0.2.2", "192.0.2.3"])
+        );
+
+        // A preferred address that is not a candidate changes nothing
+        assert_eq!(sort_candidates(addrs.clone(), Some("198.51.100.1".parse().unwrap())), sort_candidates(addrs, None));
+        assert!(sort_candidates(Vec::new(), None).is_empty());
+        assert_eq!(sort_candidates(sockets(&["192.0.2.1", "192.0.2.2"]), None), sockets(&["192.0.2.1", "192.0.2.2"]));
+    }
+}
@@ -0,0 +1,34 @@
+/*
+ * Shared Tokio Runtime (Rust Implementation)
+ * One multi-thread runtime for every QuicheClient in the process
+ */
+
+use log::info;
+use std::sync::OnceLock;
+use tokio::runtime::{Builder, Handle, Runtime};
+
+// Worker thread bounds (endpoint drivers, supervisors, forwarder tasks)
+const MIN_WORKER_THREADS: usize = 2;
+const MAX_WORKER_THREADS: usize = 4;
+
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
+/// Process-wide runtime, created on first use and never shut down
+pub fn runtime() -> &'static Runtime {
+    RUNTIME.get_or_init(|| {
+        let workers = num_cpus::get().clamp(MIN_WORKER_THREADS, MAX_WORKER_THREADS);
+        info!("Starting shared QUIC runtime ({} workers)", workers);
+
+        Builder::new_multi_thread()
+            .worker_threads(workers)
+            .thread_name("quiche-rt")
+            .enable_all()
+            .build()
+            .expect("Failed to create Tokio runtime")
+    })
+}
+
+/// Handle to the shared runtime
+pub fn runtime_handle() -> Handle {
+    runtime().handle().clone()
+}
@@ -0,0 +1,75 @@
+/*
+ * Connection Scheduler (Rust Implementation)
+ * Picks which pooled QUIC connection carries a new flow
+ */
+
+use std::time::Duration;
+
+/// Policy for spreading flows over a client's connections
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionScheduler {
+    /// Take turns over the open connections
+    RoundRobin,
+    /// Lowest smoothed RTT (ties go to fewer active flows)
+    LeastRtt,
+    /// Fewest active flows (ties go to lower RTT)
+    LeastInflight,
+}
+
+/// Load of one open connection when a flow is placed
+#[derive(Clone, Copy, Debug)]
+pub struct LinkLoad {
+    pub index: usize,
+    pub rtt: Duration,
+    pub active_flows: usize,
+}
+
+impl ConnectionScheduler {
+    /// Choose a connection index from `candidates` (None if empty)
+    ///
+    /// `turn` is a counter the caller advances per pick, used by round-robin.
+    pub fn pick(self, candidates: &[LinkLoad], turn: usize) -> Option<usize> {
+        let chosen = match self {
+            Self::RoundRobin => candidates.get(turn % candidates.len().max(1)),
+            Self::LeastRtt => candidates.iter().min_by_key(|c| (c.rtt, c.active_flows)),
+            Self::LeastInflight => candidates.iter().min_by_key(|c| (c.active_flows, c.rtt)),
+        };
+        chosen.map(|c| c.index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(index: usize, rtt_ms: u64, active_flows: usize) -> LinkLoad {
+        LinkLoad { index, rtt: Duration::from_millis(rtt_ms), active_flows }
+    }
+
+    #[test]
+    fn round_robin_takes_turns() {
+        // Indexes need not be contiguous once a connection is down
+        let candidates = [load(0, 50, 9), load(2, 10, 0), load(3, 30, 1)];
+        let picks: Vec<_> = (0..5).map(|turn| ConnectionScheduler::RoundRobin.pick(&candidates, turn)).collect();
+        assert_eq!(picks, [Some(0), Some(2), Some(3), Some(0), Some(2)]);
+    }
+
+    #[test]
+    fn least_rtt_and_least_inflight_break_ties_on_each_other() {
+        let candidates = [load(0, 20, 1), load(1, 10, 5), load(2, 10, 2), load(3, 40, 0)];
+        assert_eq!(ConnectionScheduler::LeastRtt.pick(&candidates, 0), Some(2));
+        assert_eq!(ConnectionScheduler::LeastInflight.pick(&candidates, 0), Some(3));
+
+        let tied = [load(0, 30, 1), load(1, 20, 1)];
+        assert_eq!(ConnectionScheduler::LeastInflight.pick(&tied, 0), Some(1));
+        // Ignores the turn counter
+        assert_eq!(ConnectionScheduler::LeastRtt.pick(&tied, 7), Some(1));
+    }
+
+    #[test]
+    fn no_candidates_picks_nothing() {
+        for scheduler in [ConnectionScheduler::RoundRobin, ConnectionScheduler::LeastRtt, ConnectionScheduler::LeastInflight] {
+            assert_eq!(scheduler.pick(&[], 3), None);
+        }
+    }
+}
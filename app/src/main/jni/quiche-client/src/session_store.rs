@@ -0,0 +1,256 @@
+/*
+ * TLS Session Store
+ * Process-wide resumption state; key exchange groups can be exported to the app
+ */
+
+use rustls::client::{ClientSessionMemoryCache, ClientSessionStore, ResolvesClientCert, Tls12ClientSessionValue, Tls13ClientSessionValue};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::ServerName;
+use rustls::sign::CertifiedKey;
+use rustls::{NamedGroup, SignatureScheme};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+use log::{debug, warn};
+use crate::cert_verifier::{CertVerifyError, NoCertificateVerification, QuicCertVerifier, SpkiPin};
+
+// Number of cached session tickets (one or more per server name)
+const SESSION_CACHE_SIZE: usize = 64;
+
+// Most servers remembered in an exported state
+const MAX_EXPORTED_SERVERS: usize = 64;
+
+// Leading byte of the exported state
+const STATE_VERSION: u8 = 1;
+
+// Most verifiers kept alive for resumption (distinct CA bundle / pin sets)
+const MAX_VERIFIERS: usize = 8;
+
+/// Session store shared by every QuicheClient
+///
+/// Tickets are kept in memory only: rustls exposes no encoding for
+/// `Tls13ClientSessionValue`, so resumption and 0-RTT do not survive a
+/// process restart. Only the key-exchange group each server picked is
+/// exported (`export_state` / `import_state`), which lets the first full
+/// handshake after a restart skip a HelloRetryRequest round trip.
+pub struct SessionStore {
+    tickets: ClientSessionMemoryCache,
+    kx_hints: Mutex<HashMap<String, NamedGroup>>,
+}
+
+impl SessionStore {
+    fn new() -> Self {
+        Self {
+            tickets: ClientSessionMemoryCache::new(SESSION_CACHE_SIZE),
+            kx_hints: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Encode the key exchange groups (see `import_state` for the format); tickets are not included
+    pub fn export_state(&self) -> Vec<u8> {
+        let hints = self.kx_hints.lock();
+        let mut out = vec![STATE_VERSION];
+        for (name, group) in hints.iter().take(MAX_EXPORTED_SERVERS) {
+            let Ok(len) = u8::try_from(name.len()) else { continue };
+            out.push(len);
+            out.extend_from_slice(name.as_bytes());
+            out.extend_from_slice(&u16::from(*group).to_be_bytes());
+        }
+        out
+    }
+
+    /// Restore state from `export_state`, returning the number of servers
+    ///
+    /// Format: version byte, then per server a length-prefixed name and the
+    /// big-endian group id. Entries already learned this run are kept.
+    pub fn import_state(&self, state: &[u8]) -> Result<usize, String> {
+        let (&version, mut rest) = state.split_first().ok_or("Empty session state")?;
+        if version != STATE_VERSION {
+            return Err(format!("Unsupported session state version {}", version));
+        }
+
+        let mut imported = Vec::new();
+        while let Some((&len, tail)) = rest.split_first() {
+            let len = len as usize;
+            if tail.len() < len + 2 {
+                return Err("Truncated session state".into());
+            }
+            let name = std::str::from_utf8(&tail[..len]).map_err(|_| "Invalid server name in session state")?;
+            let group = u16::from_be_bytes([tail[len], tail[len + 1]]);
+            imported.push((name.to_string(), NamedGroup::from(group)));
+            rest = &tail[len + 2..];
+        }
+
+        let count = imported.len();
+        let mut hints = self.kx_hints.lock();
+        for (name, group) in imported {
+            hints.entry(name).or_insert(group);
+        }
+        debug!("Imported session state for {} servers", count);
+        Ok(count)
+    }
+}
+
+impl std::fmt::Debug for SessionStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SessionStore")
+            .field("kx_hints", &self.kx_hints.lock().len())
+            .finish_non_exhaustive()
+    }
+}
+
+fn hint_key(server_name: &ServerName<'_>) -> String {
+    server_name.to_str().to_ascii_lowercase()
+}
+
+impl ClientSessionStore for SessionStore {
+    fn set_kx_hint(&self, server_name: ServerName<'static>, group: NamedGroup) {
+        let mut hints = self.kx_hints.lock();
+        if hints.len() >= MAX_EXPORTED_SERVERS && !hints.contains_key(&hint_key(&server_name)) {
+            warn!("Session store full, not remembering key exchange for {:?}", server_name);
+        } else {
+            hints.insert(hint_key(&server_name), group);
+        }
+        drop(hints);
+        self.tickets.set_kx_hint(server_name, group);
+    }
+
+    fn kx_hint(&self, server_name: &ServerName<'_>) -> Option<NamedGroup> {
+        self.tickets.kx_hint(server_name)
+            .or_else(|| self.kx_hints.lock().get(&hint_key(server_name)).copied())
+    }
+
+    fn set_tls12_session(&self, server_name: ServerName<'static>, value: Tls12ClientSessionValue) {
+        self.tickets.set_tls12_session(server_name, value);
+    }
+
+    fn tls12_session(&self, server_name: &ServerName<'_>) -> Option<Tls12ClientSessionValue> {
+        self.tickets.tls12_session(server_name)
+    }
+
+    fn remove_tls12_session(&self, server_name: &ServerName<'static>) {
+        self.tickets.remove_tls12_session(server_name);
+    }
+
+    fn insert_tls13_ticket(&self, server_name: ServerName<'static>, value: Tls13ClientSessionValue) {
+        self.tickets.insert_tls13_ticket(server_name, value);
+    }
+
+    fn take_tls13_ticket(&self, server_name: &ServerName<'static>) -> Option<Tls13ClientSessionValue> {
+        self.tickets.take_tls13_ticket(server_name)
+    }
+}
+
+/// Process-wide session store, reused by every QuicheClient (including ones
+/// recreated after disconnect)
+pub fn session_store() -> Arc<SessionStore> {
+    static STORE: OnceLock<Arc<SessionStore>> = OnceLock::new();
+    STORE.get_or_init(|| Arc::new(SessionStore::new())).clone()
+}
+
+// Verification settings a shared verifier was built for
+type VerifierKey = (Option<String>, Vec<SpkiPin>);
+
+/// Verifier for these settings, shared by every client using them
+///
+/// rustls only resumes a ticket with the verifier and client-cert resolver
+/// instances it was received with, so both are reused across connections.
+pub fn shared_verifier(
+    ca_bundle_pem: Option<&str>,
+    spki_pins: &[SpkiPin],
+    provider: &Arc<CryptoProvider>,
+) -> Result<Arc<QuicCertVerifier>, CertVerifyError> {
+    static VERIFIERS: OnceLock<Mutex<HashMap<VerifierKey, Arc<QuicCertVerifier>>>> = OnceLock::new();
+    let key = (ca_bundle_pem.map(str::to_owned), spki_pins.to_vec());
+    let mut verifiers = VERIFIERS.get_or_init(|| Mutex::new(HashMap::new())).lock();
+    if let Some(verifier) = verifiers.get(&key) {
+        return Ok(verifier.clone());
+    }
+
+    let verifier = Arc::new(QuicCertVerifier::new(ca_bundle_pem, spki_pins.to_vec(), provider.clone())?);
+    if verifiers.len() >= MAX_VERIFIERS {
+        verifiers.clear();
+    }
+    verifiers.insert(key, verifier.clone());
+    Ok(verifier)
+}
+
+/// Accept-all verifier (allow_insecure), shared like `shared_verifier`
+pub(crate) fn insecure_verifier(provider: &CryptoProvider) -> Arc<NoCertificateVerification> {
+    static VERIFIER: OnceLock<Arc<NoCertificateVerification>> = OnceLock::new();
+    VERIFIER.get_or_init(|| Arc::new(NoCertificateVerification::new(provider))).clone()
+}
+
+// Client-cert resolver that never offers a certificate
+#[derive(Debug)]
+struct NoClientAuth;
+
+impl ResolvesClientCert for NoClientAuth {
+    fn resolve(&self, _root_hint_subjects: &[&[u8]], _sigschemes: &[SignatureScheme]) -> Option<Arc<CertifiedKey>> {
+        None
+    }
+
+    fn has_certs(&self) -> bool {
+        false
+    }
+}
+
+/// Client-cert resolver for configs without client auth (see `shared_verifier`)
+pub fn no_client_auth() -> Arc<dyn ResolvesClientCert> {
+    static RESOLVER: OnceLock<Arc<NoClientAuth>> = OnceLock::new();
+    RESOLVER.get_or_init(|| Arc::new(NoClientAuth)).clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(host: &str) -> ServerName<'static> {
+        ServerName::try_from(host.to_string()).unwrap()
+    }
+
+    #[test]
+    fn kx_hints_survive_export_and_import() {
+        let store = SessionStore::new();
+        store.set_kx_hint(name("Example.com"), NamedGroup::X25519);
+        store.set_kx_hint(name("other.test"), NamedGroup::secp256r1);
+
+        let restarted = SessionStore::new();
+        assert_eq!(restarted.import_state(&store.export_state()), Ok(2));
+        assert_eq!(restarted.kx_hint(&name("example.com")), Some(NamedGroup::X25519));
+        assert_eq!(restarted.kx_hint(&name("other.test")), Some(NamedGroup::secp256r1));
+        assert_eq!(restarted.kx_hint(&name("unknown.test")), None);
+    }
+
+    #[test]
+    fn learned_hints_win_over_imported_ones() {
+        let store = SessionStore::new();
+        store.set_kx_hint(name("example.com"), NamedGroup::X25519);
+        let state = store.export_state();
+
+        let restarted = SessionStore::new();
+        restarted.set_kx_hint(name("example.com"), NamedGroup::secp384r1);
+        restarted.import_state(&state).unwrap();
+        assert_eq!(restarted.kx_hint(&name("example.com")), Some(NamedGroup::secp384r1));
+    }
+
+    #[test]
+    fn equal_settings_share_a_verifier() {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let a = shared_verifier(None, &[[1; 32]], &provider).unwrap();
+        let b = shared_verifier(None, &[[1; 32]], &provider).unwrap();
+        let c = shared_verifier(None, &[[2; 32]], &provider).unwrap();
+        assert!(Arc::ptr_eq(&a, &b));
+        assert!(!Arc::ptr_eq(&a, &c));
+    }
+
+    #[test]
+    fn malformed_state_is_rejected() {
+        let store = SessionStore::new();
+        assert!(store.import_state(&[]).is_err());
+        assert!(store.import_state(&[STATE_VERSION + 1]).is_err());
+        assert!(store.import_state(&[STATE_VERSION, 4, b'a', b'b']).is_err());
+        assert!(store.import_state(&[STATE_VERSION, 1, 0xff, 0, 29]).is_err());
+        assert_eq!(store.import_state(&[STATE_VERSION]), Ok(0));
+    }
+}
@@ -0,0 +1,103 @@
+/*
+ * Loopback QUIC Server (test only)
+ * Echoes DATAGRAMs and flow streams so tests can run a QuicheClient without a network
+ */
+
+use crate::client::{QuicConfig, QuicheClient};
+use crate::runtime::runtime;
+use quinn::crypto::rustls::QuicServerConfig;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// Name in the test server's certificate (besides "localhost")
+pub const ECHO_SERVER_NAME: &str = "echo.test";
+
+/// Start a server on 127.0.0.1 echoing every DATAGRAM it receives (0-RTT enabled)
+///
+/// Bidirectional streams are flow streams: the flow header is read and the
+/// rest of the stream echoed back.
+pub fn echo_server() -> SocketAddr {
+    echo_server_with_cert().0
+}
+
+/// `echo_server`, plus its self-signed certificate as PEM
+pub fn echo_server_with_cert() -> (SocketAddr, String) {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into(), ECHO_SERVER_NAME.into()]).unwrap();
+    let pem = cert.cert.pem();
+    let key = rustls::pki_types::PrivateKeyDer::Pkcs8(cert.key_pair.serialize_der().into());
+    let mut tls = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert.cert.der().clone()], key)
+        .unwrap();
+    // Accept 0-RTT from resumed sessions
+    tls.max_early_data_size = u32::MAX;
+    let config = quinn::ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(tls).unwrap()));
+
+    let _guard = runtime().enter();
+    let endpoint = quinn::Endpoint::server(config, "127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = endpoint.local_addr().unwrap();
+    runtime().spawn(async move {
+        while let Some(incoming) = endpoint.accept().await {
+            tokio::spawn(async move {
+                let Ok(conn) = incoming.await else {
+                    return;
+                };
+                let streams = conn.clone();
+                tokio::spawn(async move {
+                    while let Ok((send, recv)) = streams.accept_bi().await {
+                        tokio::spawn(echo_flow_stream(send, recv));
+                    }
+                });
+                while let Ok(datagram) = conn.read_datagram().await {
+                    let _ = conn.send_datagram(datagram);
+                }
+            });
+        }
+    });
+    (addr, pem)
+}
+
+// Skip `[version][cmd][atyp][addr][port]`, then echo until the client finishes
+async fn echo_flow_stream(mut send: quinn::SendStream, mut recv: quinn::RecvStream) {
+    let mut head = [0u8; 3];
+    if recv.read_exact(&mut head).await.is_err() {
+        return;
+    }
+    let addr_len = match head[2] {
+        1 => 4 + 2,
+        4 => 16 + 2,
+        3 => {
+            let mut len = [0u8; 1];
+            if recv.read_exact(&mut len).await.is_err() {
+                return;
+            }
+            len[0] as usize + 2
+        }
+        _ => return,
+    };
+    let mut addr = vec![0u8; addr_len];
+    if recv.read_exact(&mut addr).await.is_err() {
+        return;
+    }
+    if tokio::io::copy(&mut recv, &mut send).await.is_ok() {
+        let _ = send.finish();
+    }
+}
+
+/// Client for `server` that skips certificate checks
+pub fn client_for(server: SocketAddr, connection_count: u32) -> Arc<QuicheClient> {
+    let config = QuicConfig {
+        server_host: server.ip().to_string(),
+        server_port: server.port(),
+        allow_insecure: true,
+        // Every test server has its own keys, so 0-RTT with a ticket from another is lost
+        enable_early_data: false,
+        enable_gso: false,
+        enable_gro: false,
+        connection_count,
+        ..QuicConfig::default()
+    };
+    Arc::new(QuicheClient::create(config).unwrap())
+}
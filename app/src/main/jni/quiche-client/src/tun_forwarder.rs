@@ -3,23 +3,63 @@
  * Zero-copy packet processing
  */
 
-use crate::client::QuicheClient;
+use crate::client::{ConnectionWatch, DatagramTooLarge, PacketDatagramClaim, QuicheClient};
+use crate::netstack::run_flow_stack;
+use crate::packet_pool::PacketPool;
+use crate::runtime::runtime;
+use crate::utils::NetUtils;
+use crate::vnet::{self, VirtioNetHdr, VNET_HDR_LEN};
+use bytes::Bytes;
 use std::sync::Arc;
 use parking_lot::Mutex;
 use std::os::unix::io::RawFd;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
-use std::time::Duration;
-use log::{error, info, warn};
+use std::time::{Duration, Instant};
+use log::{debug, error, info, warn};
+use quinn::Connection;
+use tokio::task::{JoinHandle, JoinSet};
+
+// Largest IP packet accepted from the server
+const MAX_PACKET_SIZE: usize = 65535;
+// Minimum interval between rate samples
+const RATE_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+// How often a paused uplink checks whether the reconnect finished
+const RECONNECT_POLL_INTERVAL: Duration = Duration::from_millis(20);
+// Longest wait for TUN readiness before re-checking the running flag
+const TUN_POLL_TIMEOUT: Duration = Duration::from_millis(100);
+// Back-off when every pool buffer is still queued in the QUIC stack
+const POOL_EXHAUSTED_BACKOFF: Duration = Duration::from_millis(1);
+// Upper bound for packets drained per wakeup
+const MAX_BATCH_SIZE: usize = 1024;
+
+/// What the forwarder sends over QUIC
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ForwarderMode {
+    /// Raw IP packets (needs a server that routes them)
+    Packet,
+    /// TCP/UDP flows terminated locally, each proxied with a flow header
+    Flow,
+}
 
 #[derive(Clone, Debug)]
 pub struct ForwarderConfig {
     pub tun_fd: RawFd,
+    pub mode: ForwarderMode,
+    /// TUN MTU; sizes the packet buffers
+    pub tun_mtu: usize,
+    /// Most packet buffers that may be in flight at once
     pub packet_pool_size: usize,
+    /// Most packets read from the TUN per wakeup
     pub batch_size: usize,
+    /// UDP GSO/GRO on the QUIC socket (applied through the client)
     pub use_gso: bool,
     pub use_gro: bool,
+    /// Send pooled TUN buffers directly instead of copying each packet
     pub use_zero_copy: bool,
+    /// TUN carries virtio_net_hdr (IFF_VNET_HDR): accept TSO/USO super-packets
+    /// and partial checksums from the kernel
+    pub use_vnet_hdr: bool,
     pub cpu_affinity: crate::client::CpuAffinity,
     pub enable_realtime: bool,
 }
@@ -28,54 +68,107 @@ impl Default for ForwarderConfig {
     fn default() -> Self {
         Self {
             tun_fd: -1,
+            mode: ForwarderMode::Packet,
+            tun_mtu: 8500,
             packet_pool_size: 8192,
             batch_size: 64,
             use_gso: true,
             use_gro: true,
             use_zero_copy: true,
+            use_vnet_hdr: false,
             cpu_affinity: crate::client::CpuAffinity::BigCores,
             enable_realtime: false,
         }
     }
 }
 
+/// Forwarder counters
+///
+/// Uplink (TUN -> QUIC): `packets_received`/`bytes_received` are read from the
+/// TUN, `packets_sent`/`bytes_sent` made it onto the connection.
+/// `packets_oversized` is the subset of `packets_dropped` that exceeded the
+/// QUIC DATAGRAM size limit.
+/// Downlink (QUIC -> TUN): `packets_to_tun`/`bytes_to_tun` were written back
+/// to the TUN, `tun_write_errors` counts packets that could not be.
+/// `read_batches` counts uplink wakeups (packets_received / read_batches is the
+/// average batch), `pool_exhausted` how often reading stalled on free buffers.
+/// In vnet mode `gso_frames` counts super-packets read from the TUN and
+/// `gso_segments` the packets they were split into.
+/// In flow mode, `bytes_sent` counts payload proxied over QUIC and
+/// `packets_sent` UDP datagrams; the `*_flows_*` counters and `flow_errors`
+/// (flows refused or reset) apply to flow mode only.
 #[derive(Clone, Debug, Default)]
 pub struct ForwarderStats {
     pub packets_received: u64,
     pub packets_sent: u64,
     pub packets_dropped: u64,
+    pub packets_oversized: u64,
     pub bytes_received: u64,
     pub bytes_sent: u64,
+    pub packets_to_tun: u64,
+    pub bytes_to_tun: u64,
+    pub tun_write_errors: u64,
+    pub read_batches: u64,
+    pub pool_exhausted: u64,
+    pub gso_frames: u64,
+    pub gso_segments: u64,
+    pub tcp_flows_active: u64,
+    pub tcp_flows_total: u64,
+    pub udp_flows_active: u64,
+    pub udp_flows_total: u64,
+    pub flow_errors: u64,
     pub rx_rate_mbps: f64,
     pub tx_rate_mbps: f64,
     pub avg_latency_us: u64,
 }
 
+// Byte counters at the last rate sample
+struct RateSample {
+    at: Instant,
+    bytes_sent: u64,
+    bytes_to_tun: u64,
+}
+
 pub struct QuicheTunForwarder {
     config: ForwarderConfig,
-    quic_client: Arc<Mutex<QuicheClient>>,
+    quic_client: Arc<QuicheClient>,
     running: Arc<AtomicBool>,
     stats: Arc<Mutex<ForwarderStats>>,
+    rate_sample: Mutex<RateSample>,
     _forward_thread: Option<thread::JoinHandle<()>>,
+    receive_task: Option<JoinHandle<()>>,
+    // Packet mode: keeps the flow router off the DATAGRAMs while running
+    datagram_claim: Option<PacketDatagramClaim>,
 }
 
 impl QuicheTunForwarder {
     pub fn create(
         config: ForwarderConfig,
-        quic_client: Arc<Mutex<QuicheClient>>,
+        quic_client: Arc<QuicheClient>,
     ) -> Result<Self, Box<dyn std::error::Error>> {
         if config.tun_fd < 0 {
             return Err("Invalid TUN fd".into());
         }
+        if config.mode == ForwarderMode::Flow && config.use_vnet_hdr {
+            return Err("vnet headers are not supported in flow mode".into());
+        }
 
-        info!("Creating TUN forwarder (tun_fd={})", config.tun_fd);
+        info!("Creating TUN forwarder (tun_fd={}, mode={:?}, vnet_hdr={})",
+              config.tun_fd, config.mode, config.use_vnet_hdr);
 
         Ok(Self {
             config,
             quic_client,
             running: Arc::new(AtomicBool::new(false)),
             stats: Arc::new(Mutex::new(ForwarderStats::default())),
+            rate_sample: Mutex::new(RateSample {
+                at: Instant::now(),
+                bytes_sent: 0,
+                bytes_to_tun: 0,
+            }),
             _forward_thread: None,
+            receive_task: None,
+            datagram_claim: None,
         })
     }
 
@@ -87,12 +180,63 @@ impl QuicheTunForwarder {
 
         info!("Starting TUN forwarder...");
 
+        // Take the downlink DATAGRAMs first: packet mode reads them raw, flow
+        // mode through the client's flow router, and the two cannot share them
+        let mut router = None;
+        let claimed = match self.config.mode {
+            ForwarderMode::Flow => self.quic_client.flow_router().map(|r| router = Some(r)),
+            ForwarderMode::Packet => self.quic_client.claim_packet_datagrams().map(|c| self.datagram_claim = Some(c)),
+        };
+        if let Err(e) = claimed {
+            self.running.store(false, Ordering::Release);
+            return Err(format!("Cannot read DATAGRAMs: {}", e).into());
+        }
+
         // Configure CPU affinity
         if let Err(e) = self.configure_cpu_affinity() {
             warn!("Failed to configure CPU affinity: {} (non-fatal)", e);
         }
 
-        // Start forwarding thread
+        if let Err(e) = self.prepare_tun() {
+            self.running.store(false, Ordering::Release);
+            self.datagram_claim = None;
+            return Err(e);
+        }
+
+        if let Err(e) = self.quic_client.set_udp_offload(self.config.use_gso, self.config.use_gro) {
+            warn!("Failed to configure UDP offload: {} (non-fatal)", e);
+        }
+
+        if let Some(router) = router {
+            let task = run_flow_stack(
+                self.config.tun_fd,
+                self.config.tun_mtu,
+                self.quic_client.clone(),
+                router,
+                self.stats.clone(),
+            );
+            self.receive_task = Some(runtime().spawn(task));
+            info!("TUN forwarder started (flow mode)");
+            return Ok(());
+        }
+
+        // Start receive task (QUIC -> TUN) on the shared runtime; it reads
+        // every pooled connection and follows each across reconnects
+        let watches = self.quic_client.watches();
+        let running = self.running.clone();
+        let stats = self.stats.clone();
+        let tun_fd = self.config.tun_fd;
+        let vnet_hdr = self.config.use_vnet_hdr;
+        self.receive_task = Some(runtime().spawn(async move {
+            // Aborting this task drops the set, which aborts the per-connection loops
+            let mut loops = JoinSet::new();
+            for watch in watches {
+                loops.spawn(Self::receive_loop(watch, tun_fd, vnet_hdr, running.clone(), stats.clone()));
+            }
+            while loops.join_next().await.is_some() {}
+        }));
+
+        // Start forwarding thread (TUN -> QUIC)
         let running = self.running.clone();
         let quic_client = self.quic_client.clone();
         let config = self.config.clone();
@@ -113,16 +257,50 @@ impl QuicheTunForwarder {
         }
 
         info!("Stopping TUN forwarder...");
+
+        if let Some(task) = self.receive_task.take() {
+            task.abort();
+        }
         
         if let Some(handle) = self._forward_thread.take() {
             let _ = handle.join();
         }
+        self.datagram_claim = None;
 
         info!("TUN forwarder stopped");
     }
 
     pub fn get_stats(&self) -> ForwarderStats {
-        self.stats.lock().clone()
+        let mut stats = self.stats.lock();
+
+        // Refresh per-direction rates at most once per sample interval
+        let mut sample = self.rate_sample.lock();
+        let elapsed = sample.at.elapsed();
+        if elapsed >= RATE_SAMPLE_INTERVAL {
+            let secs = elapsed.as_secs_f64();
+            stats.tx_rate_mbps = (stats.bytes_sent - sample.bytes_sent) as f64 * 8.0 / secs / 1_000_000.0;
+            stats.rx_rate_mbps = (stats.bytes_to_tun - sample.bytes_to_tun) as f64 * 8.0 / secs / 1_000_000.0;
+            *sample = RateSample {
+                at: Instant::now(),
+                bytes_sent: stats.bytes_sent,
+                bytes_to_tun: stats.bytes_to_tun,
+            };
+        }
+
+        stats.clone()
+    }
+
+    fn prepare_tun(&self) -> Result<(), Box<dyn std::error::Error>> {
+        // Readiness-driven uplink needs a non-blocking TUN
+        NetUtils::set_non_blocking(self.config.tun_fd)
+            .map_err(|e| format!("Failed to make TUN non-blocking: {}", e))?;
+
+        if self.config.use_vnet_hdr {
+            let offload = vnet::enable_tun_offload(self.config.tun_fd)
+                .map_err(|e| format!("Failed to enable TUN offload: {}", e))?;
+            info!("TUN offload enabled (flags={:#x})", offload);
+        }
+        Ok(())
     }
 
     fn configure_cpu_affinity(&self) -> Result<(), Box<dyn std::error::Error>> {
@@ -153,57 +331,364 @@ impl QuicheTunForwarder {
 
     fn forwarding_loop(
         running: Arc<AtomicBool>,
-        quic_client: Arc<Mutex<QuicheClient>>,
+        quic_client: Arc<QuicheClient>,
         config: ForwarderConfig,
         stats: Arc<Mutex<ForwarderStats>>,
     ) {
-        const BATCH_SIZE: usize = 64;
-        let mut buffer = vec![0u8; 65536];
+        let batch_size = config.batch_size.clamp(1, MAX_BATCH_SIZE);
+        let mut pool = PacketPool::new(batch_size, config.packet_pool_size.max(batch_size), config.tun_mtu);
+        // vnet frames may be 64 KiB super-packets regardless of the MTU
+        let copy_len = if config.use_vnet_hdr { VNET_HDR_LEN + MAX_PACKET_SIZE } else { config.tun_mtu };
+        let mut copy_buffer = vec![0u8; copy_len];
+        let mut batch: Vec<Bytes> = Vec::with_capacity(batch_size);
+        let mut gso = GsoCounts::default();
+        let mut paused = false;
+
+        info!("Uplink started (batch={}, pool={}, zero_copy={}, vnet_hdr={})",
+              batch_size, config.packet_pool_size, config.use_zero_copy, config.use_vnet_hdr);
 
         while running.load(Ordering::Acquire) {
-            // Read from TUN
-            use nix::unistd::read;
-            match read(config.tun_fd, &mut buffer) {
-                Ok(0) => {
-                    // EOF
+            // Leave packets queued in the TUN while no connection is usable and
+            // one is being re-established
+            if quic_client.is_reconnecting() && !quic_client.is_connected() {
+                if !paused {
+                    info!("QUIC reconnecting, uplink paused");
+                    paused = true;
+                }
+                thread::sleep(RECONNECT_POLL_INTERVAL);
+                continue;
+            } else if paused {
+                info!("QUIC reconnected, uplink resumed");
+                paused = false;
+            }
+
+            match Self::wait_readable(config.tun_fd, TUN_POLL_TIMEOUT) {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => {
+                    error!("Poll on TUN failed: {}", e);
                     break;
                 }
-                Ok(len) if len > 0 => {
-                    let mut stats_guard = stats.lock();
-                    stats_guard.packets_received += 1;
-                    stats_guard.bytes_received += len as u64;
-                    drop(stats_guard);
-
-                    // Send via QUIC
-                    let mut client = quic_client.lock();
-                    if let Err(e) = client.send(&buffer[..len]) {
-                        error!("Failed to send via QUIC: {}", e);
-                        let mut stats_guard = stats.lock();
-                        stats_guard.packets_dropped += 1;
-                    } else {
-                        let mut stats_guard = stats.lock();
-                        stats_guard.packets_sent += 1;
-                        stats_guard.bytes_sent += len as u64;
+            }
+
+            // Drain up to batch_size packets per wakeup
+            let (eof, exhausted) = if config.use_vnet_hdr {
+                Self::read_batch_vnet(config.tun_fd, &mut copy_buffer, batch_size, &mut batch, &mut gso)
+            } else if config.use_zero_copy {
+                Self::read_batch_pooled(config.tun_fd, &mut pool, batch_size, &mut batch)
+            } else {
+                Self::read_batch_copied(config.tun_fd, &mut copy_buffer, batch_size, &mut batch)
+            };
+
+            let read_bytes: usize = batch.iter().map(|p| p.len()).sum();
+            let read_count = batch.len() as u64;
+
+            let mut sent = 0u64;
+            let mut sent_bytes = 0u64;
+            let mut dropped = 0u64;
+            let mut oversized = 0u64;
+            let mut first_error = None;
+            for packet in batch.drain(..) {
+                let len = packet.len() as u64;
+                match quic_client.send_packet(packet) {
+                    Ok(_) => {
+                        sent += 1;
+                        sent_bytes += len;
+                    }
+                    Err(e) => {
+                        dropped += 1;
+                        if e.downcast_ref::<DatagramTooLarge>().is_some() {
+                            oversized += 1;
+                        } else if first_error.is_none() {
+                            first_error = Some(e.to_string());
+                        }
                     }
                 }
-                Ok(_) => {
-                    // Should not happen, but handle for exhaustiveness
-                    continue;
+            }
+
+            if let Some(e) = first_error {
+                error!("Failed to send via QUIC: {}", e);
+            }
+
+            let mut stats_guard = stats.lock();
+            if read_count > 0 {
+                stats_guard.read_batches += 1;
+            }
+            stats_guard.packets_received += read_count;
+            stats_guard.bytes_received += read_bytes as u64;
+            stats_guard.packets_sent += sent;
+            stats_guard.bytes_sent += sent_bytes;
+            stats_guard.packets_dropped += dropped + gso.malformed;
+            stats_guard.packets_oversized += oversized;
+            stats_guard.gso_frames += gso.frames;
+            stats_guard.gso_segments += gso.segments;
+            if exhausted {
+                stats_guard.pool_exhausted += 1;
+            }
+            drop(stats_guard);
+            gso = GsoCounts::default();
+
+            if eof {
+                info!("TUN closed, uplink stopping");
+                break;
+            }
+            if exhausted {
+                // Every buffer is still queued for sending; let the connection drain
+                thread::sleep(POOL_EXHAUSTED_BACKOFF);
+            }
+        }
+
+        debug!("Uplink stopped ({} packet buffers allocated)", pool.allocated());
+    }
+
+    // Read packets into pool buffers until EAGAIN or the batch is full.
+    // Returns (eof, pool_exhausted).
+    fn read_batch_pooled(
+        tun_fd: RawFd,
+        pool: &mut PacketPool,
+        batch_size: usize,
+        batch: &mut Vec<Bytes>,
+    ) -> (bool, bool) {
+        while batch.len() < batch_size {
+            let Some(mut slot) = pool.take() else {
+                return (false, true);
+            };
+            match nix::unistd::read(tun_fd, &mut slot) {
+                Ok(0) => {
+                    pool.put(slot);
+                    return (true, false);
+                }
+                Ok(len) => batch.push(pool.freeze(slot, len)),
+                Err(e) => {
+                    pool.put(slot);
+                    return (Self::is_fatal_read_error(e), false);
                 }
-                Err(nix::errno::Errno::EAGAIN) => {
-                    // No data available (EAGAIN and EWOULDBLOCK are the same on Linux)
-                    thread::sleep(Duration::from_millis(1));
+            }
+        }
+        (false, false)
+    }
+
+    // Same as read_batch_pooled, copying each packet out of one buffer
+    fn read_batch_copied(
+        tun_fd: RawFd,
+        buffer: &mut [u8],
+        batch_size: usize,
+        batch: &mut Vec<Bytes>,
+    ) -> (bool, bool) {
+        while batch.len() < batch_size {
+            match nix::unistd::read(tun_fd, buffer) {
+                Ok(0) => return (true, false),
+                Ok(len) => batch.push(Bytes::copy_from_slice(&buffer[..len])),
+                Err(e) => return (Self::is_fatal_read_error(e), false),
+            }
+        }
+        (false, false)
+    }
+
+    // Read virtio-net frames, splitting super-packets into MTU-sized packets.
+    // Up to batch_size frames are read; one frame may yield many packets.
+    fn read_batch_vnet(
+        tun_fd: RawFd,
+        buffer: &mut [u8],
+        batch_size: usize,
+        batch: &mut Vec<Bytes>,
+        gso: &mut GsoCounts,
+    ) -> (bool, bool) {
+        for _ in 0..batch_size {
+            let len = match nix::unistd::read(tun_fd, buffer) {
+                Ok(0) => return (true, false),
+                Ok(len) => len,
+                Err(e) => return (Self::is_fatal_read_error(e), false),
+            };
+
+            let frame = &mut buffer[..len];
+            let is_gso = vnet::is_gso_frame(frame);
+            match vnet::split_frame(frame, batch) {
+                Ok(segments) if is_gso => {
+                    gso.frames += 1;
+                    gso.segments += segments as u64;
                 }
+                Ok(_) => {}
                 Err(e) => {
-                    error!("Read from TUN failed: {}", e);
-                    break;
+                    debug!("Dropping vnet frame: {}", e);
+                    gso.malformed += 1;
                 }
             }
         }
+        (false, false)
+    }
+
+    // EAGAIN/EINTR end the batch; anything else stops the uplink
+    fn is_fatal_read_error(e: nix::errno::Errno) -> bool {
+        match e {
+            nix::errno::Errno::EAGAIN | nix::errno::Errno::EINTR => false,
+            e => {
+                error!("Read from TUN failed: {}", e);
+                true
+            }
+        }
+    }
+
+    // Wait until the TUN has a packet; Ok(false) on timeout or signal
+    fn wait_readable(tun_fd: RawFd, timeout: Duration) -> Result<bool, nix::Error> {
+        let mut pfd = libc::pollfd {
+            fd: tun_fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let ret = unsafe { libc::poll(&mut pfd, 1, timeout.as_millis() as libc::c_int) };
+
+        if ret < 0 {
+            return match nix::Error::last() {
+                nix::errno::Errno::EINTR => Ok(false),
+                e => Err(e),
+            };
+        }
+        if ret == 0 {
+            return Ok(false);
+        }
+        if pfd.revents & (libc::POLLERR | libc::POLLNVAL) != 0 {
+            return Err(nix::errno::Errno::EBADF);
+        }
+        Ok(true)
+    }
+
+    async fn receive_loop(
+        mut watch: ConnectionWatch,
+        tun_fd: RawFd,
+        vnet_hdr: bool,
+        running: Arc<AtomicBool>,
+        stats: Arc<Mutex<ForwarderStats>>,
+    ) {
+        while running.load(Ordering::Acquire) {
+            // Waits out reconnects; None only once the client is destroyed
+            let Some(conn) = watch.open_connection().await else {
+                break;
+            };
+            Self::receive_from(&conn, tun_fd, vnet_hdr, &running, &stats).await;
+        }
+    }
+
+    // Forward one connection's packets to the TUN until it closes
+    async fn receive_from(
+        conn: &Connection,
+        tun_fd: RawFd,
+        vnet_hdr: bool,
+        running: &AtomicBool,
+        stats: &Arc<Mutex<ForwarderStats>>,
+    ) {
+        info!("Downlink started (remote={})", conn.remote_address());
+
+        while running.load(Ordering::Acquire) {
+            tokio::select! {
+                stream = conn.accept_uni() => match stream {
+                    Ok(mut recv) => {
+                        // One IP packet per stream, mirroring QuicheClient::send
+                        let stats = stats.clone();
+                        tokio::spawn(async move {
+                            match recv.read_to_end(MAX_PACKET_SIZE).await {
+                                Ok(packet) => Self::write_to_tun(tun_fd, vnet_hdr, &packet, &stats),
+                                Err(e) => {
+                                    debug!("Failed to read packet stream: {}", e);
+                                    stats.lock().tun_write_errors += 1;
+                                }
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        info!("QUIC connection closed, downlink waiting for reconnect: {}", e);
+                        break;
+                    }
+                },
+                datagram = conn.read_datagram() => match datagram {
+                    Ok(packet) => Self::write_to_tun(tun_fd, vnet_hdr, &packet, stats),
+                    Err(e) => {
+                        info!("QUIC connection closed, downlink waiting for reconnect: {}", e);
+                        break;
+                    }
+                },
+            }
+        }
+    }
+
+    fn write_to_tun(tun_fd: RawFd, vnet_hdr: bool, packet: &[u8], stats: &Mutex<ForwarderStats>) {
+        let result = if vnet_hdr {
+            Self::write_vnet_frame(tun_fd, packet)
+        } else {
+            use std::os::fd::BorrowedFd;
+            let fd = unsafe { BorrowedFd::borrow_raw(tun_fd) };
+            nix::unistd::write(fd, packet)
+        };
+
+        match result {
+            Ok(written) if written == packet.len() => {
+                let mut stats_guard = stats.lock();
+                stats_guard.packets_to_tun += 1;
+                stats_guard.bytes_to_tun += written as u64;
+            }
+            Ok(written) => {
+                warn!("Short write to TUN ({} of {} bytes)", written, packet.len());
+                stats.lock().tun_write_errors += 1;
+            }
+            Err(e) => {
+                debug!("Write to TUN failed: {}", e);
+                stats.lock().tun_write_errors += 1;
+            }
+        }
+    }
+
+    // Write a packet behind a DATA_VALID virtio_net_hdr; returns packet bytes written
+    fn write_vnet_frame(tun_fd: RawFd, packet: &[u8]) -> Result<usize, nix::Error> {
+        let hdr = VirtioNetHdr::DOWNLINK.encode();
+        let iov = [
+            libc::iovec { iov_base: hdr.as_ptr() as *mut libc::c_void, iov_len: hdr.len() },
+            libc::iovec { iov_base: packet.as_ptr() as *mut libc::c_void, iov_len: packet.len() },
+        ];
+        let ret = unsafe { libc::writev(tun_fd, iov.as_ptr(), iov.len() as libc::c_int) };
+        if ret < 0 {
+            return Err(nix::Error::last());
+        }
+        Ok((ret as usize).saturating_sub(VNET_HDR_LEN))
     }
 }
 
+// Per-batch vnet counters, folded into ForwarderStats with the rest
+#[derive(Default)]
+struct GsoCounts {
+    frames: u64,
+    segments: u64,
+    malformed: u64,
+}
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::client_for;
+    use std::os::fd::AsRawFd;
+    use std::os::unix::net::UnixDatagram;
 
+    #[test]
+    fn failed_start_releases_the_datagrams() {
+        let client = client_for("127.0.0.1:9".parse().unwrap(), 1);
 
+        // A socket is no TUN, so enabling offload fails after the claim is taken
+        let (tun, _peer) = UnixDatagram::pair().unwrap();
+        let config = ForwarderConfig {
+            tun_fd: tun.as_raw_fd(),
+            mode: ForwarderMode::Packet,
+            use_vnet_hdr: true,
+            ..ForwarderConfig::default()
+        };
+        let mut forwarder = QuicheTunForwarder::create(config, client.clone()).unwrap();
+        assert!(forwarder.start().is_err());
+        assert!(!forwarder.running.load(Ordering::Acquire));
+        drop(client.claim_packet_datagrams().unwrap());
 
+        // And the forwarder can be started again once the TUN is usable
+        forwarder.config.use_vnet_hdr = false;
+        forwarder.start().unwrap();
+        assert!(client.claim_packet_datagrams().is_err());
+        forwarder.stop();
+        assert!(client.claim_packet_datagrams().is_ok());
+    }
+}
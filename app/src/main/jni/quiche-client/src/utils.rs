@@ -76,20 +76,41 @@ impl TimeUtils {
     }
 }
 
+// Linux UDP socket options (not exported by libc on every Android target)
+const UDP_SEGMENT: libc::c_int = 103;
+const UDP_GRO: libc::c_int = 104;
+
 pub struct NetUtils;
 
 impl NetUtils {
-    pub fn enable_udp_gso(_sockfd: RawFd) -> Result<(), nix::Error> {
-        // UDP_SEGMENT = 103
-        // SoZerocopy is not available in nix, skip for now
-        // In production, use libc directly if needed
-        Ok(())
-    }
-
-    pub fn enable_udp_gro(_sockfd: RawFd) -> Result<(), nix::Error> {
-        // UDP_GRO = 104 (not directly supported in nix, would need libc)
-        // For now, just return Ok
-        Ok(())
+    /// Set the UDP GSO segment size for sends on this socket (0 = per-send only)
+    ///
+    /// Fails with ENOPROTOOPT on kernels without UDP GSO, so it doubles as a probe.
+    pub fn enable_udp_gso(sockfd: RawFd, segment_size: u16) -> Result<(), nix::Error> {
+        Self::set_udp_option(sockfd, UDP_SEGMENT, segment_size as libc::c_int)
+    }
+
+    /// Enable or disable UDP GRO (coalesced receives) on this socket
+    pub fn enable_udp_gro(sockfd: RawFd, enable: bool) -> Result<(), nix::Error> {
+        Self::set_udp_option(sockfd, UDP_GRO, enable as libc::c_int)
+    }
+
+    // nix has no sockopt wrappers for UDP_SEGMENT/UDP_GRO
+    fn set_udp_option(sockfd: RawFd, name: libc::c_int, value: libc::c_int) -> Result<(), nix::Error> {
+        let ret = unsafe {
+            libc::setsockopt(
+                sockfd,
+                libc::SOL_UDP,
+                name,
+                &value as *const libc::c_int as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            Err(nix::Error::last())
+        } else {
+            Ok(())
+        }
     }
 
     pub fn set_socket_buffers(sockfd: RawFd, sndbuf: usize, rcvbuf: usize) -> Result<(), nix::Error> {
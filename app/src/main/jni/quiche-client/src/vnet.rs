@@ -0,0 +1,681 @@
+/*
+ * virtio-net Header Offload (Rust Implementation)
+ * Parse and emit virtio_net_hdr for TUN devices opened with IFF_VNET_HDR
+ */
+
+use bytes::Bytes;
+use std::fmt;
+use std::os::unix::io::RawFd;
+
+/// Size of `struct virtio_net_hdr` (no num_buffers field)
+pub const VNET_HDR_LEN: usize = 10;
+
+// virtio_net_hdr flags
+const VIRTIO_NET_HDR_F_NEEDS_CSUM: u8 = 1;
+const VIRTIO_NET_HDR_F_DATA_VALID: u8 = 2;
+
+// virtio_net_hdr gso_type values
+const VIRTIO_NET_HDR_GSO_NONE: u8 = 0;
+const VIRTIO_NET_HDR_GSO_TCPV4: u8 = 1;
+const VIRTIO_NET_HDR_GSO_TCPV6: u8 = 4;
+const VIRTIO_NET_HDR_GSO_UDP_L4: u8 = 5;
+const VIRTIO_NET_HDR_GSO_ECN: u8 = 0x80;
+
+// TUN ioctls and flags (linux/if_tun.h)
+const TUNSETOFFLOAD: u64 = 0x400454d0;
+const TUNGETIFF: u64 = 0x800454d2;
+const TUNSETVNETHDRSZ: u64 = 0x400454d8;
+const IFF_VNET_HDR: i16 = 0x4000;
+const TUN_F_CSUM: u32 = 0x01;
+const TUN_F_TSO4: u32 = 0x02;
+const TUN_F_TSO6: u32 = 0x04;
+const TUN_F_TSO_ECN: u32 = 0x08;
+const TUN_F_USO4: u32 = 0x20;
+const TUN_F_USO6: u32 = 0x40;
+
+// struct ifreq: 16-byte name followed by a union (flags for TUN ioctls)
+const IFREQ_SIZE: usize = 40;
+const IFNAMSIZ: usize = 16;
+
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+const IPV6_HDR_LEN: usize = 40;
+const UDP_HDR_LEN: usize = 8;
+const TCP_HDR_LEN: usize = 20;
+
+// TCP flags adjusted per segment
+const TCP_FIN: u8 = 0x01;
+const TCP_PSH: u8 = 0x08;
+const TCP_CWR: u8 = 0x80;
+
+/// Why a frame from a vnet TUN could not be turned into IP packets
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VnetError {
+    /// Frame shorter than the headers it claims to carry
+    Truncated,
+    /// gso_type this forwarder does not segment
+    UnsupportedGso(u8),
+    /// Segmentation requested for a non-TCP/UDP packet
+    UnsupportedProtocol(u8),
+    /// Not an IPv4 or IPv6 packet
+    UnsupportedIpVersion(u8),
+    /// csum_start/csum_offset point outside the packet
+    BadChecksumOffset,
+    /// gso_size of zero on a GSO frame
+    BadGsoSize,
+}
+
+impl fmt::Display for VnetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "truncated vnet frame"),
+            Self::UnsupportedGso(t) => write!(f, "unsupported gso_type {}", t),
+            Self::UnsupportedProtocol(p) => write!(f, "cannot segment IP protocol {}", p),
+            Self::UnsupportedIpVersion(v) => write!(f, "unsupported IP version {}", v),
+            Self::BadChecksumOffset => write!(f, "checksum offset outside packet"),
+            Self::BadGsoSize => write!(f, "gso_size is zero"),
+        }
+    }
+}
+
+impl std::error::Error for VnetError {}
+
+/// `struct virtio_net_hdr` (fields in host byte order, as TUN uses them)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VirtioNetHdr {
+    pub flags: u8,
+    pub gso_type: u8,
+    pub hdr_len: u16,
+    pub gso_size: u16,
+    pub csum_start: u16,
+    pub csum_offset: u16,
+}
+
+impl VirtioNetHdr {
+    /// Header for packets written to the TUN: complete, already-checksummed
+    /// packets (QUIC protects them end to end, so the kernel may skip validation)
+    pub const DOWNLINK: Self = Self {
+        flags: VIRTIO_NET_HDR_F_DATA_VALID,
+        gso_type: VIRTIO_NET_HDR_GSO_NONE,
+        hdr_len: 0,
+        gso_size: 0,
+        csum_start: 0,
+        csum_offset: 0,
+    };
+
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.len() < VNET_HDR_LEN {
+            return None;
+        }
+        let u16_at = |i: usize| u16::from_ne_bytes([buf[i], buf[i + 1]]);
+        Some(Self {
+            flags: buf[0],
+            gso_type: buf[1],
+            hdr_len: u16_at(2),
+            gso_size: u16_at(4),
+            csum_start: u16_at(6),
+            csum_offset: u16_at(8),
+        })
+    }
+
+    pub fn encode(&self) -> [u8; VNET_HDR_LEN] {
+        let mut buf = [0u8; VNET_HDR_LEN];
+        buf[0] = self.flags;
+        buf[1] = self.gso_type;
+        buf[2..4].copy_from_slice(&self.hdr_len.to_ne_bytes());
+        buf[4..6].copy_from_slice(&self.gso_size.to_ne_bytes());
+        buf[6..8].copy_from_slice(&self.csum_start.to_ne_bytes());
+        buf[8..10].copy_from_slice(&self.csum_offset.to_ne_bytes());
+        buf
+    }
+}
+
+/// Turn one frame read from a vnet TUN into plain IP packets
+///
+/// `frame` starts with the virtio_net_hdr. Partial checksums are completed in
+/// place; GSO super-packets are split into `gso_size` segments with lengths,
+/// IDs, sequence numbers and checksums fixed up. Returns the packet count.
+pub fn split_frame(frame: &mut [u8], out: &mut Vec<Bytes>) -> Result<usize, VnetError> {
+    let hdr = VirtioNetHdr::parse(frame).ok_or(VnetError::Truncated)?;
+    let packet = &mut frame[VNET_HDR_LEN..];
+
+    match hdr.gso_type & !VIRTIO_NET_HDR_GSO_ECN {
+        VIRTIO_NET_HDR_GSO_NONE => {
+            if hdr.flags & VIRTIO_NET_HDR_F_NEEDS_CSUM != 0 {
+                complete_checksum(packet, hdr.csum_start as usize, hdr.csum_offset as usize)?;
+            }
+            out.push(Bytes::copy_from_slice(packet));
+            Ok(1)
+        }
+        VIRTIO_NET_HDR_GSO_TCPV4 | VIRTIO_NET_HDR_GSO_TCPV6 => {
+            segment(packet, hdr.gso_size as usize, IPPROTO_TCP, out)
+        }
+        VIRTIO_NET_HDR_GSO_UDP_L4 => segment(packet, hdr.gso_size as usize, IPPROTO_UDP, out),
+        other => Err(VnetError::UnsupportedGso(other)),
+    }
+}
+
+/// Whether a frame carries a GSO super-packet
+pub fn is_gso_frame(frame: &[u8]) -> bool {
+    VirtioNetHdr::parse(frame)
+        .map(|h| h.gso_type & !VIRTIO_NET_HDR_GSO_ECN != VIRTIO_NET_HDR_GSO_NONE)
+        .unwrap_or(false)
+}
+
+// Layout of the IP header at the start of a packet
+struct IpInfo {
+    is_v6: bool,
+    hdr_len: usize,
+    protocol: u8,
+}
+
+fn parse_ip(packet: &[u8]) -> Result<IpInfo, VnetError> {
+    let first = *packet.first().ok_or(VnetError::Truncated)?;
+    match first >> 4 {
+        4 => {
+            let hdr_len = ((first & 0x0f) as usize) * 4;
+            if hdr_len < 20 || packet.len() < hdr_len {
+                return Err(VnetError::Truncated);
+            }
+            Ok(IpInfo { is_v6: false, hdr_len, protocol: packet[9] })
+        }
+        6 => {
+            if packet.len() < IPV6_HDR_LEN {
+                return Err(VnetError::Truncated);
+            }
+            // Extension headers are not produced by TSO/USO; only direct TCP/UDP
+            Ok(IpInfo { is_v6: true, hdr_len: IPV6_HDR_LEN, protocol: packet[6] })
+        }
+        v => Err(VnetError::UnsupportedIpVersion(v)),
+    }
+}
+
+// Split a TCP (TSO) or UDP (USO) super-packet into gso_size payload chunks
+fn segment(packet: &[u8], gso_size: usize, protocol: u8, out: &mut Vec<Bytes>) -> Result<usize, VnetError> {
+    if gso_size == 0 {
+        return Err(VnetError::BadGsoSize);
+    }
+
+    let ip = parse_ip(packet)?;
+    if ip.protocol != protocol {
+        return Err(VnetError::UnsupportedProtocol(ip.protocol));
+    }
+
+    let l4_start = ip.hdr_len;
+    let l4_hdr_len = if protocol == IPPROTO_TCP {
+        let off = *packet.get(l4_start + 12).ok_or(VnetError::Truncated)?;
+        ((off >> 4) as usize) * 4
+    } else {
+        UDP_HDR_LEN
+    };
+    let min_l4_hdr_len = if protocol == IPPROTO_TCP { TCP_HDR_LEN } else { UDP_HDR_LEN };
+    let payload_start = l4_start + l4_hdr_len;
+    if l4_hdr_len < min_l4_hdr_len || packet.len() < payload_start {
+        return Err(VnetError::Truncated);
+    }
+
+    let headers = &packet[..payload_start];
+    let payload = &packet[payload_start..];
+    let count = payload.len().div_ceil(gso_size).max(1);
+
+    let ip_id = if ip.is_v6 { 0 } else { u16::from_be_bytes([packet[4], packet[5]]) };
+    let tcp_seq = if protocol == IPPROTO_TCP {
+        u32::from_be_bytes([
+            packet[l4_start + 4],
+            packet[l4_start + 5],
+            packet[l4_start + 6],
+            packet[l4_start + 7],
+        ])
+    } else {
+        0
+    };
+
+    // A header-only super-packet still yields one segment
+    let chunks: Vec<&[u8]> = if payload.is_empty() {
+        vec![payload]
+    } else {
+        payload.chunks(gso_size).collect()
+    };
+
+    for (i, chunk) in chunks.into_iter().enumerate() {
+        let mut seg = Vec::with_capacity(headers.len() + chunk.len());
+        seg.extend_from_slice(headers);
+        seg.extend_from_slice(chunk);
+        let total = seg.len();
+        let l4_len = total - l4_start;
+
+        // IP header: length, ID and header checksum
+        if ip.is_v6 {
+            seg[4..6].copy_from_slice(&(l4_len as u16).to_be_bytes());
+        } else {
+            seg[2..4].copy_from_slice(&(total as u16).to_be_bytes());
+            seg[4..6].copy_from_slice(&ip_id.wrapping_add(i as u16).to_be_bytes());
+            seg[10..12].copy_from_slice(&[0, 0]);
+            let csum = fold(sum(0, &seg[..ip.hdr_len]));
+            seg[10..12].copy_from_slice(&csum.to_be_bytes());
+        }
+
+        // L4 header: sequence/flags (TCP) or length (UDP), then checksum
+        let csum_at = if protocol == IPPROTO_TCP {
+            let seq = tcp_seq.wrapping_add((i * gso_size) as u32);
+            seg[l4_start + 4..l4_start + 8].copy_from_slice(&seq.to_be_bytes());
+            let flags = &mut seg[l4_start + 13];
+            if i + 1 < count {
+                *flags &= !(TCP_FIN | TCP_PSH);
+            }
+            if i > 0 {
+                *flags &= !TCP_CWR;
+            }
+            l4_start + 16
+        } else {
+            seg[l4_start + 4..l4_start + 6].copy_from_slice(&(l4_len as u16).to_be_bytes());
+            l4_start + 6
+        };
+        seg[csum_at..csum_at + 2].copy_from_slice(&[0, 0]);
+        let mut csum = fold(sum(pseudo_header_sum(&seg, &ip, protocol, l4_len), &seg[l4_start..]));
+        if protocol == IPPROTO_UDP && csum == 0 {
+            csum = 0xffff;
+        }
+        seg[csum_at..csum_at + 2].copy_from_slice(&csum.to_be_bytes());
+
+        out.push(Bytes::from(seg));
+    }
+
+    Ok(count)
+}
+
+// NEEDS_CSUM: the field holds the pseudo-header sum; finish it over the L4 data
+fn complete_checksum(packet: &mut [u8], csum_start: usize, csum_offset: usize) -> Result<(), VnetError> {
+    let field = csum_start + csum_offset;
+    if csum_start >= packet.len() || field + 2 > packet.len() {
+        return Err(VnetError::BadChecksumOffset);
+    }
+    let csum = fold(sum(0, &packet[csum_start..]));
+    packet[field..field + 2].copy_from_slice(&csum.to_be_bytes());
+    Ok(())
+}
+
+fn pseudo_header_sum(packet: &[u8], ip: &IpInfo, protocol: u8, l4_len: usize) -> u64 {
+    let mut acc = if ip.is_v6 {
+        sum(0, &packet[8..40])
+    } else {
+        sum(0, &packet[12..20])
+    };
+    acc += protocol as u64;
+    acc += l4_len as u64;
+    acc
+}
+
+// One's complement sum of big-endian 16-bit words
+fn sum(mut acc: u64, data: &[u8]) -> u64 {
+    let mut words = data.chunks_exact(2);
+    for w in &mut words {
+        acc += u16::from_be_bytes([w[0], w[1]]) as u64;
+    }
+    if let [last] = words.remainder() {
+        acc += (*last as u64) << 8;
+    }
+    acc
+}
+
+fn fold(mut acc: u64) -> u16 {
+    while acc >> 16 != 0 {
+        acc = (acc & 0xffff) + (acc >> 16);
+    }
+    !(acc as u16)
+}
+
+fn tun_ioctl(fd: RawFd, request: u64, arg: *mut libc::c_void) -> Result<(), nix::Error> {
+    let ret = unsafe { libc::ioctl(fd, request as _, arg) };
+    if ret < 0 {
+        Err(nix::Error::last())
+    } else {
+        Ok(())
+    }
+}
+
+/// Prepare a TUN fd for vnet mode; returns the TUN_F_* offloads enabled
+///
+/// The device must have been created with IFF_VNET_HDR (Android's VpnService
+/// does not do this; the network namespace test below creates such a device).
+pub fn enable_tun_offload(tun_fd: RawFd) -> Result<u32, Box<dyn std::error::Error>> {
+    let mut ifr = [0u8; IFREQ_SIZE];
+    tun_ioctl(tun_fd, TUNGETIFF, ifr.as_mut_ptr() as *mut libc::c_void)
+        .map_err(|e| format!("TUNGETIFF failed: {}", e))?;
+    let flags = i16::from_ne_bytes([ifr[IFNAMSIZ], ifr[IFNAMSIZ + 1]]);
+    if flags & IFF_VNET_HDR == 0 {
+        return Err("TUN device was not created with IFF_VNET_HDR".into());
+    }
+
+    let mut hdr_size = VNET_HDR_LEN as libc::c_int;
+    tun_ioctl(tun_fd, TUNSETVNETHDRSZ, &mut hdr_size as *mut libc::c_int as *mut libc::c_void)
+        .map_err(|e| format!("TUNSETVNETHDRSZ failed: {}", e))?;
+
+    // USO needs Linux 6.2; fall back to checksum + TSO only
+    let base = TUN_F_CSUM | TUN_F_TSO4 | TUN_F_TSO6 | TUN_F_TSO_ECN;
+    for offload in [base | TUN_F_USO4 | TUN_F_USO6, base] {
+        if tun_ioctl(tun_fd, TUNSETOFFLOAD, offload as usize as *mut libc::c_void).is_ok() {
+            return Ok(offload);
+        }
+    }
+    Err(format!("TUNSETOFFLOAD failed: {}", nix::Error::last()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+    use std::os::fd::AsRawFd;
+
+    const TUNSETIFF: u64 = 0x400454ca;
+    const IFF_TUN: i16 = 0x0001;
+    const IFF_NO_PI: i16 = 0x1000;
+    const SIOCSIFFLAGS: u64 = 0x8914;
+    const SIOCSIFADDR: u64 = 0x8916;
+    const SIOCSIFNETMASK: u64 = 0x891c;
+    const UDP_SEGMENT: libc::c_int = 103;
+
+    const TCP_ACK: u8 = 0x10;
+
+    fn frame(hdr: VirtioNetHdr, packet: &[u8]) -> Vec<u8> {
+        let mut frame = hdr.encode().to_vec();
+        frame.extend_from_slice(packet);
+        frame
+    }
+
+    fn gso(gso_type: u8, gso_size: u16) -> VirtioNetHdr {
+        VirtioNetHdr { gso_type, gso_size, ..VirtioNetHdr::default() }
+    }
+
+    /// IPv4/TCP packet with a `tcp_hdr_len` byte header and `payload_len` bytes of data
+    fn tcp4(payload_len: usize, flags: u8, tcp_hdr_len: usize) -> Vec<u8> {
+        let mut packet = vec![0u8; 20 + tcp_hdr_len.max(20)];
+        packet.truncate(20 + tcp_hdr_len);
+        packet[0] = 0x45;
+        packet[4..6].copy_from_slice(&7u16.to_be_bytes());
+        packet[8] = 64;
+        packet[9] = IPPROTO_TCP;
+        packet[12..16].copy_from_slice(&[10, 0, 0, 1]);
+        packet[16..20].copy_from_slice(&[10, 0, 0, 2]);
+        if tcp_hdr_len >= 14 {
+            packet[24..28].copy_from_slice(&1000u32.to_be_bytes());
+            packet[32] = ((tcp_hdr_len / 4) as u8) << 4;
+            packet[33] = flags;
+        }
+        packet.extend((0..payload_len).map(|i| i as u8));
+        packet
+    }
+
+    /// IPv6/UDP packet with `payload_len` bytes of data
+    fn udp6(payload_len: usize) -> Vec<u8> {
+        let mut packet = vec![0u8; IPV6_HDR_LEN + UDP_HDR_LEN];
+        packet[0] = 0x60;
+        packet[6] = IPPROTO_UDP;
+        packet[7] = 64;
+        packet[23] = 1;
+        packet[39] = 2;
+        packet[40..42].copy_from_slice(&5000u16.to_be_bytes());
+        packet[42..44].copy_from_slice(&443u16.to_be_bytes());
+        packet.extend((0..payload_len).map(|i| (i * 7) as u8));
+        packet
+    }
+
+    fn ip_header_valid(packet: &[u8]) -> bool {
+        let ip = parse_ip(packet).unwrap();
+        ip.is_v6 || fold(sum(0, &packet[..ip.hdr_len])) == 0
+    }
+
+    fn l4_checksum_valid(packet: &[u8]) -> bool {
+        let ip = parse_ip(packet).unwrap();
+        let l4_len = packet.len() - ip.hdr_len;
+        fold(sum(pseudo_header_sum(packet, &ip, ip.protocol, l4_len), &packet[ip.hdr_len..])) == 0
+    }
+
+    #[test]
+    fn checksum_helpers() {
+        // RFC 1071 section 3 example
+        let data = [0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7];
+        assert_eq!(fold(sum(0, &data)), !0xddf2);
+        // An odd trailing byte is padded with zero
+        assert_eq!(sum(0, &[0x01, 0x02, 0x03]), 0x0102 + 0x0300);
+        // Carries fold back in
+        assert_eq!(fold(0x1_fffe), !0xffff);
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let hdr = VirtioNetHdr {
+            flags: VIRTIO_NET_HDR_F_NEEDS_CSUM,
+            gso_type: VIRTIO_NET_HDR_GSO_TCPV4 | VIRTIO_NET_HDR_GSO_ECN,
+            hdr_len: 54,
+            gso_size: 1448,
+            csum_start: 34,
+            csum_offset: 16,
+        };
+        assert_eq!(VirtioNetHdr::parse(&hdr.encode()), Some(hdr));
+        assert_eq!(VirtioNetHdr::parse(&[0u8; VNET_HDR_LEN - 1]), None);
+        assert!(is_gso_frame(&hdr.encode()));
+        assert!(!is_gso_frame(&VirtioNetHdr::DOWNLINK.encode()));
+        assert!(!is_gso_frame(&[0u8; 4]));
+    }
+
+    #[test]
+    fn split_frame_completes_partial_checksums() {
+        let mut packet = tcp4(333, TCP_ACK, 20);
+        assert!(split_frame(&mut frame(VirtioNetHdr::default(), &packet), &mut Vec::new()).is_ok());
+
+        // The kernel leaves the pseudo-header sum in the checksum field
+        let ip = parse_ip(&packet).unwrap();
+        let partial = !fold(pseudo_header_sum(&packet, &ip, IPPROTO_TCP, packet.len() - 20));
+        packet[36..38].copy_from_slice(&partial.to_be_bytes());
+        let hdr = VirtioNetHdr { flags: VIRTIO_NET_HDR_F_NEEDS_CSUM, csum_start: 20, csum_offset: 16, ..Default::default() };
+
+        let mut out = Vec::new();
+        assert_eq!(split_frame(&mut frame(hdr, &packet), &mut out), Ok(1));
+        assert_eq!(out[0].len(), packet.len());
+        assert!(l4_checksum_valid(&out[0]));
+
+        let bad = VirtioNetHdr { csum_offset: packet.len() as u16, ..hdr };
+        assert_eq!(split_frame(&mut frame(bad, &packet), &mut out), Err(VnetError::BadChecksumOffset));
+    }
+
+    #[test]
+    fn split_frame_rejects_bad_frames() {
+        let mut out = Vec::new();
+        assert_eq!(split_frame(&mut [0u8; 4], &mut out), Err(VnetError::Truncated));
+        let packet = tcp4(100, TCP_ACK, 20);
+        assert_eq!(split_frame(&mut frame(gso(3, 1000), &packet), &mut out), Err(VnetError::UnsupportedGso(3)));
+        assert_eq!(
+            split_frame(&mut frame(gso(VIRTIO_NET_HDR_GSO_TCPV4, 0), &packet), &mut out),
+            Err(VnetError::BadGsoSize)
+        );
+        assert_eq!(
+            split_frame(&mut frame(gso(VIRTIO_NET_HDR_GSO_UDP_L4, 50), &packet), &mut out),
+            Err(VnetError::UnsupportedProtocol(IPPROTO_TCP))
+        );
+        let mut v5 = packet.clone();
+        v5[0] = 0x55;
+        assert_eq!(
+            split_frame(&mut frame(gso(VIRTIO_NET_HDR_GSO_TCPV4, 50), &v5), &mut out),
+            Err(VnetError::UnsupportedIpVersion(5))
+        );
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn tso_segments_are_fixed_up() {
+        let packet = tcp4(2500, TCP_ACK | TCP_PSH | TCP_FIN | TCP_CWR, 20);
+        let mut out = Vec::new();
+        let hdr = gso(VIRTIO_NET_HDR_GSO_TCPV4 | VIRTIO_NET_HDR_GSO_ECN, 1000);
+        assert_eq!(split_frame(&mut frame(hdr, &packet), &mut out), Ok(3));
+
+        let mut payload = Vec::new();
+        for (i, seg) in out.iter().enumerate() {
+            let len = if i < 2 { 1000 } else { 500 };
+            assert_eq!(seg.len(), 40 + len);
+            assert_eq!(u16::from_be_bytes([seg[2], seg[3]]) as usize, seg.len());
+            assert_eq!(u16::from_be_bytes([seg[4], seg[5]]), 7 + i as u16);
+            assert_eq!(u32::from_be_bytes([seg[24], seg[25], seg[26], seg[27]]), 1000 + 1000 * i as u32);
+            let flags = seg[33];
+            assert_eq!(flags & TCP_ACK, TCP_ACK);
+            assert_eq!(flags & TCP_CWR != 0, i == 0);
+            assert_eq!(flags & (TCP_FIN | TCP_PSH) != 0, i == 2);
+            assert!(ip_header_valid(seg));
+            assert!(l4_checksum_valid(seg));
+            payload.extend_from_slice(&seg[40..]);
+        }
+        assert_eq!(payload, packet[40..]);
+    }
+
+    #[test]
+    fn tso_rejects_short_tcp_headers() {
+        let mut out = Vec::new();
+        // Data offset below the fixed header
+        for hdr_len in [8, 16] {
+            let packet = tcp4(100, TCP_ACK, hdr_len);
+            assert_eq!(
+                split_frame(&mut frame(gso(VIRTIO_NET_HDR_GSO_TCPV4, 50), &packet), &mut out),
+                Err(VnetError::Truncated)
+            );
+        }
+        // Options claimed past the end of the packet
+        let mut packet = tcp4(0, TCP_ACK, 20);
+        packet[32] = 15 << 4;
+        assert_eq!(
+            split_frame(&mut frame(gso(VIRTIO_NET_HDR_GSO_TCPV4, 50), &packet), &mut out),
+            Err(VnetError::Truncated)
+        );
+        // Header-only super-packet: one segment
+        let packet = tcp4(0, TCP_ACK | TCP_FIN, 32);
+        assert_eq!(split_frame(&mut frame(gso(VIRTIO_NET_HDR_GSO_TCPV4, 50), &packet), &mut out), Ok(1));
+        assert_eq!(out[0][33], TCP_ACK | TCP_FIN);
+        assert!(l4_checksum_valid(&out[0]));
+    }
+
+    #[test]
+    fn uso_segments_over_ipv6() {
+        let packet = udp6(2500);
+        let mut out = Vec::new();
+        assert_eq!(split_frame(&mut frame(gso(VIRTIO_NET_HDR_GSO_UDP_L4, 1200), &packet), &mut out), Ok(3));
+
+        let mut payload = Vec::new();
+        for (i, seg) in out.iter().enumerate() {
+            let len = if i < 2 { 1200 } else { 100 };
+            assert_eq!(seg.len(), 48 + len);
+            assert_eq!(u16::from_be_bytes([seg[4], seg[5]]) as usize, 8 + len);
+            assert_eq!(u16::from_be_bytes([seg[44], seg[45]]) as usize, 8 + len);
+            assert_ne!(u16::from_be_bytes([seg[46], seg[47]]), 0);
+            assert!(l4_checksum_valid(seg));
+            payload.extend_from_slice(&seg[48..]);
+        }
+        assert_eq!(payload, packet[48..]);
+    }
+
+    /// Open a TUN device with virtio-net headers (needs CAP_NET_ADMIN)
+    fn open_vnet_tun(name: &str) -> Result<RawFd, Box<dyn std::error::Error>> {
+        let fd = unsafe { libc::open(c"/dev/net/tun".as_ptr(), libc::O_RDWR | libc::O_CLOEXEC | libc::O_NONBLOCK) };
+        if fd < 0 {
+            return Err(format!("Failed to open /dev/net/tun: {}", nix::Error::last()).into());
+        }
+
+        let mut ifr = [0u8; IFREQ_SIZE];
+        ifr[..name.len()].copy_from_slice(name.as_bytes());
+        ifr[IFNAMSIZ..IFNAMSIZ + 2].copy_from_slice(&(IFF_TUN | IFF_NO_PI | IFF_VNET_HDR).to_ne_bytes());
+        if let Err(e) = tun_ioctl(fd, TUNSETIFF, ifr.as_mut_ptr() as *mut libc::c_void) {
+            unsafe { libc::close(fd) };
+            return Err(format!("TUNSETIFF failed: {}", e).into());
+        }
+        Ok(fd)
+    }
+
+    /// Give interface `name` address `addr`/24 and bring it up
+    fn configure(name: &str, addr: Ipv4Addr) {
+        let sock = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let request = |request: u64, value: &[u8]| {
+            let mut ifr = [0u8; IFREQ_SIZE];
+            ifr[..name.len()].copy_from_slice(name.as_bytes());
+            ifr[IFNAMSIZ..IFNAMSIZ + value.len()].copy_from_slice(value);
+            tun_ioctl(sock.as_raw_fd(), request, ifr.as_mut_ptr() as *mut libc::c_void).unwrap();
+        };
+        let sockaddr_in = |ip: Ipv4Addr| {
+            let mut sa = [0u8; 8];
+            sa[..2].copy_from_slice(&(libc::AF_INET as u16).to_ne_bytes());
+            sa[4..].copy_from_slice(&ip.octets());
+            sa
+        };
+        request(SIOCSIFADDR, &sockaddr_in(addr));
+        request(SIOCSIFNETMASK, &sockaddr_in(Ipv4Addr::new(255, 255, 255, 0)));
+        request(SIOCSIFFLAGS, &(libc::IFF_UP as i16).to_ne_bytes());
+    }
+
+    /// Read frames until `want` IPv4 packets to `port` arrive; also reports
+    /// whether any came as a GSO frame
+    fn read_packets(fd: RawFd, port: u16, want: usize) -> (Vec<Bytes>, bool) {
+        let mut packets = Vec::new();
+        let mut saw_gso = false;
+        let mut buf = vec![0u8; 65536 + VNET_HDR_LEN];
+        while packets.len() < want {
+            let mut pfd = libc::pollfd { fd, events: libc::POLLIN, revents: 0 };
+            assert_eq!(unsafe { libc::poll(&mut pfd, 1, 2000) }, 1, "timed out waiting for the TUN");
+            let n = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+            assert!(n > 0);
+            let frame = &mut buf[..n as usize];
+            saw_gso |= is_gso_frame(frame);
+            let mut out = Vec::new();
+            split_frame(frame, &mut out).unwrap();
+            packets.extend(out.into_iter().filter(|p| {
+                p[0] >> 4 == 4 && u16::from_be_bytes([p[22], p[23]]) == port
+            }));
+        }
+        (packets, saw_gso)
+    }
+
+    #[test]
+    fn kernel_frames_through_a_vnet_tun() {
+        // A private network namespace for this test's thread (needs root)
+        if unsafe { libc::unshare(libc::CLONE_NEWNET) } != 0 {
+            eprintln!("skipped: cannot create a network namespace: {}", nix::Error::last());
+            return;
+        }
+        let fd = open_vnet_tun("vnet0").unwrap();
+        let offload = enable_tun_offload(fd).unwrap();
+        configure("vnet0", Ipv4Addr::new(10, 99, 0, 1));
+
+        // A TCP SYN, checksummed by us (TUN_F_CSUM)
+        let tcp = socket2::Socket::new(socket2::Domain::IPV4, socket2::Type::STREAM, None).unwrap();
+        tcp.set_nonblocking(true).unwrap();
+        let _ = tcp.connect(&SocketAddr::from(([10, 99, 0, 2], 80)).into());
+        let (syn, _) = read_packets(fd, 80, 1);
+        assert_eq!(syn[0][9], IPPROTO_TCP);
+        assert!(ip_header_valid(&syn[0]));
+        assert!(l4_checksum_valid(&syn[0]));
+
+        // A UDP_SEGMENT send: one USO frame, or segments if the kernel lacks USO
+        let udp = UdpSocket::bind("10.99.0.1:0").unwrap();
+        let gso_size: libc::c_int = 1000;
+        let ret = unsafe {
+            libc::setsockopt(
+                udp.as_raw_fd(),
+                libc::SOL_UDP,
+                UDP_SEGMENT,
+                &gso_size as *const libc::c_int as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        assert_eq!(ret, 0);
+        let data: Vec<u8> = (0..2500).map(|i| (i % 251) as u8).collect();
+        udp.send_to(&data, "10.99.0.2:9").unwrap();
+
+        let (segments, saw_gso) = read_packets(fd, 9, 3);
+        assert_eq!(saw_gso, offload & TUN_F_USO4 != 0);
+        let mut payload = Vec::new();
+        for seg in &segments {
+            assert!(ip_header_valid(seg));
+            assert!(l4_checksum_valid(seg));
+            payload.extend_from_slice(&seg[28..]);
+        }
+        assert_eq!(payload, data);
+
+        unsafe { libc::close(fd) };
+    }
+}
@@ -6,21 +6,33 @@ package com.simplexray.an.chain.pepper
 data class PepperParams(
     val mode: PepperMode = PepperMode.BURST_FRIENDLY,
     val maxBurstBytes: Long = 64 * 1024, // 64KB
-    val targetRateBps: Long = 0, // 0 = unlimited
+    val targetRateBps: Long = 0, // 0 = unlimited; ceiling in ADAPTIVE mode
     val queueDiscipline: QueueDiscipline = QueueDiscipline.FQ,
     val lossAwareBackoff: Boolean = true,
     val enablePacing: Boolean = true
 )
 
+/**
+ * Rates of a bandwidth class, or of a handle placed in one
+ * 
+ * A class with spare rate lends it to children that have used up theirs,
+ * split between them by weight.
+ */
+data class PepperClassParams(
+    val rateBps: Long,      // Guaranteed rate (0 = none; unlimited for a top-level class)
+    val ceilBps: Long = 0,  // Upper bound including borrowed rate (0 = none)
+    val weight: Int = 1     // Share of the parent's spare rate
+)
+
 enum class PepperMode {
     BURST_FRIENDLY, // Allow bursts, smooth over time
     CONSTANT_RATE,  // Strict rate limiting
-    ADAPTIVE        // Adjust based on loss/RTT
+    ADAPTIVE        // Pace at the estimated bottleneck bandwidth (BBR-style)
 }
 
 enum class QueueDiscipline {
-    FQ,      // Fair Queue
-    CODEL,   // CoDel-lite
+    FQ,      // FQ-CoDel: per-flow queues for TUN packets, CoDel on each
+    CODEL,   // CoDel: bounds queueing delay by dropping or ECN marking
     SIMPLE   // Simple FIFO
 }
 
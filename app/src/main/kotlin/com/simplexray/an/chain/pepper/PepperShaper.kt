@@ -58,9 +58,9 @@ object PepperShaper {
     /**
      * Attach shaper to a socket/file descriptor pair
      * 
-     * @param fdPair Pair of (readFd, writeFd)
+     * @param fdPair Pair of (readFd, writeFd); data read from the first is paced out to the second
      * @param mode TCP or UDP mode
-     * @param params Shaping parameters
+     * @param params Shaping parameters; [PepperParams.mode] is fixed at attach time
      * @return Shaper handle or null on failure
      */
     fun attach(
@@ -74,6 +74,12 @@ object PepperShaper {
             return null
         }
         
+        // Data is forwarded from readFd to writeFd, so they must be different fds
+        if (fdPair.first == fdPair.second) {
+            AppLogger.e("PepperShaper: readFd and writeFd must differ: ${fdPair.first}")
+            return null
+        }
+        
         if (!isInitialized) {
             AppLogger.e("PepperShaper: Not initialized, cannot attach")
             return null
@@ -81,7 +87,9 @@ object PepperShaper {
         
         return try {
             AppLogger.d("PepperShaper: Attaching to fds ${fdPair.first}/${fdPair.second}, mode=$mode")
-            val handle = nativeAttach(fdPair.first, fdPair.second, mode.ordinal, params)
+            // Socket mode in the low byte, pacing mode in the next
+            val nativeMode = mode.ordinal or (params.mode.ordinal shl 8)
+            val handle = nativeAttach(fdPair.first, fdPair.second, nativeMode, params)
             if (handle > 0) {
                 AppLogger.d("PepperShaper: Attached successfully, handle=$handle")
                 handle
@@ -167,6 +175,215 @@ object PepperShaper {
      */
     fun getStats(): PepperStats = _stats.value
     
+    /**
+     * Report loss and RTT measured by the app, for flows the shaper cannot
+     * sample itself (UDP, QUIC). Drives loss-aware backoff, and in
+     * [PepperMode.ADAPTIVE] the bandwidth estimate.
+     * 
+     * On TCP handles the shaper samples TCP_INFO and its next sample replaces these values.
+     * 
+     * @param lossRate Loss rate in 0.0..1.0
+     * @param rttUs Round-trip time in microseconds
+     * @param deliveryRateBps Measured delivery rate in bits per second (0 = unknown)
+     */
+    fun reportMetrics(handle: Long, lossRate: Float, rttUs: Long, deliveryRateBps: Long = 0): Boolean {
+        if (handle <= 0 || !isInitialized) {
+            return false
+        }
+        
+        return try {
+            nativeReportMetrics(handle, lossRate, rttUs, deliveryRateBps)
+        } catch (e: UnsatisfiedLinkError) {
+            AppLogger.e("PepperShaper: Native library not loaded: ${e.message}", e)
+            false
+        } catch (e: Exception) {
+            AppLogger.e("PepperShaper: Exception reporting metrics for handle=$handle: ${e.javaClass.simpleName}: ${e.message}", e)
+            false
+        }
+    }
+    
+    /**
+     * Get pump statistics for an attached shaper
+     * 
+     * @return Statistics or null if the handle is unknown
+     */
+    fun getHandleStats(handle: Long): HandleStats? {
+        if (handle <= 0 || !isInitialized) {
+            return null
+        }
+        
+        return try {
+            val values = nativeGetHandleStats(handle) ?: return null
+            HandleStats(
+                queuedBytes = values[0],
+                sentBytes = values[1],
+                sentChunks = values[2],
+                delayed = values[3],
+                dropped = values[4],
+                queueDepth = values[5].toInt(),
+                rttUs = values[6],
+                lossRate = values[7] / 1_000_000f,
+                deliveryRateBps = values[8],
+                pacingRateBps = values[9],
+                aqmDropped = values[10],
+                ecnMarked = values[11]
+            )
+        } catch (e: UnsatisfiedLinkError) {
+            AppLogger.e("PepperShaper: Native library not loaded: ${e.message}", e)
+            null
+        } catch (e: Exception) {
+            AppLogger.e("PepperShaper: Exception getting stats for handle=$handle: ${e.javaClass.simpleName}: ${e.message}", e)
+            null
+        }
+    }
+    
+    /**
+     * Get how long chunks waited in the shaper queue
+     * 
+     * @return Histogram or null if the handle is unknown
+     */
+    fun getQueueDelayHistogram(handle: Long): QueueDelayHistogram? {
+        if (handle <= 0 || !isInitialized) {
+            return null
+        }
+        
+        return try {
+            val values = nativeGetQueueDelayHistogram(handle) ?: return null
+            QueueDelayHistogram(values.toList())
+        } catch (e: UnsatisfiedLinkError) {
+            AppLogger.e("PepperShaper: Native library not loaded: ${e.message}", e)
+            null
+        } catch (e: Exception) {
+            AppLogger.e("PepperShaper: Exception getting delay histogram for handle=$handle: ${e.javaClass.simpleName}: ${e.message}", e)
+            null
+        }
+    }
+    
+    /**
+     * Create a bandwidth class, or change its rates live
+     * 
+     * Classes form a tree; handles placed in a class share its rate, borrowing
+     * spare rate from the classes above up to their ceilings.
+     * 
+     * @param classId Class id, > 0
+     * @param parentId Parent class id, 0 for a top-level class
+     */
+    fun setClass(classId: Int, parentId: Int = 0, params: PepperClassParams): Boolean {
+        if (classId <= 0 || parentId < 0 || !isInitialized) {
+            return false
+        }
+        
+        return try {
+            val result = nativeSetClass(classId, parentId, params)
+            if (!result) {
+                AppLogger.w("PepperShaper: Set class returned false for class=$classId parent=$parentId")
+            }
+            result
+        } catch (e: UnsatisfiedLinkError) {
+            AppLogger.e("PepperShaper: Native library not loaded: ${e.message}", e)
+            false
+        } catch (e: Exception) {
+            AppLogger.e("PepperShaper: Exception setting class=$classId: ${e.javaClass.simpleName}: ${e.message}", e)
+            false
+        }
+    }
+    
+    /**
+     * Remove a class with its subclasses; handles in them are no longer shaped by class
+     */
+    fun removeClass(classId: Int): Boolean {
+        if (classId <= 0 || !isInitialized) {
+            return false
+        }
+        
+        return try {
+            nativeRemoveClass(classId)
+        } catch (e: UnsatisfiedLinkError) {
+            AppLogger.e("PepperShaper: Native library not loaded: ${e.message}", e)
+            false
+        } catch (e: Exception) {
+            AppLogger.e("PepperShaper: Exception removing class=$classId: ${e.javaClass.simpleName}: ${e.message}", e)
+            false
+        }
+    }
+    
+    /**
+     * Place an attached shaper in a class, or update its rates there
+     * 
+     * The handle's own pacing still applies on top of the class budget.
+     */
+    fun assignToClass(
+        handle: Long,
+        classId: Int,
+        params: PepperClassParams = PepperClassParams(rateBps = 0)
+    ): Boolean {
+        if (handle <= 0 || classId <= 0 || !isInitialized) {
+            return false
+        }
+        
+        return try {
+            val result = nativeAssignClass(handle, classId, params)
+            if (!result) {
+                AppLogger.w("PepperShaper: Assign class returned false for handle=$handle class=$classId")
+            }
+            result
+        } catch (e: UnsatisfiedLinkError) {
+            AppLogger.e("PepperShaper: Native library not loaded: ${e.message}", e)
+            false
+        } catch (e: Exception) {
+            AppLogger.e("PepperShaper: Exception assigning handle=$handle: ${e.javaClass.simpleName}: ${e.message}", e)
+            false
+        }
+    }
+    
+    /**
+     * Take an attached shaper out of its class
+     */
+    fun unassignFromClass(handle: Long): Boolean {
+        if (handle <= 0 || !isInitialized) {
+            return false
+        }
+        
+        return try {
+            nativeUnassignClass(handle)
+        } catch (e: UnsatisfiedLinkError) {
+            AppLogger.e("PepperShaper: Native library not loaded: ${e.message}", e)
+            false
+        } catch (e: Exception) {
+            AppLogger.e("PepperShaper: Exception unassigning handle=$handle: ${e.javaClass.simpleName}: ${e.message}", e)
+            false
+        }
+    }
+    
+    /**
+     * Get statistics for a class
+     * 
+     * @return Statistics or null if the class is unknown
+     */
+    fun getClassStats(classId: Int): ClassStats? {
+        if (classId <= 0 || !isInitialized) {
+            return null
+        }
+        
+        return try {
+            val values = nativeGetClassStats(classId) ?: return null
+            ClassStats(
+                rateBps = values[0],
+                ceilBps = values[1],
+                sentBytes = values[2],
+                borrowedBytes = values[3],
+                delayed = values[4],
+                children = values[5].toInt()
+            )
+        } catch (e: UnsatisfiedLinkError) {
+            AppLogger.e("PepperShaper: Native library not loaded: ${e.message}", e)
+            null
+        } catch (e: Exception) {
+            AppLogger.e("PepperShaper: Exception getting stats for class=$classId: ${e.javaClass.simpleName}: ${e.message}", e)
+            null
+        }
+    }
+    
     /**
      * Cleanup and shutdown
      */
@@ -191,6 +408,68 @@ object PepperShaper {
         val queueDepth: Int
     )
     
+    /**
+     * Per-handle pump counters
+     * 
+     * A chunk is one datagram in UDP/TUN mode, or one paced slice of the stream in TCP mode.
+     */
+    data class HandleStats(
+        val queuedBytes: Long,
+        val sentBytes: Long,
+        val sentChunks: Long,
+        val delayed: Long,      // Chunks held back by the pacer
+        val dropped: Long,      // Datagrams dropped (queue full or send error)
+        val queueDepth: Int,    // Bytes waiting in the queue
+        val rttUs: Long,
+        val lossRate: Float,
+        val deliveryRateBps: Long,  // 0 if not measured
+        val pacingRateBps: Long,    // Estimated in ADAPTIVE mode, else the target rate
+        val aqmDropped: Long,       // Datagrams CoDel dropped to bound queueing delay
+        val ecnMarked: Long         // Chunks CoDel marked instead (ECN CE on TUN, read-ahead cut on TCP)
+    )
+    
+    /**
+     * Queue delay histogram: [counts] has one entry per bucket of
+     * [BUCKET_BOUNDS_MS], plus a last one for longer delays
+     */
+    data class QueueDelayHistogram(
+        val counts: List<Long>
+    ) {
+        val total: Long get() = counts.sum()
+        
+        /**
+         * Upper bound in ms of the bucket holding the given percentile (0..100),
+         * or null if that is the open-ended last bucket or nothing was recorded
+         */
+        fun percentileBoundMs(percentile: Double): Long? {
+            val total = total
+            if (total == 0L) return null
+            val rank = (total * percentile / 100.0).coerceAtLeast(1.0)
+            var seen = 0L
+            counts.forEachIndexed { i, count ->
+                seen += count
+                if (seen >= rank) return BUCKET_BOUNDS_MS.getOrNull(i)
+            }
+            return null
+        }
+        
+        companion object {
+            val BUCKET_BOUNDS_MS = listOf(1L, 2L, 5L, 10L, 20L, 50L, 100L, 200L, 500L, 1000L)
+        }
+    }
+    
+    /**
+     * Bandwidth class counters, including traffic of its subclasses
+     */
+    data class ClassStats(
+        val rateBps: Long,
+        val ceilBps: Long,
+        val sentBytes: Long,
+        val borrowedBytes: Long,   // Sent above the guaranteed rate
+        val delayed: Long,         // Sends held back for the class budget
+        val children: Int          // Subclasses and handles
+    )
+    
     // Native methods
     private external fun nativeInit()
     private external fun nativeAttach(
@@ -201,6 +480,19 @@ object PepperShaper {
     ): Long
     private external fun nativeDetach(handle: Long): Boolean
     private external fun nativeUpdateParams(handle: Long, params: PepperParams): Boolean
+    private external fun nativeReportMetrics(
+        handle: Long,
+        lossRate: Float,
+        rttUs: Long,
+        deliveryRateBps: Long
+    ): Boolean
+    private external fun nativeGetHandleStats(handle: Long): LongArray?
+    private external fun nativeGetQueueDelayHistogram(handle: Long): LongArray?
+    private external fun nativeSetClass(classId: Int, parentId: Int, params: PepperClassParams): Boolean
+    private external fun nativeRemoveClass(classId: Int): Boolean
+    private external fun nativeAssignClass(handle: Long, classId: Int, params: PepperClassParams): Boolean
+    private external fun nativeUnassignClass(handle: Long): Boolean
+    private external fun nativeGetClassStats(classId: Int): LongArray?
     private external fun nativeShutdown()
 }
 
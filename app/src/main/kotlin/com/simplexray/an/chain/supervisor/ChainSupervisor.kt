@@ -3,6 +3,7 @@ package com.simplexray.an.chain.supervisor
 import android.content.Context
 import com.simplexray.an.common.AppLogger
 import com.simplexray.an.chain.pepper.PepperShaper
+import com.simplexray.an.quiche.ConnectResult
 import com.simplexray.an.quiche.QuicheClient
 import com.simplexray.an.quiche.QuicheTunForwarder
 import com.simplexray.an.quiche.CongestionControl
@@ -22,6 +23,9 @@ import kotlinx.coroutines.sync.withLock
 import java.io.File
 import java.util.concurrent.atomic.AtomicLong
 
+// TLS key exchange groups exported by the QUICME client between runs
+private const val QUICHE_SESSION_STATE_FILE = "quiche_session_state"
+
 /**
  * Chain Supervisor: Orchestrates the full tunneling stack
  *
@@ -326,6 +330,7 @@ class ChainSupervisor(private val context: Context) {
             // Stop QUICME client
             quicheClient?.let { client ->
                 val quicheClientResult = runCatching {
+                    saveQuicheSessionState()
                     client.close()
                 }
                 stopResults.add(quicheClientResult.map { })
@@ -460,6 +465,28 @@ class ChainSupervisor(private val context: Context) {
         }
     }
     
+    private fun quicheSessionStateFile() = File(context.filesDir, QUICHE_SESSION_STATE_FILE)
+
+    private fun saveQuicheSessionState() {
+        try {
+            val state = QuicheClient.exportSessionState() ?: return
+            quicheSessionStateFile().writeBytes(state)
+        } catch (e: Exception) {
+            AppLogger.w("ChainSupervisor: Failed to save QUICME session state: ${e.message}")
+        }
+    }
+
+    private fun restoreQuicheSessionState() {
+        try {
+            val file = quicheSessionStateFile()
+            if (file.exists()) {
+                QuicheClient.importSessionState(file.readBytes())
+            }
+        } catch (e: Exception) {
+            AppLogger.w("ChainSupervisor: Failed to restore QUICME session state: ${e.message}")
+        }
+    }
+
     /**
      * Attach QUICME to TUN file descriptor for QUIC tunneling
      *
@@ -485,6 +512,9 @@ class ChainSupervisor(private val context: Context) {
 
             AppLogger.i("ChainSupervisor: Attaching QUICME to TUN FD $tunFd ($serverHost:$serverPort) - QUICME is part of the chain (TUN → QUICME → Xray)")
 
+            // Restore the server's key exchange group (tickets do not survive a restart)
+            restoreQuicheSessionState()
+
             // Create QUICME client
             val client = QuicheClient.create(
                 serverHost = serverHost,
@@ -501,11 +531,20 @@ class ChainSupervisor(private val context: Context) {
             }
 
             // Connect QUICME client
-            if (!client.connect()) {
-                AppLogger.e("ChainSupervisor: Failed to connect QUICME client")
-                client.close()
-                updateLayerStatus("quicme", false, "Failed to connect")
-                return false
+            when (client.connect()) {
+                ConnectResult.CONNECTED -> Unit
+                ConnectResult.CERTIFICATE_INVALID -> {
+                    AppLogger.e("ChainSupervisor: QUICME server certificate rejected")
+                    client.close()
+                    updateLayerStatus("quicme", false, "Server certificate rejected")
+                    return false
+                }
+                ConnectResult.NETWORK_ERROR -> {
+                    AppLogger.e("ChainSupervisor: Failed to connect QUICME client")
+                    client.close()
+                    updateLayerStatus("quicme", false, "Failed to connect")
+                    return false
+                }
             }
 
             quicheClient = client
@@ -553,61 +592,6 @@ class ChainSupervisor(private val context: Context) {
         }
     }
 
-    /**
-     * Attach PepperShaper to TUN file descriptor for traffic shaping
-     *
-     * @param tunFd The TUN interface file descriptor
-     * @return true if successfully attached, false otherwise
-     */
-    fun attachPepperToTunFd(tunFd: Int): Boolean {
-        return try {
-            // Check if PepperShaper is initialized
-            if (currentConfig?.pepperParams == null) {
-                AppLogger.w("ChainSupervisor: Cannot attach PepperShaper - no pepper params configured")
-                return false
-            }
-
-            // Don't re-attach if already attached
-            pepperHandle?.let { handle ->
-                if (handle > 0) {
-                    AppLogger.i("ChainSupervisor: PepperShaper already attached (handle=$handle)")
-                    return true
-                }
-            }
-
-            AppLogger.i("ChainSupervisor: Attaching PepperShaper to TUN FD $tunFd")
-
-            // Safe access to pepperParams (already validated above)
-            val pepperParams = currentConfig?.pepperParams
-            if (pepperParams == null) {
-                AppLogger.e("ChainSupervisor: pepperParams is null after validation check")
-                updateLayerStatus("pepper", false, "pepperParams is null")
-                return false
-            }
-
-            val handle = PepperShaper.attach(
-                fdPair = Pair(tunFd, tunFd),
-                mode = PepperShaper.SocketMode.TUN,
-                params = pepperParams
-            )
-
-            if (handle != null && handle > 0) {
-                pepperHandle = handle
-                updateLayerStatus("pepper", true, null)
-                AppLogger.i("ChainSupervisor: PepperShaper attached successfully (handle=$handle)")
-                true
-            } else {
-                AppLogger.w("ChainSupervisor: PepperShaper.attach() returned invalid handle")
-                updateLayerStatus("pepper", false, "Invalid handle returned")
-                false
-            }
-        } catch (e: Exception) {
-            AppLogger.e("ChainSupervisor: Failed to attach PepperShaper to TUN FD: ${e.message}", e)
-            updateLayerStatus("pepper", false, e.message)
-            false
-        }
-    }
-
     /**
      * Shutdown and cleanup all resources
      * Prevents memory leaks by cancelling all coroutines
@@ -281,6 +281,8 @@ class PerformanceManager private constructor(context: Context) {
     
     /**
      * Connect pooled socket by slot index
+     * @param host hostname (resolved via [setDnsUpstreams]) or IPv4/IPv6 literal
+     * @return 0 if connected or connecting (see [waitPooledSocketConnect]), -1 on error
      */
     fun connectPooledSocket(poolType: PoolType, slotIndex: Int, host: String, port: Int): Int {
         return nativeConnectPooledSocket(poolType.value, slotIndex, host, port)
@@ -288,11 +290,56 @@ class PerformanceManager private constructor(context: Context) {
     
     /**
      * Connect pooled socket by file descriptor (alternative API)
+     * @param host hostname (resolved via [setDnsUpstreams]) or IPv4/IPv6 literal
      */
     fun connectPooledSocketByFd(poolType: PoolType, fd: Int, host: String, port: Int): Int {
         return nativeConnectPooledSocketByFd(poolType.value, fd, host, port)
     }
     
+    /**
+     * Wait for a pooled socket's non-blocking connect to finish
+     * Blocks up to timeoutMs; call off the main thread. 0 only queries the state.
+     * On failure the next resolved address (IPv6/IPv4) is tried within the timeout.
+     * @return 0 connected, 1 still connecting, negative errno on failure
+     */
+    fun waitPooledSocketConnect(poolType: PoolType, slotIndex: Int, timeoutMs: Int): Int {
+        return nativeWaitPooledSocketConnect(poolType.value, slotIndex, timeoutMs)
+    }
+    
+    /**
+     * Wait for a pooled socket's connect by file descriptor (alternative API)
+     */
+    fun waitPooledSocketConnectByFd(poolType: PoolType, fd: Int, timeoutMs: Int): Int {
+        return nativeWaitPooledSocketConnectByFd(poolType.value, fd, timeoutMs)
+    }
+    
+    /**
+     * Set limits for free pooled sockets, enforced on lease and by a background reaper
+     * Sockets the peer closed are always evicted.
+     * @param idleTimeoutMs close after this long unused (0 = no limit)
+     * @param maxLifetimeMs close this long after connecting (0 = no limit)
+     */
+    fun setConnectionPoolLimits(idleTimeoutMs: Long = 60_000, maxLifetimeMs: Long = 600_000): Int {
+        return nativeSetConnectionPoolLimits(idleTimeoutMs, maxLifetimeMs)
+    }
+    
+    /**
+     * Get connection pool statistics
+     */
+    fun getConnectionPoolStats(poolType: PoolType): ConnectionPoolStats? {
+        val values = nativeGetConnectionPoolStats(poolType.value) ?: return null
+        
+        return ConnectionPoolStats(
+            hits = values[0],
+            misses = values[1],
+            evictedIdle = values[2],
+            evictedLifetime = values[3],
+            evictedUnhealthy = values[4],
+            inUse = values[5].toInt(),
+            idleConnected = values[6].toInt()
+        )
+    }
+    
     /**
      * Return socket to pool by slot index
      */
@@ -307,6 +354,130 @@ class PerformanceManager private constructor(context: Context) {
         nativeReturnPooledSocketByFd(poolType.value, fd)
     }
     
+    // ==================== DNS Resolver ====================
+    
+    /**
+     * Set DNS upstreams used to resolve pooled connect hosts, tried in order
+     * @param upstreams "https://host[:port][/path]" (DoH) or "quic://host[:port]" (DoQ);
+     *                  empty uses the system resolver
+     * @return number of upstreams configured, -1 if any is invalid (old list kept)
+     */
+    fun setDnsUpstreams(upstreams: List<String>): Int {
+        return nativeSetDnsUpstreams(upstreams.toTypedArray())
+    }
+    
+    /**
+     * Resolve a hostname to IP addresses (IPv6/IPv4 alternating)
+     * @return addresses, empty on failure
+     */
+    fun resolveHost(host: String): List<String> {
+        return nativeResolveHost(host)?.toList() ?: emptyList()
+    }
+    
+    /**
+     * Drop cached DNS answers
+     */
+    fun clearDnsCache() {
+        nativeClearDnsCache()
+    }
+    
+    // ==================== Warm Connection Pool ====================
+    
+    /**
+     * Keep spare connections to host:port ready, optionally with TLS already established
+     * @param alpn ALPN protocol offered in the handshake, also part of the pool key (null = none)
+     * @param spares number of idle connections kept ready
+     * @param tls complete the TLS handshake ahead of time (WebPKI roots, hostname checked)
+     * @param idleTimeoutMs close idle connections after this long
+     * @param spkiPins SHA-256 digests of trusted SubjectPublicKeyInfo (32 bytes each), enforced with TLS
+     * @return 0 on success, -1 on error
+     */
+    fun configureWarmPool(
+        host: String,
+        port: Int,
+        alpn: String? = null,
+        spares: Int = 2,
+        tls: Boolean = true,
+        idleTimeoutMs: Long = 60_000,
+        spkiPins: List<ByteArray> = emptyList()
+    ): Int {
+        if (spkiPins.any { it.size != 32 }) {
+            AppLogger.e("$TAG: SPKI pins must be 32-byte SHA-256 digests")
+            return -1
+        }
+        val pins = if (spkiPins.isEmpty()) null else spkiPins.reduce { acc, pin -> acc + pin }
+        return nativeWarmPoolConfigure(host, port, alpn, spares, tls, idleTimeoutMs, pins)
+    }
+    
+    /**
+     * Stop keeping spare connections for host:port:alpn
+     */
+    fun removeWarmPool(host: String, port: Int, alpn: String? = null) {
+        nativeWarmPoolRemove(host, port, alpn)
+    }
+    
+    /**
+     * Lease a ready connection for host:port:alpn, connecting on demand if none is idle
+     * @return lease handle, 0 on failure; pass to [returnWarmLease] when done
+     */
+    fun leaseWarmConnection(host: String, port: Int, alpn: String? = null, timeoutMs: Int = 5000): Long {
+        return nativeWarmPoolLease(host, port, alpn, timeoutMs)
+    }
+    
+    /**
+     * Socket fd of a lease (owned by the lease, do not close)
+     */
+    fun getWarmLeaseFd(handle: Long): Int {
+        return nativeWarmLeaseFd(handle)
+    }
+    
+    /**
+     * ALPN protocol negotiated for a lease (null without TLS or ALPN)
+     */
+    fun getWarmLeaseAlpn(handle: Long): String? {
+        return nativeWarmLeaseAlpn(handle)
+    }
+    
+    /**
+     * Write through a lease (encrypted if TLS was pre-established)
+     * @return bytes written, -1 on error
+     */
+    fun writeWarmLease(handle: Long, data: ByteArray, offset: Int = 0, length: Int = data.size - offset): Int {
+        return nativeWarmLeaseWrite(handle, data, offset, length)
+    }
+    
+    /**
+     * Read through a lease, blocking
+     * @return bytes read, 0 at end of stream, -1 on error
+     */
+    fun readWarmLease(handle: Long, buffer: ByteArray, offset: Int = 0, length: Int = buffer.size - offset): Int {
+        return nativeWarmLeaseRead(handle, buffer, offset, length)
+    }
+    
+    /**
+     * Return a lease to its pool (closed if not reusable); the handle is invalid afterwards
+     */
+    fun returnWarmLease(handle: Long, reusable: Boolean = true) {
+        nativeWarmPoolReturn(handle, reusable)
+    }
+    
+    /**
+     * Get warm pool statistics for host:port:alpn
+     */
+    fun getWarmPoolStats(host: String, port: Int, alpn: String? = null): WarmPoolStats? {
+        val values = nativeWarmPoolGetStats(host, port, alpn) ?: return null
+        
+        return WarmPoolStats(
+            idle = values[0].toInt(),
+            pending = values[1].toInt(),
+            hits = values[2],
+            misses = values[3],
+            connects = values[4],
+            connectFailures = values[5],
+            evicted = values[6]
+        )
+    }
+    
     // ==================== Crypto Acceleration ====================
     
     /**
@@ -583,10 +754,33 @@ class PerformanceManager private constructor(context: Context) {
     private external fun nativeGetPooledSocketSlotIndex(poolType: Int, fd: Int): Int
     private external fun nativeConnectPooledSocket(poolType: Int, slotIndex: Int, host: String, port: Int): Int
     private external fun nativeConnectPooledSocketByFd(poolType: Int, fd: Int, host: String, port: Int): Int
+    private external fun nativeWaitPooledSocketConnect(poolType: Int, slotIndex: Int, timeoutMs: Int): Int
+    private external fun nativeWaitPooledSocketConnectByFd(poolType: Int, fd: Int, timeoutMs: Int): Int
     private external fun nativeReturnPooledSocket(poolType: Int, slotIndex: Int)
     private external fun nativeReturnPooledSocketByFd(poolType: Int, fd: Int)
+    private external fun nativeSetConnectionPoolLimits(idleTimeoutMs: Long, maxLifetimeMs: Long): Int
+    private external fun nativeGetConnectionPoolStats(poolType: Int): LongArray?
     private external fun nativeDestroyConnectionPool()
     
+    // DNS Resolver
+    private external fun nativeSetDnsUpstreams(upstreams: Array<String>): Int
+    private external fun nativeResolveHost(host: String): Array<String>?
+    private external fun nativeClearDnsCache()
+    
+    // Warm Connection Pool
+    private external fun nativeWarmPoolConfigure(
+        host: String, port: Int, alpn: String?, spares: Int, tls: Boolean, idleTimeoutMs: Long,
+        spkiPins: ByteArray?
+    ): Int
+    private external fun nativeWarmPoolRemove(host: String, port: Int, alpn: String?)
+    private external fun nativeWarmPoolLease(host: String, port: Int, alpn: String?, timeoutMs: Int): Long
+    private external fun nativeWarmLeaseFd(handle: Long): Int
+    private external fun nativeWarmLeaseAlpn(handle: Long): String?
+    private external fun nativeWarmLeaseWrite(handle: Long, data: ByteArray, offset: Int, length: Int): Int
+    private external fun nativeWarmLeaseRead(handle: Long, buffer: ByteArray, offset: Int, length: Int): Int
+    private external fun nativeWarmPoolReturn(handle: Long, reusable: Boolean)
+    private external fun nativeWarmPoolGetStats(host: String, port: Int, alpn: String?): LongArray?
+    
     // Crypto
     private external fun nativeHasNEON(): Boolean
     private external fun nativeHasCryptoExtensions(): Boolean
@@ -661,3 +855,32 @@ class PerformanceManager private constructor(context: Context) {
     private external fun nativeDestroyBatchMapper(handle: Long)
 }
 
+/**
+ * Connection pool statistics for one [PerformanceManager.PoolType]
+ *
+ * A hit is a lease that got a still-connected socket back.
+ */
+data class ConnectionPoolStats(
+    val hits: Long,
+    val misses: Long,
+    val evictedIdle: Long,
+    val evictedLifetime: Long,
+    val evictedUnhealthy: Long,     // Peer closed/reset, unread data or failed connect
+    val inUse: Int,
+    val idleConnected: Int
+)
+
+/**
+ * Warm connection pool statistics for one host:port:alpn
+ *
+ * A hit is a lease served from a spare connection.
+ */
+data class WarmPoolStats(
+    val idle: Int,
+    val pending: Int,               // Background connects in flight
+    val hits: Long,
+    val misses: Long,
+    val connects: Long,
+    val connectFailures: Long,
+    val evicted: Long               // Idle timeout, peer close or unexpected data
+)
@@ -13,6 +13,7 @@ class QuicheClient private constructor(
 
     companion object {
         private const val TAG = "QuicheClient"
+        private const val CONNECT_CERT_INVALID = -2
 
         init {
             try {
@@ -25,20 +26,31 @@ class QuicheClient private constructor(
 
         /**
          * Create QUIC client
+         *
+         * @param connectionCount Parallel connections to keep open (at least one per server, at most 16)
+         * @param scheduler How new proxied flows pick a connection when there are several
+         * @param additionalServers More servers as "host:port" ("[v6]:port"),
+         *                          connections are spread over them and serverHost (16 servers at most)
          */
         fun create(
             serverHost: String,
             serverPort: Int,
             congestionControl: CongestionControl = CongestionControl.BBR2,
             enableZeroCopy: Boolean = true,
-            cpuAffinity: CpuAffinity = CpuAffinity.BIG_CORES
+            cpuAffinity: CpuAffinity = CpuAffinity.BIG_CORES,
+            connectionCount: Int = 1,
+            scheduler: ConnectionScheduler = ConnectionScheduler.ROUND_ROBIN,
+            additionalServers: List<String> = emptyList()
         ): QuicheClient? {
             val handle = nativeCreate(
                 serverHost,
                 serverPort,
                 congestionControl.ordinal,
                 enableZeroCopy,
-                cpuAffinity.ordinal
+                cpuAffinity.ordinal,
+                connectionCount,
+                scheduler.ordinal,
+                additionalServers.toTypedArray()
             )
 
             if (handle == 0L) {
@@ -49,6 +61,65 @@ class QuicheClient private constructor(
             return QuicheClient(handle)
         }
 
+        /**
+         * Use addresses resolved by the app for [host] (e.g. outside the VPN)
+         *
+         * Applies to every client's next connect; all addresses are raced
+         * (happy eyeballs). An empty list goes back to the system resolver.
+         *
+         * @param addresses IP literals
+         * @param ttlMs How long to use them (0 = until replaced or cleared)
+         */
+        fun setResolvedAddresses(host: String, addresses: List<String>, ttlMs: Long = 0): Boolean {
+            val result = nativeSetResolvedAddresses(host, addresses.toTypedArray(), ttlMs)
+            if (result != 0) {
+                AppLogger.e("$TAG: Invalid resolved addresses for $host: $addresses")
+                return false
+            }
+            return true
+        }
+
+        /**
+         * Export each server's TLS key exchange group, to persist across restarts
+         *
+         * Session tickets are NOT included: the native TLS stack cannot encode
+         * them, so session resumption and 0-RTT do not survive a process
+         * restart. The first connect after a restart is a full handshake; the
+         * restored group only saves a HelloRetryRequest round trip.
+         */
+        fun exportSessionState(): ByteArray? {
+            return try {
+                nativeExportSessionState()
+            } catch (e: Exception) {
+                AppLogger.e("$TAG: Error exporting session state", e)
+                null
+            }
+        }
+
+        /**
+         * Restore key exchange groups from [exportSessionState], before the first connect
+         *
+         * @return true if the state was accepted
+         */
+        fun importSessionState(state: ByteArray): Boolean {
+            if (state.isEmpty()) {
+                AppLogger.w("$TAG: Empty session state, ignoring")
+                return false
+            }
+            return try {
+                val restored = nativeImportSessionState(state)
+                if (restored < 0) {
+                    AppLogger.e("$TAG: Invalid session state")
+                    return false
+                }
+                AppLogger.d("$TAG: Restored session state for $restored servers")
+                true
+            } catch (e: Exception) {
+                AppLogger.e("$TAG: Error importing session state", e)
+                false
+            }
+        }
+
         // Remove @JvmStatic to fix JNI name mangling issue
         // JNI will look for: Java_com_simplexray_an_quiche_QuicheClient_00024Companion_nativeCreate
         private external fun nativeCreate(
@@ -56,11 +127,33 @@ class QuicheClient private constructor(
             serverPort: Int,
             congestionControl: Int,
             enableZeroCopy: Boolean,
-            cpuAffinity: Int
+            cpuAffinity: Int,
+            connectionCount: Int,
+            scheduler: Int,
+            additionalServers: Array<String>
         ): Long
 
         private external fun nativeConnect(handle: Long): Int
 
+        private external fun nativeConnectAsync(handle: Long, callback: QuicheCallback): Int
+
+        private external fun nativeDisconnectAsync(handle: Long, callback: QuicheCallback): Int
+
+        private external fun nativeSetTlsOptions(
+            handle: Long,
+            caBundlePem: String?,
+            spkiPins: ByteArray?,
+            allowInsecure: Boolean
+        ): Int
+
+        private external fun nativeSetReconnectPolicy(
+            handle: Long,
+            enabled: Boolean,
+            maxAttempts: Int,
+            baseDelayMs: Long,
+            maxDelayMs: Long
+        ): Int
+
         private external fun nativeDisconnect(handle: Long)
 
         private external fun nativeDestroy(handle: Long)
@@ -70,22 +163,101 @@ class QuicheClient private constructor(
         private external fun nativeSend(handle: Long, data: ByteArray): Int
 
         private external fun nativeGetMetrics(handle: Long): DoubleArray?
+
+        private external fun nativeGetConnectionMetrics(handle: Long, index: Int): DoubleArray?
+
+        private external fun nativeGetConfigWarnings(handle: Long): Array<String>?
+
+        private external fun nativeSetResolvedAddresses(
+            host: String,
+            addresses: Array<String>,
+            ttlMs: Long
+        ): Int
+
+        private external fun nativeExportSessionState(): ByteArray?
+
+        private external fun nativeImportSessionState(state: ByteArray): Int
+
+        private external fun nativeRebind(
+            handle: Long,
+            localAddress: String?,
+            localPort: Int,
+            socketFd: Int
+        ): Int
     }
 
     /**
-     * Connect to QUIC server
+     * Configure server certificate validation (applied on next connect)
+     *
+     * @param caBundlePem Extra trusted roots in PEM format, added to the WebPKI roots
+     * @param spkiPins SHA-256 digests of trusted SubjectPublicKeyInfo (32 bytes each)
+     * @param allowInsecure Disable certificate verification entirely (testing only)
      */
-    fun connect(): Boolean {
-        val result = nativeConnect(handle)
-        if (result != 0) {
-            AppLogger.e("$TAG: Failed to connect: $result")
+    fun setTlsOptions(
+        caBundlePem: String? = null,
+        spkiPins: List<ByteArray> = emptyList(),
+        allowInsecure: Boolean = false
+    ): Boolean {
+        require(spkiPins.all { it.size == 32 }) { "SPKI pins must be 32-byte SHA-256 digests" }
+        val pins = if (spkiPins.isEmpty()) null else spkiPins.reduce { acc, pin -> acc + pin }
+        return nativeSetTlsOptions(handle, caBundlePem, pins, allowInsecure) == 0
+    }
+
+    /**
+     * Configure automatic reconnect after the connection is lost
+     *
+     * Delays grow exponentially from [baseDelayMs] up to [maxDelayMs], with jitter.
+     *
+     * @param maxAttempts Attempts before giving up (0 retries forever)
+     */
+    fun setReconnectPolicy(
+        enabled: Boolean = true,
+        maxAttempts: Int = 10,
+        baseDelayMs: Long = 500,
+        maxDelayMs: Long = 30_000
+    ): Boolean {
+        return nativeSetReconnectPolicy(handle, enabled, maxAttempts, baseDelayMs, maxDelayMs) == 0
+    }
+
+    /**
+     * Connect to QUIC server (blocks until the handshake finishes)
+     */
+    fun connect(): ConnectResult {
+        return toConnectResult(nativeConnect(handle))
+    }
+
+    /**
+     * Connect without blocking the caller
+     *
+     * @param onResult Invoked once with the outcome, on a native thread
+     * @return false if the attempt could not be started
+     */
+    fun connectAsync(onResult: (ConnectResult) -> Unit): Boolean {
+        val started = nativeConnectAsync(handle) { code -> onResult(toConnectResult(code)) }
+        if (started != 0) {
+            AppLogger.e("$TAG: Failed to start connect: $started")
             return false
         }
-
-        AppLogger.i("$TAG: Connected to QUIC server")
         return true
     }
 
+    private fun toConnectResult(result: Int): ConnectResult {
+        return when (result) {
+            0 -> {
+                AppLogger.i("$TAG: Connected to QUIC server")
+                ConnectResult.CONNECTED
+            }
+            CONNECT_CERT_INVALID -> {
+                AppLogger.e("$TAG: Server certificate rejected")
+                ConnectResult.CERTIFICATE_INVALID
+            }
+            else -> {
+                AppLogger.e("$TAG: Failed to connect: $result")
+                ConnectResult.NETWORK_ERROR
+            }
+        }
+    }
+
     /**
      * Disconnect from server
      */
@@ -94,6 +266,19 @@ class QuicheClient private constructor(
         AppLogger.i("$TAG: Disconnected from server")
     }
 
+    /**
+     * Disconnect without blocking the caller
+     *
+     * @param onDone Invoked once the connection is closed, on a native thread
+     */
+    fun disconnectAsync(onDone: () -> Unit = {}): Boolean {
+        val started = nativeDisconnectAsync(handle) {
+            AppLogger.i("$TAG: Disconnected from server")
+            onDone()
+        }
+        return started == 0
+    }
+
     /**
      * Check if connected
      */
@@ -109,11 +294,23 @@ class QuicheClient private constructor(
     }
 
     /**
-     * Get metrics
+     * Get metrics, summed over all pooled connections
      */
     fun getMetrics(): QuicMetrics? {
-        val values = nativeGetMetrics(handle) ?: return null
+        return nativeGetMetrics(handle)?.let(::toQuicMetrics)
+    }
 
+    /**
+     * Get metrics of each pooled connection
+     */
+    fun getConnectionMetrics(): List<QuicMetrics> {
+        val count = getMetrics()?.connections ?: return emptyList()
+        return (0 until count).mapNotNull { index ->
+            nativeGetConnectionMetrics(handle, index)?.let(::toQuicMetrics)
+        }
+    }
+
+    private fun toQuicMetrics(values: DoubleArray): QuicMetrics {
         return QuicMetrics(
             throughputMbps = values[0],
             rttUs = values[1].toLong(),
@@ -122,10 +319,54 @@ class QuicheClient private constructor(
             bytesReceived = values[4].toLong(),
             packetsSent = values[5].toLong(),
             packetsReceived = values[6].toLong(),
-            cwnd = values[7].toLong()
+            cwnd = values[7].toLong(),
+            datagramsSent = values[8].toLong(),
+            datagramsReceived = values[9].toLong(),
+            datagramsOversized = values[10].toLong(),
+            minRttUs = values[11].toLong(),
+            packetsLost = values[12].toLong(),
+            handshakeDurationUs = values[13].toLong(),
+            isInEarlyData = values[14] != 0.0,
+            zeroRttAttempts = values[15].toLong(),
+            zeroRttAccepted = values[16].toLong(),
+            zeroRttRejected = values[17].toLong(),
+            migrationCount = values[18].toLong(),
+            lastMigrationTimestampMs = values[19].toLong(),
+            isReconnecting = values[20] != 0.0,
+            reconnectAttempts = values[21].toLong(),
+            reconnectCount = values[22].toLong(),
+            connections = values[23].toInt(),
+            connectionsEstablished = values[24].toInt()
         )
     }
 
+    /**
+     * Migrate the connection to a new local socket after a network change
+     *
+     * @param localAddress Local IP to bind (null binds the wildcard address)
+     * @param localPort Local port (0 picks an ephemeral port)
+     * @param socketFd Already-bound IPv6 (dual-stack) UDP socket to adopt, e.g. from
+     *   Network.bindSocket + ParcelFileDescriptor.detachFd(); ownership moves to native code,
+     *   which closes it if it cannot be used
+     */
+    fun rebind(localAddress: String? = null, localPort: Int = 0, socketFd: Int = -1): Boolean {
+        val result = nativeRebind(handle, localAddress, localPort, socketFd)
+        if (result != 0) {
+            AppLogger.e("$TAG: Failed to rebind: $result")
+            return false
+        }
+
+        AppLogger.i("$TAG: Connection migrated to new socket")
+        return true
+    }
+
+    /**
+     * Config values the native transport could not honor (downgraded or ignored)
+     */
+    fun getConfigWarnings(): List<String> {
+        return nativeGetConfigWarnings(handle)?.toList() ?: emptyList()
+    }
+
     /**
      * Get native handle (for TUN forwarder)
      */
@@ -138,6 +379,22 @@ class QuicheClient private constructor(
     }
 }
 
+/**
+ * Result callback for asynchronous native operations (invoked on a native thread)
+ */
+fun interface QuicheCallback {
+    fun onResult(code: Int)
+}
+
+/**
+ * Outcome of [QuicheClient.connect]
+ */
+enum class ConnectResult {
+    CONNECTED,
+    CERTIFICATE_INVALID,
+    NETWORK_ERROR
+}
+
 /**
  * Congestion control algorithms
  */
@@ -145,7 +402,7 @@ enum class CongestionControl {
     RENO,
     CUBIC,
     BBR,
-    BBR2  // Recommended for mobile
+    BBR2  // Not supported natively, downgraded to BBR (see getConfigWarnings)
 }
 
 /**
@@ -158,6 +415,15 @@ enum class CpuAffinity {
     CUSTOM
 }
 
+/**
+ * How proxied flows are spread over pooled connections
+ */
+enum class ConnectionScheduler {
+    ROUND_ROBIN,
+    LEAST_RTT,
+    LEAST_INFLIGHT  // Fewest active flows
+}
+
 /**
  * QUIC connection metrics
  */
@@ -169,11 +435,28 @@ data class QuicMetrics(
     val bytesReceived: Long,
     val packetsSent: Long,
     val packetsReceived: Long,
-    val cwnd: Long
+    val cwnd: Long,
+    val datagramsSent: Long,
+    val datagramsReceived: Long,
+    val datagramsOversized: Long,  // Dropped: larger than max DATAGRAM size
+    val minRttUs: Long,
+    val packetsLost: Long,
+    val handshakeDurationUs: Long,
+    val isInEarlyData: Boolean,     // 0-RTT sent, server verdict pending
+    val zeroRttAttempts: Long,
+    val zeroRttAccepted: Long,
+    val zeroRttRejected: Long,
+    val migrationCount: Long,
+    val lastMigrationTimestampMs: Long,
+    val isReconnecting: Boolean,
+    val reconnectAttempts: Long,
+    val reconnectCount: Long,      // Successful automatic reconnects
+    val connections: Int,          // Pooled connections (1 unless a pool is configured)
+    val connectionsEstablished: Int
 ) {
     override fun toString(): String {
         return "QuicMetrics(throughput=${"%.2f".format(throughputMbps)} Mbps, " +
-                "rtt=${rttUs / 1000} ms, " +
+                "rtt=${rttUs / 1000} ms (min ${minRttUs / 1000} ms), " +
                 "loss=${"%.3f".format(packetLossRate * 100)}%, " +
                 "sent=$bytesSent bytes, " +
                 "received=$bytesReceived bytes)"
@@ -0,0 +1,147 @@
+package com.simplexray.an.quiche
+
+import com.simplexray.an.common.AppLogger
+
+/**
+ * Local SOCKS5 / HTTP CONNECT Proxy
+ *
+ * Listens on a local port and tunnels each accepted connection
+ * over a QUIC stream of an existing client connection
+ */
+class QuicheLocalProxy private constructor(
+    private val handle: Long
+) : AutoCloseable {
+
+    companion object {
+        private const val TAG = "QuicheLocalProxy"
+
+        init {
+            try {
+                System.loadLibrary("quiche-client")
+            } catch (e: UnsatisfiedLinkError) {
+                AppLogger.e("$TAG: Failed to load QUICHE client library", e)
+            }
+        }
+
+        /**
+         * Start listening
+         *
+         * SOCKS5 (CONNECT, UDP ASSOCIATE, no auth) and HTTP CONNECT share the port.
+         *
+         * @param bindHost IP literal to listen on; keep loopback unless other apps need it
+         * @param port 0 picks a free port (see [port])
+         * @param maxConnections Connections beyond this are closed on accept
+         */
+        fun start(
+            quicClient: QuicheClient,
+            bindHost: String = "127.0.0.1",
+            port: Int = 1080,
+            maxConnections: Int = 1024
+        ): QuicheLocalProxy? {
+            val handle = nativeStart(
+                quicClient.getHandle(),
+                bindHost,
+                port,
+                maxConnections
+            )
+
+            if (handle == 0L) {
+                AppLogger.e("$TAG: Failed to start local proxy on $bindHost:$port")
+                return null
+            }
+
+            val proxy = QuicheLocalProxy(handle)
+            AppLogger.i("$TAG: Local proxy listening on $bindHost:${proxy.port}")
+            return proxy
+        }
+
+        // Remove @JvmStatic to fix JNI name mangling issue (same as QuicheClient)
+        private external fun nativeStart(
+            clientHandle: Long,
+            bindHost: String,
+            port: Int,
+            maxConnections: Int
+        ): Long
+
+        private external fun nativeStop(handle: Long)
+
+        private external fun nativeDestroy(handle: Long)
+
+        private external fun nativeGetPort(handle: Long): Int
+
+        private external fun nativeGetStats(handle: Long): LongArray?
+    }
+
+    /**
+     * Port actually bound
+     */
+    val port: Int
+        get() = nativeGetPort(handle)
+
+    /**
+     * Stop listening and close all proxied connections
+     */
+    fun stop() {
+        nativeStop(handle)
+        AppLogger.i("$TAG: Local proxy stopped")
+    }
+
+    /**
+     * Get statistics
+     */
+    fun getStats(): LocalProxyStats? {
+        val values = nativeGetStats(handle) ?: return null
+
+        return LocalProxyStats(
+            connectionsTotal = values[0],
+            connectionsActive = values[1],
+            connectionsRejected = values[2],
+            socksConnects = values[3],
+            socksUdpAssociates = values[4],
+            httpConnects = values[5],
+            handshakeErrors = values[6],
+            streamErrors = values[7],
+            bytesUp = values[8],
+            bytesDown = values[9],
+            udpDatagramsUp = values[10],
+            udpDatagramsDown = values[11]
+        )
+    }
+
+    override fun close() {
+        stop()
+        nativeDestroy(handle)
+        AppLogger.i("$TAG: Local proxy destroyed")
+    }
+}
+
+/**
+ * Local proxy statistics
+ *
+ * bytesUp/bytesDown are TCP payload counted when each connection ends.
+ */
+data class LocalProxyStats(
+    val connectionsTotal: Long,
+    val connectionsActive: Long,
+    val connectionsRejected: Long,  // Over maxConnections
+    val socksConnects: Long,
+    val socksUdpAssociates: Long,
+    val httpConnects: Long,
+    val handshakeErrors: Long,      // Malformed, unsupported or timed-out requests
+    val streamErrors: Long,         // QUIC stream open or relay failures
+    val bytesUp: Long,
+    val bytesDown: Long,
+    val udpDatagramsUp: Long,
+    val udpDatagramsDown: Long
+) {
+    override fun toString(): String {
+        return "LocalProxyStats(active=$connectionsActive, " +
+                "total=$connectionsTotal, " +
+                "socks=$socksConnects, " +
+                "udp=$socksUdpAssociates, " +
+                "http=$httpConnects, " +
+                "errors=${handshakeErrors + streamErrors}, " +
+                "up=$bytesUp B, " +
+                "down=$bytesDown B)"
+    }
+}
@@ -25,20 +25,33 @@ class QuicheTunForwarder private constructor(
 
         /**
          * Create TUN forwarder
+         *
+         * @param batchSize Most packets read from the TUN per wakeup
+         * @param tunMtu MTU the TUN interface was configured with (sizes packet buffers)
+         * @param useVnetHdr TUN was opened with IFF_VNET_HDR; enables TSO/checksum offload.
+         *                   VpnService TUNs are not, so leave false on Android.
+         * @param mode PACKET ships raw IP packets; FLOW terminates TCP/UDP locally
+         *             and proxies each flow (no packet-aware server needed)
          */
         fun create(
             tunFd: Int,
             quicClient: QuicheClient,
             batchSize: Int = 64,
             useGSO: Boolean = true,
-            useGRO: Boolean = true
+            useGRO: Boolean = true,
+            tunMtu: Int = 8500,
+            useVnetHdr: Boolean = false,
+            mode: ForwarderMode = ForwarderMode.PACKET
         ): QuicheTunForwarder? {
             val handle = nativeCreate(
                 tunFd,
                 quicClient.getHandle(),
                 batchSize,
                 useGSO,
-                useGRO
+                useGRO,
+                tunMtu,
+                useVnetHdr,
+                mode.ordinal
             )
 
             if (handle == 0L) {
@@ -55,7 +68,10 @@ class QuicheTunForwarder private constructor(
             clientHandle: Long,
             batchSize: Int,
             useGSO: Boolean,
-            useGRO: Boolean
+            useGRO: Boolean,
+            tunMtu: Int,
+            useVnetHdr: Boolean,
+            mode: Int
         ): Long
 
         private external fun nativeStart(handle: Long): Int
@@ -100,7 +116,22 @@ class QuicheTunForwarder private constructor(
             packetsSent = values[1],
             packetsDropped = values[2],
             bytesReceived = values[3],
-            bytesSent = values[4]
+            bytesSent = values[4],
+            packetsToTun = values[5],
+            bytesToTun = values[6],
+            tunWriteErrors = values[7],
+            txRateMbps = values[8] / 1000.0,
+            rxRateMbps = values[9] / 1000.0,
+            packetsOversized = values[10],
+            readBatches = values[11],
+            poolExhausted = values[12],
+            gsoFrames = values[13],
+            gsoSegments = values[14],
+            tcpFlowsActive = values[15],
+            tcpFlowsTotal = values[16],
+            udpFlowsActive = values[17],
+            udpFlowsTotal = values[18],
+            flowErrors = values[19]
         )
     }
 
@@ -113,13 +144,31 @@ class QuicheTunForwarder private constructor(
 
 /**
  * Forwarder statistics
+ *
+ * Uplink (TUN -> QUIC): packetsReceived/bytesReceived read from TUN, packetsSent/bytesSent sent.
+ * Downlink (QUIC -> TUN): packetsToTun/bytesToTun written back to TUN.
  */
 data class ForwarderStats(
     val packetsReceived: Long,
     val packetsSent: Long,
     val packetsDropped: Long,
     val bytesReceived: Long,
-    val bytesSent: Long
+    val bytesSent: Long,
+    val packetsToTun: Long,
+    val bytesToTun: Long,
+    val tunWriteErrors: Long,
+    val txRateMbps: Double,
+    val rxRateMbps: Double,
+    val packetsOversized: Long,  // Subset of packetsDropped exceeding the DATAGRAM size
+    val readBatches: Long,       // TUN wakeups; packetsReceived / readBatches = average batch
+    val poolExhausted: Long,     // Reads stalled waiting for a free packet buffer
+    val gsoFrames: Long,         // vnet mode: TSO/USO super-packets read from TUN
+    val gsoSegments: Long,       // vnet mode: packets those super-packets were split into
+    val tcpFlowsActive: Long,    // flow mode: open TCP flows
+    val tcpFlowsTotal: Long,
+    val udpFlowsActive: Long,    // flow mode: UDP flows seen within the idle timeout
+    val udpFlowsTotal: Long,
+    val flowErrors: Long         // flow mode: flows refused or reset
 ) {
     val packetLossRate: Double
         get() = if (packetsReceived > 0) {
@@ -131,7 +180,18 @@ data class ForwarderStats(
     override fun toString(): String {
         return "ForwarderStats(rx=$packetsReceived pkts, " +
                 "tx=$packetsSent pkts, " +
+                "toTun=$packetsToTun pkts, " +
                 "dropped=$packetsDropped pkts, " +
-                "loss=${"%.3f".format(packetLossRate * 100)}%)"
+                "loss=${"%.3f".format(packetLossRate * 100)}%, " +
+                "up=${"%.2f".format(txRateMbps)} Mbps, " +
+                "down=${"%.2f".format(rxRateMbps)} Mbps)"
     }
 }
+
+/**
+ * What the forwarder sends over QUIC
+ */
+enum class ForwarderMode {
+    PACKET,  // Raw IP packets; needs a server that routes them
+    FLOW     // TCP/UDP flows terminated locally (tun2quic)
+}